@@ -5,7 +5,7 @@ use fedimint_core::fedimint_build_code_version_env;
 async fn main() -> anyhow::Result<()> {
     FedimintCli::new(fedimint_build_code_version_env!())?
         .with_default_modules()
-        .with_module(fedimint_prediction_markets_client::PredictionMarketsClientInit)
+        .with_module(fedimint_prediction_markets_client::PredictionMarketsClientInit::default())
         .run()
         .await;
     Ok(())
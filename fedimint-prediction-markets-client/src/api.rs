@@ -3,14 +3,19 @@ use fedimint_core::module::ApiRequestErased;
 use fedimint_core::task::{MaybeSend, MaybeSync};
 use fedimint_core::{apply, async_trait_maybe_send};
 use fedimint_prediction_markets_common::api::{
-    GetEventPayoutAttestationsUsedToPermitPayoutParams,
+    GetConsensusTimestampResult, GetEventPayoutAttestationsUsedToPermitPayoutParams,
     GetEventPayoutAttestationsUsedToPermitPayoutResult, GetMarketDynamicParams,
-    GetMarketDynamicResult, GetMarketOutcomeCandlesticksParams, GetMarketOutcomeCandlesticksResult,
+    GetMarketDynamicResult, GetMarketOrdersParams, GetMarketOrdersResult,
+    GetMarketOutcomeCandlesticksParams, GetMarketOutcomeCandlesticksResult,
     GetMarketOutcomeOrderBookParams, GetMarketOutcomeOrderBookResult, GetMarketParams,
-    GetMarketResult, GetOrderParams, GetOrderResult, WaitMarketOutcomeCandlesticksParams,
+    GetMarketResult, GetMarketsByEventHashParams, GetMarketsByEventHashResult,
+    GetMarketsByPayoutControlParams, GetMarketsByPayoutControlResult, GetMarketsParams,
+    GetMarketsResult, GetOrderParams, GetOrderResult, WaitMarketOutcomeCandlesticksParams,
     WaitMarketOutcomeCandlesticksResult, WaitOrderMatchParams, WaitOrderMatchResult,
-    GET_EVENT_PAYOUT_ATTESTATIONS_USED_TO_PERMIT_PAYOUT_ENDPOINT, GET_MARKET_DYNAMIC_ENDPOINT,
-    GET_MARKET_ENDPOINT, GET_MARKET_OUTCOME_CANDLESTICKS_ENDPOINT,
+    GET_CONSENSUS_TIMESTAMP_ENDPOINT, GET_EVENT_PAYOUT_ATTESTATIONS_USED_TO_PERMIT_PAYOUT_ENDPOINT,
+    GET_MARKETS_BY_EVENT_HASH_ENDPOINT, GET_MARKETS_BY_PAYOUT_CONTROL_ENDPOINT,
+    GET_MARKETS_ENDPOINT, GET_MARKET_DYNAMIC_ENDPOINT, GET_MARKET_ENDPOINT,
+    GET_MARKET_ORDERS_ENDPOINT, GET_MARKET_OUTCOME_CANDLESTICKS_ENDPOINT,
     GET_MARKET_OUTCOME_ORDER_BOOK_ENDPOINT, GET_ORDER_ENDPOINT,
     WAIT_MARKET_OUTCOME_CANDLESTICKS_ENDPOINT, WAIT_ORDER_MATCH_ENDPOINT,
 };
@@ -18,6 +23,15 @@ use fedimint_prediction_markets_common::api::{
 #[apply(async_trait_maybe_send!)]
 pub trait PredictionMarketsFederationApi {
     async fn get_market(&self, params: GetMarketParams) -> FederationResult<GetMarketResult>;
+    async fn get_markets(&self, params: GetMarketsParams) -> FederationResult<GetMarketsResult>;
+    async fn get_markets_by_payout_control(
+        &self,
+        params: GetMarketsByPayoutControlParams,
+    ) -> FederationResult<GetMarketsByPayoutControlResult>;
+    async fn get_markets_by_event_hash(
+        &self,
+        params: GetMarketsByEventHashParams,
+    ) -> FederationResult<GetMarketsByEventHashResult>;
     async fn get_market_dynamic(
         &self,
         params: GetMarketDynamicParams,
@@ -27,6 +41,10 @@ pub trait PredictionMarketsFederationApi {
         params: GetEventPayoutAttestationsUsedToPermitPayoutParams,
     ) -> FederationResult<GetEventPayoutAttestationsUsedToPermitPayoutResult>;
     async fn get_order(&self, params: GetOrderParams) -> FederationResult<GetOrderResult>;
+    async fn get_market_orders(
+        &self,
+        params: GetMarketOrdersParams,
+    ) -> FederationResult<GetMarketOrdersResult>;
     async fn wait_order_match(
         &self,
         params: WaitOrderMatchParams,
@@ -43,6 +61,7 @@ pub trait PredictionMarketsFederationApi {
         &self,
         params: GetMarketOutcomeOrderBookParams,
     ) -> FederationResult<GetMarketOutcomeOrderBookResult>;
+    async fn get_consensus_timestamp(&self) -> FederationResult<GetConsensusTimestampResult>;
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -55,6 +74,33 @@ where
             .await
     }
 
+    async fn get_markets(&self, params: GetMarketsParams) -> FederationResult<GetMarketsResult> {
+        self.request_current_consensus(GET_MARKETS_ENDPOINT.into(), ApiRequestErased::new(params))
+            .await
+    }
+
+    async fn get_markets_by_payout_control(
+        &self,
+        params: GetMarketsByPayoutControlParams,
+    ) -> FederationResult<GetMarketsByPayoutControlResult> {
+        self.request_current_consensus(
+            GET_MARKETS_BY_PAYOUT_CONTROL_ENDPOINT.into(),
+            ApiRequestErased::new(params),
+        )
+        .await
+    }
+
+    async fn get_markets_by_event_hash(
+        &self,
+        params: GetMarketsByEventHashParams,
+    ) -> FederationResult<GetMarketsByEventHashResult> {
+        self.request_current_consensus(
+            GET_MARKETS_BY_EVENT_HASH_ENDPOINT.into(),
+            ApiRequestErased::new(params),
+        )
+        .await
+    }
+
     async fn get_market_dynamic(
         &self,
         params: GetMarketDynamicParams,
@@ -82,6 +128,17 @@ where
             .await
     }
 
+    async fn get_market_orders(
+        &self,
+        params: GetMarketOrdersParams,
+    ) -> FederationResult<GetMarketOrdersResult> {
+        self.request_current_consensus(
+            GET_MARKET_ORDERS_ENDPOINT.into(),
+            ApiRequestErased::new(params),
+        )
+        .await
+    }
+
     async fn wait_order_match(
         &self,
         params: WaitOrderMatchParams,
@@ -125,4 +182,12 @@ where
         )
         .await
     }
+
+    async fn get_consensus_timestamp(&self) -> FederationResult<GetConsensusTimestampResult> {
+        self.request_current_consensus(
+            GET_CONSENSUS_TIMESTAMP_ENDPOINT.into(),
+            ApiRequestErased::default(),
+        )
+        .await
+    }
 }
@@ -1,28 +1,29 @@
-use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::{ffi, iter};
 
-use anyhow::bail;
+use anyhow::{anyhow, bail};
 use clap::Parser;
 use fedimint_core::{Amount, TransactionId};
 use fedimint_prediction_markets_common::{
-    ContractOfOutcomeAmount, PredictionMarketEventHashHex, PredictionMarketEventJson, Seconds,
-    Side, UnixTimestamp, WeightRequiredForPayout,
+    ContractOfOutcomeAmount, PredictionMarketEventHashHex, Seconds, Side, UnixTimestamp,
 };
+use futures::StreamExt;
 use prediction_market_event::Outcome;
-use prediction_market_event_nostr_client::nostr_sdk::JsonUtil;
 use serde::Serialize;
 use serde_json::json;
 
-use crate::order_filter::{self};
+use crate::order_filter::{self, OrderSort};
+use crate::payout_attestations::NostrRelay;
 use crate::{market_outpoint_from_tx_id, OrderId, PredictionMarketsClientModule};
 
 #[derive(Parser, Serialize)]
 enum Opts {
     NewMarket {
         event_hash_hex: PredictionMarketEventHashHex,
-        contract_price: Amount,
+        contract_price: CliAmount,
         payout_control: prediction_market_event_nostr_client::nostr_sdk::nostr::PublicKey,
+        #[clap(long)]
+        max_fee: Option<CliAmount>,
     },
     GetMarket {
         market_txid: TransactionId,
@@ -35,36 +36,55 @@ enum Opts {
     GetEventPayoutAttestationsUsedToPermitPayout {
         market_txid: TransactionId,
     },
+    SuggestPayout {
+        market_txid: TransactionId,
+    },
     NewOrder {
         market_txid: TransactionId,
         outcome: Outcome,
         side: Side,
-        price: Amount,
+        price: CliAmount,
         quantity: ContractOfOutcomeAmount,
+        #[clap(long)]
+        max_fee: Option<CliAmount>,
     },
     GetOrder {
         id: OrderId,
         #[clap(short, long, default_value = "false")]
         from_local_cache: bool,
     },
+    GetOrderLedger {
+        id: OrderId,
+    },
     CancelOrder {
         id: OrderId,
     },
-    WithdrawAvailableBitcoin,
+    WithdrawAvailableBitcoin {
+        #[clap(long)]
+        max_fee: Option<CliAmount>,
+    },
     SyncPayouts {
         #[clap(short, long)]
         market_txid: Option<TransactionId>,
     },
+    SyncAllPayouts,
     ListOrders {
         #[clap(short, long)]
         market_txid: Option<TransactionId>,
         #[clap(short, long)]
         outcome: Option<Outcome>,
+        /// id-asc, created-desc or resting-quantity-desc. Lists the orders as
+        /// an array in this order instead of a map keyed by id.
+        #[clap(long)]
+        sort: Option<OrderSort>,
     },
     RecoverOrders {
         #[clap(short, long)]
         gap_size_to_check: Option<usize>,
     },
+    /// Lists the candlestick intervals that can be passed to
+    /// get-candlesticks
+    CandlestickIntervals,
     GetCandlesticks {
         market_txid: TransactionId,
         outcome: Outcome,
@@ -73,6 +93,59 @@ enum Opts {
     },
 }
 
+/// Amount argument. A number without a denomination is in msats, otherwise
+/// it is suffixed with `msat`, `sat` or `btc`, such as `1000sat` or
+/// `0.00001btc`. Amounts that are not a whole number of msats are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+struct CliAmount(Amount);
+
+impl FromStr for CliAmount {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+        let (number, denomination) = s.split_at(split);
+        let msats_per_unit: u64 = match denomination.trim().to_lowercase().as_str() {
+            "" | "msat" => 1,
+            "sat" => 1_000,
+            "btc" => 100_000_000_000,
+            _ => bail!("unknown denomination, expected msat, sat or btc"),
+        };
+
+        let number = number.trim();
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if (whole.is_empty() && fraction.is_empty())
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            bail!("could not parse amount")
+        }
+
+        let decimals = msats_per_unit.ilog10() as usize;
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() > decimals {
+            bail!("amount is not a whole number of msats")
+        }
+
+        let whole = match whole {
+            "" => 0,
+            whole => whole.parse::<u64>()?,
+        };
+        let fraction = match fraction {
+            "" => 0,
+            fraction => format!("{fraction:0<decimals$}").parse::<u64>()?,
+        };
+        let msats = whole
+            .checked_mul(msats_per_unit)
+            .and_then(|msats| msats.checked_add(fraction))
+            .ok_or(anyhow!("amount is too large"))?;
+
+        Ok(Self(Amount::from_msats(msats)))
+    }
+}
+
 pub async fn handle_cli_command(
     prediction_markets: &PredictionMarketsClientModule,
     args: &[ffi::OsString],
@@ -85,6 +158,7 @@ pub async fn handle_cli_command(
             event_hash_hex,
             contract_price,
             payout_control,
+            max_fee,
         } => {
             let payout_control_weight_map =
                 vec![(payout_control.to_hex(), 1u16)].into_iter().collect();
@@ -105,14 +179,14 @@ pub async fn handle_cli_command(
             let event_json = event.try_to_json_string()?;
 
             let res = prediction_markets
-                .new_market(
+                .new_market_detailed(
                     event_json,
-                    contract_price,
+                    contract_price.0,
                     payout_control_weight_map,
                     weight_required_for_payout,
+                    max_fee.map(|v| v.0),
                 )
-                .await?
-                .txid;
+                .await?;
             json!(res)
         }
         Opts::GetMarket {
@@ -125,55 +199,11 @@ pub async fn handle_cli_command(
             json!(res)
         }
         Opts::PayoutMarket { market_txid } => {
-            let Some(market) = prediction_markets
-                .get_market(market_outpoint_from_tx_id(market_txid), false)
-                .await?
-            else {
-                bail!("market does not exist")
-            };
-            let event_hash_hex = market.0.event()?.hash_hex()?;
-            let nostr_client = get_nostr_client().await?;
-            let event_payout_attestation_result = nostr_client.get::<prediction_market_event_nostr_client::prediction_market_event::nostr_event_types::EventPayoutAttestation>(|f| {
-                market.0.payout_control_weight_map.iter().map(|(pk, _)| {
-                    let author = prediction_market_event_nostr_client::nostr_sdk::PublicKey::parse(pk).unwrap();
-                    f.clone().author(author).hashtag(&event_hash_hex.0)
-                }).collect()
-            }, None).await?;
-            let mut seen_payout_controls: HashSet<
-                prediction_market_event_nostr_client::prediction_market_event::nostr_event_types::NostrPublicKeyHex
-            > = HashSet::new();
-            let mut event_payout_stats: HashMap<
-                prediction_market_event_nostr_client::prediction_market_event::EventPayout,
-                (Vec<PredictionMarketEventJson>, WeightRequiredForPayout),
-            > = HashMap::new();
-
-            for (nostr_event, (payout_control, event_payout)) in event_payout_attestation_result {
-                let Some(weight) = market.0.payout_control_weight_map.get(&payout_control.0) else {
-                    continue;
-                };
-                if !seen_payout_controls.insert(payout_control) {
-                    continue;
-                }
-                if !event_payout_stats.contains_key(&event_payout) {
-                    event_payout_stats.insert(event_payout.clone(), (Vec::new(), 0));
-                }
-
-                let event_payout_stats_value = event_payout_stats.get_mut(&event_payout).unwrap();
-                event_payout_stats_value.0.push(nostr_event.try_as_json()?);
-                event_payout_stats_value.1 += WeightRequiredForPayout::from(*weight);
-            }
-            let mut found_payout = None;
-            for (event_payout, (event_payout_attestations_json, total_weight)) in event_payout_stats
-            {
-                if market.0.weight_required_for_payout > total_weight {
-                    continue;
-                }
-
-                found_payout = Some((event_payout, event_payout_attestations_json));
-                break;
-            }
+            let attestations = prediction_markets
+                .collect_payout_attestations(market_outpoint_from_tx_id(market_txid))
+                .await?;
 
-            match found_payout {
+            match attestations.payout {
                 Some((event_payout, event_payout_attestations_json)) => {
                     prediction_markets
                         .payout_market(
@@ -190,6 +220,9 @@ pub async fn handle_cli_command(
                 None => {
                     json!({
                         "payout_submitted": false,
+                        "relays_queried": attestations.relays_queried,
+                        "relays_answered": attestations.relays_answered,
+                        "served_by": attestations.served_by,
                     })
                 }
             }
@@ -203,6 +236,13 @@ pub async fn handle_cli_command(
 
             json!(res)
         }
+        Opts::SuggestPayout { market_txid } => {
+            let res = prediction_markets
+                .suggest_payout(market_outpoint_from_tx_id(market_txid))
+                .await?;
+
+            json!(res)
+        }
 
         Opts::NewOrder {
             market_txid,
@@ -210,14 +250,16 @@ pub async fn handle_cli_command(
             side,
             price,
             quantity,
+            max_fee,
         } => {
             let res = prediction_markets
                 .new_order(
                     market_outpoint_from_tx_id(market_txid),
                     outcome,
                     side,
-                    price,
+                    price.0,
                     quantity,
+                    max_fee.map(|v| v.0),
                 )
                 .await?;
 
@@ -231,14 +273,19 @@ pub async fn handle_cli_command(
 
             json!(res)
         }
+        Opts::GetOrderLedger { id } => {
+            let res = prediction_markets.get_order_ledger(id).await;
+
+            json!(res)
+        }
         Opts::CancelOrder { id } => {
             let res = prediction_markets.cancel_order(id).await?;
 
             json!(res)
         }
-        Opts::WithdrawAvailableBitcoin => {
+        Opts::WithdrawAvailableBitcoin { max_fee } => {
             let res = prediction_markets
-                .send_order_bitcoin_balance_to_primary_module()
+                .send_order_bitcoin_balance_to_primary_module(max_fee.map(|v| v.0))
                 .await?;
 
             json!(res)
@@ -250,9 +297,15 @@ pub async fn handle_cli_command(
 
             json!(res)
         }
+        Opts::SyncAllPayouts => {
+            let res: Vec<_> = prediction_markets.sync_all_payouts().collect().await;
+
+            json!(res)
+        }
         Opts::ListOrders {
             market_txid,
             outcome,
+            sort,
         } => {
             let order_path = match market_txid {
                 None => order_filter::OrderPath::All,
@@ -266,14 +319,11 @@ pub async fn handle_cli_command(
                     },
                 },
             };
-            let res = prediction_markets
-                .get_orders_from_db(order_filter::OrderFilter(
-                    order_path,
-                    order_filter::OrderState::Any,
-                ))
-                .await;
-
-            json!(res)
+            let filter = order_filter::OrderFilter(order_path, order_filter::OrderState::Any);
+            match sort {
+                None => json!(prediction_markets.get_tagged_orders_from_db(filter).await),
+                Some(sort) => json!(prediction_markets.list_orders(filter, sort).await),
+            }
         }
         Opts::RecoverOrders { gap_size_to_check } => {
             let res = prediction_markets
@@ -297,6 +347,11 @@ pub async fn handle_cli_command(
                 )
                 .await?;
 
+            json!(res)
+        }
+        Opts::CandlestickIntervals => {
+            let res = prediction_markets.supported_candlestick_intervals();
+
             json!(res)
         }
     };
@@ -315,14 +370,65 @@ const RECOMMENDED_RELAY_LIST: &[&str] = &[
     "wss://nostrrelay.com",
 ];
 
-async fn get_nostr_client() -> anyhow::Result<prediction_market_event_nostr_client::Client> {
-    let relays = RECOMMENDED_RELAY_LIST
+fn recommended_relay_urls(
+) -> impl Iterator<Item = prediction_market_event_nostr_client::nostr_sdk::Url> {
+    RECOMMENDED_RELAY_LIST
         .iter()
         .map(|s| prediction_market_event_nostr_client::nostr_sdk::Url::from_str(s).unwrap())
-        .collect();
+}
+
+pub(crate) fn recommended_relays() -> Vec<NostrRelay> {
+    recommended_relay_urls().map(NostrRelay).collect()
+}
+
+pub(crate) async fn get_nostr_client(
+) -> anyhow::Result<prediction_market_event_nostr_client::Client> {
+    let relays = recommended_relay_urls().collect();
     let client =
         prediction_market_event_nostr_client::Client::new_initialized_client_query_only(relays)
             .await?;
 
     Ok(client)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cli_amount() {
+        for (s, msats) in [
+            ("1500", 1500),
+            ("1500msat", 1500),
+            ("2sat", 2_000),
+            ("1.5 sat", 1_500),
+            ("0.001sat", 1),
+            ("1BTC", 100_000_000_000),
+            ("0.00000001btc", 1_000),
+            ("0.00000000001btc", 1),
+            (".5sat", 500),
+            ("2.000sat", 2_000),
+        ] {
+            assert_eq!(
+                CliAmount::from_str(s).unwrap(),
+                CliAmount(Amount::from_msats(msats)),
+                "{s}"
+            );
+        }
+
+        for s in [
+            "1.5",
+            "1.5msat",
+            "0.0001sat",
+            "0.000000000001btc",
+            "",
+            ".",
+            "sat",
+            "-1sat",
+            "1eur",
+            "200000000btc",
+        ] {
+            assert!(CliAmount::from_str(s).is_err(), "{s}");
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::iter;
+
+use fedimint_prediction_markets_common::{Candlestick, UnixTimestamp};
+
+use crate::{OrderId, TaggedOrder};
+
+/// Amounts are in msats.
+pub fn orders_table(orders: &[(OrderId, TaggedOrder)]) -> String {
+    table(
+        &[
+            "id",
+            "market",
+            "outcome",
+            "side",
+            "price",
+            "quantity",
+            "waiting",
+            "contracts",
+            "bitcoin",
+            "tag",
+        ],
+        orders
+            .iter()
+            .map(|(id, TaggedOrder { order, tag })| {
+                vec![
+                    id.0.to_string(),
+                    order.market.to_string(),
+                    order.outcome.to_string(),
+                    format!("{:?}", order.side),
+                    order.price.msats.to_string(),
+                    order.original_quantity.0.to_string(),
+                    order.quantity_waiting_for_match.0.to_string(),
+                    order.contract_of_outcome_balance.0.to_string(),
+                    order.bitcoin_balance.msats.to_string(),
+                    tag.clone().unwrap_or_default(),
+                ]
+            })
+            .collect(),
+    )
+}
+
+/// Prices are in msats.
+pub fn candlesticks_table(candlesticks: &BTreeMap<UnixTimestamp, Candlestick>) -> String {
+    table(
+        &["timestamp", "open", "high", "low", "close", "volume"],
+        candlesticks
+            .iter()
+            .map(|(timestamp, candlestick)| {
+                vec![
+                    timestamp.0.to_string(),
+                    candlestick.open.msats.to_string(),
+                    candlestick.high.msats.to_string(),
+                    candlestick.low.msats.to_string(),
+                    candlestick.close.msats.to_string(),
+                    candlestick.volume.0.to_string(),
+                ]
+            })
+            .collect(),
+    )
+}
+
+/// Left aligns each column to its widest cell.
+fn table(header: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<_> = header.iter().map(|h| h.len()).collect();
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    iter::once(header.iter().map(|h| h.to_string()).collect())
+        .chain(rows)
+        .map(|row: Vec<String>| {
+            let line = row
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            line.trim_end().to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use fedimint_core::{Amount, TransactionId};
+    use fedimint_prediction_markets_common::{ContractOfOutcomeAmount, Order, Side, SignedAmount};
+
+    use super::*;
+    use crate::market_outpoint_from_tx_id;
+
+    #[test]
+    fn two_order_table() {
+        let market = market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64)).unwrap());
+        let order = Order {
+            market,
+            outcome: 0,
+            side: Side::Buy,
+            price: Amount::from_msats(60),
+            original_quantity: ContractOfOutcomeAmount(10),
+            time_ordering: 0,
+            created_consensus_timestamp: UnixTimestamp::ZERO,
+            quantity_waiting_for_match: ContractOfOutcomeAmount(4),
+            contract_of_outcome_balance: ContractOfOutcomeAmount(6),
+            bitcoin_balance: Amount::ZERO,
+            quantity_fulfilled: ContractOfOutcomeAmount(6),
+            bitcoin_acquired_from_order_matches: SignedAmount::ZERO,
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        };
+        let orders = vec![
+            (
+                OrderId(1),
+                TaggedOrder {
+                    order: order.clone(),
+                    tag: Some("mm".to_owned()),
+                },
+            ),
+            (
+                OrderId(12),
+                TaggedOrder {
+                    order: Order {
+                        outcome: 1,
+                        side: Side::Sell,
+                        price: Amount::from_msats(105),
+                        ..order
+                    },
+                    tag: None,
+                },
+            ),
+        ];
+
+        let padding = " ".repeat(market.to_string().len() - "market".len());
+        assert_eq!(
+            orders_table(&orders),
+            [
+                format!("id  market{padding}  outcome  side  price  quantity  waiting  contracts  bitcoin  tag"),
+                format!("1   {market}  0        Buy   60     10        4        6          0        mm"),
+                format!("12  {market}  1        Sell  105    10        4        6          0"),
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -1,10 +1,22 @@
+use fedimint_core::core::{ModuleInstanceId, OperationId};
+use fedimint_core::db::{DatabaseTransaction, IDatabaseTransactionOpsCoreTyped};
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::{impl_db_lookup, impl_db_record, OutPoint};
 use fedimint_prediction_markets_common::{
-    Market, NostrPublicKeyHex, Order, Outcome, Side, TimeOrdering, UnixTimestamp,
+    Market, MarketDynamic, MarketStaticV0, NostrPublicKeyHex, Order, Outcome,
+    PredictionMarketEventHashHex, Side, TimeOrdering, UnixTimestamp,
 };
+use futures::StreamExt;
 
-use crate::OrderId;
+use crate::states::{
+    CancelOrderState, ConsumeOrderBitcoinBalanceState, NewOrderState, PayoutMarketState,
+    PredictionMarketState, PredictionMarketsStateMachine, UNKNOWN_REJECTION_REASON,
+};
+use crate::{
+    DeadMansSwitch, MarketDisplayOverride, MarketTemplate, OperationOutcome, OrderBalanceRecord,
+    OrderId, OrderSyncCursor, SavedMarketMeta, SubmittedTransaction,
+};
 
 #[repr(u8)]
 #[derive(Clone, Debug)]
@@ -41,11 +53,104 @@ pub enum DbKeyPrefix {
     /// (Market's [OutPoint], [Outcome], [Side], [OrderId]) to ()
     OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSide = 0x23,
 
-    /// (Market's [OutPoint]) to (Saved to db [UnixTimestamp])
+    /// (Market's [OutPoint]) to ([SavedMarketMeta])
     ClientSavedMarkets = 0x41,
 
     /// (Name [String]) to (Payout control [NostrPublicKeyHex])
     ClientNamedPayoutControls = 0x42,
+
+    /// Written before the market is submitted, with no market until it was.
+    ///
+    /// (Idempotency key [[u8; 32]]) to (Market's [Option]<[OutPoint]>)
+    NewMarketIdempotency = 0x43,
+
+    /// [OperationId] to [OperationOutcome]
+    OperationOutcome = 0x44,
+
+    /// (Market's [OutPoint], [Outcome], [Side]) to [OrderSyncCursor]
+    OrderSyncCursor = 0x45,
+
+    /// Cache for markets by payout control
+    ///
+    /// (Payout control [NostrPublicKeyHex], Market's created
+    /// [UnixTimestamp], Market's [OutPoint]) to ()
+    PayoutControlMarkets = 0x46,
+
+    /// Cache for markets by event hash. Only holds the earliest created
+    /// market for the event.
+    ///
+    /// (Event's [PredictionMarketEventHashHex]) to (Market's [OutPoint])
+    EventHashMarket = 0x47,
+
+    /// Client side order tags. Each order has at most one tag.
+    ///
+    /// (Tag [String], [OrderId]) to ()
+    OrderTag = 0x48,
+
+    /// Tag of each tagged order, mirrors [DbKeyPrefix::OrderTag]
+    ///
+    /// [OrderId] to Tag [String]
+    OrderTagByOrder = 0x49,
+
+    /// Order ids reserved by the caller that no order was created with yet.
+    /// Their [OrderKey] slot is [OrderIdSlot::Reserved].
+    ///
+    /// [OrderId] to ()
+    OrderIdReservation = 0x4A,
+
+    /// Singleton. Only set while a dead man's switch is registered.
+    ///
+    /// () to [DeadMansSwitch]
+    DeadMansSwitch = 0x4B,
+
+    /// Written just before the module submits a transaction
+    ///
+    /// (submitted at [UnixTimestamp], [OperationId]) to [SubmittedTransaction]
+    SubmittedTransaction = 0x4C,
+
+    /// Singleton. Last time orders were synced from the federation.
+    ///
+    /// () to [UnixTimestamp]
+    LastOrderSync = 0x4D,
+
+    /// Orders whose new order transaction was rejected but that the
+    /// federation has anyway
+    ///
+    /// [OrderId] to rejection reason [String]
+    RejectedOrderDiscrepancy = 0x4E,
+
+    /// Balances of an order each time a change was cached. Entries are
+    /// numbered from 0 in the order they were recorded.
+    ///
+    /// ([OrderId], entry [u64]) to [OrderBalanceRecord]
+    OrderLedger = 0x4F,
+
+    /// Operations the caller stopped waiting for
+    ///
+    /// [OperationId] to when it was abandoned [UnixTimestamp]
+    AbandonedOperation = 0x50,
+
+    /// Client side replacement of how a market is displayed
+    ///
+    /// Market's [OutPoint] to [MarketDisplayOverride]
+    MarketDisplayOverride = 0x51,
+
+    /// Reusable parameters for creating similar markets
+    ///
+    /// Template name [String] to [MarketTemplate]
+    MarketTemplate = 0x52,
+
+    /// Markets that paid out while the client was not running, found on
+    /// startup
+    ///
+    /// Market's [OutPoint] to when the payout was found [UnixTimestamp]
+    PendingSettlement = 0x53,
+
+    /// Orders whose new order transaction was submitted but that are not
+    /// cached yet. Their [OrderKey] slot is [OrderIdSlot::Reserved].
+    ///
+    /// [OrderId] to ()
+    OrderAwaitingCache = 0x54,
 }
 
 // Market
@@ -63,11 +168,32 @@ impl_db_record!(
 
 impl_db_lookup!(key = MarketKey, query_prefix = MarketPrefixAll);
 
+/// [Market] as encoded before outcome display hints, see [migrate_to_v2]
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct MarketV0(pub MarketStaticV0, pub MarketDynamic);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct MarketKeyV0(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MarketPrefixAllV0;
+
+impl_db_record!(
+    key = MarketKeyV0,
+    value = MarketV0,
+    db_prefix = DbKeyPrefix::Market,
+);
+
+impl_db_lookup!(key = MarketKeyV0, query_prefix = MarketPrefixAllV0);
+
 // Order
 #[derive(Debug, Encodable, Decodable, PartialEq, Eq, Clone)]
 pub enum OrderIdSlot {
     Reserved,
     Order(Order),
+    /// The order was settled and its cached body removed. The slot is kept so
+    /// that the id is never allocated again.
+    Pruned,
 }
 
 impl OrderIdSlot {
@@ -75,6 +201,7 @@ impl OrderIdSlot {
         match self {
             Self::Reserved => None,
             Self::Order(order) => Some(order),
+            Self::Pruned => None,
         }
     }
 }
@@ -237,7 +364,7 @@ pub struct ClientSavedMarketsPrefixAll;
 
 impl_db_record!(
     key = ClientSavedMarketsKey,
-    value = UnixTimestamp,
+    value = SavedMarketMeta,
     db_prefix = DbKeyPrefix::ClientSavedMarkets,
 );
 
@@ -246,6 +373,132 @@ impl_db_lookup!(
     query_prefix = ClientSavedMarketsPrefixAll
 );
 
+/// Saved markets as stored before database version 1, valued by the
+/// [UnixTimestamp] they were saved at.
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct ClientSavedMarketsKeyV0 {
+    pub market: OutPoint,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ClientSavedMarketsPrefixAllV0;
+
+impl_db_record!(
+    key = ClientSavedMarketsKeyV0,
+    value = UnixTimestamp,
+    db_prefix = DbKeyPrefix::ClientSavedMarkets,
+);
+
+impl_db_lookup!(
+    key = ClientSavedMarketsKeyV0,
+    query_prefix = ClientSavedMarketsPrefixAllV0
+);
+
+/// Migrates saved markets from bare [UnixTimestamp] values to
+/// [SavedMarketMeta] with no note or tags.
+pub async fn migrate_to_v1(
+    dbtx: &mut DatabaseTransaction<'_>,
+) -> anyhow::Result<Option<(Vec<(Vec<u8>, OperationId)>, Vec<(Vec<u8>, OperationId)>)>> {
+    let saved_markets: Vec<_> = dbtx
+        .find_by_prefix(&ClientSavedMarketsPrefixAllV0)
+        .await
+        .collect()
+        .await;
+
+    for (key, saved_at) in saved_markets {
+        dbtx.insert_entry(
+            &ClientSavedMarketsKey { market: key.market },
+            &SavedMarketMeta {
+                saved_at,
+                note: None,
+                tags: Vec::new(),
+            },
+        )
+        .await;
+    }
+
+    Ok(None)
+}
+
+/// Re-encodes cached markets with no outcome display hints.
+pub async fn migrate_to_v2(
+    dbtx: &mut DatabaseTransaction<'_>,
+) -> anyhow::Result<Option<(Vec<(Vec<u8>, OperationId)>, Vec<(Vec<u8>, OperationId)>)>> {
+    let markets: Vec<_> = dbtx
+        .find_by_prefix(&MarketPrefixAllV0)
+        .await
+        .collect()
+        .await;
+
+    for (MarketKeyV0(market), MarketV0(market_static, market_dynamic)) in markets {
+        dbtx.insert_entry(
+            &MarketKey(market),
+            &Market(market_static.into(), market_dynamic),
+        )
+        .await;
+    }
+
+    Ok(None)
+}
+
+/// Moves state machines in a rejected state without a reason to the state
+/// that replaced it, with [UNKNOWN_REJECTION_REASON].
+///
+/// State bytes start with the [ModuleInstanceId] of the module, which is kept.
+pub fn migrate_to_v3(
+    active_states: Vec<(Vec<u8>, OperationId)>,
+    inactive_states: Vec<(Vec<u8>, OperationId)>,
+) -> anyhow::Result<Option<(Vec<(Vec<u8>, OperationId)>, Vec<(Vec<u8>, OperationId)>)>> {
+    let migrate = |states: Vec<(Vec<u8>, OperationId)>| {
+        states
+            .into_iter()
+            .map(|(bytes, operation_id)| {
+                let decoders = ModuleDecoderRegistry::default();
+                let mut bytes = bytes.as_slice();
+                let module_instance_id = ModuleInstanceId::consensus_decode(&mut bytes, &decoders)?;
+                let mut state_machine =
+                    PredictionMarketsStateMachine::consensus_decode(&mut bytes, &decoders)?;
+                state_machine.state = state_with_rejection_reason(state_machine.state);
+
+                let mut migrated = module_instance_id.consensus_encode_to_vec();
+                migrated.extend(state_machine.consensus_encode_to_vec());
+                Ok((migrated, operation_id))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    };
+
+    Ok(Some((migrate(active_states)?, migrate(inactive_states)?)))
+}
+
+fn state_with_rejection_reason(state: PredictionMarketState) -> PredictionMarketState {
+    let reason = UNKNOWN_REJECTION_REASON.to_owned();
+
+    match state {
+        PredictionMarketState::NewOrder(NewOrderState::Rejected {
+            order_id,
+            orders_to_sync_on_rejected,
+        }) => NewOrderState::Rejected3 {
+            order_id,
+            orders_to_sync_on_rejected,
+            reason,
+        }
+        .into(),
+        PredictionMarketState::NewOrder(NewOrderState::Rejected2 { order_id }) => {
+            NewOrderState::Rejected4 { order_id, reason }.into()
+        }
+        PredictionMarketState::CancelOrder(CancelOrderState::Rejected) => {
+            CancelOrderState::Rejected2 { reason }.into()
+        }
+        PredictionMarketState::ConsumeOrderBitcoinBalance(
+            ConsumeOrderBitcoinBalanceState::Rejected,
+        ) => ConsumeOrderBitcoinBalanceState::Rejected2 { reason }.into(),
+        PredictionMarketState::PayoutMarket(PayoutMarketState::Rejected) => {
+            PayoutMarketState::Rejected2 { reason }.into()
+        }
+        state => state,
+    }
+}
+
 // ClientSavedPayoutControls
 #[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
 pub struct ClientNamedPayoutControlsKey {
@@ -266,6 +519,381 @@ impl_db_lookup!(
     query_prefix = ClientNamedPayoutControlsPrefixAll
 );
 
+// NewMarketIdempotency
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct NewMarketIdempotencyKey {
+    pub key: [u8; 32],
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct NewMarketIdempotencyPrefixAll;
+
+impl_db_record!(
+    key = NewMarketIdempotencyKey,
+    value = Option<OutPoint>,
+    db_prefix = DbKeyPrefix::NewMarketIdempotency,
+);
+
+impl_db_lookup!(
+    key = NewMarketIdempotencyKey,
+    query_prefix = NewMarketIdempotencyPrefixAll
+);
+
+// OperationOutcome
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct OperationOutcomeKey(pub OperationId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OperationOutcomePrefixAll;
+
+impl_db_record!(
+    key = OperationOutcomeKey,
+    value = OperationOutcome,
+    db_prefix = DbKeyPrefix::OperationOutcome,
+);
+
+impl_db_lookup!(
+    key = OperationOutcomeKey,
+    query_prefix = OperationOutcomePrefixAll
+);
+
+// OrderSyncCursor
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct OrderSyncCursorKey {
+    pub market: OutPoint,
+    pub outcome: Outcome,
+    pub side: Side,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OrderSyncCursorPrefixAll;
+
+impl_db_record!(
+    key = OrderSyncCursorKey,
+    value = OrderSyncCursor,
+    db_prefix = DbKeyPrefix::OrderSyncCursor,
+);
+
+impl_db_lookup!(
+    key = OrderSyncCursorKey,
+    query_prefix = OrderSyncCursorPrefixAll
+);
+
+// PayoutControlMarkets
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct PayoutControlMarketsKey {
+    pub payout_control: NostrPublicKeyHex,
+    pub created_consensus_timestamp: UnixTimestamp,
+    pub market: OutPoint,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PayoutControlMarketsPrefixAll;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PayoutControlMarketsPrefix1 {
+    pub payout_control: NostrPublicKeyHex,
+}
+
+impl_db_record!(
+    key = PayoutControlMarketsKey,
+    value = (),
+    db_prefix = DbKeyPrefix::PayoutControlMarkets,
+);
+
+impl_db_lookup!(
+    key = PayoutControlMarketsKey,
+    query_prefix = PayoutControlMarketsPrefixAll,
+    query_prefix = PayoutControlMarketsPrefix1
+);
+
+// EventHashMarket
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct EventHashMarketKey {
+    pub event_hash_hex: PredictionMarketEventHashHex,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct EventHashMarketPrefixAll;
+
+impl_db_record!(
+    key = EventHashMarketKey,
+    value = OutPoint,
+    db_prefix = DbKeyPrefix::EventHashMarket,
+);
+
+impl_db_lookup!(
+    key = EventHashMarketKey,
+    query_prefix = EventHashMarketPrefixAll
+);
+
+// OrderTag
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct OrderTagKey {
+    pub tag: String,
+    pub order: OrderId,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OrderTagPrefixAll;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OrderTagPrefix1 {
+    pub tag: String,
+}
+
+impl_db_record!(
+    key = OrderTagKey,
+    value = (),
+    db_prefix = DbKeyPrefix::OrderTag,
+);
+
+impl_db_lookup!(
+    key = OrderTagKey,
+    query_prefix = OrderTagPrefixAll,
+    query_prefix = OrderTagPrefix1
+);
+
+// OrderTagByOrder
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct OrderTagByOrderKey(pub OrderId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OrderTagByOrderPrefixAll;
+
+impl_db_record!(
+    key = OrderTagByOrderKey,
+    value = String,
+    db_prefix = DbKeyPrefix::OrderTagByOrder,
+);
+
+impl_db_lookup!(
+    key = OrderTagByOrderKey,
+    query_prefix = OrderTagByOrderPrefixAll
+);
+
+// OrderIdReservation
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct OrderIdReservationKey(pub OrderId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OrderIdReservationPrefixAll;
+
+impl_db_record!(
+    key = OrderIdReservationKey,
+    value = (),
+    db_prefix = DbKeyPrefix::OrderIdReservation,
+);
+
+impl_db_lookup!(
+    key = OrderIdReservationKey,
+    query_prefix = OrderIdReservationPrefixAll
+);
+
+// OrderAwaitingCache
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct OrderAwaitingCacheKey(pub OrderId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OrderAwaitingCachePrefixAll;
+
+impl_db_record!(
+    key = OrderAwaitingCacheKey,
+    value = (),
+    db_prefix = DbKeyPrefix::OrderAwaitingCache,
+);
+
+impl_db_lookup!(
+    key = OrderAwaitingCacheKey,
+    query_prefix = OrderAwaitingCachePrefixAll
+);
+
+/// Indexes reserved order slots whose reservation was used by a new order.
+pub async fn migrate_to_v4(
+    dbtx: &mut DatabaseTransaction<'_>,
+) -> anyhow::Result<Option<(Vec<(Vec<u8>, OperationId)>, Vec<(Vec<u8>, OperationId)>)>> {
+    let reserved_slots: Vec<_> = dbtx
+        .find_by_prefix(&OrderPrefixAll)
+        .await
+        .filter_map(
+            |(key, slot)| async move { matches!(slot, OrderIdSlot::Reserved).then_some(key.0) },
+        )
+        .collect()
+        .await;
+
+    for order in reserved_slots {
+        if dbtx
+            .get_value(&OrderIdReservationKey(order))
+            .await
+            .is_none()
+        {
+            dbtx.insert_entry(&OrderAwaitingCacheKey(order), &()).await;
+        }
+    }
+
+    Ok(None)
+}
+
+// DeadMansSwitch
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct DeadMansSwitchKey;
+
+impl_db_record!(
+    key = DeadMansSwitchKey,
+    value = DeadMansSwitch,
+    db_prefix = DbKeyPrefix::DeadMansSwitch,
+);
+
+// SubmittedTransaction
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct SubmittedTransactionKey {
+    pub submitted_at: UnixTimestamp,
+    pub operation_id: OperationId,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct SubmittedTransactionPrefixAll;
+
+impl_db_record!(
+    key = SubmittedTransactionKey,
+    value = SubmittedTransaction,
+    db_prefix = DbKeyPrefix::SubmittedTransaction,
+);
+
+impl_db_lookup!(
+    key = SubmittedTransactionKey,
+    query_prefix = SubmittedTransactionPrefixAll
+);
+
+// LastOrderSync
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct LastOrderSyncKey;
+
+impl_db_record!(
+    key = LastOrderSyncKey,
+    value = UnixTimestamp,
+    db_prefix = DbKeyPrefix::LastOrderSync,
+);
+
+// RejectedOrderDiscrepancy
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct RejectedOrderDiscrepancyKey(pub OrderId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct RejectedOrderDiscrepancyPrefixAll;
+
+impl_db_record!(
+    key = RejectedOrderDiscrepancyKey,
+    value = String,
+    db_prefix = DbKeyPrefix::RejectedOrderDiscrepancy,
+);
+
+impl_db_lookup!(
+    key = RejectedOrderDiscrepancyKey,
+    query_prefix = RejectedOrderDiscrepancyPrefixAll
+);
+
+// OrderLedger
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct OrderLedgerKey {
+    pub order: OrderId,
+    pub entry: u64,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OrderLedgerPrefixAll;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OrderLedgerPrefix1 {
+    pub order: OrderId,
+}
+
+impl_db_record!(
+    key = OrderLedgerKey,
+    value = OrderBalanceRecord,
+    db_prefix = DbKeyPrefix::OrderLedger,
+);
+
+impl_db_lookup!(
+    key = OrderLedgerKey,
+    query_prefix = OrderLedgerPrefixAll,
+    query_prefix = OrderLedgerPrefix1
+);
+
+// AbandonedOperation
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct AbandonedOperationKey(pub OperationId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct AbandonedOperationPrefixAll;
+
+impl_db_record!(
+    key = AbandonedOperationKey,
+    value = UnixTimestamp,
+    db_prefix = DbKeyPrefix::AbandonedOperation,
+);
+
+impl_db_lookup!(
+    key = AbandonedOperationKey,
+    query_prefix = AbandonedOperationPrefixAll
+);
+
+// MarketDisplayOverride
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct MarketDisplayOverrideKey(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MarketDisplayOverridePrefixAll;
+
+impl_db_record!(
+    key = MarketDisplayOverrideKey,
+    value = MarketDisplayOverride,
+    db_prefix = DbKeyPrefix::MarketDisplayOverride,
+);
+
+impl_db_lookup!(
+    key = MarketDisplayOverrideKey,
+    query_prefix = MarketDisplayOverridePrefixAll
+);
+
+// MarketTemplate
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct MarketTemplateKey {
+    pub name: String,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MarketTemplatePrefixAll;
+
+impl_db_record!(
+    key = MarketTemplateKey,
+    value = MarketTemplate,
+    db_prefix = DbKeyPrefix::MarketTemplate,
+);
+
+impl_db_lookup!(
+    key = MarketTemplateKey,
+    query_prefix = MarketTemplatePrefixAll
+);
+
+// PendingSettlement
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct PendingSettlementKey(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PendingSettlementPrefixAll;
+
+impl_db_record!(
+    key = PendingSettlementKey,
+    value = UnixTimestamp,
+    db_prefix = DbKeyPrefix::PendingSettlement,
+);
+
+impl_db_lookup!(
+    key = PendingSettlementKey,
+    query_prefix = PendingSettlementPrefixAll
+);
+
 /// OrderPriceTimePriority
 #[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
 pub struct OrderPriceTimePriorityKey {
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use fedimint_core::Amount;
+use fedimint_prediction_markets_common::{
+    Outcome, Seconds, UnixTimestamp, WeightRequiredForPayout,
+};
+use thiserror::Error;
+
+/// Errors detected by the client before anything is submitted to the
+/// federation.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum PredictionMarketsClientError {
+    // candlesticks
+    #[error("Federation does not serve candlesticks")]
+    CandlesticksUnsupported,
+    #[error("Candlestick interval {requested} is not one of the supported {supported:?}")]
+    UnsupportedInterval {
+        requested: Seconds,
+        supported: Vec<Seconds>,
+    },
+    #[error("Candlestick at {timestamp:?} from the federation is corrupt: {violation}")]
+    CorruptCandlestick {
+        timestamp: UnixTimestamp,
+        violation: CandlestickViolation,
+    },
+
+    // client
+    #[error("Trading is disabled on this client")]
+    TradingDisabled,
+    #[error("Background sync interval must not be zero")]
+    ZeroSyncInterval,
+
+    // federation
+    #[error("Federation did not respond within {timeout:?} in {attempts} attempts")]
+    FederationTimeout { timeout: Duration, attempts: u32 },
+    #[error("Operation does not exist")]
+    OperationDoesNotExist,
+    #[error("Operation was abandoned, the federation may still accept its transaction")]
+    OperationAbandoned,
+
+    // fees
+    #[error("Fee of {fee} exceeds the limit of {limit}")]
+    FeeExceedsLimit { fee: Amount, limit: Amount },
+
+    // markets
+    #[error("Market does not exist")]
+    MarketDoesNotExist,
+    #[error("Event has {count} outcomes but the federation allows at most {max}")]
+    TooManyOutcomes { count: Outcome, max: Outcome },
+    #[error("Market has a payout and is closed for trading")]
+    MarketClosedForTrading,
+    #[error("Outcome {outcome} is out of range, the market has {outcomes} outcomes")]
+    OutcomeOutOfRange { outcome: Outcome, outcomes: Outcome },
+    #[error("Market has {outcomes} outcomes, binary markets have two")]
+    NotABinaryMarket { outcomes: Outcome },
+    #[error("No outcome is titled {0:?}")]
+    UnknownOutcomeTitle(String),
+    #[error("More than one outcome is titled {0:?}")]
+    AmbiguousOutcomeTitle(String),
+    #[error("Got {hints} outcome display hints for {outcomes} outcomes")]
+    OutcomeDisplayHintCountMismatch { hints: usize, outcomes: Outcome },
+    #[error("Market already has matched contracts")]
+    MarketAlreadyTraded,
+    #[error("No market template is named {0:?}")]
+    UnknownMarketTemplate(String),
+    #[error("Market template placeholder {{{0}}} has no substitution")]
+    MissingTemplateSubstitution(String),
+
+    // orders
+    #[error("Order does not exist")]
+    OrderDoesNotExist,
+    #[error("Order id is not reserved")]
+    OrderIdNotReserved,
+    #[error("Can not reserve {count} order ids at once, the limit is {max}")]
+    TooManyOrderIdsReserved { count: usize, max: usize },
+    #[error("Order needs {needed} but the primary module only has {available}")]
+    InsufficientFunds { needed: Amount, available: Amount },
+    #[error("Price times quantity of the order does not fit in an amount")]
+    AmountOverflow,
+    #[error("Buy orders would commit {would_be}, above the exposure limit of {limit}")]
+    ExposureLimitExceeded { would_be: Amount, limit: Amount },
+    #[error("Order rate limit reached, retry after {retry_after:?}")]
+    RateLimited { retry_after: Duration },
+    #[error("Order book can not fill the order within the slippage limit")]
+    NoLiquidity,
+    #[error("Post-only order would match the order book immediately")]
+    WouldCrossBook,
+    #[error("Sell order sources had less balance than the local cache. Sync orders and try again")]
+    StaleOrderSources,
+    #[error("Shared quote could not be decoded")]
+    InvalidSharedQuote,
+    #[error("Order cache export could not be decoded")]
+    InvalidOrderCacheExport,
+    #[error("Order cache export was created by a client with a different root secret")]
+    OrderCacheFromOtherClient,
+    #[error("Client already has orders, import with force to keep them and add the rest")]
+    OrderCacheNotEmpty,
+    #[error("Order rejected by pre-order hook: {0}")]
+    RejectedByHook(String),
+    #[error(
+        "Probability must be between 0 and 1 and give a price between zero and the contract price"
+    )]
+    InvalidProbability,
+
+    // payouts
+    #[error("A payout already exists for market")]
+    PayoutAlreadyExists,
+    #[error("Event payout attestation could not be interpreted")]
+    InvalidEventPayoutAttestation,
+    #[error("Event payout attestation is not signed by a payout control of the market")]
+    UnknownPayoutControl,
+    #[error("Multiple event payout attestations from the same payout control")]
+    DuplicatePayoutControl,
+    #[error("Event payout attestations do not agree on the same payout")]
+    EventPayoutMismatch,
+    #[error("Event payout has {actual} outcomes but the event has {expected}")]
+    EventPayoutOutcomeCountMismatch { expected: u64, actual: u64 },
+    #[error("Event payout units sum to {actual} but the event pays out {expected}")]
+    EventPayoutUnitsSumMismatch { expected: u64, actual: u64 },
+    #[error("Event payout does not pass validation")]
+    EventPayoutValidationFailed,
+    #[error("Event payout attestations have weight {attested} but {required} is required")]
+    InsufficientPayoutWeight {
+        attested: WeightRequiredForPayout,
+        required: WeightRequiredForPayout,
+    },
+}
+
+/// Price invariant broken by a candlestick, see
+/// [crate::check_candlestick].
+#[derive(Debug, Clone, Copy, Error, PartialEq, Eq)]
+pub enum CandlestickViolation {
+    #[error("high is below low")]
+    HighBelowLow,
+    #[error("open is outside of low and high")]
+    OpenOutOfRange,
+    #[error("close is outside of low and high")]
+    CloseOutOfRange,
+}
@@ -0,0 +1,60 @@
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::time::{sleep, timeout};
+
+use crate::error::PredictionMarketsClientError;
+
+/// Bounds how long the client waits on the federation.
+///
+/// Each attempt of a request is given `timeout`. Attempts that time out are
+/// retried up to `retries` times with exponential backoff, after which the
+/// request fails with [PredictionMarketsClientError::FederationTimeout].
+/// Errors returned by the federation are not retried.
+///
+/// Requests that wait for something to change on the federation, such as
+/// waiting for order matches, are not bounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FederationCallPolicy {
+    pub timeout: Duration,
+    pub retries: u8,
+}
+
+impl Default for FederationCallPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5 * 60),
+            retries: 2,
+        }
+    }
+}
+
+impl FederationCallPolicy {
+    const MAX_FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+    /// Runs `request`, creating a new request future for each attempt.
+    pub async fn call<T, E, F, Fut>(&self, mut request: F) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Into<anyhow::Error>,
+    {
+        let mut backoff = self.timeout.min(Self::MAX_FIRST_BACKOFF);
+        for attempt in 0..=self.retries {
+            if attempt != 0 {
+                sleep(backoff).await;
+                backoff = backoff.saturating_mul(2);
+            }
+
+            if let Ok(res) = timeout(self.timeout, request()).await {
+                return res.map_err(Into::into);
+            }
+        }
+
+        Err(PredictionMarketsClientError::FederationTimeout {
+            timeout: self.timeout,
+            attempts: u32::from(self.retries) + 1,
+        }
+        .into())
+    }
+}
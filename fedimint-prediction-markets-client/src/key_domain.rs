@@ -0,0 +1,38 @@
+use fedimint_client::derivable_secret::{ChildId, DerivableSecret};
+use secp256k1::{KeyPair, Secp256k1};
+
+/// Purpose a key is derived from the module root secret for. Each domain
+/// derives below its own [ChildId] of the root secret, so keys of different
+/// purposes never collide.
+///
+/// The child ids decide which keys existing orders are recovered with, so a
+/// domain's child id must never change and a child id must never be given to
+/// a second domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum KeyDomain {
+    /// Owners of orders, indexed by [crate::OrderId]
+    Order,
+    /// Reserved for payout control keys of markets created by the client
+    PayoutControl,
+    /// Reserved for the nostr identity of the client
+    NostrIdentity,
+}
+
+impl KeyDomain {
+    pub const fn child_id(self) -> ChildId {
+        match self {
+            Self::Order => ChildId(0),
+            Self::PayoutControl => ChildId(1),
+            Self::NostrIdentity => ChildId(2),
+        }
+    }
+}
+
+/// Key at `index` of `domain`, derived at root secret / domain / index. All
+/// keys derived from the module root secret go through here.
+pub fn derive_key(root_secret: &DerivableSecret, domain: KeyDomain, index: u64) -> KeyPair {
+    root_secret
+        .child_key(domain.child_id())
+        .child_key(ChildId(index))
+        .to_secp_key(&Secp256k1::new())
+}
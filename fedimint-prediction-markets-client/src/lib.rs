@@ -1,15 +1,22 @@
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::btree_map::OccupiedEntry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::iter;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::ops::Bound::{Excluded, Unbounded};
 use std::str::FromStr;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::{anyhow, bail};
 use async_stream::stream;
+use bech32::{Bech32m, Hrp};
 use db::OrderIdSlot;
+use federation_call_policy::FederationCallPolicy;
 use fedimint_api_client::api::DynModuleApi;
-use fedimint_client::derivable_secret::{ChildId, DerivableSecret};
+use fedimint_client::db::ClientMigrationFn;
+use fedimint_client::derivable_secret::DerivableSecret;
 use fedimint_client::module::init::{ClientModuleInit, ClientModuleInitArgs};
 use fedimint_client::module::recovery::NoModuleBackup;
 use fedimint_client::module::{ClientContext, ClientModule, IClientModule};
@@ -20,40 +27,52 @@ use fedimint_core::db::{
     Database, DatabaseTransaction, DatabaseVersion, IDatabaseTransactionOpsCoreTyped,
 };
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::module::{
     ApiVersion, CommonModuleInit, ModuleCommon, ModuleInit, MultiApiVersion,
 };
-use fedimint_core::task::{sleep_until, spawn};
+use fedimint_core::task::{sleep, sleep_until, spawn};
 use fedimint_core::util::BoxStream;
 use fedimint_core::{apply, async_trait_maybe_send, Amount, OutPoint, TransactionId};
 use fedimint_prediction_markets_common::api::{
-    GetEventPayoutAttestationsUsedToPermitPayoutParams, GetMarketDynamicParams,
-    GetMarketOutcomeCandlesticksParams, GetMarketOutcomeCandlesticksResult,
-    GetMarketOutcomeOrderBookParams, GetMarketParams, GetOrderParams,
+    GetConsensusTimestampResult, GetEventPayoutAttestationsUsedToPermitPayoutParams,
+    GetMarketDynamicParams, GetMarketOrdersParams, GetMarketOutcomeCandlesticksParams,
+    GetMarketOutcomeCandlesticksResult, GetMarketOutcomeOrderBookParams, GetMarketParams,
+    GetMarketsByEventHashParams, GetMarketsByPayoutControlParams, GetMarketsParams, GetOrderParams,
     WaitMarketOutcomeCandlesticksParams, WaitMarketOutcomeCandlesticksResult, WaitOrderMatchParams,
-    WaitOrderMatchResult,
+    WaitOrderMatchResult, GET_MARKETS_BY_PAYOUT_CONTROL_MAX_MARKETS, GET_MARKETS_MAX_MARKETS,
+    GET_MARKET_ORDERS_MAX_LIMIT,
 };
 use fedimint_prediction_markets_common::config::{GeneralConsensus, PredictionMarketsClientConfig};
 use fedimint_prediction_markets_common::{
-    Candlestick, ContractOfOutcomeAmount, Market, NostrPublicKeyHex, Order, Outcome,
+    Candlestick, ContractAmount, ContractOfOutcomeAmount, Market, MarketDynamic, NostrPublicKeyHex,
+    Order, Outcome, OutcomeDisplayHint, Payout, PredictionMarketEventHashHex,
     PredictionMarketEventJson, PredictionMarketsCommonInit, PredictionMarketsInput,
-    PredictionMarketsModuleTypes, PredictionMarketsOutput, Seconds, Side, UnixTimestamp, Weight,
-    WeightRequiredForPayout,
+    PredictionMarketsInputError, PredictionMarketsModuleTypes, PredictionMarketsOutput, Seconds,
+    Side, SignedAmount, UnixTimestamp, Weight, WeightRequiredForPayout,
 };
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
-use order_filter::{OrderFilter, OrderPath, OrderState};
-use secp256k1::{KeyPair, PublicKey, Scalar, Secp256k1};
+use key_domain::{derive_key, KeyDomain};
+use order_filter::{OrderFilter, OrderPath, OrderQuery, OrderSort, OrderState};
+use order_rate_limiter::OrderRateLimiter;
+use prediction_market_event::information::Information;
+use prediction_market_event::nostr_event_types::{EventPayoutAttestation, NostrEventUtils};
+use prediction_market_event::{Event, EventPayout};
+use secp256k1::{KeyPair, PublicKey, Scalar};
 use serde::{Deserialize, Serialize};
+use snapshot::ClientSnapshot;
 use states::{
     CancelOrderState, ConsumeOrderBitcoinBalanceState, NewMarketState, NewOrderState,
     PayoutMarketState, PredictionMarketState, PredictionMarketsStateMachine,
 };
 use tokio::select;
 use tokio::sync::broadcast;
-use tokio::time::Instant;
+use tokio::time::{timeout, Instant};
+use tracing::warn;
 
 use crate::api::PredictionMarketsFederationApi;
+use crate::error::{CandlestickViolation, PredictionMarketsClientError};
 
 mod api;
 #[cfg(feature = "cli")]
@@ -62,7 +81,15 @@ mod db;
 mod rpc;
 mod states;
 
+#[cfg(feature = "cli")]
+pub mod cli_render;
+pub mod error;
+pub mod federation_call_policy;
+pub mod key_domain;
 pub mod order_filter;
+pub mod order_rate_limiter;
+pub mod payout_attestations;
+pub mod snapshot;
 pub mod stop_signal;
 
 #[derive(Debug)]
@@ -78,6 +105,24 @@ pub struct PredictionMarketsClientModule {
 
     watch_matches_id_incrementor: AtomicU64,
     watch_matches_stop_map: Mutex<HashMap<u64, Vec<stop_signal::Sender>>>,
+    background_sync_stop: Mutex<Option<stop_signal::Sender>>,
+    dead_mans_switch_heartbeat_stop: Mutex<Option<stop_signal::Sender>>,
+
+    market_events_cache: Mutex<HashMap<OutPoint, Arc<Event>>>,
+    outcome_titles_cache: Mutex<HashMap<OutPoint, Option<Arc<Vec<String>>>>>,
+    /// Last consensus timestamp returned and when it was fetched
+    consensus_timestamp_cache: Mutex<Option<(Instant, UnixTimestamp)>>,
+
+    order_rate_limiter: Option<Mutex<OrderRateLimiter>>,
+    max_total_exposure: Option<Amount>,
+    trading_enabled: AtomicBool,
+    pre_order_hook: Mutex<PreOrderHookSlot>,
+
+    federation_call_policy: FederationCallPolicy,
+    candlestick_validation: CandlestickValidation,
+
+    /// Only referenced weakly by streams that should end with the module
+    module_alive: Arc<()>,
 }
 
 /// Data needed by the state machine
@@ -90,12 +135,24 @@ pub struct PredictionMarketsClientContext {
 
 impl Context for PredictionMarketsClientContext {}
 
-#[derive(Debug, Clone)]
-pub struct PredictionMarketsClientInit;
+#[derive(Debug, Clone, Default)]
+pub struct PredictionMarketsClientInit {
+    /// Limits how many orders can be created or cancelled per minute. No
+    /// limit when `None`.
+    pub max_orders_per_minute: Option<NonZeroU32>,
+    /// Limits the bitcoin committed to the quantity waiting for match of buy
+    /// orders, counting the new order. No limit when `None`.
+    pub max_total_exposure: Option<Amount>,
+    /// Timeout and retries of requests to the federation.
+    pub federation_call_policy: FederationCallPolicy,
+    /// What [PredictionMarketsClientModule::get_candlesticks] does with
+    /// candlesticks whose prices are inconsistent.
+    pub candlestick_validation: CandlestickValidation,
+}
 
 impl ModuleInit for PredictionMarketsClientInit {
     type Common = PredictionMarketsCommonInit;
-    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(4);
 
     async fn dump_database(
         &self,
@@ -114,6 +171,24 @@ impl ClientModuleInit for PredictionMarketsClientInit {
         MultiApiVersion::try_from_iter([ApiVersion::new(0, 0)]).expect("no version conflicts")
     }
 
+    /// DB migrations to move from old to newer versions
+    fn get_database_migrations(&self) -> BTreeMap<DatabaseVersion, ClientMigrationFn> {
+        let mut migrations: BTreeMap<DatabaseVersion, ClientMigrationFn> = BTreeMap::new();
+        migrations.insert(DatabaseVersion(0), |dbtx, _, _| {
+            Box::pin(db::migrate_to_v1(dbtx))
+        });
+        migrations.insert(DatabaseVersion(1), |dbtx, _, _| {
+            Box::pin(db::migrate_to_v2(dbtx))
+        });
+        migrations.insert(DatabaseVersion(2), |_, active_states, inactive_states| {
+            Box::pin(async move { db::migrate_to_v3(active_states, inactive_states) })
+        });
+        migrations.insert(DatabaseVersion(3), |dbtx, _, _| {
+            Box::pin(db::migrate_to_v4(dbtx))
+        });
+        migrations
+    }
+
     async fn init(&self, args: &ClientModuleInitArgs<Self>) -> anyhow::Result<Self::Module> {
         Ok(PredictionMarketsClientModule {
             cfg: args.cfg().to_owned(),
@@ -127,6 +202,24 @@ impl ClientModuleInit for PredictionMarketsClientInit {
 
             watch_matches_id_incrementor: AtomicU64::new(0),
             watch_matches_stop_map: Mutex::new(HashMap::new()),
+            background_sync_stop: Mutex::new(None),
+            dead_mans_switch_heartbeat_stop: Mutex::new(None),
+
+            market_events_cache: Mutex::new(HashMap::new()),
+            outcome_titles_cache: Mutex::new(HashMap::new()),
+            consensus_timestamp_cache: Mutex::new(None),
+
+            order_rate_limiter: self
+                .max_orders_per_minute
+                .map(|max| Mutex::new(OrderRateLimiter::new(max, Instant::now()))),
+            max_total_exposure: self.max_total_exposure,
+            trading_enabled: AtomicBool::new(true),
+            pre_order_hook: Mutex::new(PreOrderHookSlot(None)),
+
+            federation_call_policy: self.federation_call_policy,
+            candlestick_validation: self.candlestick_validation,
+
+            module_alive: Arc::new(()),
         })
     }
 }
@@ -147,6 +240,11 @@ impl ClientModule for PredictionMarketsClientModule {
         }
     }
 
+    async fn start(&self) {
+        self.start_dead_mans_switch().await;
+        self.spawn_reconcile_settlements();
+    }
+
     fn input_fee(&self, input: &<Self::Common as ModuleCommon>::Input) -> Option<Amount> {
         Some(match input {
             PredictionMarketsInput::CancelOrder { .. } => Amount::ZERO,
@@ -159,7 +257,10 @@ impl ClientModule for PredictionMarketsClientModule {
 
     fn output_fee(&self, output: &<Self::Common as ModuleCommon>::Output) -> Option<Amount> {
         Some(match output {
-            PredictionMarketsOutput::NewMarket { .. } => self.cfg.gc.new_market_fee,
+            PredictionMarketsOutput::NewMarket { .. }
+            | PredictionMarketsOutput::NewMarketWithDisplayHints { .. } => {
+                self.cfg.gc.new_market_fee
+            }
             PredictionMarketsOutput::NewBuyOrder { .. } => self.cfg.gc.new_order_fee,
             PredictionMarketsOutput::PayoutMarket { .. } => Amount::ZERO,
         })
@@ -192,56 +293,197 @@ impl PredictionMarketsClientModule {
         self.cfg.gc.to_owned()
     }
 
+    /// Whether the federation keeps candlesticks, which it does not when
+    /// configured without candlestick intervals. When it does not,
+    /// candlestick methods fail with
+    /// [PredictionMarketsClientError::CandlesticksUnsupported].
+    pub fn candlesticks_supported(&self) -> bool {
+        !self.cfg.gc.candlestick_intervals.is_empty()
+    }
+
+    /// Candlestick intervals the federation keeps candlesticks for, smallest
+    /// first. Candlestick methods fail with
+    /// [PredictionMarketsClientError::UnsupportedInterval] for other
+    /// intervals.
+    pub fn supported_candlestick_intervals(&self) -> Vec<Seconds> {
+        let mut intervals = self.cfg.gc.candlestick_intervals.clone();
+        intervals.sort_unstable();
+
+        intervals
+    }
+
+    /// Public key that owns the order with the given id on the federation.
+    /// Can be used to correlate federation orders with client order ids
+    /// without placing an order.
+    pub fn order_owner_pubkey(&self, id: OrderId) -> PublicKey {
+        id.owner_public_key(self.root_secret.clone())
+    }
+
+    /// Fails without submitting anything with
+    /// [PredictionMarketsClientError::FeeExceedsLimit] if the fee is above
+    /// `max_fee`, or with [PredictionMarketsClientError::TooManyOutcomes] if
+    /// the event has more outcomes than the federation allows.
     pub async fn new_market(
         &self,
         event_json: PredictionMarketEventJson,
         contract_price: Amount,
         payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
         weight_required_for_payout: WeightRequiredForPayout,
+        max_fee: Option<Amount>,
     ) -> anyhow::Result<OutPoint> {
         let operation_id = OperationId::new_random();
 
-        let output = ClientOutput {
-            output: PredictionMarketsOutput::NewMarket {
+        let market = self
+            .submit_new_market(
+                operation_id,
                 event_json,
                 contract_price,
                 payout_control_weight_map,
                 weight_required_for_payout,
-            },
-            amount: Amount::ZERO,
-            state_machines: Arc::new(move |tx_id, _| {
-                vec![PredictionMarketsStateMachine {
-                    operation_id,
-                    state: NewMarketState::Pending { tx_id }.into(),
-                }]
-            }),
-        };
+                Vec::new(),
+                max_fee,
+            )
+            .await?;
+        self.await_new_market(operation_id, market).await?;
 
-        let tx = TransactionBuilder::new().with_output(self.ctx.make_client_output(output));
-        let out_point = |txid, _| OutPoint { txid, out_idx: 0 };
-        let (tx_id, _) = self
-            .ctx
-            .finalize_and_submit_transaction(
+        Ok(market)
+    }
+
+    /// Like [Self::new_market], but with a suggested display for each
+    /// outcome. The hints are stored with the market, so every client can
+    /// render it the same way. `outcome_display_hints` must be empty or have
+    /// one hint per outcome.
+    pub async fn new_market_with_display_hints(
+        &self,
+        event_json: PredictionMarketEventJson,
+        contract_price: Amount,
+        payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
+        weight_required_for_payout: WeightRequiredForPayout,
+        outcome_display_hints: Vec<OutcomeDisplayHint>,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<OutPoint> {
+        let operation_id = OperationId::new_random();
+
+        let market = self
+            .submit_new_market(
                 operation_id,
-                PredictionMarketsCommonInit::KIND.as_str(),
-                out_point,
-                tx,
+                event_json,
+                contract_price,
+                payout_control_weight_map,
+                weight_required_for_payout,
+                outcome_display_hints,
+                max_fee,
             )
             .await?;
+        self.await_new_market(operation_id, market).await?;
 
-        self.await_accepted(operation_id, tx_id).await?;
-        self.await_state(operation_id, |s| {
-            matches!(
-                s,
-                PredictionMarketState::NewMarket(NewMarketState::Complete)
+        Ok(market)
+    }
+
+    /// Like [Self::new_market], but also returns details of the market's
+    /// event so callers don't need a follow up [Self::get_market].
+    pub async fn new_market_detailed(
+        &self,
+        event_json: PredictionMarketEventJson,
+        contract_price: Amount,
+        payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
+        weight_required_for_payout: WeightRequiredForPayout,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<NewMarketResult> {
+        // parsed the same way the federation parses it in MarketStatic::event
+        let event = Event::try_from_json_str(&event_json)?;
+        let event_hash_hex = event.hash_hex()?.0;
+
+        let operation_id = OperationId::new_random();
+        let out_point = self
+            .submit_new_market(
+                operation_id,
+                event_json,
+                contract_price,
+                payout_control_weight_map,
+                weight_required_for_payout,
+                Vec::new(),
+                max_fee,
             )
+            .await?;
+        self.await_new_market(operation_id, out_point).await?;
+
+        Ok(NewMarketResult {
+            out_point,
+            operation_id,
+            event_hash_hex,
+            outcome_count: event.outcome_count,
+            outcome_titles: outcome_titles(&event.information).unwrap_or_default(),
+            information: event.information,
         })
-        .await;
+    }
 
-        Ok(OutPoint {
-            txid: tx_id,
-            out_idx: 0,
-        })
+    /// Like [Self::new_market], but only ever creates one market per
+    /// `idempotency_key`. Calling again with a key that was already used
+    /// returns the market created by the first call instead of creating
+    /// another one.
+    ///
+    /// The operation id is derived from `idempotency_key`, so keys should be
+    /// generated randomly by the caller. The key is recorded before the market
+    /// is submitted, so a call interrupted after submitting is picked up by
+    /// the next call with the same key instead of failing on the existing
+    /// operation.
+    pub async fn new_market_idempotent(
+        &self,
+        idempotency_key: [u8; 32],
+        event_json: PredictionMarketEventJson,
+        contract_price: Amount,
+        payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
+        weight_required_for_payout: WeightRequiredForPayout,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<OutPoint> {
+        let operation_id = OperationId(idempotency_key);
+        let key = db::NewMarketIdempotencyKey {
+            key: idempotency_key,
+        };
+
+        let mut dbtx = self.db.begin_transaction().await;
+        let existing = dbtx.get_value(&key).await;
+        if existing.is_none() {
+            dbtx.insert_new_entry(&key, &None).await;
+        }
+        dbtx.commit_tx_result().await?;
+
+        let submitted = match existing {
+            Some(Some(market)) => Some(market),
+            // recorded by an earlier call, which got as far as submitting if
+            // its operation exists
+            Some(None) => self
+                .ctx
+                .get_operation(operation_id)
+                .await
+                .ok()
+                .map(|operation| operation.meta::<OutPoint>()),
+            None => None,
+        };
+        let market = match submitted {
+            Some(market) => market,
+            None => {
+                self.submit_new_market(
+                    operation_id,
+                    event_json,
+                    contract_price,
+                    payout_control_weight_map,
+                    weight_required_for_payout,
+                    Vec::new(),
+                    max_fee,
+                )
+                .await?
+            }
+        };
+        if !matches!(existing, Some(Some(_))) {
+            let mut dbtx = self.db.begin_transaction().await;
+            dbtx.insert_entry(&key, &Some(market)).await;
+            dbtx.commit_tx_result().await?;
+        }
+        self.await_new_market(operation_id, market).await?;
+
+        Ok(market)
     }
 
     pub async fn get_market(
@@ -264,9 +506,11 @@ impl PredictionMarketsClientModule {
 
                     // if we have market but not finished, update market dynamic
                     let result = self
-                        .module_api
-                        .get_market_dynamic(GetMarketDynamicParams {
-                            market: market_out_point,
+                        .federation_call_policy
+                        .call(|| {
+                            self.module_api.get_market_dynamic(GetMarketDynamicParams {
+                                market: market_out_point,
+                            })
                         })
                         .await?;
                     let Some(market_dynamic) = result.market_dynamic else {
@@ -281,9 +525,11 @@ impl PredictionMarketsClientModule {
                 }
 
                 let result = self
-                    .module_api
-                    .get_market(GetMarketParams {
-                        market: market_out_point,
+                    .federation_call_policy
+                    .call(|| {
+                        self.module_api.get_market(GetMarketParams {
+                            market: market_out_point,
+                        })
                     })
                     .await?;
                 if let Some(market) = result.market.as_ref() {
@@ -297,11 +543,234 @@ impl PredictionMarketsClientModule {
         }
     }
 
+    /// Payout per contract of each outcome, or `None` while the market has not
+    /// paid out. A market that has paid out is served from the local cache
+    /// without contacting the federation.
+    pub async fn get_market_payout(&self, market: OutPoint) -> anyhow::Result<Option<Vec<Amount>>> {
+        let Some(Market(_, market_dynamic)) = self.get_market(market, false).await? else {
+            bail!(PredictionMarketsClientError::MarketDoesNotExist)
+        };
+
+        Ok(market_dynamic
+            .payout
+            .map(|payout| payout.amount_per_outcome))
+    }
+
+    /// Bulk version of [Self::get_market].
+    ///
+    /// Cached markets are read in one database transaction. When not reading
+    /// from local cache, markets that already have a payout are served from
+    /// cache and the rest are fetched from the federation in batches.
+    pub async fn get_markets(
+        &self,
+        markets: Vec<OutPoint>,
+        from_local_cache: bool,
+    ) -> anyhow::Result<BTreeMap<OutPoint, Option<Market>>> {
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        let mut result = BTreeMap::new();
+        for market in markets {
+            let cached = dbtx.get_value(&db::MarketKey(market)).await;
+            result.insert(market, cached);
+        }
+        drop(dbtx);
+
+        if from_local_cache {
+            return Ok(result);
+        }
+
+        let fetched = markets_to_fetch(&result)
+            .chunks(GET_MARKETS_MAX_MARKETS)
+            .map(|chunk| {
+                self.federation_call_policy.call(|| {
+                    self.module_api.get_markets(GetMarketsParams {
+                        markets: chunk.to_vec(),
+                    })
+                })
+            })
+            .collect::<FuturesUnordered<_>>()
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut dbtx = self.db.begin_transaction().await;
+        for res in fetched {
+            for (market_out_point, market) in res?.markets {
+                dbtx.insert_entry(&db::MarketKey(market_out_point), &market)
+                    .await;
+                result.insert(market_out_point, Some(market));
+            }
+        }
+        dbtx.commit_tx_result().await?;
+
+        Ok(result)
+    }
+
+    /// Gets the markets created after `created_after` where `payout_control`
+    /// has nonzero weight, oldest first.
+    ///
+    /// The federation indexes every market by payout control, so unlike
+    /// [Self::get_saved_markets] this includes markets created by others that
+    /// this client never interacted with. Results are cached, and with
+    /// `from_local_cache` only markets returned by earlier calls are known.
+    pub async fn get_markets_where_payout_control(
+        &self,
+        payout_control: NostrPublicKeyHex,
+        created_after: UnixTimestamp,
+        from_local_cache: bool,
+    ) -> anyhow::Result<Vec<OutPoint>> {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        let markets = match from_local_cache {
+            true => dbtx
+                .find_by_prefix(&db::PayoutControlMarketsPrefix1 { payout_control })
+                .await
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .filter(|(key, _)| key.created_consensus_timestamp > created_after)
+                .map(|(key, _)| key.market)
+                .collect(),
+
+            false => {
+                let mut markets = Vec::new();
+                let mut page_created_after = created_after;
+                loop {
+                    let result = self
+                        .federation_call_policy
+                        .call(|| {
+                            self.module_api.get_markets_by_payout_control(
+                                GetMarketsByPayoutControlParams {
+                                    payout_control: payout_control.clone(),
+                                    created_after: page_created_after,
+                                },
+                            )
+                        })
+                        .await?;
+
+                    for (market, created_consensus_timestamp) in result.markets.iter() {
+                        dbtx.insert_entry(
+                            &db::PayoutControlMarketsKey {
+                                payout_control: payout_control.clone(),
+                                created_consensus_timestamp: *created_consensus_timestamp,
+                                market: *market,
+                            },
+                            &(),
+                        )
+                        .await;
+                    }
+
+                    let is_last_page =
+                        result.markets.len() < GET_MARKETS_BY_PAYOUT_CONTROL_MAX_MARKETS;
+                    let Some((_, last_created)) = result.markets.last().copied() else {
+                        break;
+                    };
+                    markets.extend(result.markets.into_iter().map(|(market, _)| market));
+                    if is_last_page {
+                        break;
+                    }
+                    page_created_after = last_created;
+                }
+
+                markets
+            }
+        };
+
+        dbtx.commit_tx_result().await?;
+
+        Ok(markets)
+    }
+
+    /// Finds the market created for the event with `event_hash_hex`. If
+    /// several markets were created for the same event, the earliest created
+    /// one is returned.
+    ///
+    /// Found markets are cached, so only misses are sent to the federation.
+    pub async fn find_market_by_event_hash(
+        &self,
+        event_hash_hex: PredictionMarketEventHashHex,
+    ) -> anyhow::Result<Option<OutPoint>> {
+        let key = db::EventHashMarketKey { event_hash_hex };
+
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        if let Some(market) = dbtx.get_value(&key).await {
+            return Ok(Some(market));
+        }
+        drop(dbtx);
+
+        let result = self
+            .federation_call_policy
+            .call(|| {
+                self.module_api
+                    .get_markets_by_event_hash(GetMarketsByEventHashParams {
+                        event_hash_hex: key.event_hash_hex.clone(),
+                    })
+            })
+            .await?;
+        let Some((market, _)) = result.markets.into_iter().next() else {
+            return Ok(None);
+        };
+
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.insert_entry(&key, &market).await;
+        dbtx.commit_tx_result().await?;
+
+        Ok(Some(market))
+    }
+
     pub async fn payout_market(
         &self,
         market: OutPoint,
         event_payout_attestations_json: Vec<PredictionMarketEventJson>,
     ) -> anyhow::Result<()> {
+        let Some(Market(market_static, market_dynamic)) = self.get_market(market, false).await?
+        else {
+            bail!(PredictionMarketsClientError::MarketDoesNotExist)
+        };
+        if market_dynamic.payout.is_some() {
+            bail!(PredictionMarketsClientError::PayoutAlreadyExists)
+        }
+
+        // mirror the validation done by the federation so mistakes are caught
+        // before submitting
+        let event = market_static.event()?;
+        let mut sum_weight: WeightRequiredForPayout = 0;
+        let mut used_payout_controls = HashSet::new();
+        let mut event_payout: Option<EventPayout> = None;
+        for event_json in &event_payout_attestations_json {
+            let Ok((payout_control, loop_event_payout)) =
+                EventPayoutAttestation::interpret_nostr_event_json(event_json)
+            else {
+                bail!(PredictionMarketsClientError::InvalidEventPayoutAttestation)
+            };
+            let Some(weight) = market_static
+                .payout_control_weight_map
+                .get(&payout_control.0)
+            else {
+                bail!(PredictionMarketsClientError::UnknownPayoutControl)
+            };
+            if !used_payout_controls.insert(payout_control.0.clone()) {
+                bail!(PredictionMarketsClientError::DuplicatePayoutControl)
+            }
+            sum_weight += WeightRequiredForPayout::from(*weight);
+
+            match event_payout.as_ref() {
+                None => {
+                    validate_event_payout(&event, &loop_event_payout)?;
+                    event_payout = Some(loop_event_payout);
+                }
+                Some(ep) => {
+                    if ep != &loop_event_payout {
+                        bail!(PredictionMarketsClientError::EventPayoutMismatch)
+                    }
+                }
+            }
+        }
+        if sum_weight < market_static.weight_required_for_payout {
+            bail!(PredictionMarketsClientError::InsufficientPayoutWeight {
+                attested: sum_weight,
+                required: market_static.weight_required_for_payout,
+            })
+        }
+
         let operation_id = OperationId::new_random();
 
         let output = ClientOutput {
@@ -319,6 +788,11 @@ impl PredictionMarketsClientModule {
         };
 
         let tx = TransactionBuilder::new().with_output(self.ctx.make_client_output(output));
+        self.log_submitted_transaction(
+            operation_id,
+            SubmittedTransactionSummary::PayoutMarket { market },
+        )
+        .await?;
         let out_point = |txid, _| OutPoint { txid, out_idx: 0 };
         let (tx_id, _) = self
             .ctx
@@ -346,16 +820,115 @@ impl PredictionMarketsClientModule {
         &self,
         market: OutPoint,
     ) -> anyhow::Result<Option<Vec<PredictionMarketEventJson>>> {
+        self.ensure_market_exists(market).await?;
+
         let result = self
-            .module_api
-            .get_event_payout_attestations_used_to_permit_payout(
-                GetEventPayoutAttestationsUsedToPermitPayoutParams { market },
-            )
+            .federation_call_policy
+            .call(|| {
+                self.module_api
+                    .get_event_payout_attestations_used_to_permit_payout(
+                        GetEventPayoutAttestationsUsedToPermitPayoutParams { market },
+                    )
+            })
             .await;
 
         Ok(result?.event_payout_attestations)
     }
 
+    /// Reports how much payout control weight currently backs `event_payout`
+    /// out of the given attestations, and whether the caller's own
+    /// attestation would be enough to reach the market's
+    /// `weight_required_for_payout`.
+    pub async fn get_payout_attestation_status(
+        &self,
+        market: OutPoint,
+        event_payout: EventPayout,
+        event_payout_attestations_json: Vec<PredictionMarketEventJson>,
+        own_payout_control: Option<NostrPublicKeyHex>,
+    ) -> anyhow::Result<PayoutAttestationStatus> {
+        let market = self.ensure_market_exists(market).await?;
+
+        let group = group_event_payout_attestations(&market, event_payout_attestations_json)
+            .remove(&event_payout)
+            .unwrap_or_default();
+
+        let weight_required_for_payout = market.0.weight_required_for_payout;
+        let own_weight = own_payout_control
+            .as_ref()
+            .and_then(|pk| market.0.payout_control_weight_map.get(pk))
+            .map_or(0, |w| WeightRequiredForPayout::from(*w));
+        let own_attestation_included = own_payout_control
+            .as_ref()
+            .is_some_and(|pk| group.payout_controls.contains(pk));
+        let weight_with_own = match own_attestation_included {
+            true => group.total_weight,
+            false => group.total_weight + own_weight,
+        };
+
+        Ok(PayoutAttestationStatus {
+            weight_attested: group.total_weight,
+            weight_required_for_payout,
+            weight_remaining: weight_required_for_payout.saturating_sub(group.total_weight),
+            own_weight,
+            own_attestation_included,
+            own_attestation_reaches_threshold: weight_with_own >= weight_required_for_payout,
+            event_payout_attestations_json: group.event_payout_attestations_json,
+        })
+    }
+
+    /// Amount the primary module pays to create an order, including the new
+    /// order fee. Sell orders are funded by contracts of outcome, so only the
+    /// fee is paid. Fails with [PredictionMarketsClientError::AmountOverflow]
+    /// if the cost does not fit in an [Amount].
+    pub fn new_order_cost(
+        &self,
+        side: Side,
+        price: Amount,
+        quantity: ContractOfOutcomeAmount,
+    ) -> Result<Amount, PredictionMarketsClientError> {
+        let contracts = match side {
+            Side::Buy => Order::checked_cost(price, quantity)
+                .ok_or(PredictionMarketsClientError::AmountOverflow)?,
+            Side::Sell => Amount::ZERO,
+        };
+
+        contracts
+            .msats
+            .checked_add(self.cfg.gc.new_order_fee.msats)
+            .map(Amount::from_msats)
+            .ok_or(PredictionMarketsClientError::AmountOverflow)
+    }
+
+    /// Fails with [PredictionMarketsClientError::InsufficientFunds] if
+    /// `primary_module_balance` can not pay [Self::new_order_cost], so that
+    /// an order can be rejected before anything is built.
+    ///
+    /// Modules can not read the primary module's balance, so the caller passes
+    /// the client's balance and [Self::new_order] does not check it. Without
+    /// the check, an unaffordable order fails when the transaction is
+    /// finalized.
+    pub fn check_new_order_affordable(
+        &self,
+        side: Side,
+        price: Amount,
+        quantity: ContractOfOutcomeAmount,
+        primary_module_balance: Amount,
+    ) -> anyhow::Result<()> {
+        let needed = self.new_order_cost(side, price, quantity)?;
+        if needed > primary_module_balance {
+            bail!(PredictionMarketsClientError::InsufficientFunds {
+                needed,
+                available: primary_module_balance,
+            })
+        }
+
+        Ok(())
+    }
+
+    /// Fails with [PredictionMarketsClientError::FeeExceedsLimit] without
+    /// submitting anything if the fee is above `max_fee`, and with
+    /// [PredictionMarketsClientError::MarketClosedForTrading] if the market is
+    /// cached with a payout.
     pub async fn new_order(
         &self,
         market: OutPoint,
@@ -363,178 +936,343 @@ impl PredictionMarketsClientModule {
         side: Side,
         price: Amount,
         quantity: ContractOfOutcomeAmount,
+        max_fee: Option<Amount>,
     ) -> anyhow::Result<OrderId> {
-        let operation_id = OperationId::new_random();
-        let db = self.db.clone();
-        let mut dbtx = db.begin_transaction().await;
-
-        let order_id = {
-            let mut stream = dbtx
-                .find_by_prefix_sorted_descending(&db::OrderPrefixAll)
-                .await;
-            match stream.next().await {
-                Some((mut key, _)) => {
-                    key.0 .0 += 1;
-                    key.0
-                }
-                None => OrderId(0),
-            }
-        };
+        self.new_order_inner(
+            OperationId::new_random(),
+            None,
+            market,
+            outcome,
+            side,
+            price,
+            quantity,
+            max_fee,
+            false,
+        )
+        .await
+    }
 
-        dbtx.insert_entry(&db::OrderKey(order_id), &OrderIdSlot::Reserved)
-            .await;
+    /// [Self::new_order] for a market with two outcomes, where `yes` picks
+    /// outcome [YES_OUTCOME] and otherwise [NO_OUTCOME]. Fails with
+    /// [PredictionMarketsClientError::NotABinaryMarket] for other markets.
+    pub async fn new_binary_order(
+        &self,
+        market: OutPoint,
+        yes: bool,
+        side: Side,
+        price: Amount,
+        quantity: ContractOfOutcomeAmount,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<OrderId> {
+        self.check_binary_market(market).await?;
 
-        let order_key = self.order_id_to_key_pair(order_id);
-        let owner = PublicKey::from_keypair(&order_key);
+        self.new_order(market, binary_outcome(yes), side, price, quantity, max_fee)
+            .await
+    }
 
-        let mut tx = TransactionBuilder::new();
-        let mut orders_to_sync_on_accepted = BTreeSet::new();
-        orders_to_sync_on_accepted.insert(order_id);
-        let mut orders_to_sync_on_rejected = BTreeSet::new();
-        match side {
-            Side::Buy => {
-                let output = ClientOutput {
-                    output: PredictionMarketsOutput::NewBuyOrder {
-                        owner,
-                        market,
-                        outcome,
-                        price,
-                        quantity,
-                    },
-                    amount: price * quantity.0,
-                    state_machines: Arc::new(move |tx_id, _| {
-                        vec![PredictionMarketsStateMachine {
-                            operation_id,
-                            state: NewOrderState::Pending {
-                                tx_id,
-                                order_id,
-                                orders_to_sync_on_accepted: orders_to_sync_on_accepted.clone(),
-                                orders_to_sync_on_rejected: orders_to_sync_on_rejected.clone(),
-                            }
-                            .into(),
-                        }]
-                    }),
-                };
+    /// [Self::new_order] priced by the implied probability of the outcome,
+    /// such as 0.4 to buy at 40%. The price is
+    /// `round(probability * contract_price)` in msats, see
+    /// [price_from_probability].
+    pub async fn new_order_by_probability(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        probability: f64,
+        quantity: ContractOfOutcomeAmount,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<OrderId> {
+        let Market(market_static, _) = self.ensure_market_exists(market).await?;
+        let price = price_from_probability(market_static.contract_price, probability)?;
 
-                tx = tx.with_output(self.ctx.make_client_output(output));
-            }
-            Side::Sell => {
-                let mut sources = BTreeMap::new();
-                let mut sources_keys_combined = None;
+        self.new_order(market, outcome, side, price, quantity, max_fee)
+            .await
+    }
 
-                let possible_source_orders = Self::get_order_ids(
-                    &mut dbtx.to_ref_nc(),
-                    OrderFilter(
-                        OrderPath::MarketOutcomeSide {
-                            market,
-                            outcome,
-                            side,
-                        },
-                        OrderState::NonZeroContractOfOutcomeBalance,
-                    ),
-                )
-                .await;
+    /// [Self::new_order] for the outcome whose title matches `outcome_title`,
+    /// see [outcome_by_title].
+    pub async fn new_order_by_outcome_title(
+        &self,
+        market: OutPoint,
+        outcome_title: &str,
+        side: Side,
+        price: Amount,
+        quantity: ContractOfOutcomeAmount,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<OrderId> {
+        let titles = self.get_outcome_titles(market).await?.unwrap_or_default();
+        let outcome = outcome_by_title(&titles, outcome_title)?;
 
-                let mut sourced_quantity = ContractOfOutcomeAmount::ZERO;
-                for (i, loop_order_id) in possible_source_orders.into_iter().enumerate() {
-                    if i == usize::from(self.cfg.gc.max_sell_order_sources) {
-                        bail!("max number of sell order sources reached. try again with a quantity less than or equal to {}", sourced_quantity.0)
-                    }
+        self.new_order(market, outcome, side, price, quantity, max_fee)
+            .await
+    }
 
-                    let mut loop_order = dbtx
-                        .get_value(&db::OrderKey(loop_order_id))
-                        .await
-                        .unwrap()
-                        .to_order()
-                        .unwrap();
+    /// Like [Self::new_order], but submitted under the caller's
+    /// `operation_id`, which must not have been used before. The id can be
+    /// passed to [Self::get_operation_status] to follow the order, including
+    /// after a restart or when this call fails.
+    pub async fn new_order_with_operation_id(
+        &self,
+        operation_id: OperationId,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        price: Amount,
+        quantity: ContractOfOutcomeAmount,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<OrderId> {
+        self.new_order_inner(
+            operation_id,
+            None,
+            market,
+            outcome,
+            side,
+            price,
+            quantity,
+            max_fee,
+            false,
+        )
+        .await
+    }
 
-                    let loop_order_key = self.order_id_to_key_pair(loop_order_id);
-                    let loop_sourced_quantity_from_order = loop_order
-                        .contract_of_outcome_balance
-                        .min(quantity - sourced_quantity);
-                    loop_order.contract_of_outcome_balance -= loop_sourced_quantity_from_order;
-                    sourced_quantity += loop_sourced_quantity_from_order;
+    /// Sets a hook that every new order is passed to after the client's own
+    /// checks and before anything is submitted. Orders the hook returns an
+    /// error for fail with [PredictionMarketsClientError::RejectedByHook].
+    /// Replaces any previously set hook.
+    pub fn set_pre_order_hook(&self, hook: PreOrderHook) {
+        self.pre_order_hook.lock().unwrap().0 = Some(hook);
+    }
 
-                    sources.insert(
-                        loop_order_key.public_key(),
-                        loop_sourced_quantity_from_order,
-                    );
+    /// Removes the hook set by [Self::set_pre_order_hook].
+    pub fn clear_pre_order_hook(&self) {
+        self.pre_order_hook.lock().unwrap().0 = None;
+    }
 
-                    dbtx.insert_entry(
-                        &db::OrderKey(loop_order_id),
-                        &OrderIdSlot::Order(loop_order),
-                    )
+    /// Emergency stop for trading. While disabled, creating markets and
+    /// orders fails with [PredictionMarketsClientError::TradingDisabled].
+    /// Cancelling orders, withdrawing bitcoin balances and payouts still
+    /// work.
+    ///
+    /// Kept in memory only, trading is enabled again when the client
+    /// restarts.
+    pub fn set_trading_enabled(&self, enabled: bool) {
+        self.trading_enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    /// See [Self::set_trading_enabled].
+    pub fn trading_enabled(&self) -> bool {
+        self.trading_enabled.load(Ordering::SeqCst)
+    }
+
+    /// Reserves a contiguous block of `count` order ids for
+    /// [Self::new_order_with_id]. Reserved ids are never handed out by other
+    /// order creation methods until released with [Self::release_order_id].
+    ///
+    /// At most [MAX_RESERVED_ORDER_IDS] ids are reserved per call.
+    pub async fn reserve_order_ids(&self, count: usize) -> anyhow::Result<Vec<OrderId>> {
+        const MAX_ATTEMPTS: usize = 10;
+
+        if count > MAX_RESERVED_ORDER_IDS {
+            bail!(PredictionMarketsClientError::TooManyOrderIdsReserved {
+                count,
+                max: MAX_RESERVED_ORDER_IDS,
+            })
+        }
+
+        let mut last_error = None;
+        for _ in 0..MAX_ATTEMPTS {
+            let mut dbtx = self.db.begin_transaction().await;
+
+            let first = Self::next_order_id(&mut dbtx.to_ref_nc()).await;
+            let order_ids: Vec<_> = (0..count as u64).map(|i| OrderId(first.0 + i)).collect();
+            for order_id in order_ids.iter() {
+                dbtx.insert_entry(&db::OrderKey(*order_id), &OrderIdSlot::Reserved)
                     .await;
-                    orders_to_sync_on_accepted.insert(loop_order_id);
-                    orders_to_sync_on_rejected.insert(loop_order_id);
+                dbtx.insert_entry(&db::OrderIdReservationKey(*order_id), &())
+                    .await;
+            }
 
-                    sources_keys_combined = match sources_keys_combined {
-                        None => Some(loop_order_key),
-                        Some(combined_keys) => {
-                            let p1 = combined_keys.secret_key();
-                            let p2 = loop_order_key.secret_key();
-                            let p3 = p1.add_tweak(&Scalar::from(p2))?;
+            // concurrent reservations or new orders conflict on the same ids
+            match dbtx.commit_tx_result().await {
+                Ok(()) => return Ok(order_ids),
+                Err(e) => last_error = Some(e),
+            }
+        }
 
-                            Some(p3.keypair(secp256k1::SECP256K1))
-                        }
-                    };
+        Err(last_error.unwrap().into())
+    }
 
-                    if quantity == sourced_quantity {
-                        break;
-                    }
-                }
+    /// Releases an order id reserved by [Self::reserve_order_ids] that no
+    /// order was created with.
+    pub async fn release_order_id(&self, order_id: OrderId) -> anyhow::Result<()> {
+        let mut dbtx = self.db.begin_transaction().await;
 
-                if quantity != sourced_quantity {
-                    bail!("Insufficient outcome quantity for new sell order");
-                }
+        if dbtx
+            .remove_entry(&db::OrderIdReservationKey(order_id))
+            .await
+            .is_none()
+        {
+            bail!(PredictionMarketsClientError::OrderIdNotReserved)
+        }
+        dbtx.remove_entry(&db::OrderKey(order_id)).await;
 
-                let input = ClientInput {
-                    input: PredictionMarketsInput::NewSellOrder {
-                        owner,
-                        market,
-                        outcome,
-                        price,
-                        sources,
-                    },
-                    amount: Amount::ZERO,
-                    state_machines: Arc::new(move |tx_id, _| {
-                        vec![PredictionMarketsStateMachine {
-                            operation_id,
-                            state: NewOrderState::Pending {
-                                tx_id,
-                                order_id,
-                                orders_to_sync_on_accepted: orders_to_sync_on_accepted.clone(),
-                                orders_to_sync_on_rejected: orders_to_sync_on_rejected.clone(),
-                            }
-                            .into(),
-                        }]
-                    }),
-                    keys: vec![sources_keys_combined.unwrap()],
-                };
+        dbtx.commit_tx_result().await?;
 
-                tx = tx.with_input(self.ctx.make_client_input(input));
+        Ok(())
+    }
+
+    /// Like [Self::new_order], but creates the order with an id reserved by
+    /// [Self::reserve_order_ids]. If the order is not submitted, the id stays
+    /// reserved.
+    pub async fn new_order_with_id(
+        &self,
+        order_id: OrderId,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        price: Amount,
+        quantity: ContractOfOutcomeAmount,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<()> {
+        self.new_order_inner(
+            OperationId::new_random(),
+            Some(order_id),
+            market,
+            outcome,
+            side,
+            price,
+            quantity,
+            max_fee,
+            false,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Like [Self::new_order], but the price is given by `order_type`. See
+    /// [OrderType::Market] for how market orders are priced and
+    /// [OrderType::PostOnly] for orders that must not match immediately.
+    pub async fn new_order_with_type(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        order_type: OrderType,
+        quantity: ContractOfOutcomeAmount,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<OrderId> {
+        let (price, quantity) = match order_type {
+            OrderType::Limit { price } => (price, quantity),
+            OrderType::Market { max_slippage } => {
+                self.market_order_price_quantity(market, outcome, side, quantity, max_slippage)
+                    .await?
+            }
+            OrderType::PostOnly { price } => {
+                self.check_post_only(market, outcome, side, price).await?;
+                (price, quantity)
             }
+        };
+
+        self.new_order_inner(
+            OperationId::new_random(),
+            None,
+            market,
+            outcome,
+            side,
+            price,
+            quantity,
+            max_fee,
+            false,
+        )
+        .await
+    }
+
+    /// Like [Self::new_order], but for sell orders the orders that could be
+    /// used as sources are synced from the federation first, so sourcing is
+    /// computed against fresh contract of outcome balances instead of the
+    /// local cache.
+    pub async fn new_order_verify_sources(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        price: Amount,
+        quantity: ContractOfOutcomeAmount,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<OrderId> {
+        self.new_order_inner(
+            OperationId::new_random(),
+            None,
+            market,
+            outcome,
+            side,
+            price,
+            quantity,
+            max_fee,
+            true,
+        )
+        .await
+    }
+
+    /// Places several orders, possibly in different markets, in a single
+    /// transaction so they are either all accepted or all rejected. Every
+    /// order goes through the same client side checks as [Self::new_order],
+    /// and the exposure limit is checked against the buy orders combined.
+    ///
+    /// Returns the ids of the orders in the order of `requests`. If nothing
+    /// was submitted, the reserved ids are released again. Fails with
+    /// [PredictionMarketsClientError::FeeExceedsLimit] without submitting
+    /// anything if the fee of all orders combined is above `max_fee`.
+    pub async fn submit_multi_market(
+        &self,
+        requests: Vec<OrderRequest>,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<Vec<OrderId>> {
+        if requests.is_empty() {
+            return Ok(Vec::new());
         }
 
-        dbtx.commit_tx_result().await?;
+        self.check_trading_enabled()?;
+        let fee = u64::try_from(requests.len())
+            .ok()
+            .and_then(|count| self.cfg.gc.new_order_fee.msats.checked_mul(count))
+            .map(Amount::from_msats)
+            .ok_or(PredictionMarketsClientError::AmountOverflow)?;
+        Self::check_fee(fee, max_fee)?;
+        let mut buy_exposure = Amount::ZERO;
+        for request in requests.iter() {
+            // a payout is final, so the cached market is enough to know the
+            // federation would reject the order
+            if let Some(Market(_, market_dynamic)) = self.get_market(request.market, true).await? {
+                if market_dynamic.payout.is_some() {
+                    bail!(PredictionMarketsClientError::MarketClosedForTrading)
+                }
+            }
+            self.check_outcome(request.market, request.outcome).await?;
+            if request.side == Side::Buy {
+                let cost = Order::checked_cost(request.price, request.quantity)
+                    .ok_or(PredictionMarketsClientError::AmountOverflow)?;
+                buy_exposure = checked_add_amounts(buy_exposure, cost)?;
+            }
+            self.check_pre_order_hook(request)?;
+        }
+        self.check_exposure_limit(buy_exposure).await?;
 
-        let (tx_id, _) = self
-            .ctx
-            .finalize_and_submit_transaction(
-                operation_id,
-                PredictionMarketsCommonInit::KIND.as_str(),
-                |_, _| (),
-                tx,
-            )
-            .await?;
+        let order_ids = self.reserve_order_ids(requests.len()).await?;
 
-        self.await_accepted(operation_id, tx_id).await?;
-        self.await_state(operation_id, |s| {
-            matches!(s, PredictionMarketState::NewOrder(NewOrderState::Complete))
-        })
-        .await;
+        let res = self
+            .submit_new_orders(OperationId::new_random(), &order_ids, &requests)
+            .await;
+        if res.is_err() {
+            for order_id in order_ids.iter() {
+                // fails if the orders were submitted, the slots are then in use
+                let _ = self.release_order_id(*order_id).await;
+            }
+        }
 
-        Ok(order_id)
+        res.map(|()| order_ids)
     }
 
     pub async fn get_order(
@@ -555,8 +1293,11 @@ impl PredictionMarketsClientModule {
 
             false => {
                 let result = self
-                    .module_api
-                    .get_order(GetOrderParams { order: order_owner })
+                    .federation_call_policy
+                    .call(|| {
+                        self.module_api
+                            .get_order(GetOrderParams { order: order_owner })
+                    })
                     .await?;
 
                 if let Some(order) = result.order.as_ref() {
@@ -577,21 +1318,357 @@ impl PredictionMarketsClientModule {
         res
     }
 
-    pub async fn get_orders_from_db(&self, filter: OrderFilter) -> BTreeMap<OrderId, Order> {
-        Self::get_order_ids(&mut self.db.begin_transaction_nc().await, filter)
-            .await
-            .into_iter()
-            .map(|order_id| async move {
-                (
-                    order_id,
-                    self.get_order(order_id, true).await.unwrap().unwrap(),
-                )
+    /// Fetches the order from the federation and caches it, like
+    /// [Self::get_order] with `from_local_cache` unset. Also returns whether
+    /// the order differs from the previously cached version, which is the
+    /// case for orders that were not cached before.
+    pub async fn get_order_checked(&self, order_id: OrderId) -> anyhow::Result<(Order, bool)> {
+        let order_owner = self.order_id_to_key_pair(order_id).public_key();
+        let result = self
+            .federation_call_policy
+            .call(|| {
+                self.module_api
+                    .get_order(GetOrderParams { order: order_owner })
             })
-            .collect::<FuturesUnordered<_>>()
-            .collect()
-            .await
-    }
-
+            .await?;
+        let Some(order) = result.order else {
+            bail!(PredictionMarketsClientError::OrderDoesNotExist)
+        };
+
+        let mut dbtx = self.db.begin_transaction().await;
+        let previous = Self::save_order_to_db(&mut dbtx.to_ref_nc(), order_id, &order).await;
+        dbtx.commit_tx_result().await?;
+
+        let changed = previous.as_ref() != Some(&order);
+        Ok((order, changed))
+    }
+
+    /// Balance changes of an order, oldest first, reconstructed from every
+    /// version of the order cached by this client. Changes that happened
+    /// between two syncs from the federation show up as a single entry.
+    ///
+    /// Uses the local cache, so the ledger only reaches as far as the last
+    /// sync. Pruning an order removes its ledger.
+    pub async fn get_order_ledger(&self, id: OrderId) -> Vec<LedgerEntry> {
+        let records: Vec<_> = self
+            .db
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix(&db::OrderLedgerPrefix1 { order: id })
+            .await
+            .map(|(_, record)| record)
+            .collect()
+            .await;
+
+        order_ledger_entries(&records)
+    }
+
+    /// Encodes what is left of an order as a [SharedQuote] string that can be
+    /// given to someone else, who can read it with
+    /// [Self::preview_shared_quote] and place a matching order.
+    ///
+    /// Uses the local cache. The quote does not contain any keys and is not
+    /// an offer the recipient can take on its own.
+    pub async fn export_order_quote(&self, id: OrderId) -> anyhow::Result<String> {
+        let Some(order) = self.get_order(id, true).await? else {
+            bail!(PredictionMarketsClientError::OrderDoesNotExist)
+        };
+
+        Ok(SharedQuote {
+            market: order.market,
+            outcome: order.outcome,
+            side: order.side,
+            price: order.price,
+            quantity: order.quantity_waiting_for_match,
+        }
+        .to_string())
+    }
+
+    /// Decodes a string created by [Self::export_order_quote] and checks that
+    /// the market it refers to exists.
+    pub async fn preview_shared_quote(&self, s: &str) -> anyhow::Result<SharedQuote> {
+        let quote = SharedQuote::from_str(s)?;
+        self.ensure_market_exists(quote.market).await?;
+
+        Ok(quote)
+    }
+
+    /// Orders of a market on the federation, including orders of other
+    /// clients, a page at a time. Pass the returned [PublicKey] as `after` to
+    /// get the next page. It is `None` once there are no more orders.
+    ///
+    /// `limit` is capped by the federation at [GET_MARKET_ORDERS_MAX_LIMIT].
+    pub async fn get_market_orders(
+        &self,
+        market: OutPoint,
+        after: Option<PublicKey>,
+        limit: u32,
+    ) -> anyhow::Result<(Vec<(PublicKey, Order)>, Option<PublicKey>)> {
+        let result = self
+            .federation_call_policy
+            .call(|| {
+                self.module_api.get_market_orders(GetMarketOrdersParams {
+                    market,
+                    after,
+                    limit: limit.min(GET_MARKET_ORDERS_MAX_LIMIT),
+                })
+            })
+            .await?;
+
+        Ok((result.orders, result.next))
+    }
+
+    pub async fn get_orders_from_db(&self, filter: OrderFilter) -> BTreeMap<OrderId, Order> {
+        Self::get_order_ids(&mut self.db.begin_transaction_nc().await, filter)
+            .await
+            .into_iter()
+            .map(|order_id| async move {
+                (
+                    order_id,
+                    self.get_order(order_id, true).await.unwrap().unwrap(),
+                )
+            })
+            .collect::<FuturesUnordered<_>>()
+            .collect()
+            .await
+    }
+
+    /// Like [Self::get_orders_from_db], but a page of at most `limit` orders at
+    /// a time, ordered by [OrderId]. Only the orders of the page are loaded.
+    ///
+    /// Pass the returned [OrderCursor] to get the next page. It is `None` once
+    /// there are no more orders. Orders created while paging are included if
+    /// their id is after the cursor, which new orders always are.
+    pub async fn get_orders_from_db_paginated(
+        &self,
+        filter: OrderFilter,
+        cursor: Option<OrderCursor>,
+        limit: NonZeroUsize,
+    ) -> (Vec<(OrderId, Order)>, Option<OrderCursor>) {
+        Self::get_orders_page(
+            &mut self.db.begin_transaction_nc().await,
+            filter,
+            cursor,
+            limit,
+        )
+        .await
+    }
+
+    /// Like [Self::get_orders_from_db], but as a list in the order given by
+    /// `sort`.
+    pub async fn list_orders(&self, filter: OrderFilter, sort: OrderSort) -> Vec<(OrderId, Order)> {
+        let mut orders: Vec<_> = self.get_orders_from_db(filter).await.into_iter().collect();
+        sort.sort(&mut orders);
+
+        orders
+    }
+
+    /// Like [Self::get_orders_from_db], but with each order's tag set by
+    /// [Self::set_order_tag].
+    pub async fn get_tagged_orders_from_db(
+        &self,
+        filter: OrderFilter,
+    ) -> BTreeMap<OrderId, TaggedOrder> {
+        let mut tags = self.get_order_tags().await;
+
+        self.get_orders_from_db(filter)
+            .await
+            .into_iter()
+            .map(|(order_id, order)| {
+                let tag = tags.remove(&order_id);
+                (order_id, TaggedOrder { order, tag })
+            })
+            .collect()
+    }
+
+    /// Tags an order for client side organization, replacing any previous
+    /// tag. Tags are only stored locally and never sent to the federation.
+    pub async fn set_order_tag(&self, order_id: OrderId, tag: String) -> anyhow::Result<()> {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        if dbtx
+            .get_value(&db::OrderKey(order_id))
+            .await
+            .and_then(|slot| slot.to_order())
+            .is_none()
+        {
+            bail!(PredictionMarketsClientError::OrderDoesNotExist)
+        }
+
+        if let Some(previous_tag) = dbtx
+            .insert_entry(&db::OrderTagByOrderKey(order_id), &tag)
+            .await
+        {
+            dbtx.remove_entry(&db::OrderTagKey {
+                tag: previous_tag,
+                order: order_id,
+            })
+            .await;
+        }
+
+        dbtx.insert_entry(
+            &db::OrderTagKey {
+                tag,
+                order: order_id,
+            },
+            &(),
+        )
+        .await;
+
+        dbtx.commit_tx_result().await?;
+
+        Ok(())
+    }
+
+    /// Cached orders tagged with `tag` by [Self::set_order_tag].
+    pub async fn get_orders_by_tag(&self, tag: &str) -> BTreeMap<OrderId, Order> {
+        let mut dbtx = self.db.begin_transaction_nc().await;
+
+        let order_ids: Vec<_> = dbtx
+            .find_by_prefix(&db::OrderTagPrefix1 {
+                tag: tag.to_owned(),
+            })
+            .await
+            .map(|(key, _)| key.order)
+            .collect()
+            .await;
+
+        let mut orders = BTreeMap::new();
+        for order_id in order_ids {
+            // pruned orders keep their tag but have no cached body
+            if let Some(order) = dbtx
+                .get_value(&db::OrderKey(order_id))
+                .await
+                .and_then(|slot| slot.to_order())
+            {
+                orders.insert(order_id, order);
+            }
+        }
+
+        orders
+    }
+
+    /// Cached orders matching `query`. Filters and tags in the query are
+    /// looked up in their index to narrow down the orders that are checked.
+    /// Every cached order is checked if the query can not be narrowed down,
+    /// such as when it only constrains the creation time.
+    pub async fn get_orders_matching(
+        &self,
+        query: impl Into<OrderQuery>,
+    ) -> BTreeMap<OrderId, Order> {
+        let query = query.into();
+        let tags = self.get_order_tags().await;
+        let mut dbtx = self.db.begin_transaction_nc().await;
+
+        let mut index = HashMap::new();
+        for leaf in query.indexed_leaves() {
+            let order_ids = match leaf {
+                OrderQuery::Filter(filter) => Self::get_order_ids(&mut dbtx, *filter).await,
+                OrderQuery::Tag(tag) => {
+                    dbtx.find_by_prefix(&db::OrderTagPrefix1 {
+                        tag: tag.to_owned(),
+                    })
+                    .await
+                    .map(|(key, _)| key.order)
+                    .collect()
+                    .await
+                }
+                _ => continue,
+            };
+            index.insert(leaf, order_ids);
+        }
+
+        let order_ids = match query.candidates(&index) {
+            Some(order_ids) => order_ids,
+            None => {
+                Self::get_order_ids(&mut dbtx, OrderFilter(OrderPath::All, OrderState::Any)).await
+            }
+        };
+
+        let mut orders = BTreeMap::new();
+        for order_id in order_ids {
+            // pruned orders keep their tag but have no cached body
+            let Some(order) = dbtx
+                .get_value(&db::OrderKey(order_id))
+                .await
+                .and_then(|slot| slot.to_order())
+            else {
+                continue;
+            };
+            if query.matches(&order, tags.get(&order_id).map(String::as_str)) {
+                orders.insert(order_id, order);
+            }
+        }
+
+        orders
+    }
+
+    async fn get_order_tags(&self) -> HashMap<OrderId, String> {
+        self.db
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix(&db::OrderTagPrefixAll)
+            .await
+            .map(|(key, _)| (key.order, key.tag))
+            .collect()
+            .await
+    }
+
+    /// Removes cached orders that can no longer change: orders in a market
+    /// that has paid out, with nothing waiting for match, zero balances, and
+    /// created before `older_than`. Only the cached bodies are removed; pruned
+    /// order ids are never allocated again.
+    ///
+    /// Market resolution is read from the local market cache.
+    ///
+    /// Returns the number of orders pruned.
+    pub async fn prune_settled_orders(&self, older_than: UnixTimestamp) -> anyhow::Result<usize> {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        let orders: Vec<(OrderId, Order)> = dbtx
+            .find_by_prefix(&db::OrderPrefixAll)
+            .await
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .filter_map(|(key, slot)| slot.to_order().map(|order| (key.0, order)))
+            .collect();
+
+        let mut market_resolved: HashMap<OutPoint, bool> = HashMap::new();
+        let mut pruned = 0;
+        for (order_id, order) in orders {
+            if order.created_consensus_timestamp >= older_than
+                || order.quantity_waiting_for_match != ContractOfOutcomeAmount::ZERO
+                || order.contract_of_outcome_balance != ContractOfOutcomeAmount::ZERO
+                || order.bitcoin_balance != Amount::ZERO
+            {
+                continue;
+            }
+
+            let resolved = match market_resolved.get(&order.market) {
+                Some(resolved) => *resolved,
+                None => {
+                    let resolved = dbtx
+                        .get_value(&db::MarketKey(order.market))
+                        .await
+                        .map_or(false, |market| market.1.payout.is_some());
+                    market_resolved.insert(order.market, resolved);
+                    resolved
+                }
+            };
+            if !resolved {
+                continue;
+            }
+
+            Self::prune_order_from_db(&mut dbtx.to_ref_nc(), order_id, &order).await;
+            pruned += 1;
+        }
+
+        dbtx.commit_tx_result().await?;
+
+        Ok(pruned)
+    }
+
     pub async fn stream_order_from_db<'a>(&self, id: OrderId) -> BoxStream<'a, Option<Order>> {
         let db = self.db.clone();
 
@@ -624,7 +1701,134 @@ impl PredictionMarketsClientModule {
         })
     }
 
+    /// Yields every cached order matching `filter` in ascending id order,
+    /// reading them one at a time so memory use does not grow with the size
+    /// of the orders. Unlike [Self::get_orders_from_db], only the ids of
+    /// matching orders are collected up front, from the index for `filter`.
+    ///
+    /// Reads from a single database snapshot taken on the first poll, so
+    /// orders written later are not yielded.
+    pub fn iter_orders(&self, filter: OrderFilter) -> BoxStream<'static, (OrderId, Order)> {
+        let db = self.db.clone();
+
+        Box::pin(stream! {
+            let mut dbtx = db.begin_transaction_nc().await;
+            for order_id in Self::get_order_ids(&mut dbtx, filter).await {
+                let Some(order) = dbtx
+                    .get_value(&db::OrderKey(order_id))
+                    .await
+                    .and_then(|slot| slot.to_order())
+                else {
+                    continue;
+                };
+                if filter.filter(&order) {
+                    yield (order_id, order);
+                }
+            }
+        })
+    }
+
+    /// Yields `(order id, order)` every time one of the caller's orders
+    /// changes, including when new orders are created.
+    ///
+    /// Changes are picked up from the local cache every
+    /// [ORDER_UPDATES_POLL_INTERVAL], and orders waiting for a match are
+    /// synced from the federation every [ORDER_UPDATES_SYNC_INTERVAL]. The
+    /// latest state of an order is yielded, so multiple changes made while the
+    /// consumer is not polling are coalesced into one.
+    ///
+    /// Ends when the module is dropped.
+    pub async fn subscribe_all_order_updates(&self) -> BoxStream<'static, (OrderId, Order)> {
+        let db = self.db.clone();
+        let module_api = self.module_api.clone();
+        let root_secret = self.root_secret.clone();
+        let federation_call_policy = self.federation_call_policy;
+        let module_alive = Arc::downgrade(&self.module_alive);
+        let mut new_order_reciever = self.new_order_broadcast.0.subscribe();
+
+        let mut known_orders =
+            Self::get_changeable_orders(&mut db.begin_transaction_nc().await, BTreeSet::new())
+                .await;
+
+        Box::pin(stream! {
+            let mut next_sync = Instant::now() + ORDER_UPDATES_SYNC_INTERVAL;
+
+            loop {
+                let now = Instant::now();
+
+                select! {
+                    _ = new_order_reciever.recv() => {}
+                    _ = sleep_until(now + ORDER_UPDATES_POLL_INTERVAL) => {}
+                    _ = sleep_until(next_sync) => {
+                        let orders_waiting_for_match = known_orders
+                            .iter()
+                            .filter(|(_, order)| {
+                                order.quantity_waiting_for_match != ContractOfOutcomeAmount::ZERO
+                            })
+                            .map(|(order_id, _)| *order_id)
+                            .collect();
+                        // failed syncs are retried on the next interval
+                        _ = Self::sync_orders_from_federation_concurrent(
+                            root_secret.clone(),
+                            module_api.clone(),
+                            federation_call_policy,
+                            db.clone(),
+                            orders_waiting_for_match,
+                        )
+                        .await;
+                        next_sync = Instant::now() + ORDER_UPDATES_SYNC_INTERVAL;
+                    }
+                }
+
+                if module_alive.strong_count() == 0 {
+                    return;
+                }
+
+                // previously known orders are included so that their final
+                // change is seen
+                let orders = Self::get_changeable_orders(
+                    &mut db.begin_transaction_nc().await,
+                    known_orders.keys().copied().collect(),
+                )
+                .await;
+                for (order_id, order) in orders.iter() {
+                    if known_orders.get(order_id) != Some(order) {
+                        yield (*order_id, order.to_owned());
+                    }
+                }
+
+                known_orders = orders
+                    .into_iter()
+                    .filter(|(_, order)| Self::order_can_change(order))
+                    .collect();
+            }
+        })
+    }
+
+    /// Cancels the quantity of the order still waiting for match.
+    ///
+    /// Does nothing if the order has no quantity waiting for match, because
+    /// it was fully matched or already cancelled, so it is safe to call more
+    /// than once. A quantity of zero in the local cache is final. Otherwise
+    /// the order is refreshed from the federation before anything is
+    /// submitted. Fails with [PredictionMarketsClientError::OrderDoesNotExist]
+    /// if the federation does not know the order.
     pub async fn cancel_order(&self, order_id: OrderId) -> anyhow::Result<()> {
+        let order = match self.get_order(order_id, true).await? {
+            Some(order) if order.quantity_waiting_for_match == ContractOfOutcomeAmount::ZERO => {
+                Some(order)
+            }
+            _ => self.get_order(order_id, false).await?,
+        };
+        let Some(order) = order else {
+            bail!(PredictionMarketsClientError::OrderDoesNotExist)
+        };
+        if order.quantity_waiting_for_match == ContractOfOutcomeAmount::ZERO {
+            return Ok(());
+        }
+
+        self.check_order_rate_limit()?;
+
         let operation_id = OperationId::new_random();
 
         let order_key = self.order_id_to_key_pair(order_id);
@@ -647,6 +1851,13 @@ impl PredictionMarketsClientModule {
         };
 
         let tx = TransactionBuilder::new().with_input(self.ctx.make_client_input(input));
+        self.log_submitted_transaction(
+            operation_id,
+            SubmittedTransactionSummary::CancelOrders {
+                order_ids: vec![order_id],
+            },
+        )
+        .await?;
         let (tx_id, _) = self
             .ctx
             .finalize_and_submit_transaction(
@@ -655,7 +1866,8 @@ impl PredictionMarketsClientModule {
                 |_, _| (),
                 tx,
             )
-            .await?;
+            .await
+            .inspect_err(|_| self.release_order_rate_limit())?;
 
         self.await_accepted(operation_id, tx_id).await?;
         self.await_state(operation_id, |s| {
@@ -669,65 +1881,252 @@ impl PredictionMarketsClientModule {
         Ok(())
     }
 
-    /// send all bitcoin balance from orders to primary module
-    pub async fn send_order_bitcoin_balance_to_primary_module(&self) -> anyhow::Result<Amount> {
+    /// Cancels every order matching the filter that still has quantity
+    /// waiting for match. All cancellations are batched into a single
+    /// transaction. The cached candidates are synced first, so orders that
+    /// were fully matched or cancelled since the last sync are skipped.
+    ///
+    /// Returns the ids of the orders that were cancelled.
+    pub async fn cancel_all_orders(&self, filter: OrderFilter) -> anyhow::Result<Vec<OrderId>> {
         let operation_id = OperationId::new_random();
 
-        let mut dbtx = self.db.begin_transaction().await;
-
-        let orders_with_non_zero_bitcoin_balance = Self::get_order_ids(
-            &mut dbtx.to_ref_nc(),
-            OrderFilter(OrderPath::All, OrderState::NonZeroBitcoinBalance),
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        let mut candidates = Vec::new();
+        for order_id in Self::get_order_ids(
+            &mut dbtx,
+            OrderFilter(filter.0, OrderState::NonZeroQuantityWaitingForMatch),
         )
-        .await;
+        .await
+        {
+            let Some(order) = dbtx
+                .get_value(&db::OrderKey(order_id))
+                .await
+                .and_then(|slot| slot.to_order())
+            else {
+                continue;
+            };
 
-        if orders_with_non_zero_bitcoin_balance.len() == 0 {
-            return Ok(Amount::ZERO);
+            if filter.filter(&order) {
+                candidates.push(order_id);
+            }
         }
+        drop(dbtx);
+
+        // one stale order would fail the whole transaction
+        self.sync_orders_from_federation_concurrent_with_self(candidates.clone())
+            .await?;
+
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        let mut orders_to_cancel = Vec::new();
+        for order_id in candidates {
+            let Some(order) = dbtx
+                .get_value(&db::OrderKey(order_id))
+                .await
+                .and_then(|slot| slot.to_order())
+            else {
+                continue;
+            };
+
+            if order.quantity_waiting_for_match != ContractOfOutcomeAmount::ZERO {
+                orders_to_cancel.push(order_id);
+            }
+        }
+
+        if orders_to_cancel.is_empty() {
+            return Ok(orders_to_cancel);
+        }
+
+        self.check_order_rate_limit()?;
 
-        let mut total_amount = Amount::ZERO;
         let mut tx = TransactionBuilder::new();
-        for order_id in orders_with_non_zero_bitcoin_balance {
-            let order = self.get_order(order_id, true).await?.unwrap();
+        for order_id in orders_to_cancel.iter().copied() {
             let order_key = self.order_id_to_key_pair(order_id);
 
             let input = ClientInput {
-                input: PredictionMarketsInput::ConsumeOrderBitcoinBalance {
+                input: PredictionMarketsInput::CancelOrder {
                     order: order_key.public_key(),
-                    amount: order.bitcoin_balance,
                 },
-                amount: order.bitcoin_balance,
                 state_machines: Arc::new(move |tx_id, _| {
                     vec![PredictionMarketsStateMachine {
                         operation_id,
-                        state: ConsumeOrderBitcoinBalanceState::Pending {
+                        state: CancelOrderState::Pending {
                             tx_id,
                             order_to_sync_on_accepted: order_id,
                         }
                         .into(),
                     }]
                 }),
+                amount: Amount::ZERO,
                 keys: vec![order_key],
             };
 
             tx = tx.with_input(self.ctx.make_client_input(input));
-
-            total_amount += order.bitcoin_balance;
         }
 
-        let outpoint = |txid, _| OutPoint { txid, out_idx: 0 };
+        self.log_submitted_transaction(
+            operation_id,
+            SubmittedTransactionSummary::CancelOrders {
+                order_ids: orders_to_cancel.clone(),
+            },
+        )
+        .await?;
         let (tx_id, _) = self
             .ctx
             .finalize_and_submit_transaction(
                 operation_id,
                 PredictionMarketsCommonInit::KIND.as_str(),
-                outpoint,
+                |_, _| (),
                 tx,
             )
-            .await?;
+            .await
+            .inspect_err(|_| self.release_order_rate_limit())?;
 
         self.await_accepted(operation_id, tx_id).await?;
-        self.await_state(operation_id, |s| {
+        self.await_state_count(operation_id, orders_to_cancel.len(), |s| {
+            matches!(
+                s,
+                PredictionMarketState::CancelOrder(CancelOrderState::Complete)
+            )
+        })
+        .await;
+
+        Ok(orders_to_cancel)
+    }
+
+    /// Registers a dead man's switch that cancels the orders matching
+    /// `filter` if the client stops running for longer than `interval`,
+    /// replacing any switch registered before.
+    ///
+    /// The federation can not cancel orders for the client, so the switch is
+    /// only a guard driven by restarts: while the module is alive it refreshes
+    /// the switch with a heartbeat, and when the client starts again after
+    /// missing the heartbeat for `interval`, the orders are cancelled with
+    /// [Self::cancel_all_orders] before anything else and the switch is
+    /// removed. Orders stay in the order book while the client is not
+    /// running.
+    pub async fn register_dead_mans_switch(
+        &self,
+        interval: Duration,
+        filter: OrderFilter,
+    ) -> anyhow::Result<()> {
+        if interval.as_secs() == 0 {
+            bail!("dead man's switch interval must be at least one second")
+        }
+
+        let switch = DeadMansSwitch {
+            interval: interval.as_secs(),
+            filter,
+            last_heartbeat: UnixTimestamp::now(),
+        };
+
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.insert_entry(&db::DeadMansSwitchKey, &switch).await;
+        dbtx.commit_tx_result().await?;
+
+        self.spawn_dead_mans_switch_heartbeat(switch).await;
+
+        Ok(())
+    }
+
+    /// Removes the switch registered by [Self::register_dead_mans_switch], if
+    /// any, so that no orders are cancelled on the next start.
+    pub async fn clear_dead_mans_switch(&self) -> anyhow::Result<()> {
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.remove_entry(&db::DeadMansSwitchKey).await;
+        dbtx.commit_tx_result().await?;
+
+        self.stop_dead_mans_switch_heartbeat().await;
+
+        Ok(())
+    }
+
+    pub async fn get_dead_mans_switch(&self) -> Option<DeadMansSwitch> {
+        self.db
+            .begin_transaction_nc()
+            .await
+            .get_value(&db::DeadMansSwitchKey)
+            .await
+    }
+
+    /// Read only view of the bitcoin balance that
+    /// [Self::send_order_bitcoin_balance_to_primary_module] would withdraw.
+    ///
+    /// Uses the local order cache unless `refresh` is set, in which case
+    /// orders that may have gained bitcoin balance from matches or payouts are
+    /// synced from the federation first.
+    pub async fn get_available_bitcoin_balance(
+        &self,
+        refresh: bool,
+    ) -> anyhow::Result<AvailableBitcoinBalance> {
+        if refresh {
+            self.sync_matches(OrderPath::All, true).await?;
+            self.sync_payouts(None).await?;
+        }
+
+        Ok(self
+            .available_bitcoin_balance_from_db(&mut self.db.begin_transaction_nc().await)
+            .await)
+    }
+
+    /// send all bitcoin balance from orders to primary module
+    pub async fn send_order_bitcoin_balance_to_primary_module(
+        &self,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<Amount> {
+        let operation_id = OperationId::new_random();
+
+        let mut dbtx = self.db.begin_transaction().await;
+
+        let orders_with_non_zero_bitcoin_balance = Self::get_order_ids(
+            &mut dbtx.to_ref_nc(),
+            OrderFilter(OrderPath::All, OrderState::NonZeroBitcoinBalance),
+        )
+        .await;
+
+        if orders_with_non_zero_bitcoin_balance.len() == 0 {
+            return Ok(Amount::ZERO);
+        }
+
+        let mut total_amount = Amount::ZERO;
+        let mut settled_markets = BTreeSet::new();
+        for order_id in orders_with_non_zero_bitcoin_balance.iter() {
+            let order = self.get_order(*order_id, true).await?.unwrap();
+            total_amount += order.bitcoin_balance;
+            if order.bitcoin_acquired_from_payout != Amount::ZERO {
+                settled_markets.insert(order.market);
+            }
+        }
+        let order_ids: Vec<_> = orders_with_non_zero_bitcoin_balance.into_iter().collect();
+        let tx = self
+            .consume_order_bitcoin_balance_into(
+                operation_id,
+                order_ids.clone(),
+                TransactionBuilder::new(),
+                max_fee,
+            )
+            .await?;
+
+        self.log_submitted_transaction(
+            operation_id,
+            SubmittedTransactionSummary::ConsumeOrderBitcoinBalance {
+                order_ids,
+                amount: total_amount,
+            },
+        )
+        .await?;
+        let outpoint = |txid, _| OutPoint { txid, out_idx: 0 };
+        let (tx_id, _) = self
+            .ctx
+            .finalize_and_submit_transaction(
+                operation_id,
+                PredictionMarketsCommonInit::KIND.as_str(),
+                outpoint,
+                tx,
+            )
+            .await?;
+
+        self.await_accepted(operation_id, tx_id).await?;
+        self.await_state(operation_id, |s| {
             matches!(
                 s,
                 PredictionMarketState::ConsumeOrderBitcoinBalance(
@@ -737,9 +2136,114 @@ impl PredictionMarketsClientModule {
         })
         .await;
 
+        // payouts were withdrawn, nothing left to tell the user about
+        let mut dbtx = self.db.begin_transaction().await;
+        for market in settled_markets {
+            dbtx.remove_entry(&db::PendingSettlementKey(market)).await;
+        }
+        dbtx.commit_tx_result().await?;
+
         Ok(total_amount)
     }
 
+    /// Winds down all trading: cancels every order still waiting for match,
+    /// then sends the bitcoin balance of all orders, including what the
+    /// cancellations freed and any payouts, to the primary module.
+    ///
+    /// Orders are synced from the federation before cancelling. A market
+    /// paying out while this runs finishes its orders on the federation, which
+    /// gets the cancellation rejected, so it is retried once after syncing
+    /// again. Any other error is returned right away.
+    pub async fn close_all_positions(&self) -> anyhow::Result<CloseAllSummary> {
+        const CANCEL_ATTEMPTS: usize = 2;
+
+        let mut attempt = 1;
+        let cancelled_orders = loop {
+            self.sync_matches(OrderPath::All, true).await?;
+            match self
+                .cancel_all_orders(OrderFilter(
+                    OrderPath::All,
+                    OrderState::NonZeroQuantityWaitingForMatch,
+                ))
+                .await
+            {
+                Ok(order_ids) => break order_ids,
+                Err(e) if attempt < CANCEL_ATTEMPTS && is_rejected_as_finished_order(&e) => {
+                    attempt += 1
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        // cancelled orders were synced once the cancellation was accepted
+        self.sync_payouts(None).await?;
+        let withdrawn = self
+            .send_order_bitcoin_balance_to_primary_module(None)
+            .await?;
+
+        Ok(CloseAllSummary {
+            cancelled_orders,
+            withdrawn,
+        })
+    }
+
+    /// Adds inputs consuming the full cached bitcoin balance of each order to
+    /// a caller provided transaction, so the freed bitcoin can be spent by
+    /// other outputs in the same transaction instead of going to the primary
+    /// module first.
+    ///
+    /// State machines syncing the orders once the transaction is accepted are
+    /// attached to `operation_id`, which must be the operation the transaction
+    /// is submitted under.
+    ///
+    /// Each input is charged the consume order bitcoin balance fee, so the
+    /// amount available to the transaction's outputs is the orders' summed
+    /// bitcoin balance minus one fee per order. Orders with zero bitcoin
+    /// balance are skipped. Fails if the summed fee is above `max_fee`.
+    pub async fn consume_order_bitcoin_balance_into(
+        &self,
+        operation_id: OperationId,
+        order_ids: Vec<OrderId>,
+        mut tx: TransactionBuilder,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<TransactionBuilder> {
+        let mut fee = Amount::ZERO;
+        for order_id in order_ids {
+            let Some(order) = self.get_order(order_id, true).await? else {
+                bail!("order {} does not exist", order_id.0)
+            };
+            if order.bitcoin_balance == Amount::ZERO {
+                continue;
+            }
+            let order_key = self.order_id_to_key_pair(order_id);
+
+            let input = ClientInput {
+                input: PredictionMarketsInput::ConsumeOrderBitcoinBalance {
+                    order: order_key.public_key(),
+                    amount: order.bitcoin_balance,
+                },
+                amount: order.bitcoin_balance,
+                state_machines: Arc::new(move |tx_id, _| {
+                    vec![PredictionMarketsStateMachine {
+                        operation_id,
+                        state: ConsumeOrderBitcoinBalanceState::Pending {
+                            tx_id,
+                            order_to_sync_on_accepted: order_id,
+                        }
+                        .into(),
+                    }]
+                }),
+                keys: vec![order_key],
+            };
+
+            tx = tx.with_input(self.ctx.make_client_input(input));
+            fee += self.cfg.gc.consume_order_bitcoin_balance_fee;
+        }
+        Self::check_fee(fee, max_fee)?;
+
+        Ok(tx)
+    }
+
     /// TODO docs
     pub async fn sync_payouts(&self, market_specifier: Option<OutPoint>) -> anyhow::Result<()> {
         let mut dbtx = self.db.begin_transaction().await;
@@ -790,19 +2294,340 @@ impl PredictionMarketsClientModule {
         Ok(())
     }
 
-    pub async fn sync_matches(&self, order_path: OrderPath) -> anyhow::Result<()> {
-        let active_quantiy_orders: BTreeSet<_> = Self::get_order_ids(
-            &mut self.db.begin_transaction_nc().await,
+    /// Checks every market the client has orders in for a payout that is not
+    /// in the local cache yet, which means the market paid out since the
+    /// client last looked, such as while it was not running. Each such
+    /// market where the client still has payout to claim is recorded for
+    /// [Self::get_pending_settlements] and returned. At most
+    /// [MAX_CONCURRENT_SETTLEMENT_CHECKS] markets are fetched at a time.
+    /// Markets that could not be checked are logged and skipped.
+    ///
+    /// Runs in the background when the client starts.
+    pub async fn reconcile_settlements(&self) -> anyhow::Result<Vec<OutPoint>> {
+        Self::reconcile_settlements_with(
+            self.db.clone(),
+            self.module_api.clone(),
+            self.federation_call_policy,
+        )
+        .await
+    }
+
+    /// Markets found by [Self::reconcile_settlements] with when they were
+    /// found. A market stays until [Self::acknowledge_pending_settlement] or
+    /// until its payout is withdrawn with
+    /// [Self::send_order_bitcoin_balance_to_primary_module].
+    pub async fn get_pending_settlements(&self) -> BTreeMap<OutPoint, UnixTimestamp> {
+        self.db
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix(&db::PendingSettlementPrefixAll)
+            .await
+            .map(|(k, v)| (k.0, v))
+            .collect()
+            .await
+    }
+
+    /// Removes a market from [Self::get_pending_settlements].
+    pub async fn acknowledge_pending_settlement(&self, market: OutPoint) {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        dbtx.remove_entry(&db::PendingSettlementKey(market)).await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Pays out every market the client holds contracts in that can be paid
+    /// out, yielding progress as each market is processed.
+    ///
+    /// For each market without a payout, event payout attestations from the
+    /// market's payout controls are looked up on nostr and the payout is
+    /// submitted once they reach `weight_required_for_payout`. Orders in
+    /// markets that have paid out are then synced like [Self::sync_payouts].
+    /// A failure in one market is reported in its progress item and does not
+    /// stop the others.
+    #[cfg(feature = "cli")]
+    pub fn sync_all_payouts(&self) -> BoxStream<'_, PayoutSyncProgress> {
+        Box::pin(stream! {
+            let markets: BTreeSet<_> = self
+                .db
+                .begin_transaction_nc()
+                .await
+                .find_by_prefix(
+                    &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSidePrefixAll,
+                )
+                .await
+                .map(|(k, _)| k.market)
+                .collect()
+                .await;
+            let markets_total = markets.len();
+
+            for (i, market) in markets.into_iter().enumerate() {
+                let result = self
+                    .sync_market_payout(market)
+                    .await
+                    .unwrap_or_else(|e| PayoutSyncResult::Failed {
+                        reason: e.to_string(),
+                    });
+
+                yield PayoutSyncProgress {
+                    market,
+                    markets_processed: i + 1,
+                    markets_total,
+                    result,
+                };
+            }
+        })
+    }
+
+    #[cfg(feature = "cli")]
+    async fn sync_market_payout(&self, market: OutPoint) -> anyhow::Result<PayoutSyncResult> {
+        let Some(market_data) = self.get_market(market, false).await? else {
+            bail!(PredictionMarketsClientError::MarketDoesNotExist)
+        };
+
+        if market_data.1.payout.is_some() {
+            self.sync_payouts(Some(market)).await?;
+            return Ok(PayoutSyncResult::AlreadyPaidOut);
+        }
+
+        let payout_attestations::PayoutAttestationSet {
+            payout: Some((event_payout, event_payout_attestations_json)),
+            ..
+        } = self.collect_payout_attestations_for(&market_data).await
+        else {
+            return Ok(PayoutSyncResult::NoPayoutAvailable);
+        };
+
+        self.payout_market(market, event_payout_attestations_json)
+            .await?;
+        self.sync_payouts(Some(market)).await?;
+
+        Ok(PayoutSyncResult::PayoutSubmitted { event_payout })
+    }
+
+    /// Looks for event payout attestations from the market's payout controls
+    /// on the recommended nostr relays, see
+    /// [payout_attestations::collect_from_relays]. Slow or unreachable relays
+    /// are given up on, so this returns whatever was found by the deadline
+    /// instead of hanging.
+    #[cfg(feature = "cli")]
+    pub async fn collect_payout_attestations(
+        &self,
+        market: OutPoint,
+    ) -> anyhow::Result<payout_attestations::PayoutAttestationSet> {
+        let Some(market) = self.get_market(market, false).await? else {
+            bail!(PredictionMarketsClientError::MarketDoesNotExist)
+        };
+
+        Ok(self.collect_payout_attestations_for(&market).await)
+    }
+
+    /// Suggests the payout per contract of each outcome that payout controls
+    /// of the market are converging on, see [suggest_event_payout]. Useful to
+    /// a payout control deciding what to attest to when attestations on the
+    /// recommended nostr relays disagree.
+    ///
+    /// Returns `None` if no valid attestation was found.
+    #[cfg(feature = "cli")]
+    pub async fn suggest_payout(&self, market: OutPoint) -> anyhow::Result<Option<Vec<Amount>>> {
+        let Some(market) = self.get_market(market, false).await? else {
+            bail!(PredictionMarketsClientError::MarketDoesNotExist)
+        };
+        let event = market.0.event()?;
+
+        let attestations = self.collect_payout_attestations_for(&market).await;
+        let groups =
+            group_event_payout_attestations(&market, attestations.event_payout_attestations_json);
+
+        Ok(suggest_event_payout(&event, &groups)
+            .map(|event_payout| payout_amount_per_outcome(&market, &event, &event_payout)))
+    }
+
+    #[cfg(feature = "cli")]
+    async fn collect_payout_attestations_for(
+        &self,
+        market: &Market,
+    ) -> payout_attestations::PayoutAttestationSet {
+        payout_attestations::collect_from_relays(
+            market,
+            &cli::recommended_relays(),
+            Default::default(),
+        )
+        .await
+    }
+
+    /// Cheap check for a "sync needed" indicator. True if a submitted new
+    /// order has not been cached yet or if any order has quantity waiting for
+    /// match, which may have been matched since the last sync.
+    ///
+    /// Only reads the local cache, nothing is fetched from the federation.
+    pub async fn needs_sync(&self) -> bool {
+        Self::needs_sync_from_db(&mut self.db.begin_transaction_nc().await).await
+    }
+
+    /// Syncs orders that have quantity waiting for match from the federation.
+    ///
+    /// Unless `force` is set, only orders on market outcome sides with trading
+    /// activity since the last sync and orders in markets that have paid out
+    /// are fetched. Activity is detected using the newest candlestick of the
+    /// smallest candlestick interval, which is one request per market outcome
+    /// instead of one per order, and payouts by fetching the markets in bulk.
+    /// Matches are not missed as long as the candlestick data is available.
+    /// Candlesticks only change with matches though, so an order cancelled
+    /// by another client holding the same keys is only noticed with `force`.
+    /// If the federation keeps no candlesticks, see
+    /// [Self::candlesticks_supported], every order is fetched.
+    ///
+    /// Returns the number of orders fetched from the federation.
+    pub async fn sync_matches(&self, order_path: OrderPath, force: bool) -> anyhow::Result<usize> {
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        let active_quantity_orders = Self::get_order_ids(
+            &mut dbtx,
             OrderFilter(order_path, OrderState::NonZeroQuantityWaitingForMatch),
         )
         .await;
 
-        self.sync_orders_from_federation_concurrent_with_self(
-            active_quantiy_orders.into_iter().collect(),
-        )
-        .await?;
+        let mut orders_by_market_outcome_side: BTreeMap<(OutPoint, Outcome, Side), Vec<OrderId>> =
+            BTreeMap::new();
+        for order_id in active_quantity_orders {
+            let Some(order) = dbtx
+                .get_value(&db::OrderKey(order_id))
+                .await
+                .and_then(|slot| slot.to_order())
+            else {
+                continue;
+            };
+            orders_by_market_outcome_side
+                .entry((order.market, order.outcome, order.side))
+                .or_default()
+                .push(order_id);
+        }
+        let mut previous_cursors = BTreeMap::new();
+        for (market, outcome, side) in orders_by_market_outcome_side.keys().copied() {
+            let cursor = dbtx
+                .get_value(&db::OrderSyncCursorKey {
+                    market,
+                    outcome,
+                    side,
+                })
+                .await;
+            previous_cursors.insert((market, outcome, side), cursor);
+        }
+        drop(dbtx);
+
+        // only candlesticks newer than the oldest cursor of a market outcome are
+        // needed
+        let mut min_candlestick_timestamps: BTreeMap<(OutPoint, Outcome), UnixTimestamp> =
+            BTreeMap::new();
+        for ((market, outcome, _), cursor) in &previous_cursors {
+            let timestamp = cursor
+                .as_ref()
+                .map_or(UnixTimestamp::ZERO, |c| c.candlestick_timestamp);
+            min_candlestick_timestamps
+                .entry((*market, *outcome))
+                .and_modify(|t| *t = (*t).min(timestamp))
+                .or_insert(timestamp);
+        }
 
-        Ok(())
+        // without candlesticks there is no way to tell which orders are unchanged
+        let Some(candlestick_interval) = self.cfg.gc.candlestick_intervals.iter().min().copied()
+        else {
+            let orders_to_sync: Vec<_> = orders_by_market_outcome_side
+                .into_values()
+                .flatten()
+                .collect();
+            let orders_synced = orders_to_sync.len();
+            self.sync_orders_from_federation_concurrent_with_self(orders_to_sync)
+                .await?;
+            return Ok(orders_synced);
+        };
+        let newest_candlesticks: BTreeMap<(OutPoint, Outcome), OrderSyncCursor> =
+            min_candlestick_timestamps
+                .into_iter()
+                .map(
+                    |((market, outcome), min_candlestick_timestamp)| async move {
+                        let res = self
+                            .get_candlesticks(
+                                market,
+                                outcome,
+                                candlestick_interval,
+                                min_candlestick_timestamp,
+                            )
+                            .await;
+                        ((market, outcome), res)
+                    },
+                )
+                .collect::<FuturesUnordered<_>>()
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .map(|(market_outcome, res)| {
+                    let cursor = res?
+                        .into_iter()
+                        .next_back()
+                        .map(|(candlestick_timestamp, candlestick)| OrderSyncCursor {
+                            candlestick_timestamp,
+                            candlestick_volume: candlestick.volume,
+                        })
+                        .unwrap_or(OrderSyncCursor {
+                            candlestick_timestamp: UnixTimestamp::ZERO,
+                            candlestick_volume: ContractOfOutcomeAmount::ZERO,
+                        });
+                    Ok((market_outcome, cursor))
+                })
+                .collect::<anyhow::Result<_>>()?;
+
+        // a payout cancels every order of the market without a match
+        let markets: BTreeSet<_> = orders_by_market_outcome_side
+            .keys()
+            .map(|(market, _, _)| *market)
+            .collect();
+        let paid_out_markets: BTreeSet<_> = self
+            .get_markets(markets.into_iter().collect(), false)
+            .await?
+            .into_iter()
+            .filter(|(_, market)| {
+                market
+                    .as_ref()
+                    .is_some_and(|Market(_, market_dynamic)| market_dynamic.payout.is_some())
+            })
+            .map(|(market, _)| market)
+            .collect();
+
+        let mut orders_to_sync = Vec::new();
+        for ((market, outcome, side), order_ids) in &orders_by_market_outcome_side {
+            let previous_cursor = previous_cursors
+                .get(&(*market, *outcome, *side))
+                .and_then(|c| c.as_ref());
+            if force
+                || paid_out_markets.contains(market)
+                || previous_cursor != newest_candlesticks.get(&(*market, *outcome))
+            {
+                orders_to_sync.extend(order_ids.iter().copied());
+            }
+        }
+
+        let orders_synced = orders_to_sync.len();
+        self.sync_orders_from_federation_concurrent_with_self(orders_to_sync)
+            .await?;
+
+        let mut dbtx = self.db.begin_transaction().await;
+        for (market, outcome, side) in orders_by_market_outcome_side.keys() {
+            let Some(cursor) = newest_candlesticks.get(&(*market, *outcome)) else {
+                continue;
+            };
+            dbtx.insert_entry(
+                &db::OrderSyncCursorKey {
+                    market: *market,
+                    outcome: *outcome,
+                    side: *side,
+                },
+                cursor,
+            )
+            .await;
+        }
+        dbtx.commit_tx_result().await?;
+
+        Ok(orders_synced)
     }
 
     pub async fn start_watch_matches(&self, order_path: OrderPath) -> anyhow::Result<u64> {
@@ -827,6 +2652,7 @@ impl PredictionMarketsClientModule {
                 }
             }
             OrderPath::MarketOutcome { market, outcome } => {
+                self.check_outcome(market, outcome).await?;
                 watch_args.push((market, outcome, Side::Buy));
                 watch_args.push((market, outcome, Side::Sell));
             }
@@ -835,6 +2661,7 @@ impl PredictionMarketsClientModule {
                 outcome,
                 side,
             } => {
+                self.check_outcome(market, outcome).await?;
                 watch_args.push((market, outcome, side));
             }
         };
@@ -891,27 +2718,270 @@ impl PredictionMarketsClientModule {
         Ok(())
     }
 
-    /// Scans for all orders that the client owns.
-    pub async fn resync_order_slots(&self, gap_size_to_check: usize) -> anyhow::Result<()> {
-        let mut order_id = OrderId(0);
-        let mut slots_without_order = 0;
-        loop {
-            if let Some(_) = self.get_order(order_id, false).await? {
-                slots_without_order = 0;
-            } else {
-                slots_without_order += 1;
-                if slots_without_order == gap_size_to_check {
-                    break;
-                }
-            }
-
-            order_id.0 += 1;
-        }
-
+    /// Syncs the orders matching `filter` that can still change from the
+    /// federation every `interval`, and right after new orders are created,
+    /// until [Self::stop_background_sync] is called or the module is dropped.
+    ///
+    /// Only one background sync runs at a time, starting another stops the
+    /// previous one. After a failed sync the delay doubles with each
+    /// consecutive failure, up to [MAX_BACKGROUND_SYNC_BACKOFF], and new
+    /// orders do not trigger a sync until one succeeds.
+    ///
+    /// Fails with [PredictionMarketsClientError::ZeroSyncInterval] if
+    /// `interval` is zero.
+    pub async fn start_background_sync(
+        &self,
+        interval: Duration,
+        filter: OrderFilter,
+    ) -> anyhow::Result<()> {
+        if interval.is_zero() {
+            bail!(PredictionMarketsClientError::ZeroSyncInterval)
+        }
+
+        _ = self.stop_background_sync().await;
+
+        let db = self.db.clone();
+        let module_api = self.module_api.clone();
+        let root_secret = self.root_secret.clone();
+        let federation_call_policy = self.federation_call_policy;
+        let module_alive = Arc::downgrade(&self.module_alive);
+        let mut new_order_reciever = self.new_order_broadcast.0.subscribe();
+        let (stop_tx, mut stop_rx) = stop_signal::new();
+
+        spawn("background_sync", async move {
+            let mut consecutive_failures = 0;
+            loop {
+                let delay = match consecutive_failures {
+                    0 => interval,
+                    n => interval
+                        .saturating_mul(2u32.saturating_pow(n))
+                        .min(MAX_BACKGROUND_SYNC_BACKOFF),
+                };
+                select! {
+                    _ = stop_rx.0.recv() => {
+                        return;
+                    }
+                    _ = new_order_reciever.recv(), if consecutive_failures == 0 => {}
+                    _ = sleep(delay) => {}
+                }
+                if module_alive.strong_count() == 0 {
+                    return;
+                }
+
+                let orders_to_sync = {
+                    let mut dbtx = db.begin_transaction_nc().await;
+                    let mut orders_to_sync = Vec::new();
+                    for order_id in Self::get_order_ids(&mut dbtx, filter).await {
+                        let order = dbtx
+                            .get_value(&db::OrderKey(order_id))
+                            .await
+                            .and_then(|slot| slot.to_order());
+                        if order.as_ref().is_some_and(Self::order_can_change) {
+                            orders_to_sync.push(order_id);
+                        }
+                    }
+                    orders_to_sync
+                };
+                match Self::sync_orders_from_federation_concurrent(
+                    root_secret.clone(),
+                    module_api.clone(),
+                    federation_call_policy,
+                    db.clone(),
+                    orders_to_sync,
+                )
+                .await
+                {
+                    Ok(()) => consecutive_failures = 0,
+                    Err(e) => {
+                        consecutive_failures += 1;
+                        warn!("background sync failed {consecutive_failures} times in a row: {e}");
+                    }
+                }
+            }
+        });
+
+        *self.background_sync_stop.lock().unwrap() = Some(stop_tx);
+
+        Ok(())
+    }
+
+    /// Stops the sync started by [Self::start_background_sync], if any.
+    pub async fn stop_background_sync(&self) -> anyhow::Result<()> {
+        let Some(stop_tx) = self.background_sync_stop.lock().unwrap().take() else {
+            return Ok(());
+        };
+
+        stop_tx.wait_close().await
+    }
+
+    /// Scans for all orders that the client owns.
+    pub async fn resync_order_slots(&self, gap_size_to_check: usize) -> anyhow::Result<()> {
+        let mut progress = self.resync_order_slots_streamed(gap_size_to_check);
+        while let Some(res) = progress.next().await {
+            res?;
+        }
+
         Ok(())
     }
 
+    /// [Self::resync_order_slots], yielding progress after each order id
+    /// scanned so that a long recovery can be shown to the user. The last
+    /// item has `complete` set. The stream ends after the first error.
+    pub fn resync_order_slots_streamed(
+        &self,
+        gap_size_to_check: usize,
+    ) -> BoxStream<'_, anyhow::Result<ResyncProgress>> {
+        Box::pin(stream! {
+            let mut progress = ResyncProgress {
+                scanned: 0,
+                found: 0,
+                current_id: OrderId(0),
+                highest_order_id: None,
+                complete: false,
+            };
+            let mut slots_without_order = 0;
+            loop {
+                match self.get_order(progress.current_id, false).await {
+                    Ok(Some(_)) => {
+                        progress.found += 1;
+                        progress.highest_order_id = Some(progress.current_id);
+                        slots_without_order = 0;
+                    }
+                    Ok(None) => slots_without_order += 1,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+                progress.scanned += 1;
+                progress.complete = slots_without_order >= gap_size_to_check;
+
+                yield Ok(progress.clone());
+                if progress.complete {
+                    return;
+                }
+                progress.current_id.0 += 1;
+            }
+        })
+    }
+
+    /// Every cached order and the highest order id used, for
+    /// [Self::import_order_cache] on another client restored from the same
+    /// root secret.
+    pub async fn export_order_cache(&self) -> Vec<u8> {
+        let export = Self::order_cache_export(
+            &mut self.db.begin_transaction_nc().await,
+            OrderId(0).owner_public_key(self.root_secret.clone()),
+        )
+        .await;
+
+        let mut bytes = Vec::new();
+        export
+            .consensus_encode(&mut bytes)
+            .expect("writing to a vec can not fail");
+
+        bytes
+    }
+
+    /// Restores orders exported by [Self::export_order_cache], without
+    /// scanning the federation like [Self::resync_order_slots]. Order ids up
+    /// to the exported highest order id are never allocated again.
+    ///
+    /// If `verify` is set, each order is fetched from the federation and its
+    /// current state is saved instead, at most
+    /// [MAX_CONCURRENT_ORDER_IMPORT_CHECKS] at a time. Fails if any of them
+    /// does not exist.
+    ///
+    /// Fails if the client already has orders, unless `force` is set. Orders
+    /// already cached are then kept as they are. Exports with a highest order
+    /// id above [MAX_IMPORTED_ORDER_ID] are rejected.
+    ///
+    /// Returns the number of orders imported.
+    pub async fn import_order_cache(
+        &self,
+        bytes: &[u8],
+        verify: bool,
+        force: bool,
+    ) -> anyhow::Result<usize> {
+        let mut reader = bytes;
+        let export =
+            OrderCacheExport::consensus_decode(&mut reader, &ModuleDecoderRegistry::default())
+                .map_err(|_| PredictionMarketsClientError::InvalidOrderCacheExport)?;
+        if !reader.is_empty() {
+            bail!(PredictionMarketsClientError::InvalidOrderCacheExport)
+        }
+        if export.first_order_owner != OrderId(0).owner_public_key(self.root_secret.clone()) {
+            bail!(PredictionMarketsClientError::OrderCacheFromOtherClient)
+        }
+        // every id up to the highest is written, and every order must be
+        // below it
+        if export
+            .highest_order_id
+            .is_some_and(|id| id.0 > MAX_IMPORTED_ORDER_ID)
+            || export
+                .orders
+                .keys()
+                .next_back()
+                .is_some_and(|id| Some(*id) > export.highest_order_id)
+        {
+            bail!(PredictionMarketsClientError::InvalidOrderCacheExport)
+        }
+
+        let orders = match verify {
+            false => export.orders,
+            true => {
+                let mut order_ids = export.orders.into_keys();
+                let request = |order_id: OrderId| async move {
+                    let order_owner = self.order_id_to_key_pair(order_id).public_key();
+                    let res = self
+                        .federation_call_policy
+                        .call(|| {
+                            self.module_api
+                                .get_order(GetOrderParams { order: order_owner })
+                        })
+                        .await;
+                    (order_id, res)
+                };
+                let mut futures = order_ids
+                    .by_ref()
+                    .take(MAX_CONCURRENT_ORDER_IMPORT_CHECKS)
+                    .map(&request)
+                    .collect::<FuturesUnordered<_>>();
+
+                let mut orders = BTreeMap::new();
+                while let Some((order_id, res)) = futures.next().await {
+                    let Some(order) = res?.order else {
+                        bail!("order {} does not exist on the federation", order_id.0)
+                    };
+                    orders.insert(order_id, order);
+
+                    if let Some(next_order_id) = order_ids.next() {
+                        futures.push(request(next_order_id));
+                    }
+                }
+
+                orders
+            }
+        };
+
+        let mut dbtx = self.db.begin_transaction().await;
+        let imported = Self::save_order_cache_export(
+            &mut dbtx.to_ref_nc(),
+            orders,
+            export.highest_order_id,
+            force,
+        )
+        .await?;
+        dbtx.commit_tx_result().await?;
+
+        Ok(imported)
+    }
+
     /// get most recent candlesticks
+    ///
+    /// Candlesticks with inconsistent prices are repaired or rejected with
+    /// [PredictionMarketsClientError::CorruptCandlestick], see
+    /// [CandlestickValidation]. The same goes for [Self::wait_candlesticks].
     pub async fn get_candlesticks(
         &self,
         market: OutPoint,
@@ -919,19 +2989,168 @@ impl PredictionMarketsClientModule {
         candlestick_interval: Seconds,
         min_candlestick_timestamp: UnixTimestamp,
     ) -> anyhow::Result<BTreeMap<UnixTimestamp, Candlestick>> {
+        self.check_candlesticks_supported()?;
+        self.check_candlestick_interval(candlestick_interval)?;
+        self.check_outcome(market, outcome).await?;
+
         let GetMarketOutcomeCandlesticksResult { candlesticks } = self
-            .module_api
-            .get_market_outcome_candlesticks(GetMarketOutcomeCandlesticksParams {
+            .federation_call_policy
+            .call(|| {
+                self.module_api.get_market_outcome_candlesticks(
+                    GetMarketOutcomeCandlesticksParams {
+                        market,
+                        outcome,
+                        candlestick_interval,
+                        min_candlestick_timestamp,
+                    },
+                )
+            })
+            .await?;
+
+        self.validate_candlesticks(candlesticks)
+    }
+
+    /// Like [Self::get_candlesticks] but returns one entry per candlestick
+    /// interval between the first and last candlestick, see
+    /// [fill_candlestick_gaps].
+    pub async fn get_candlesticks_filled(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        candlestick_interval: Seconds,
+        min_candlestick_timestamp: UnixTimestamp,
+        fill: FillMode,
+    ) -> anyhow::Result<Vec<(UnixTimestamp, Option<Candlestick>)>> {
+        let candlesticks = self
+            .get_candlesticks(
                 market,
                 outcome,
                 candlestick_interval,
                 min_candlestick_timestamp,
-            })
+            )
+            .await?;
+
+        Ok(fill_candlestick_gaps(
+            &candlesticks,
+            candlestick_interval,
+            fill,
+        ))
+    }
+
+    /// Like [Self::get_candlesticks] but with an approximate trade count for
+    /// each candlestick. The federation only reports volume, so the count is
+    /// derived from the candlesticks at the smallest configured interval, see
+    /// [approximate_trade_counts]. `volume` remains the authoritative measure.
+    pub async fn get_candlesticks_with_trade_count(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        candlestick_interval: Seconds,
+        min_candlestick_timestamp: UnixTimestamp,
+    ) -> anyhow::Result<BTreeMap<UnixTimestamp, CandlestickWithTradeCount>> {
+        let Some(fine_candlestick_interval) =
+            self.cfg.gc.candlestick_intervals.iter().min().copied()
+        else {
+            bail!(PredictionMarketsClientError::CandlesticksUnsupported)
+        };
+
+        let candlesticks = self
+            .get_candlesticks(
+                market,
+                outcome,
+                candlestick_interval,
+                min_candlestick_timestamp,
+            )
             .await?;
 
-        let candlesticks = candlesticks.into_iter().collect::<BTreeMap<_, _>>();
+        // the federation only keeps a limited number of candlesticks per interval
+        let fine_coverage_start = UnixTimestamp(
+            UnixTimestamp::now()
+                .round_down(fine_candlestick_interval)
+                .0
+                .saturating_sub(
+                    fine_candlestick_interval
+                        * self
+                            .cfg
+                            .gc
+                            .max_candlesticks_kept_per_market_outcome_interval,
+                ),
+        );
+        let fine_candlesticks = match fine_candlestick_interval == candlestick_interval {
+            true => candlesticks.clone(),
+            false => {
+                self.get_candlesticks(
+                    market,
+                    outcome,
+                    fine_candlestick_interval,
+                    min_candlestick_timestamp.max(fine_coverage_start),
+                )
+                .await?
+            }
+        };
+
+        Ok(approximate_trade_counts(
+            &candlesticks,
+            candlestick_interval,
+            &fine_candlesticks,
+            fine_coverage_start,
+        ))
+    }
+
+    /// Gets candlesticks for every outcome of a market. Requests are made
+    /// concurrently, at most [MAX_CONCURRENT_CANDLESTICK_REQUESTS] at a time.
+    pub async fn get_market_candlesticks(
+        &self,
+        market: OutPoint,
+        candlestick_interval: Seconds,
+        min_candlestick_timestamp: UnixTimestamp,
+    ) -> anyhow::Result<BTreeMap<Outcome, BTreeMap<UnixTimestamp, Candlestick>>> {
+        let Market(market_static, _) = self.ensure_market_exists(market).await?;
+        let mut outcomes = 0..market_static.event()?.outcome_count;
+
+        let request = |outcome: Outcome| async move {
+            let res = self
+                .get_candlesticks(
+                    market,
+                    outcome,
+                    candlestick_interval,
+                    min_candlestick_timestamp,
+                )
+                .await;
+            (outcome, res)
+        };
+        let mut futures = outcomes
+            .by_ref()
+            .take(MAX_CONCURRENT_CANDLESTICK_REQUESTS)
+            .map(&request)
+            .collect::<FuturesUnordered<_>>();
+
+        let mut market_candlesticks = BTreeMap::new();
+        while let Some((outcome, res)) = futures.next().await {
+            market_candlesticks.insert(outcome, res?);
+
+            if let Some(next_outcome) = outcomes.next() {
+                futures.push(request(next_outcome));
+            }
+        }
+
+        Ok(market_candlesticks)
+    }
+
+    /// Implied probability of every outcome of a market over time, from the
+    /// close prices of [Self::get_market_candlesticks] normalized to sum to
+    /// 1.0 at each timestamp, see [normalize_candlestick_closes].
+    pub async fn get_market_candlesticks_normalized(
+        &self,
+        market: OutPoint,
+        candlestick_interval: Seconds,
+        min_candlestick_timestamp: UnixTimestamp,
+    ) -> anyhow::Result<BTreeMap<UnixTimestamp, Vec<f64>>> {
+        let market_candlesticks = self
+            .get_market_candlesticks(market, candlestick_interval, min_candlestick_timestamp)
+            .await?;
 
-        Ok(candlesticks)
+        Ok(normalize_candlestick_closes(&market_candlesticks))
     }
 
     /// wait for new candlesticks
@@ -943,6 +3162,10 @@ impl PredictionMarketsClientModule {
         candlestick_timestamp: UnixTimestamp,
         candlestick_volume: ContractOfOutcomeAmount,
     ) -> anyhow::Result<BTreeMap<UnixTimestamp, Candlestick>> {
+        self.check_candlesticks_supported()?;
+        self.check_candlestick_interval(candlestick_interval)?;
+        self.check_outcome(market, outcome).await?;
+
         let WaitMarketOutcomeCandlesticksResult { candlesticks } = self
             .module_api
             .wait_market_outcome_candlesticks(WaitMarketOutcomeCandlesticksParams {
@@ -954,11 +3177,13 @@ impl PredictionMarketsClientModule {
             })
             .await?;
 
-        let candlesticks = candlesticks.into_iter().collect::<BTreeMap<_, _>>();
-
-        Ok(candlesticks)
+        self.validate_candlesticks(candlesticks)
     }
 
+    /// Ends immediately if the federation does not serve candlesticks or
+    /// `candlestick_interval` is not supported, see
+    /// [Self::candlesticks_supported] and
+    /// [Self::supported_candlestick_intervals].
     pub async fn stream_candlesticks<'a>(
         &self,
         market: OutPoint,
@@ -967,6 +3192,16 @@ impl PredictionMarketsClientModule {
         min_candlestick_timestamp: UnixTimestamp,
         min_duration_between_requests: Duration,
     ) -> BoxStream<'a, Vec<(UnixTimestamp, Candlestick)>> {
+        if !self.candlesticks_supported()
+            || !self
+                .cfg
+                .gc
+                .candlestick_intervals
+                .contains(&candlestick_interval)
+        {
+            return Box::pin(futures::stream::empty());
+        }
+
         let module_api = self.module_api.clone();
 
         Box::pin(stream! {
@@ -1004,292 +3239,2756 @@ impl PredictionMarketsClientModule {
         })
     }
 
+    /// Like [Self::stream_candlesticks] for each of `candlestick_intervals`,
+    /// merged into a single stream whose items are tagged with their
+    /// interval. Each interval waits from its own newest candlestick, so
+    /// new candlesticks in one interval do not hold back the others.
+    /// Unsupported intervals are left out.
+    pub async fn stream_candlesticks_multi_interval<'a>(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        candlestick_intervals: Vec<Seconds>,
+        min_candlestick_timestamp: UnixTimestamp,
+        min_duration_between_requests: Duration,
+    ) -> BoxStream<'a, (Seconds, BTreeMap<UnixTimestamp, Candlestick>)> {
+        let candlestick_intervals: BTreeSet<_> = candlestick_intervals.into_iter().collect();
+
+        let mut streams = Vec::with_capacity(candlestick_intervals.len());
+        for candlestick_interval in candlestick_intervals {
+            let stream = self
+                .stream_candlesticks(
+                    market,
+                    outcome,
+                    candlestick_interval,
+                    min_candlestick_timestamp,
+                    min_duration_between_requests,
+                )
+                .await;
+            streams.push(stream.map(move |candlesticks| {
+                (candlestick_interval, candlesticks.into_iter().collect())
+            }));
+        }
+
+        Box::pin(futures::stream::select_all(streams))
+    }
+
     pub async fn get_order_book(
         &self,
         market: OutPoint,
         outcome: Outcome,
     ) -> anyhow::Result<OrderBookInformation> {
+        let Market(market_static, _) = self.ensure_market_exists(market).await?;
+        let contract_price = market_static.contract_price;
+        let outcome_count = market_static.event()?.outcome_count;
+        if outcome >= outcome_count {
+            bail!(PredictionMarketsClientError::OutcomeOutOfRange {
+                outcome,
+                outcomes: outcome_count,
+            })
+        }
+
         let res = self
-            .module_api
-            .get_market_outcome_order_book(GetMarketOutcomeOrderBookParams { market, outcome })
+            .federation_call_policy
+            .call(|| {
+                self.module_api
+                    .get_market_outcome_order_book(GetMarketOutcomeOrderBookParams {
+                        market,
+                        outcome,
+                    })
+            })
             .await?;
 
         Ok(OrderBookInformation {
             buys: res.buys.into_iter().collect(),
             sells: res.sells.into_iter().collect(),
+            contract_price,
+            outcome_count,
         })
     }
 
-    /// Interacts with client saved markets.
-    pub async fn save_market(&self, market: OutPoint) {
-        let mut dbtx = self.db.begin_transaction().await;
+    /// Best bid and ask of both outcomes of a market with two outcomes, see
+    /// [Self::get_order_book]. Fails with
+    /// [PredictionMarketsClientError::NotABinaryMarket] for other markets.
+    pub async fn get_binary_market_bbo(&self, market: OutPoint) -> anyhow::Result<BinaryMarketBbo> {
+        self.check_binary_market(market).await?;
+        let (_, books) = self.get_market_order_books(market).await?;
+
+        let bbo = |outcome| {
+            let book = &books[&outcome];
+            BestBidOffer {
+                bid: book.buys.keys().next_back().copied(),
+                ask: book.sells.keys().next().copied(),
+            }
+        };
 
-        dbtx.insert_entry(&db::ClientSavedMarketsKey { market }, &UnixTimestamp::now())
-            .await;
-        dbtx.commit_tx().await;
+        Ok(BinaryMarketBbo {
+            yes: bbo(YES_OUTCOME),
+            no: bbo(NO_OUTCOME),
+        })
     }
 
-    /// Interacts with client saved markets.
-    pub async fn unsave_market(&self, market: OutPoint) {
-        let mut dbtx = self.db.begin_transaction().await;
+    /// Previews filling `quantity` immediately with an order on `side` of
+    /// `outcome`, walking the order books of every outcome of the market from
+    /// the best price. Nothing is submitted and the order books may change
+    /// before an order is.
+    pub async fn quote_fill(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        quantity: ContractOfOutcomeAmount,
+    ) -> anyhow::Result<FillQuote> {
+        self.check_outcome(market, outcome).await?;
+        let (contract_price, books) = self.get_market_order_books(market).await?;
 
-        dbtx.remove_entry(&db::ClientSavedMarketsKey { market })
-            .await;
-        dbtx.commit_tx().await;
+        let matches = walk_order_books(books, contract_price, outcome, side, quantity, None, None);
+
+        Ok(fill_quote(&matches, side, quantity))
     }
 
-    /// Interacts with client saved markets.
-    ///
-    /// return is Vec<(market outpoint, saved timestamp)>
-    pub async fn get_saved_markets(&self) -> Vec<(OutPoint, UnixTimestamp)> {
-        let mut dbtx = self.db.begin_transaction().await;
+    /// Like [Self::quote_fill], but only price levels within `max_slippage`
+    /// of the best price are filled, which is what an [OrderType::Market]
+    /// order with the same parameters is submitted for. Such an order is
+    /// priced at [FillQuote::worst_price] for [FillQuote::quantity], and is
+    /// expected to pay [FillQuote::total] on average.
+    pub async fn quote_market_order(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        quantity: ContractOfOutcomeAmount,
+        max_slippage: Amount,
+    ) -> anyhow::Result<FillQuote> {
+        self.check_outcome(market, outcome).await?;
+        let (contract_price, books) = self.get_market_order_books(market).await?;
+
+        let matches = walk_order_books(
+            books,
+            contract_price,
+            outcome,
+            side,
+            quantity,
+            Some(max_slippage),
+            None,
+        );
 
-        dbtx.find_by_prefix(&db::ClientSavedMarketsPrefixAll)
-            .await
-            .map(|(k, v)| (k.market, v))
-            .collect()
-            .await
+        Ok(fill_quote(&matches, side, quantity))
     }
 
-    /// Interacts with client named payout control public keys
-    pub async fn set_name_to_payout_control(
+    /// Estimates what happens to a new limit order, without submitting
+    /// anything: how much of it matches immediately, at which prices, and how
+    /// much waits in the order book. Like [Self::quote_fill], but only price
+    /// levels at or better than `price` are matched.
+    ///
+    /// This is only an estimate from the order books at the time they are
+    /// fetched. The federation matches the order against the order books when
+    /// the order is accepted, which may have changed by then.
+    pub async fn simulate_order_match(
         &self,
-        name: String,
-        payout_control: Option<NostrPublicKeyHex>,
-    ) {
-        let mut dbtx = self.db.begin_transaction().await;
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        price: Amount,
+        quantity: ContractOfOutcomeAmount,
+    ) -> anyhow::Result<MatchSimulation> {
+        self.check_outcome(market, outcome).await?;
+        let (contract_price, books) = self.get_market_order_books(market).await?;
 
-        match payout_control {
-            Some(pk) => {
-                dbtx.insert_entry(&db::ClientNamedPayoutControlsKey { name }, &pk)
-                    .await;
-            }
-            None => {
-                dbtx.remove_entry(&db::ClientNamedPayoutControlsKey { name })
-                    .await;
+        let matches = walk_order_books(
+            books,
+            contract_price,
+            outcome,
+            side,
+            quantity,
+            None,
+            Some(price),
+        );
+        let matched_quantity = matches_quantity(&matches);
+
+        Ok(MatchSimulation {
+            matched_total: matches_total(&matches),
+            matches,
+            matched_quantity,
+            resting_quantity: quantity - matched_quantity,
+        })
+    }
+
+    /// Estimates what placing every quote in `quotes`, given as side, price
+    /// and quantity, on `outcome` needs. Buy quotes are paid for by the
+    /// primary module, see [Self::new_order_cost], and sell quotes need
+    /// contracts of the outcome, which are compared against the contracts
+    /// held by the client's cached orders of the outcome.
+    pub async fn market_making_capital_estimate(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        quotes: Vec<(Side, Amount, ContractOfOutcomeAmount)>,
+    ) -> anyhow::Result<CapitalEstimate> {
+        self.check_outcome(market, outcome).await?;
+
+        let mut bitcoin_required = Amount::ZERO;
+        let mut contracts_required = ContractOfOutcomeAmount::ZERO;
+        for (side, price, quantity) in quotes.iter().copied() {
+            let cost = self.new_order_cost(side, price, quantity)?;
+            bitcoin_required = checked_add_amounts(bitcoin_required, cost)?;
+            if side == Side::Sell {
+                contracts_required = contracts_required
+                    .0
+                    .checked_add(quantity.0)
+                    .map(ContractOfOutcomeAmount)
+                    .ok_or(PredictionMarketsClientError::AmountOverflow)?;
             }
         }
-        dbtx.commit_tx().await;
-    }
 
-    /// Interacts with client named payout control public keys
-    pub async fn get_name_to_payout_control(&self, name: String) -> Option<NostrPublicKeyHex> {
-        let mut dbtx = self.db.begin_transaction().await;
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        let mut contracts_held = ContractOfOutcomeAmount::ZERO;
+        for order_id in Self::get_order_ids(
+            &mut dbtx,
+            OrderFilter(
+                OrderPath::MarketOutcome { market, outcome },
+                OrderState::NonZeroContractOfOutcomeBalance,
+            ),
+        )
+        .await
+        {
+            if let Some(order) = dbtx
+                .get_value(&db::OrderKey(order_id))
+                .await
+                .and_then(|slot| slot.to_order())
+            {
+                contracts_held += order.contract_of_outcome_balance;
+            }
+        }
 
-        dbtx.get_value(&db::ClientNamedPayoutControlsKey { name })
-            .await
+        Ok(CapitalEstimate {
+            bitcoin_required,
+            fees: Amount::from_msats(self.cfg.gc.new_order_fee.msats * quotes.len() as u64),
+            contracts_required,
+            contracts_held,
+            additional_contracts_required: ContractOfOutcomeAmount(
+                contracts_required.0.saturating_sub(contracts_held.0),
+            ),
+        })
     }
 
-    /// Interacts with client named payout control public keys
-    pub async fn get_name_to_payout_control_map(&self) -> HashMap<String, NostrPublicKeyHex> {
-        let mut dbtx = self.db.begin_transaction().await;
+    /// Summary of trading activity for a market outcome. Candlesticks and the
+    /// order book are fetched concurrently. A market without trades or resting
+    /// orders gives zero quantities and no last trade price, as does a
+    /// federation that keeps no candlesticks, see
+    /// [Self::candlesticks_supported].
+    pub async fn get_market_outcome_stats(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+    ) -> anyhow::Result<OutcomeStats> {
+        // the largest interval covers the most history
+        let candlestick_interval = self.cfg.gc.candlestick_intervals.iter().max().copied();
+
+        let (candlesticks, order_book) = tokio::try_join!(
+            self.get_candlesticks_if_supported(market, outcome, candlestick_interval),
+            self.get_order_book(market, outcome),
+        )?;
+
+        let mut volume = ContractOfOutcomeAmount::ZERO;
+        for candlestick in candlesticks.values() {
+            volume += candlestick.volume;
+        }
 
-        dbtx.find_by_prefix(&db::ClientNamedPayoutControlsPrefixAll)
-            .await
-            .map(|(k, v)| (k.name, v))
-            .collect()
-            .await
-    }
-}
+        let mut resting_bid_quantity = ContractOfOutcomeAmount::ZERO;
+        for quantity in order_book.buys.values() {
+            resting_bid_quantity += *quantity;
+        }
 
-/// private
-impl PredictionMarketsClientModule {
-    fn order_id_to_key_pair(&self, order_id: OrderId) -> KeyPair {
-        order_id.into_key_pair(self.root_secret.clone())
+        let mut resting_ask_quantity = ContractOfOutcomeAmount::ZERO;
+        for quantity in order_book.sells.values() {
+            resting_ask_quantity += *quantity;
+        }
+
+        Ok(OutcomeStats {
+            volume,
+            resting_bid_quantity,
+            resting_ask_quantity,
+            last_trade_price: candlesticks.values().next_back().map(|c| c.close),
+        })
     }
 
-    async fn save_order_to_db(dbtx: &mut DatabaseTransaction<'_>, id: OrderId, order: &Order) {
-        dbtx.insert_entry(&db::OrderKey(id), &OrderIdSlot::Order(order.to_owned()))
-            .await;
+    /// Self-contained summary of a market for rendering a preview, such as a
+    /// card for a shared link. The market is read from the cache when
+    /// possible. The order book and candlesticks of each outcome are fetched
+    /// concurrently, for at most [MAX_CONCURRENT_MARKET_SUMMARY_OUTCOMES]
+    /// outcomes at a time.
+    pub async fn get_market_summary(&self, market: OutPoint) -> anyhow::Result<MarketSummary> {
+        let Market(market_static, market_dynamic) = self.ensure_market_exists(market).await?;
+        let event = self.get_market_event(market).await?;
+        let display_override = self.get_market_display_override(market).await;
+        let titles = match display_override
+            .as_ref()
+            .and_then(|display_override| display_override.outcome_titles.clone())
+        {
+            Some(titles) => Some(Arc::new(titles)),
+            None => self.get_outcome_titles(market).await?,
+        };
+        let display_hints = market_static.outcome_display_hints(event.outcome_count);
+        // the largest interval covers the most history
+        let candlestick_interval = self.cfg.gc.candlestick_intervals.iter().max().copied();
+
+        let mut outcomes = 0..event.outcome_count;
+        let request = |outcome: Outcome| async move {
+            let res = tokio::try_join!(
+                self.get_candlesticks_if_supported(market, outcome, candlestick_interval),
+                self.get_order_book(market, outcome),
+            );
+            (outcome, res)
+        };
+        let mut futures = outcomes
+            .by_ref()
+            .take(MAX_CONCURRENT_MARKET_SUMMARY_OUTCOMES)
+            .map(&request)
+            .collect::<FuturesUnordered<_>>();
 
-        dbtx.insert_entry(
-            &db::OrdersByMarketOutcomeKey {
-                market: order.market,
-                outcome: order.outcome,
-                side: order.side,
-                order: id,
-            },
-            &(),
-        )
-        .await;
+        let mut outcome_summaries = BTreeMap::new();
+        while let Some((outcome, res)) = futures.next().await {
+            let (candlesticks, order_book) = res?;
+            let mut volume = ContractOfOutcomeAmount::ZERO;
+            for candlestick in candlesticks.values() {
+                volume += candlestick.volume;
+            }
+            outcome_summaries.insert(
+                outcome,
+                OutcomeSummary {
+                    title: titles
+                        .as_ref()
+                        .and_then(|titles| titles.get(usize::from(outcome)).cloned()),
+                    display_hint: display_hints.get(usize::from(outcome)).cloned(),
+                    bbo: BestBidOffer {
+                        bid: order_book.buys.keys().next_back().copied(),
+                        ask: order_book.sells.keys().next().copied(),
+                    },
+                    volume,
+                },
+            );
 
-        if order.quantity_waiting_for_match != ContractOfOutcomeAmount::ZERO {
-            dbtx.insert_entry(&db::OrderPriceTimePriorityKey::from_order(order), &id)
-                .await;
-        } else {
-            dbtx.remove_entry(&db::OrderPriceTimePriorityKey::from_order(order))
-                .await;
+            if let Some(next_outcome) = outcomes.next() {
+                futures.push(request(next_outcome));
+            }
         }
 
-        if order.contract_of_outcome_balance != ContractOfOutcomeAmount::ZERO {
-            dbtx.insert_entry(
-                &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSideKey {
-                    market: order.market,
-                    outcome: order.outcome,
-                    side: order.side,
-                    order: id,
-                },
-                &(),
-            )
-            .await;
-        } else {
-            dbtx.remove_entry(
-                &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSideKey {
-                    market: order.market,
-                    outcome: order.outcome,
-                    side: order.side,
-                    order: id,
-                },
-            )
-            .await;
+        let mut volume = ContractOfOutcomeAmount::ZERO;
+        for outcome_summary in outcome_summaries.values() {
+            volume += outcome_summary.volume;
         }
 
-        if order.bitcoin_balance != Amount::ZERO {
-            dbtx.insert_entry(
-                &db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSideKey {
-                    market: order.market,
-                    outcome: order.outcome,
-                    side: order.side,
-                    order: id,
-                },
-                &(),
-            )
-            .await;
-        } else {
-            dbtx.remove_entry(&db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSideKey {
-                market: order.market,
-                outcome: order.outcome,
-                side: order.side,
-                order: id,
-            })
-            .await;
-        }
+        Ok(MarketSummary {
+            market,
+            information: event.information.clone(),
+            display_override,
+            contract_price: market_static.contract_price,
+            status: match market_dynamic.payout {
+                Some(_) => MarketStatus::PaidOut,
+                None => MarketStatus::Open,
+            },
+            outcomes: outcome_summaries.into_values().collect(),
+            volume,
+        })
     }
 
-    async fn sync_orders_from_federation_concurrent(
-        root_secret: DerivableSecret,
-        module_api: DynModuleApi,
-        db: Database,
-        ids: Vec<OrderId>,
-    ) -> anyhow::Result<()> {
-        let mut futures = ids
-            .into_iter()
-            .map(|order_id| {
-                let root_secret = root_secret.clone();
-                let module_api = module_api.clone();
-                async move {
-                    let order_owner = order_id.into_key_pair(root_secret).public_key();
+    /// Opens a [ClientSnapshot] for reading the local cache from a single
+    /// consistent point in time.
+    pub async fn snapshot(&self) -> ClientSnapshot<'_> {
+        ClientSnapshot::new(self, self.db.begin_transaction_nc().await)
+    }
 
-                    (
-                        order_id,
-                        module_api
-                            .get_order(GetOrderParams { order: order_owner })
-                            .await,
-                    )
+    /// Returns whether the transaction submitted by the operation was
+    /// accepted or rejected, including the federation's reason for a
+    /// rejection. Returns `None` if the outcome is not known yet.
+    pub async fn get_operation_outcome(
+        &self,
+        operation_id: OperationId,
+    ) -> Option<OperationOutcome> {
+        let mut dbtx = self.db.begin_transaction_nc().await;
+
+        dbtx.get_value(&db::OperationOutcomeKey(operation_id)).await
+    }
+
+    /// Status of an operation started by this module. Returns `None` if the
+    /// operation does not exist or belongs to another module.
+    ///
+    /// Read from the client database, so it also reflects operations started
+    /// before a restart.
+    pub async fn get_operation_status(
+        &self,
+        operation_id: OperationId,
+    ) -> Option<PredictionMarketOperationStatus> {
+        if self
+            .db
+            .begin_transaction_nc()
+            .await
+            .get_value(&db::AbandonedOperationKey(operation_id))
+            .await
+            .is_some()
+        {
+            return Some(PredictionMarketOperationStatus::Abandoned);
+        }
+
+        if let Some(outcome) = self.get_operation_outcome(operation_id).await {
+            return Some(match outcome {
+                OperationOutcome::Accepted => PredictionMarketOperationStatus::Accepted,
+                OperationOutcome::Rejected { reason } => {
+                    PredictionMarketOperationStatus::Failed { reason }
                 }
-            })
-            .collect::<FuturesUnordered<_>>();
+            });
+        }
 
-        let mut dbtx = db.begin_transaction().await;
-        while let Some((order_id, res)) = futures.next().await {
-            if let Some(order) = res?.order {
-                PredictionMarketsClientModule::save_order_to_db(
-                    &mut dbtx.to_ref_nc(),
-                    order_id,
-                    &order,
-                )
-                .await;
-            }
+        let operation = self.ctx.get_operation(operation_id).await.ok()?;
+        if operation.operation_module_kind() != PredictionMarketsCommonInit::KIND.as_str() {
+            return None;
+        }
+
+        Some(PredictionMarketOperationStatus::Pending)
+    }
+
+    /// Stops waiting for the federation to accept an operation's transaction.
+    /// Calls still waiting on it, such as [Self::new_order], fail with
+    /// [PredictionMarketsClientError::OperationAbandoned] and its status
+    /// becomes [PredictionMarketOperationStatus::Abandoned].
+    ///
+    /// This does not cancel the transaction. The federation may still accept
+    /// it, in which case its effects, such as a new order, show up like for
+    /// any other operation and [Self::get_operation_outcome] reports what the
+    /// federation decided.
+    pub async fn abandon_operation(&self, operation_id: OperationId) -> anyhow::Result<()> {
+        if self.get_operation_status(operation_id).await.is_none() {
+            bail!(PredictionMarketsClientError::OperationDoesNotExist)
         }
+
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.insert_entry(
+            &db::AbandonedOperationKey(operation_id),
+            &UnixTimestamp::now(),
+        )
+        .await;
         dbtx.commit_tx_result().await?;
 
         Ok(())
     }
 
-    async fn sync_orders_from_federation_concurrent_with_self(
-        &self,
-        ids: Vec<OrderId>,
-    ) -> anyhow::Result<()> {
-        Self::sync_orders_from_federation_concurrent(
-            self.root_secret.clone(),
-            self.module_api.clone(),
-            self.db.clone(),
-            ids,
+    /// The federation's current time as agreed on by the guardians. Use this
+    /// instead of the local clock for anything compared with consensus
+    /// timestamps, such as when a market was created.
+    ///
+    /// Kept in memory for [CONSENSUS_TIMESTAMP_CACHE_DURATION]. Never goes
+    /// backwards while the module is running.
+    pub async fn get_consensus_timestamp(&self) -> anyhow::Result<UnixTimestamp> {
+        let cached = *self.consensus_timestamp_cache.lock().unwrap();
+        if let Some((fetched_at, consensus_timestamp)) = cached {
+            if fetched_at.elapsed() < CONSENSUS_TIMESTAMP_CACHE_DURATION {
+                return Ok(consensus_timestamp);
+            }
+        }
+
+        let GetConsensusTimestampResult {
+            consensus_timestamp,
+        } = self
+            .federation_call_policy
+            .call(|| self.module_api.get_consensus_timestamp())
+            .await?;
+
+        // guardians answering at different points of consensus can disagree
+        let mut cache = self.consensus_timestamp_cache.lock().unwrap();
+        let consensus_timestamp = match *cache {
+            Some((_, cached)) => consensus_timestamp.max(cached),
+            None => consensus_timestamp,
+        };
+        *cache = Some((Instant::now(), consensus_timestamp));
+
+        Ok(consensus_timestamp)
+    }
+
+    /// Summary of how fresh the local cache is. Asks the federation for a
+    /// single order to check that it is reachable, waiting at most
+    /// [HEALTH_CHECK_TIMEOUT] without retrying.
+    pub async fn health_check(&self) -> anyhow::Result<ClientHealth> {
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        let next_order_id = Self::next_order_id(&mut dbtx).await;
+        let orders_waiting_for_match = Self::get_order_ids(
+            &mut dbtx,
+            OrderFilter(OrderPath::All, OrderState::NonZeroQuantityWaitingForMatch),
         )
         .await
+        .len();
+        let last_order_sync = dbtx.get_value(&db::LastOrderSyncKey).await;
+        drop(dbtx);
+
+        let ping = self.module_api.get_order(GetOrderParams {
+            order: self.order_id_to_key_pair(next_order_id).public_key(),
+        });
+        let federation_reachable = matches!(timeout(HEALTH_CHECK_TIMEOUT, ping).await, Ok(Ok(_)));
+
+        Ok(ClientHealth {
+            federation_reachable,
+            last_order_sync,
+            orders_waiting_for_match,
+            highest_order_id: next_order_id.0.checked_sub(1).map(OrderId),
+        })
     }
 
-    async fn await_accepted(
+    /// Orders whose new order transaction the federation rejected, but that
+    /// the federation has an order for anyway, with the rejection reason.
+    ///
+    /// Every rejected order is looked up on the federation. Normally it does
+    /// not exist, as a rejected transaction moves no funds. If it does, the
+    /// order id was used before, such as by a client restored without its
+    /// database. The order is then cached like any other order, so its funds
+    /// can be recovered with [Self::cancel_order] and
+    /// [Self::send_order_bitcoin_balance_to_primary_module].
+    pub async fn get_rejected_order_discrepancies(&self) -> BTreeMap<OrderId, String> {
+        self.db
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix(&db::RejectedOrderDiscrepancyPrefixAll)
+            .await
+            .map(|(k, reason)| (k.0, reason))
+            .collect()
+            .await
+    }
+
+    /// Removes an entry of [Self::get_rejected_order_discrepancies] once it
+    /// has been dealt with.
+    pub async fn dismiss_rejected_order_discrepancy(
         &self,
-        operation_id: OperationId,
-        tx_id: TransactionId,
+        order_id: OrderId,
     ) -> anyhow::Result<()> {
-        let tx_subscription = self.ctx.transaction_updates(operation_id).await;
-        tx_subscription
-            .await_tx_accepted(tx_id)
-            .await
-            .map_err(|e| anyhow!(e))?;
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.remove_entry(&db::RejectedOrderDiscrepancyKey(order_id))
+            .await;
+        dbtx.commit_tx_result().await?;
 
         Ok(())
     }
 
-    async fn await_state(
+    /// Transactions submitted by the module at or after `since`, oldest
+    /// first.
+    ///
+    /// Entries are written just before a transaction is submitted, so an
+    /// entry can exist for a transaction the federation never saw. Use
+    /// [Self::get_operation_status] to tell.
+    pub async fn get_submitted_transactions(
         &self,
-        operation_id: OperationId,
-        state_matcher: impl Fn(PredictionMarketState) -> bool,
-    ) {
-        let mut state_stream = self.notifier.subscribe(operation_id).await;
-        while let Some(PredictionMarketsStateMachine {
-            operation_id: _,
-            state,
-        }) = state_stream.next().await
-        {
-            if state_matcher(state) {
-                break;
-            }
-        }
+        since: UnixTimestamp,
+    ) -> Vec<(OperationId, SubmittedTransaction)> {
+        self.db
+            .begin_transaction_nc()
+            .await
+            .find_by_range(
+                db::SubmittedTransactionKey {
+                    submitted_at: since,
+                    operation_id: OperationId([0; 32]),
+                }..db::SubmittedTransactionKey {
+                    submitted_at: UnixTimestamp(u64::MAX),
+                    operation_id: OperationId([0; 32]),
+                },
+            )
+            .await
+            .map(|(key, transaction)| (key.operation_id, transaction))
+            .collect()
+            .await
     }
 
-    pub async fn stream_order_from_db_internal<'a>(
-        db: Database,
-        id: OrderId,
-    ) -> BoxStream<'a, Option<Order>> {
-        Box::pin(stream! {
-            let mut previous_entry: Option<OrderIdSlot> = None;
-            loop {
-                let new_entry = db
-                    .wait_key_check(&db::OrderKey(id), |db_value| { if db_value != previous_entry {
-                        Some(db_value)
-                    } else {
-                        None
-                    }})
-                    .await
-                    .0;
+    /// Interacts with client saved markets.
+    ///
+    /// Saving an already saved market keeps its note and tags, and its
+    /// `saved_at` unless `update_timestamp` is set. Returns whether the market
+    /// was newly saved.
+    pub async fn save_market(&self, market: OutPoint, update_timestamp: bool) -> bool {
+        let mut dbtx = self.db.begin_transaction().await;
 
-                yield new_entry.clone().map(|s| s.to_order()).flatten();
+        let previous = dbtx.get_value(&db::ClientSavedMarketsKey { market }).await;
+        let meta = match previous.clone() {
+            Some(meta) if !update_timestamp => meta,
+            Some(meta) => SavedMarketMeta {
+                saved_at: UnixTimestamp::now(),
+                ..meta
+            },
+            None => SavedMarketMeta {
+                saved_at: UnixTimestamp::now(),
+                note: None,
+                tags: Vec::new(),
+            },
+        };
+        dbtx.insert_entry(&db::ClientSavedMarketsKey { market }, &meta)
+            .await;
+        dbtx.commit_tx().await;
 
-                previous_entry = new_entry;
-            }
-        })
+        previous.is_none()
     }
 
-    async fn get_order_ids<'a>(
-        dbtx: &mut DatabaseTransaction<'a>,
-        filter: OrderFilter,
-    ) -> BTreeSet<OrderId> {
-        match filter.1 {
-            OrderState::Any => {
-                match filter.0 {
-                    OrderPath::All => {
-                        dbtx.find_by_prefix(&db::OrdersByMarketOutcomePrefixAll)
-                            .await
-                    }
-                    OrderPath::Market { market } => {
+    /// Interacts with client saved markets.
+    ///
+    /// Saving an already saved market replaces its note and tags. Its
+    /// `saved_at` is kept, so lists ordered by save time stay stable, unless
+    /// `update_timestamp` is set. Returns whether the market was newly saved.
+    pub async fn save_market_with_note(
+        &self,
+        market: OutPoint,
+        note: Option<String>,
+        tags: Vec<String>,
+        update_timestamp: bool,
+    ) -> bool {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        let previous = dbtx.get_value(&db::ClientSavedMarketsKey { market }).await;
+        let saved_at = match &previous {
+            Some(meta) if !update_timestamp => meta.saved_at,
+            _ => UnixTimestamp::now(),
+        };
+        dbtx.insert_entry(
+            &db::ClientSavedMarketsKey { market },
+            &SavedMarketMeta {
+                saved_at,
+                note,
+                tags,
+            },
+        )
+        .await;
+        dbtx.commit_tx().await;
+
+        previous.is_none()
+    }
+
+    /// Interacts with client saved markets.
+    pub async fn update_saved_market_note(
+        &self,
+        market: OutPoint,
+        note: Option<String>,
+    ) -> anyhow::Result<()> {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        let Some(mut meta) = dbtx.get_value(&db::ClientSavedMarketsKey { market }).await else {
+            bail!("market is not saved")
+        };
+        meta.note = note;
+        dbtx.insert_entry(&db::ClientSavedMarketsKey { market }, &meta)
+            .await;
+        dbtx.commit_tx_result().await?;
+
+        Ok(())
+    }
+
+    /// Replaces how this client displays a market, such as to fix a typo in
+    /// its title. Only [Self::get_market_summary] uses the override, the
+    /// event stays unchanged for hashing and payouts and other clients still
+    /// see the original. Replaces any previous override of the market.
+    ///
+    /// Fails with [PredictionMarketsClientError::MarketAlreadyTraded] once
+    /// the market has matched contracts on the federation, since traders
+    /// may have relied on the original text.
+    pub async fn set_market_display_override(
+        &self,
+        market: OutPoint,
+        display_override: MarketDisplayOverride,
+    ) -> anyhow::Result<()> {
+        let Some(Market(_, market_dynamic)) = self.get_market(market, false).await? else {
+            bail!(PredictionMarketsClientError::MarketDoesNotExist)
+        };
+        if market_dynamic.open_contracts != ContractAmount::ZERO {
+            bail!(PredictionMarketsClientError::MarketAlreadyTraded)
+        }
+
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.insert_entry(&db::MarketDisplayOverrideKey(market), &display_override)
+            .await;
+        dbtx.commit_tx_result().await?;
+
+        Ok(())
+    }
+
+    /// Override set by [Self::set_market_display_override], if any.
+    pub async fn get_market_display_override(
+        &self,
+        market: OutPoint,
+    ) -> Option<MarketDisplayOverride> {
+        self.db
+            .begin_transaction_nc()
+            .await
+            .get_value(&db::MarketDisplayOverrideKey(market))
+            .await
+    }
+
+    /// Removes the override set by [Self::set_market_display_override].
+    pub async fn clear_market_display_override(&self, market: OutPoint) {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        dbtx.remove_entry(&db::MarketDisplayOverrideKey(market))
+            .await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Saves a template for [Self::new_market_from_template] under `name`,
+    /// replacing any previous template with that name.
+    pub async fn save_market_template(&self, name: String, template: MarketTemplate) {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        dbtx.insert_entry(&db::MarketTemplateKey { name }, &template)
+            .await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Templates saved with [Self::save_market_template], by name.
+    pub async fn get_market_templates(&self) -> BTreeMap<String, MarketTemplate> {
+        self.db
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix(&db::MarketTemplatePrefixAll)
+            .await
+            .map(|(k, v)| (k.name, v))
+            .collect()
+            .await
+    }
+
+    /// Removes a template saved with [Self::save_market_template].
+    pub async fn remove_market_template(&self, name: String) {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        dbtx.remove_entry(&db::MarketTemplateKey { name }).await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Creates a market from the template saved as `name`, replacing each
+    /// `{placeholder}` in its event with the substitution of the same name.
+    /// Every market gets a new event nonce, so one template can create many
+    /// markets even with the same substitutions.
+    ///
+    /// Fails with [PredictionMarketsClientError::UnknownMarketTemplate] if no
+    /// template is saved as `name` and with
+    /// [PredictionMarketsClientError::MissingTemplateSubstitution] before
+    /// anything is submitted if a placeholder has no substitution.
+    pub async fn new_market_from_template(
+        &self,
+        name: &str,
+        substitutions: BTreeMap<String, String>,
+    ) -> anyhow::Result<OutPoint> {
+        let Some(template) = self
+            .db
+            .begin_transaction_nc()
+            .await
+            .get_value(&db::MarketTemplateKey {
+                name: name.to_owned(),
+            })
+            .await
+        else {
+            bail!(PredictionMarketsClientError::UnknownMarketTemplate(
+                name.to_owned()
+            ))
+        };
+
+        let event_json = fill_market_template(&template.event_json, &substitutions)?;
+        let event = Event::try_from_json_str(&event_json)?;
+        let event_json = Event::new_with_random_nonce(
+            event.outcome_count,
+            event.units_to_payout,
+            event.information,
+        )
+        .try_to_json_string()?;
+
+        self.new_market(
+            event_json,
+            template.contract_price,
+            template.payout_control_weight_map,
+            template.weight_required_for_payout,
+            None,
+        )
+        .await
+    }
+
+    /// Interacts with client saved markets.
+    pub async fn unsave_market(&self, market: OutPoint) {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        dbtx.remove_entry(&db::ClientSavedMarketsKey { market })
+            .await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Interacts with client saved markets.
+    ///
+    /// return is Vec<(market outpoint, saved market meta)>
+    pub async fn get_saved_markets(&self) -> Vec<(OutPoint, SavedMarketMeta)> {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        dbtx.find_by_prefix(&db::ClientSavedMarketsPrefixAll)
+            .await
+            .map(|(k, v)| (k.market, v))
+            .collect()
+            .await
+    }
+
+    /// The market's event, parsed from the event json stored in the market.
+    /// Gives the outcome count, units to payout and information of the event
+    /// without looking it up on nostr.
+    ///
+    /// Parsed events are kept in memory after the first lookup of a market,
+    /// for up to [MAX_CACHED_MARKETS] markets.
+    pub async fn get_market_event(&self, market: OutPoint) -> anyhow::Result<Arc<Event>> {
+        let cached = self
+            .market_events_cache
+            .lock()
+            .unwrap()
+            .get(&market)
+            .cloned();
+        if let Some(event) = cached {
+            return Ok(event);
+        }
+
+        let Market(market_static, _) = self.ensure_market_exists(market).await?;
+        let event = Arc::new(market_static.event()?);
+        insert_into_bounded_cache(
+            &mut self.market_events_cache.lock().unwrap(),
+            market,
+            event.clone(),
+        );
+
+        Ok(event)
+    }
+
+    /// Title of `outcome` from the market's event information. Returns `None`
+    /// if the information has no outcome titles or `outcome` is out of range.
+    ///
+    /// Titles are kept in memory after the first lookup of a market, for up to
+    /// [MAX_CACHED_MARKETS] markets.
+    pub async fn get_outcome_title(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+    ) -> anyhow::Result<Option<String>> {
+        let titles = self.get_outcome_titles(market).await?;
+
+        Ok(titles.and_then(|titles| titles.get(usize::from(outcome)).cloned()))
+    }
+
+    async fn get_outcome_titles(
+        &self,
+        market: OutPoint,
+    ) -> anyhow::Result<Option<Arc<Vec<String>>>> {
+        let cached = self
+            .outcome_titles_cache
+            .lock()
+            .unwrap()
+            .get(&market)
+            .cloned();
+        let titles = match cached {
+            Some(titles) => titles,
+            None => {
+                let event = self.get_market_event(market).await?;
+                let titles = outcome_titles(&event.information).map(Arc::new);
+                insert_into_bounded_cache(
+                    &mut self.outcome_titles_cache.lock().unwrap(),
+                    market,
+                    titles.clone(),
+                );
+                titles
+            }
+        };
+
+        Ok(titles)
+    }
+
+    /// Interacts with client saved markets.
+    ///
+    /// return is Vec<(market outpoint, saved market meta)> of saved markets
+    /// tagged with `tag`
+    pub async fn get_saved_markets_by_tag(&self, tag: &str) -> Vec<(OutPoint, SavedMarketMeta)> {
+        self.get_saved_markets()
+            .await
+            .into_iter()
+            .filter(|(_, meta)| meta.tags.iter().any(|t| t == tag))
+            .collect()
+    }
+
+    /// Interacts with client named payout control public keys
+    pub async fn set_name_to_payout_control(
+        &self,
+        name: String,
+        payout_control: Option<NostrPublicKeyHex>,
+    ) {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        match payout_control {
+            Some(pk) => {
+                dbtx.insert_entry(&db::ClientNamedPayoutControlsKey { name }, &pk)
+                    .await;
+            }
+            None => {
+                dbtx.remove_entry(&db::ClientNamedPayoutControlsKey { name })
+                    .await;
+            }
+        }
+        dbtx.commit_tx().await;
+    }
+
+    /// Interacts with client named payout control public keys
+    pub async fn get_name_to_payout_control(&self, name: String) -> Option<NostrPublicKeyHex> {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        dbtx.get_value(&db::ClientNamedPayoutControlsKey { name })
+            .await
+    }
+
+    /// Interacts with client named payout control public keys
+    pub async fn get_name_to_payout_control_map(&self) -> HashMap<String, NostrPublicKeyHex> {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        dbtx.find_by_prefix(&db::ClientNamedPayoutControlsPrefixAll)
+            .await
+            .map(|(k, v)| (k.name, v))
+            .collect()
+            .await
+    }
+
+    /// Interacts with client named payout control public keys
+    ///
+    /// Returns every name to payout control assignment as a JSON object, for
+    /// [Self::import_payout_control_names].
+    pub async fn export_payout_control_names(&self) -> String {
+        let names: BTreeMap<_, _> = self
+            .get_name_to_payout_control_map()
+            .await
+            .into_iter()
+            .collect();
+
+        serde_json::to_string(&names).expect("map of strings serializes")
+    }
+
+    /// Interacts with client named payout control public keys
+    ///
+    /// Assigns the names of a JSON object created by
+    /// [Self::export_payout_control_names]. Names that are already assigned
+    /// are only replaced if `overwrite` is set. Entries whose payout control
+    /// is not a valid public key are skipped and returned. Fails without
+    /// assigning anything if `json` is not an object of strings.
+    pub async fn import_payout_control_names(
+        &self,
+        json: &str,
+        overwrite: bool,
+    ) -> anyhow::Result<PayoutControlNamesImport> {
+        let names: BTreeMap<String, NostrPublicKeyHex> = serde_json::from_str(json)?;
+
+        let mut dbtx = self.db.begin_transaction().await;
+        let mut result = PayoutControlNamesImport::default();
+        for (name, payout_control) in names {
+            if !prediction_market_event::nostr_event_types::NostrPublicKeyHex::is_valid_format(
+                &payout_control,
+            ) {
+                result.malformed.push(name);
+                continue;
+            }
+
+            let key = db::ClientNamedPayoutControlsKey { name };
+            if !overwrite && dbtx.get_value(&key).await.is_some() {
+                continue;
+            }
+            dbtx.insert_entry(&key, &payout_control).await;
+            result.imported += 1;
+        }
+        dbtx.commit_tx_result().await?;
+
+        Ok(result)
+    }
+
+    /// Interacts with client named payout control public keys
+    ///
+    /// Returns the name assigned to the payout control, or a truncated hex
+    /// of the public key if it has no name. If multiple names are assigned,
+    /// the first in lexicographic order is used.
+    pub async fn resolve_payout_control_label(&self, payout_control: &NostrPublicKeyHex) -> String {
+        self.get_payout_control_to_name_map()
+            .await
+            .remove(payout_control)
+            .unwrap_or_else(|| truncated_payout_control_label(payout_control))
+    }
+
+    /// Interacts with client named payout control public keys
+    ///
+    /// Returns the market's payout control weight map with each payout
+    /// control's label, as given by [Self::resolve_payout_control_label].
+    pub async fn get_payout_control_weight_map_named(
+        &self,
+        market: OutPoint,
+    ) -> anyhow::Result<BTreeMap<NostrPublicKeyHex, NamedPayoutControlWeight>> {
+        let market = self.ensure_market_exists(market).await?;
+
+        let mut names = self.get_payout_control_to_name_map().await;
+
+        Ok(market
+            .0
+            .payout_control_weight_map
+            .iter()
+            .map(|(pk, weight)| {
+                let label = names
+                    .remove(pk)
+                    .unwrap_or_else(|| truncated_payout_control_label(pk));
+                (
+                    pk.to_owned(),
+                    NamedPayoutControlWeight {
+                        label,
+                        weight: *weight,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Each named payout control with the first of its names in lexicographic
+    /// order.
+    async fn get_payout_control_to_name_map(&self) -> HashMap<NostrPublicKeyHex, String> {
+        let name_to_payout_control: BTreeMap<_, _> = self
+            .get_name_to_payout_control_map()
+            .await
+            .into_iter()
+            .collect();
+
+        let mut names = HashMap::new();
+        for (name, pk) in name_to_payout_control {
+            names.entry(pk).or_insert(name);
+        }
+        names
+    }
+}
+
+fn truncated_payout_control_label(payout_control: &NostrPublicKeyHex) -> String {
+    const LABEL_HEX_CHARS: usize = 16;
+
+    // counted in chars so keys that are not valid hex can not split a char
+    if payout_control.chars().count() <= LABEL_HEX_CHARS {
+        return payout_control.to_owned();
+    }
+    let label: String = payout_control.chars().take(LABEL_HEX_CHARS).collect();
+    format!("{label}...")
+}
+
+/// Markets of `cached` that [PredictionMarketsClientModule::get_markets]
+/// fetches from the federation. Finished markets never change, so markets
+/// cached with a payout are not fetched.
+fn markets_to_fetch(cached: &BTreeMap<OutPoint, Option<Market>>) -> Vec<OutPoint> {
+    cached
+        .iter()
+        .filter(|(_, cached)| !cached.as_ref().is_some_and(|m| m.1.payout.is_some()))
+        .map(|(market, _)| *market)
+        .collect()
+}
+
+/// Whether `e` is [PredictionMarketsClientError::OperationAbandoned]. The
+/// state machines of an abandoned operation may never complete, so callers
+/// must not wait for them.
+fn is_operation_abandoned(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<PredictionMarketsClientError>()
+        == Some(&PredictionMarketsClientError::OperationAbandoned)
+}
+
+/// Whether the federation rejected the transaction because an order it
+/// cancels was already finished, as happens when its market pays out.
+fn is_rejected_as_finished_order(e: &anyhow::Error) -> bool {
+    e.to_string()
+        .contains(&PredictionMarketsInputError::OrderAlreadyFinished.to_string())
+}
+
+/// Inserts into an in-memory cache holding at most [MAX_CACHED_MARKETS]
+/// entries, evicting an arbitrary one when it is full.
+fn insert_into_bounded_cache<V>(cache: &mut HashMap<OutPoint, V>, market: OutPoint, value: V) {
+    if cache.len() >= MAX_CACHED_MARKETS && !cache.contains_key(&market) {
+        if let Some(evicted) = cache.keys().next().copied() {
+            cache.remove(&evicted);
+        }
+    }
+    cache.insert(market, value);
+}
+
+/// Whether the order holds contracts of an outcome that paid out, or bitcoin
+/// from a payout that was not withdrawn yet.
+fn order_has_payout_to_claim(order: &Order, payout: &Payout) -> bool {
+    let outcome_pays_out = payout
+        .amount_per_outcome
+        .get(usize::from(order.outcome))
+        .is_some_and(|amount| *amount != Amount::ZERO);
+
+    (outcome_pays_out && order.contract_of_outcome_balance != ContractOfOutcomeAmount::ZERO)
+        || (order.bitcoin_acquired_from_payout != Amount::ZERO
+            && order.bitcoin_balance != Amount::ZERO)
+}
+
+/// `a + b`, failing with [PredictionMarketsClientError::AmountOverflow]
+/// instead of panicking.
+fn checked_add_amounts(a: Amount, b: Amount) -> Result<Amount, PredictionMarketsClientError> {
+    a.msats
+        .checked_add(b.msats)
+        .map(Amount::from_msats)
+        .ok_or(PredictionMarketsClientError::AmountOverflow)
+}
+
+/// private
+impl PredictionMarketsClientModule {
+    /// Fails with [PredictionMarketsClientError::FeeExceedsLimit] if `fee` is
+    /// above the caller's `max_fee`.
+    fn check_fee(fee: Amount, max_fee: Option<Amount>) -> anyhow::Result<()> {
+        if let Some(limit) = max_fee {
+            if fee > limit {
+                bail!(PredictionMarketsClientError::FeeExceedsLimit { fee, limit })
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn log_submitted_transaction(
+        &self,
+        operation_id: OperationId,
+        summary: SubmittedTransactionSummary,
+    ) -> anyhow::Result<()> {
+        let submitted_at = UnixTimestamp::now();
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.insert_entry(
+            &db::SubmittedTransactionKey {
+                submitted_at,
+                operation_id,
+            },
+            &SubmittedTransaction {
+                submitted_at,
+                summary,
+            },
+        )
+        .await;
+        dbtx.commit_tx_result().await?;
+
+        Ok(())
+    }
+
+    /// Fails with [PredictionMarketsClientError::RejectedByHook] if the hook
+    /// set by [Self::set_pre_order_hook] rejects `request`.
+    fn check_pre_order_hook(&self, request: &OrderRequest) -> anyhow::Result<()> {
+        // not called with the lock held so the hook can replace itself
+        let Some(hook) = self.pre_order_hook.lock().unwrap().0.clone() else {
+            return Ok(());
+        };
+
+        if let Err(reason) = hook(request) {
+            bail!(PredictionMarketsClientError::RejectedByHook(reason))
+        }
+
+        Ok(())
+    }
+
+    /// Fails if `additional_exposure` on top of the bitcoin committed to the
+    /// quantity waiting for match of cached buy orders exceeds the module's
+    /// `max_total_exposure`.
+    async fn check_exposure_limit(&self, additional_exposure: Amount) -> anyhow::Result<()> {
+        let Some(limit) = self.max_total_exposure else {
+            return Ok(());
+        };
+
+        let mut dbtx = self.db.begin_transaction_nc().await;
+        let mut would_be = additional_exposure;
+        for order_id in Self::get_order_ids(
+            &mut dbtx,
+            OrderFilter(OrderPath::All, OrderState::NonZeroQuantityWaitingForMatch),
+        )
+        .await
+        {
+            let Some(order) = dbtx
+                .get_value(&db::OrderKey(order_id))
+                .await
+                .and_then(|slot| slot.to_order())
+            else {
+                continue;
+            };
+            if order.side == Side::Buy {
+                let cost = Order::checked_cost(order.price, order.quantity_waiting_for_match)
+                    .ok_or(PredictionMarketsClientError::AmountOverflow)?;
+                would_be = checked_add_amounts(would_be, cost)?;
+            }
+        }
+
+        if would_be > limit {
+            bail!(PredictionMarketsClientError::ExposureLimitExceeded { would_be, limit })
+        }
+
+        Ok(())
+    }
+
+    fn check_trading_enabled(&self) -> anyhow::Result<()> {
+        if !self.trading_enabled() {
+            bail!(PredictionMarketsClientError::TradingDisabled)
+        }
+
+        Ok(())
+    }
+
+    /// Fails with [PredictionMarketsClientError::RateLimited] if the module
+    /// was initialized with `max_orders_per_minute` and the limit is reached.
+    fn check_order_rate_limit(&self) -> anyhow::Result<()> {
+        let Some(limiter) = &self.order_rate_limiter else {
+            return Ok(());
+        };
+
+        if let Err(retry_after) = limiter.lock().unwrap().try_acquire(Instant::now()) {
+            bail!(PredictionMarketsClientError::RateLimited { retry_after })
+        }
+
+        Ok(())
+    }
+
+    /// Gives back the token taken by [Self::check_order_rate_limit] when the
+    /// transaction could not be submitted after all.
+    fn release_order_rate_limit(&self) {
+        if let Some(limiter) = &self.order_rate_limiter {
+            limiter.lock().unwrap().release();
+        }
+    }
+
+    /// Like [Self::check_order_rate_limit], but takes a token for each of
+    /// `orders` submitted in one transaction. Either all tokens are taken or
+    /// none are.
+    fn check_order_rate_limit_for(&self, orders: usize) -> anyhow::Result<()> {
+        let Some(limiter) = &self.order_rate_limiter else {
+            return Ok(());
+        };
+
+        let mut limiter = limiter.lock().unwrap();
+        let now = Instant::now();
+        for taken in 0..orders {
+            if let Err(retry_after) = limiter.try_acquire(now) {
+                for _ in 0..taken {
+                    limiter.release();
+                }
+                bail!(PredictionMarketsClientError::RateLimited { retry_after })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gives back the tokens taken by [Self::check_order_rate_limit_for].
+    fn release_order_rate_limit_for(&self, orders: usize) {
+        if let Some(limiter) = &self.order_rate_limiter {
+            let mut limiter = limiter.lock().unwrap();
+            for _ in 0..orders {
+                limiter.release();
+            }
+        }
+    }
+
+    /// Price and quantity of a limit order that fills immediately within
+    /// `max_slippage` of the best price, see [Self::quote_market_order]. The
+    /// order is priced at the worst level it touches, the federation matches
+    /// the better levels at their own price and returns the difference to the
+    /// order's bitcoin balance.
+    async fn market_order_price_quantity(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        quantity: ContractOfOutcomeAmount,
+        max_slippage: Amount,
+    ) -> anyhow::Result<(Amount, ContractOfOutcomeAmount)> {
+        let quote = self
+            .quote_market_order(market, outcome, side, quantity, max_slippage)
+            .await?;
+        let Some(worst_price) = quote.worst_price else {
+            bail!(PredictionMarketsClientError::NoLiquidity)
+        };
+
+        Ok((worst_price, quote.quantity))
+    }
+
+    /// Fails with [PredictionMarketsClientError::WouldCrossBook] if an order
+    /// at `price` would match any price level of the current order books.
+    async fn check_post_only(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        price: Amount,
+    ) -> anyhow::Result<()> {
+        let (contract_price, books) = self.get_market_order_books(market).await?;
+
+        let matches = walk_order_books(
+            books,
+            contract_price,
+            outcome,
+            side,
+            ContractOfOutcomeAmount(1),
+            None,
+            Some(price),
+        );
+        if !matches.is_empty() {
+            bail!(PredictionMarketsClientError::WouldCrossBook)
+        }
+
+        Ok(())
+    }
+
+    /// Contract price of the market and the order book of each of its
+    /// outcomes.
+    async fn get_market_order_books(
+        &self,
+        market: OutPoint,
+    ) -> anyhow::Result<(Amount, BTreeMap<Outcome, OrderBookInformation>)> {
+        let Market(market_static, _) = self.ensure_market_exists(market).await?;
+
+        let mut books = BTreeMap::new();
+        let mut futures = (0..market_static.event()?.outcome_count)
+            .map(|outcome| async move { (outcome, self.get_order_book(market, outcome).await) })
+            .collect::<FuturesUnordered<_>>();
+        while let Some((outcome, res)) = futures.next().await {
+            books.insert(outcome, res?);
+        }
+
+        Ok((market_static.contract_price, books))
+    }
+
+    async fn available_bitcoin_balance_from_db(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> AvailableBitcoinBalance {
+        let orders_with_non_zero_bitcoin_balance = Self::get_order_ids(
+            dbtx,
+            OrderFilter(OrderPath::All, OrderState::NonZeroBitcoinBalance),
+        )
+        .await;
+
+        let mut by_order = BTreeMap::new();
+        for order_id in orders_with_non_zero_bitcoin_balance {
+            let Some(order) = dbtx
+                .get_value(&db::OrderKey(order_id))
+                .await
+                .and_then(|slot| slot.to_order())
+            else {
+                continue;
+            };
+            by_order.insert(order_id, order.bitcoin_balance);
+        }
+
+        let total = by_order
+            .values()
+            .fold(Amount::ZERO, |acc, amount| acc + *amount);
+        let fees = Amount::from_msats(
+            self.cfg.gc.consume_order_bitcoin_balance_fee.msats * by_order.len() as u64,
+        );
+
+        AvailableBitcoinBalance {
+            total,
+            fees,
+            withdrawable: total.saturating_sub(fees),
+            by_order,
+        }
+    }
+
+    /// Checks that the market has `outcome`, see [Self::get_market_event].
+    async fn check_outcome(&self, market: OutPoint, outcome: Outcome) -> anyhow::Result<()> {
+        let outcomes = self.get_market_event(market).await?.outcome_count;
+        if outcome >= outcomes {
+            bail!(PredictionMarketsClientError::OutcomeOutOfRange { outcome, outcomes })
+        }
+
+        Ok(())
+    }
+
+    async fn check_binary_market(&self, market: OutPoint) -> anyhow::Result<()> {
+        let outcomes = self.get_market_event(market).await?.outcome_count;
+        if outcomes != 2 {
+            bail!(PredictionMarketsClientError::NotABinaryMarket { outcomes })
+        }
+
+        Ok(())
+    }
+
+    /// Applies the module's [CandlestickValidation] to candlesticks from the
+    /// federation.
+    fn validate_candlesticks(
+        &self,
+        candlesticks: Vec<(UnixTimestamp, Candlestick)>,
+    ) -> anyhow::Result<BTreeMap<UnixTimestamp, Candlestick>> {
+        let mut validated = BTreeMap::new();
+        for (timestamp, candlestick) in candlesticks {
+            let candlestick = match check_candlestick(&candlestick) {
+                Ok(()) => candlestick,
+                Err(violation) => match self.candlestick_validation {
+                    CandlestickValidation::Repair => repair_candlestick(candlestick),
+                    CandlestickValidation::Strict => {
+                        bail!(PredictionMarketsClientError::CorruptCandlestick {
+                            timestamp,
+                            violation,
+                        })
+                    }
+                },
+            };
+            validated.insert(timestamp, candlestick);
+        }
+
+        Ok(validated)
+    }
+
+    /// All candlesticks of `candlestick_interval`, or none if the federation
+    /// keeps no candlesticks, see [Self::candlesticks_supported].
+    async fn get_candlesticks_if_supported(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        candlestick_interval: Option<Seconds>,
+    ) -> anyhow::Result<BTreeMap<UnixTimestamp, Candlestick>> {
+        match candlestick_interval {
+            Some(candlestick_interval) => {
+                self.get_candlesticks(market, outcome, candlestick_interval, UnixTimestamp::ZERO)
+                    .await
+            }
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    fn check_candlesticks_supported(&self) -> anyhow::Result<()> {
+        if !self.candlesticks_supported() {
+            bail!(PredictionMarketsClientError::CandlesticksUnsupported)
+        }
+
+        Ok(())
+    }
+
+    fn check_candlestick_interval(&self, candlestick_interval: Seconds) -> anyhow::Result<()> {
+        if !self
+            .cfg
+            .gc
+            .candlestick_intervals
+            .contains(&candlestick_interval)
+        {
+            bail!(PredictionMarketsClientError::UnsupportedInterval {
+                requested: candlestick_interval,
+                supported: self.supported_candlestick_intervals(),
+            })
+        }
+
+        Ok(())
+    }
+
+    fn order_id_to_key_pair(&self, order_id: OrderId) -> KeyPair {
+        order_id.into_key_pair(self.root_secret.clone())
+    }
+
+    /// Returns the previously cached version of the order, if any.
+    async fn save_order_to_db(
+        dbtx: &mut DatabaseTransaction<'_>,
+        id: OrderId,
+        order: &Order,
+    ) -> Option<Order> {
+        let previous = dbtx
+            .insert_entry(&db::OrderKey(id), &OrderIdSlot::Order(order.to_owned()))
+            .await
+            .and_then(|slot| slot.to_order());
+        Self::record_order_balance_change(dbtx, id, previous.as_ref(), order).await;
+        dbtx.remove_entry(&db::OrderAwaitingCacheKey(id)).await;
+
+        dbtx.insert_entry(
+            &db::OrdersByMarketOutcomeKey {
+                market: order.market,
+                outcome: order.outcome,
+                side: order.side,
+                order: id,
+            },
+            &(),
+        )
+        .await;
+
+        if order.quantity_waiting_for_match != ContractOfOutcomeAmount::ZERO {
+            dbtx.insert_entry(&db::OrderPriceTimePriorityKey::from_order(order), &id)
+                .await;
+        } else {
+            dbtx.remove_entry(&db::OrderPriceTimePriorityKey::from_order(order))
+                .await;
+        }
+
+        if order.contract_of_outcome_balance != ContractOfOutcomeAmount::ZERO {
+            dbtx.insert_entry(
+                &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSideKey {
+                    market: order.market,
+                    outcome: order.outcome,
+                    side: order.side,
+                    order: id,
+                },
+                &(),
+            )
+            .await;
+        } else {
+            dbtx.remove_entry(
+                &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSideKey {
+                    market: order.market,
+                    outcome: order.outcome,
+                    side: order.side,
+                    order: id,
+                },
+            )
+            .await;
+        }
+
+        if order.bitcoin_balance != Amount::ZERO {
+            dbtx.insert_entry(
+                &db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSideKey {
+                    market: order.market,
+                    outcome: order.outcome,
+                    side: order.side,
+                    order: id,
+                },
+                &(),
+            )
+            .await;
+        } else {
+            dbtx.remove_entry(&db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSideKey {
+                market: order.market,
+                outcome: order.outcome,
+                side: order.side,
+                order: id,
+            })
+            .await;
+        }
+
+        previous
+    }
+
+    /// Appends to the order's ledger if this is the first cached version of the
+    /// order or if any of its balances changed.
+    async fn record_order_balance_change(
+        dbtx: &mut DatabaseTransaction<'_>,
+        id: OrderId,
+        previous: Option<&Order>,
+        order: &Order,
+    ) {
+        let now = UnixTimestamp::now();
+        let record = OrderBalanceRecord::from_order(now, order);
+        let unchanged = previous
+            .is_some_and(|previous| OrderBalanceRecord::from_order(now, previous) == record);
+        if unchanged {
+            return;
+        }
+
+        let entry = dbtx
+            .find_by_prefix_sorted_descending(&db::OrderLedgerPrefix1 { order: id })
+            .await
+            .next()
+            .await
+            .map_or(0, |(key, _)| key.entry + 1);
+        dbtx.insert_entry(&db::OrderLedgerKey { order: id, entry }, &record)
+            .await;
+    }
+
+    /// Replaces the cached order with [OrderIdSlot::Pruned] and removes it from
+    /// every order index along with its ledger. The [db::OrderKey] stays
+    /// occupied so the id is not reused.
+    async fn prune_order_from_db(dbtx: &mut DatabaseTransaction<'_>, id: OrderId, order: &Order) {
+        dbtx.insert_entry(&db::OrderKey(id), &OrderIdSlot::Pruned)
+            .await;
+
+        dbtx.remove_entry(&db::OrdersByMarketOutcomeKey {
+            market: order.market,
+            outcome: order.outcome,
+            side: order.side,
+            order: id,
+        })
+        .await;
+
+        dbtx.remove_entry(&db::OrderPriceTimePriorityKey::from_order(order))
+            .await;
+
+        dbtx.remove_entry(
+            &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSideKey {
+                market: order.market,
+                outcome: order.outcome,
+                side: order.side,
+                order: id,
+            },
+        )
+        .await;
+
+        dbtx.remove_entry(&db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSideKey {
+            market: order.market,
+            outcome: order.outcome,
+            side: order.side,
+            order: id,
+        })
+        .await;
+
+        dbtx.remove_by_prefix(&db::OrderLedgerPrefix1 { order: id })
+            .await;
+    }
+
+    /// Next unused order id: one above the highest occupied [db::OrderKey],
+    /// including reserved and pruned slots.
+    async fn next_order_id(dbtx: &mut DatabaseTransaction<'_>) -> OrderId {
+        let mut stream = dbtx
+            .find_by_prefix_sorted_descending(&db::OrderPrefixAll)
+            .await;
+        match stream.next().await {
+            Some((mut key, _)) => {
+                key.0 .0 += 1;
+                key.0
+            }
+            None => OrderId(0),
+        }
+    }
+
+    async fn needs_sync_from_db(dbtx: &mut DatabaseTransaction<'_>) -> bool {
+        if dbtx
+            .find_by_prefix(&db::OrderPriceTimePriorityPrefixAll)
+            .await
+            .next()
+            .await
+            .is_some()
+        {
+            return true;
+        }
+
+        dbtx.find_by_prefix(&db::OrderAwaitingCachePrefixAll)
+            .await
+            .next()
+            .await
+            .is_some()
+    }
+
+    async fn order_cache_export(
+        dbtx: &mut DatabaseTransaction<'_>,
+        first_order_owner: PublicKey,
+    ) -> OrderCacheExport {
+        let highest_order_id = Self::next_order_id(dbtx)
+            .await
+            .0
+            .checked_sub(1)
+            .map(OrderId);
+        let orders = dbtx
+            .find_by_prefix(&db::OrderPrefixAll)
+            .await
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .filter_map(|(key, slot)| slot.to_order().map(|order| (key.0, order)))
+            .collect();
+
+        OrderCacheExport {
+            first_order_owner,
+            highest_order_id,
+            orders,
+        }
+    }
+
+    /// Saves `orders` that are not cached yet and marks every other id up to
+    /// `highest_order_id` as used. Returns the number of orders saved.
+    async fn save_order_cache_export(
+        dbtx: &mut DatabaseTransaction<'_>,
+        orders: BTreeMap<OrderId, Order>,
+        highest_order_id: Option<OrderId>,
+        force: bool,
+    ) -> anyhow::Result<usize> {
+        if !force
+            && dbtx
+                .find_by_prefix(&db::OrderPrefixAll)
+                .await
+                .next()
+                .await
+                .is_some()
+        {
+            bail!(PredictionMarketsClientError::OrderCacheNotEmpty)
+        }
+
+        let mut saved = 0;
+        for (order_id, order) in orders {
+            if dbtx.get_value(&db::OrderKey(order_id)).await.is_some() {
+                continue;
+            }
+            Self::save_order_to_db(dbtx, order_id, &order).await;
+            saved += 1;
+        }
+        // ids without an order were used for orders that settled or were never
+        // submitted
+        if let Some(highest_order_id) = highest_order_id {
+            for id in 0..=highest_order_id.0 {
+                let key = db::OrderKey(OrderId(id));
+                if dbtx.get_value(&key).await.is_none() {
+                    dbtx.insert_entry(&key, &OrderIdSlot::Pruned).await;
+                }
+            }
+        }
+
+        Ok(saved)
+    }
+
+    async fn sync_orders_from_federation_concurrent(
+        root_secret: DerivableSecret,
+        module_api: DynModuleApi,
+        federation_call_policy: FederationCallPolicy,
+        db: Database,
+        ids: Vec<OrderId>,
+    ) -> anyhow::Result<()> {
+        let mut futures = ids
+            .into_iter()
+            .map(|order_id| {
+                let root_secret = root_secret.clone();
+                let module_api = module_api.clone();
+                async move {
+                    let order_owner = order_id.into_key_pair(root_secret).public_key();
+
+                    (
+                        order_id,
+                        federation_call_policy
+                            .call(|| module_api.get_order(GetOrderParams { order: order_owner }))
+                            .await,
+                    )
+                }
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut dbtx = db.begin_transaction().await;
+        while let Some((order_id, res)) = futures.next().await {
+            if let Some(order) = res?.order {
+                PredictionMarketsClientModule::save_order_to_db(
+                    &mut dbtx.to_ref_nc(),
+                    order_id,
+                    &order,
+                )
+                .await;
+            }
+        }
+        dbtx.insert_entry(&db::LastOrderSyncKey, &UnixTimestamp::now())
+            .await;
+        dbtx.commit_tx_result().await?;
+
+        Ok(())
+    }
+
+    async fn sync_orders_from_federation_concurrent_with_self(
+        &self,
+        ids: Vec<OrderId>,
+    ) -> anyhow::Result<()> {
+        Self::sync_orders_from_federation_concurrent(
+            self.root_secret.clone(),
+            self.module_api.clone(),
+            self.federation_call_policy,
+            self.db.clone(),
+            ids,
+        )
+        .await
+    }
+
+    /// Creates the order with `reserved_order_id`, or with a newly reserved
+    /// id that is released again if the order is not submitted.
+    async fn new_order_inner(
+        &self,
+        operation_id: OperationId,
+        reserved_order_id: Option<OrderId>,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        price: Amount,
+        quantity: ContractOfOutcomeAmount,
+        max_fee: Option<Amount>,
+        verify_sources: bool,
+    ) -> anyhow::Result<OrderId> {
+        self.check_trading_enabled()?;
+        Self::check_fee(self.cfg.gc.new_order_fee, max_fee)?;
+        // a payout is final, so the cached market is enough to know the
+        // federation would reject the order
+        if let Some(Market(_, market_dynamic)) = self.get_market(market, true).await? {
+            if market_dynamic.payout.is_some() {
+                bail!(PredictionMarketsClientError::MarketClosedForTrading)
+            }
+        }
+        self.check_outcome(market, outcome).await?;
+        if side == Side::Buy {
+            let cost = Order::checked_cost(price, quantity)
+                .ok_or(PredictionMarketsClientError::AmountOverflow)?;
+            self.check_exposure_limit(cost).await?;
+        }
+        self.check_pre_order_hook(&OrderRequest {
+            market,
+            outcome,
+            side,
+            price,
+            quantity,
+        })?;
+
+        let order_id = match reserved_order_id {
+            Some(order_id) => order_id,
+            None => self.reserve_order_ids(1).await?[0],
+        };
+
+        let res = self
+            .submit_new_order(
+                operation_id,
+                order_id,
+                market,
+                outcome,
+                side,
+                price,
+                quantity,
+                max_fee,
+                verify_sources,
+            )
+            .await;
+        if res.is_err() && reserved_order_id.is_none() {
+            // fails if the order was submitted, the slot is then in use
+            let _ = self.release_order_id(order_id).await;
+        }
+
+        res.map(|()| order_id)
+    }
+
+    /// Adds a new order to `tx` under the reserved `order_id`. Sell orders
+    /// are sourced from the contract of outcome balances cached in `dbtx`,
+    /// which are reduced by the sourced quantities.
+    ///
+    /// Returns the orders the new order is sourced from.
+    async fn add_new_order_to_tx(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        mut tx: TransactionBuilder,
+        operation_id: OperationId,
+        order_id: OrderId,
+        request: &OrderRequest,
+    ) -> anyhow::Result<(TransactionBuilder, Vec<(OrderId, ContractOfOutcomeAmount)>)> {
+        let OrderRequest {
+            market,
+            outcome,
+            side,
+            price,
+            quantity,
+        } = request.to_owned();
+
+        if dbtx
+            .remove_entry(&db::OrderIdReservationKey(order_id))
+            .await
+            .is_none()
+        {
+            bail!(PredictionMarketsClientError::OrderIdNotReserved)
+        }
+        dbtx.insert_entry(&db::OrderAwaitingCacheKey(order_id), &())
+            .await;
+
+        let order_key = self.order_id_to_key_pair(order_id);
+        let owner = PublicKey::from_keypair(&order_key);
+
+        let mut orders_to_sync_on_accepted = BTreeSet::new();
+        orders_to_sync_on_accepted.insert(order_id);
+        let mut orders_to_sync_on_rejected = BTreeSet::new();
+        let mut sourced_from = Vec::new();
+        match side {
+            Side::Buy => {
+                let output = ClientOutput {
+                    output: PredictionMarketsOutput::NewBuyOrder {
+                        owner,
+                        market,
+                        outcome,
+                        price,
+                        quantity,
+                    },
+                    amount: Order::checked_cost(price, quantity)
+                        .ok_or(PredictionMarketsClientError::AmountOverflow)?,
+                    state_machines: Arc::new(move |tx_id, _| {
+                        vec![PredictionMarketsStateMachine {
+                            operation_id,
+                            state: NewOrderState::Pending {
+                                tx_id,
+                                order_id,
+                                orders_to_sync_on_accepted: orders_to_sync_on_accepted.clone(),
+                                orders_to_sync_on_rejected: orders_to_sync_on_rejected.clone(),
+                            }
+                            .into(),
+                        }]
+                    }),
+                };
+
+                tx = tx.with_output(self.ctx.make_client_output(output));
+            }
+            Side::Sell => {
+                let mut sources = BTreeMap::new();
+                let mut sources_keys_combined = None;
+
+                let possible_source_orders = Self::get_order_ids(
+                    dbtx,
+                    OrderFilter(
+                        OrderPath::MarketOutcomeSide {
+                            market,
+                            outcome,
+                            side,
+                        },
+                        OrderState::NonZeroContractOfOutcomeBalance,
+                    ),
+                )
+                .await;
+
+                let mut sourced_quantity = ContractOfOutcomeAmount::ZERO;
+                for loop_order_id in possible_source_orders {
+                    let mut loop_order = dbtx
+                        .get_value(&db::OrderKey(loop_order_id))
+                        .await
+                        .unwrap()
+                        .to_order()
+                        .unwrap();
+                    // an earlier order of the same transaction may have used
+                    // up the balance, the balance index is not updated until
+                    // the order is synced
+                    if loop_order.contract_of_outcome_balance == ContractOfOutcomeAmount::ZERO {
+                        continue;
+                    }
+
+                    if sources.len() == usize::from(self.cfg.gc.max_sell_order_sources) {
+                        bail!("max number of sell order sources reached. try again with a quantity less than or equal to {}", sourced_quantity.0)
+                    }
+
+                    let loop_order_key = self.order_id_to_key_pair(loop_order_id);
+                    let loop_sourced_quantity_from_order = loop_order
+                        .contract_of_outcome_balance
+                        .min(quantity - sourced_quantity);
+                    loop_order.contract_of_outcome_balance -= loop_sourced_quantity_from_order;
+                    sourced_quantity += loop_sourced_quantity_from_order;
+
+                    sources.insert(
+                        loop_order_key.public_key(),
+                        loop_sourced_quantity_from_order,
+                    );
+                    sourced_from.push((loop_order_id, loop_sourced_quantity_from_order));
+
+                    dbtx.insert_entry(
+                        &db::OrderKey(loop_order_id),
+                        &OrderIdSlot::Order(loop_order),
+                    )
+                    .await;
+                    orders_to_sync_on_accepted.insert(loop_order_id);
+                    orders_to_sync_on_rejected.insert(loop_order_id);
+
+                    sources_keys_combined = match sources_keys_combined {
+                        None => Some(loop_order_key),
+                        Some(combined_keys) => {
+                            let p1 = combined_keys.secret_key();
+                            let p2 = loop_order_key.secret_key();
+                            let p3 = p1.add_tweak(&Scalar::from(p2))?;
+
+                            Some(p3.keypair(secp256k1::SECP256K1))
+                        }
+                    };
+
+                    if quantity == sourced_quantity {
+                        break;
+                    }
+                }
+
+                if quantity != sourced_quantity {
+                    bail!("Insufficient outcome quantity for new sell order");
+                }
+
+                let input = ClientInput {
+                    input: PredictionMarketsInput::NewSellOrder {
+                        owner,
+                        market,
+                        outcome,
+                        price,
+                        sources,
+                    },
+                    amount: Amount::ZERO,
+                    state_machines: Arc::new(move |tx_id, _| {
+                        vec![PredictionMarketsStateMachine {
+                            operation_id,
+                            state: NewOrderState::Pending {
+                                tx_id,
+                                order_id,
+                                orders_to_sync_on_accepted: orders_to_sync_on_accepted.clone(),
+                                orders_to_sync_on_rejected: orders_to_sync_on_rejected.clone(),
+                            }
+                            .into(),
+                        }]
+                    }),
+                    keys: vec![sources_keys_combined.unwrap()],
+                };
+
+                tx = tx.with_input(self.ctx.make_client_input(input));
+            }
+        }
+
+        Ok((tx, sourced_from))
+    }
+
+    /// Undoes what committing [Self::add_new_order_to_tx] did for orders whose
+    /// transaction could not be submitted: their ids are reserved again and
+    /// the sources, whose cached balances were reduced, are synced.
+    async fn restore_unsubmitted_orders(&self, order_ids: &[OrderId], sources: Vec<OrderId>) {
+        let mut dbtx = self.db.begin_transaction().await;
+        for order_id in order_ids {
+            if let Some(OrderIdSlot::Reserved) = dbtx.get_value(&db::OrderKey(*order_id)).await {
+                dbtx.insert_entry(&db::OrderIdReservationKey(*order_id), &())
+                    .await;
+                dbtx.remove_entry(&db::OrderAwaitingCacheKey(*order_id))
+                    .await;
+            }
+        }
+        if let Err(e) = dbtx.commit_tx_result().await {
+            warn!("failed to restore order id reservations: {e}");
+        }
+
+        if let Err(e) = self
+            .sync_orders_from_federation_concurrent_with_self(sources)
+            .await
+        {
+            warn!("failed to sync sources of unsubmitted orders: {e}");
+        }
+    }
+
+    async fn submit_new_orders(
+        &self,
+        operation_id: OperationId,
+        order_ids: &[OrderId],
+        requests: &[OrderRequest],
+    ) -> anyhow::Result<()> {
+        let mut dbtx = self.db.begin_transaction().await;
+        let mut tx = TransactionBuilder::new();
+        let mut sources = Vec::new();
+        for (order_id, request) in order_ids.iter().zip(requests) {
+            let (order_tx, sourced_from) = self
+                .add_new_order_to_tx(&mut dbtx.to_ref_nc(), tx, operation_id, *order_id, request)
+                .await?;
+            tx = order_tx;
+            sources.extend(
+                sourced_from
+                    .into_iter()
+                    .map(|(source_order_id, _)| source_order_id),
+            );
+        }
+        dbtx.commit_tx_result().await?;
+
+        // checked last, so orders failing any other check do not use up the
+        // limit
+        if let Err(e) = self.check_order_rate_limit_for(order_ids.len()) {
+            self.restore_unsubmitted_orders(order_ids, sources).await;
+            return Err(e);
+        }
+        self.log_submitted_transaction(
+            operation_id,
+            SubmittedTransactionSummary::NewOrders {
+                order_ids: order_ids.to_vec(),
+                sources: sources.clone(),
+            },
+        )
+        .await?;
+        let (tx_id, _) = match self
+            .ctx
+            .finalize_and_submit_transaction(
+                operation_id,
+                PredictionMarketsCommonInit::KIND.as_str(),
+                |_, _| (),
+                tx,
+            )
+            .await
+        {
+            Ok(res) => res,
+            Err(e) => {
+                self.release_order_rate_limit_for(order_ids.len());
+                self.restore_unsubmitted_orders(order_ids, sources).await;
+                return Err(e);
+            }
+        };
+
+        // every order completes after syncing, whether the transaction was
+        // accepted or rejected
+        let res = self.await_accepted(operation_id, tx_id).await;
+        if res.as_ref().is_err_and(|e| is_operation_abandoned(e)) {
+            return res;
+        }
+        self.await_state_count(operation_id, order_ids.len(), |s| {
+            matches!(s, PredictionMarketState::NewOrder(NewOrderState::Complete))
+        })
+        .await;
+
+        res
+    }
+
+    /// Submits the order using `order_id`, which must be reserved. The
+    /// reservation is consumed when the order is submitted.
+    async fn submit_new_order(
+        &self,
+        operation_id: OperationId,
+        order_id: OrderId,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        price: Amount,
+        quantity: ContractOfOutcomeAmount,
+        max_fee: Option<Amount>,
+        verify_sources: bool,
+    ) -> anyhow::Result<()> {
+        if verify_sources && side == Side::Sell {
+            let possible_source_orders = Self::get_order_ids(
+                &mut self.db.begin_transaction_nc().await,
+                OrderFilter(
+                    OrderPath::MarketOutcomeSide {
+                        market,
+                        outcome,
+                        side,
+                    },
+                    OrderState::NonZeroContractOfOutcomeBalance,
+                ),
+            )
+            .await;
+            self.sync_orders_from_federation_concurrent_with_self(
+                possible_source_orders.into_iter().collect(),
+            )
+            .await?;
+        }
+
+        let mut dbtx = self.db.begin_transaction().await;
+        let (tx, sourced_from) = self
+            .add_new_order_to_tx(
+                &mut dbtx.to_ref_nc(),
+                TransactionBuilder::new(),
+                operation_id,
+                order_id,
+                &OrderRequest {
+                    market,
+                    outcome,
+                    side,
+                    price,
+                    quantity,
+                },
+            )
+            .await?;
+
+        dbtx.commit_tx_result().await?;
+
+        // checked last, so orders failing any other check do not use up the
+        // limit
+        if let Err(e) = self.check_order_rate_limit() {
+            self.restore_unsubmitted_orders(
+                &[order_id],
+                sourced_from
+                    .into_iter()
+                    .map(|(source_order_id, _)| source_order_id)
+                    .collect(),
+            )
+            .await;
+            return Err(e);
+        }
+        self.log_submitted_transaction(
+            operation_id,
+            SubmittedTransactionSummary::NewOrder {
+                order_id,
+                market,
+                outcome,
+                side,
+                price,
+                quantity,
+                sources: sourced_from
+                    .iter()
+                    .map(|(source_order_id, _)| *source_order_id)
+                    .collect(),
+            },
+        )
+        .await?;
+        let (tx_id, _) = match self
+            .ctx
+            .finalize_and_submit_transaction(
+                operation_id,
+                PredictionMarketsCommonInit::KIND.as_str(),
+                |_, _| (),
+                tx,
+            )
+            .await
+        {
+            Ok(res) => res,
+            Err(e) => {
+                self.release_order_rate_limit();
+                self.restore_unsubmitted_orders(
+                    &[order_id],
+                    sourced_from
+                        .into_iter()
+                        .map(|(source_order_id, _)| source_order_id)
+                        .collect(),
+                )
+                .await;
+                return Err(e);
+            }
+        };
+
+        if let Err(e) = self.await_accepted(operation_id, tx_id).await {
+            if is_operation_abandoned(&e) {
+                return Err(e);
+            }
+            // the rejected order is looked up on the federation before completing,
+            // see [Self::get_rejected_order_discrepancies]
+            self.await_state(operation_id, |s| {
+                matches!(s, PredictionMarketState::NewOrder(NewOrderState::Complete))
+            })
+            .await;
+            if sourced_from.is_empty() {
+                return Err(e);
+            }
+
+            // sources are synced on rejection, so the cache now shows whether any
+            // of them had less balance than we sourced from it
+            let mut dbtx = self.db.begin_transaction_nc().await;
+            for (source_order_id, sourced_quantity) in sourced_from {
+                let balance = dbtx
+                    .get_value(&db::OrderKey(source_order_id))
+                    .await
+                    .and_then(|slot| slot.to_order())
+                    .map_or(ContractOfOutcomeAmount::ZERO, |o| {
+                        o.contract_of_outcome_balance
+                    });
+                if balance < sourced_quantity {
+                    bail!(PredictionMarketsClientError::StaleOrderSources)
+                }
+            }
+
+            return Err(e);
+        }
+        self.await_state(operation_id, |s| {
+            matches!(s, PredictionMarketState::NewOrder(NewOrderState::Complete))
+        })
+        .await;
+
+        Ok(())
+    }
+
+    async fn submit_new_market(
+        &self,
+        operation_id: OperationId,
+        event_json: PredictionMarketEventJson,
+        contract_price: Amount,
+        payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
+        weight_required_for_payout: WeightRequiredForPayout,
+        outcome_display_hints: Vec<OutcomeDisplayHint>,
+        max_fee: Option<Amount>,
+    ) -> anyhow::Result<OutPoint> {
+        self.check_trading_enabled()?;
+        let event = Event::try_from_json_str(&event_json)?;
+        if event.outcome_count > self.cfg.gc.max_market_outcomes {
+            bail!(PredictionMarketsClientError::TooManyOutcomes {
+                count: event.outcome_count,
+                max: self.cfg.gc.max_market_outcomes,
+            })
+        }
+        if !outcome_display_hints.is_empty()
+            && outcome_display_hints.len() != usize::from(event.outcome_count)
+        {
+            bail!(
+                PredictionMarketsClientError::OutcomeDisplayHintCountMismatch {
+                    hints: outcome_display_hints.len(),
+                    outcomes: event.outcome_count,
+                }
+            )
+        }
+        Self::check_fee(self.cfg.gc.new_market_fee, max_fee)?;
+
+        // markets without hints use the original output, which federations
+        // that predate hints also accept
+        let output = match outcome_display_hints.is_empty() {
+            true => PredictionMarketsOutput::NewMarket {
+                event_json,
+                contract_price,
+                payout_control_weight_map,
+                weight_required_for_payout,
+            },
+            false => PredictionMarketsOutput::NewMarketWithDisplayHints {
+                event_json,
+                contract_price,
+                payout_control_weight_map,
+                weight_required_for_payout,
+                outcome_display_hints,
+            },
+        };
+        let output = ClientOutput {
+            output,
+            amount: Amount::ZERO,
+            state_machines: Arc::new(move |tx_id, _| {
+                vec![PredictionMarketsStateMachine {
+                    operation_id,
+                    state: NewMarketState::Pending { tx_id }.into(),
+                }]
+            }),
+        };
+
+        let tx = TransactionBuilder::new().with_output(self.ctx.make_client_output(output));
+        self.log_submitted_transaction(
+            operation_id,
+            SubmittedTransactionSummary::NewMarket {
+                outcome_count: event.outcome_count,
+                contract_price,
+            },
+        )
+        .await?;
+        let out_point = |txid, _| OutPoint { txid, out_idx: 0 };
+        let (tx_id, _) = self
+            .ctx
+            .finalize_and_submit_transaction(
+                operation_id,
+                PredictionMarketsCommonInit::KIND.as_str(),
+                out_point,
+                tx,
+            )
+            .await?;
+
+        Ok(market_outpoint_from_tx_id(tx_id))
+    }
+
+    async fn await_new_market(
+        &self,
+        operation_id: OperationId,
+        market: OutPoint,
+    ) -> anyhow::Result<()> {
+        self.await_accepted(operation_id, market.txid).await?;
+        self.await_state(operation_id, |s| {
+            matches!(
+                s,
+                PredictionMarketState::NewMarket(NewMarketState::Complete)
+            )
+        })
+        .await;
+
+        Ok(())
+    }
+
+    /// Like [Self::get_market_prefer_cache], but fails with
+    /// [PredictionMarketsClientError::MarketDoesNotExist] if the market does
+    /// not exist. Methods taking a market call this before anything else, so
+    /// a mistyped out point fails early and the same way everywhere.
+    async fn ensure_market_exists(&self, market: OutPoint) -> anyhow::Result<Market> {
+        let Some(market) = self.get_market_prefer_cache(market).await? else {
+            bail!(PredictionMarketsClientError::MarketDoesNotExist)
+        };
+
+        Ok(market)
+    }
+
+    /// Gets market from local cache, falling back to the federation if it is
+    /// not cached.
+    async fn get_market_prefer_cache(&self, market: OutPoint) -> anyhow::Result<Option<Market>> {
+        match self.get_market(market, true).await? {
+            Some(market) => Ok(Some(market)),
+            None => self.get_market(market, false).await,
+        }
+    }
+
+    async fn await_accepted(
+        &self,
+        operation_id: OperationId,
+        tx_id: TransactionId,
+    ) -> anyhow::Result<()> {
+        let tx_subscription = self.ctx.transaction_updates(operation_id).await;
+        // an abandoned operation is reported as such even if the transaction
+        // was accepted in the meantime
+        select! {
+            biased;
+            _ = self.db.wait_key_exists(&db::AbandonedOperationKey(operation_id)) => {
+                bail!(PredictionMarketsClientError::OperationAbandoned)
+            }
+            res = tx_subscription.await_tx_accepted(tx_id) => res.map_err(|e| anyhow!(e))?,
+        }
+
+        Ok(())
+    }
+
+    async fn await_state(
+        &self,
+        operation_id: OperationId,
+        state_matcher: impl Fn(PredictionMarketState) -> bool,
+    ) {
+        let mut state_stream = self.notifier.subscribe(operation_id).await;
+        while let Some(PredictionMarketsStateMachine {
+            operation_id: _,
+            state,
+        }) = state_stream.next().await
+        {
+            if state_matcher(state) {
+                break;
+            }
+        }
+    }
+
+    /// Like [Self::await_state] but waits for `count` matching states. Used
+    /// when one operation drives multiple state machines.
+    async fn await_state_count(
+        &self,
+        operation_id: OperationId,
+        count: usize,
+        state_matcher: impl Fn(PredictionMarketState) -> bool,
+    ) {
+        let mut remaining = count;
+        let mut state_stream = self.notifier.subscribe(operation_id).await;
+        while remaining != 0 {
+            let Some(PredictionMarketsStateMachine {
+                operation_id: _,
+                state,
+            }) = state_stream.next().await
+            else {
+                break;
+            };
+
+            if state_matcher(state) {
+                remaining -= 1;
+            }
+        }
+    }
+
+    pub async fn stream_order_from_db_internal<'a>(
+        db: Database,
+        id: OrderId,
+    ) -> BoxStream<'a, Option<Order>> {
+        Box::pin(stream! {
+            let mut previous_entry: Option<OrderIdSlot> = None;
+            loop {
+                let new_entry = db
+                    .wait_key_check(&db::OrderKey(id), |db_value| { if db_value != previous_entry {
+                        Some(db_value)
+                    } else {
+                        None
+                    }})
+                    .await
+                    .0;
+
+                yield new_entry.clone().map(|s| s.to_order()).flatten();
+
+                previous_entry = new_entry;
+            }
+        })
+    }
+
+    /// Orders that can still change, plus `include`.
+    async fn get_changeable_orders(
+        dbtx: &mut DatabaseTransaction<'_>,
+        mut include: BTreeSet<OrderId>,
+    ) -> BTreeMap<OrderId, Order> {
+        for state in [
+            OrderState::NonZeroQuantityWaitingForMatch,
+            OrderState::NonZeroContractOfOutcomeBalance,
+            OrderState::NonZeroBitcoinBalance,
+        ] {
+            include.extend(Self::get_order_ids(dbtx, OrderFilter(OrderPath::All, state)).await);
+        }
+
+        let mut orders = BTreeMap::new();
+        for order_id in include {
+            if let Some(order) = dbtx
+                .get_value(&db::OrderKey(order_id))
+                .await
+                .and_then(|slot| slot.to_order())
+            {
+                orders.insert(order_id, order);
+            }
+        }
+
+        orders
+    }
+
+    /// Cancels the orders of an expired dead man's switch, or resumes the
+    /// heartbeat of one that has not expired.
+    async fn start_dead_mans_switch(&self) {
+        let Some(switch) = self.get_dead_mans_switch().await else {
+            return;
+        };
+
+        if !switch.expired(UnixTimestamp::now()) {
+            self.spawn_dead_mans_switch_heartbeat(switch).await;
+            return;
+        }
+
+        // cancelling waits on the federation, so it must not hold up start
+        let ctx = self.ctx.clone();
+        spawn("expired_dead_mans_switch", async move {
+            let module = ctx.self_ref();
+
+            if let Err(e) = module.cancel_all_orders(switch.filter).await {
+                // the switch stays so cancelling is tried again on the next start
+                warn!("failed to cancel orders of expired dead man's switch: {e}");
+                return;
+            }
+
+            let mut dbtx = module.db.begin_transaction().await;
+            if dbtx.get_value(&db::DeadMansSwitchKey).await.as_ref() == Some(&switch) {
+                dbtx.remove_entry(&db::DeadMansSwitchKey).await;
+            }
+            if let Err(e) = dbtx.commit_tx_result().await {
+                warn!("failed to remove expired dead man's switch: {e}");
+            }
+        });
+    }
+
+    /// Refreshes the switch until it is removed or the module is dropped.
+    /// Only one heartbeat runs at a time, spawning another stops the previous
+    /// one.
+    async fn spawn_dead_mans_switch_heartbeat(&self, switch: DeadMansSwitch) {
+        self.stop_dead_mans_switch_heartbeat().await;
+
+        let db = self.db.clone();
+        let module_alive = Arc::downgrade(&self.module_alive);
+        let heartbeat_interval =
+            Duration::from_secs(switch.interval) / DEAD_MANS_SWITCH_HEARTBEATS_PER_INTERVAL;
+        let (stop_tx, mut stop_rx) = stop_signal::new();
+
+        spawn("dead_mans_switch_heartbeat", async move {
+            loop {
+                select! {
+                    _ = stop_rx.0.recv() => {
+                        break;
+                    }
+                    _ = sleep(heartbeat_interval) => {}
+                }
+                if module_alive.strong_count() == 0 {
+                    break;
+                }
+
+                let mut dbtx = db.begin_transaction().await;
+                let Some(mut current) = dbtx.get_value(&db::DeadMansSwitchKey).await else {
+                    break;
+                };
+
+                current.last_heartbeat = UnixTimestamp::now();
+                dbtx.insert_entry(&db::DeadMansSwitchKey, &current).await;
+                if let Err(e) = dbtx.commit_tx_result().await {
+                    warn!("failed to refresh dead man's switch: {e}");
+                }
+            }
+        });
+
+        *self.dead_mans_switch_heartbeat_stop.lock().unwrap() = Some(stop_tx);
+    }
+
+    async fn stop_dead_mans_switch_heartbeat(&self) {
+        let Some(stop_tx) = self.dead_mans_switch_heartbeat_stop.lock().unwrap().take() else {
+            return;
+        };
+
+        // fails if the heartbeat already ended on its own
+        _ = stop_tx.wait_close().await;
+    }
+
+    fn spawn_reconcile_settlements(&self) {
+        let db = self.db.clone();
+        let module_api = self.module_api.clone();
+        let federation_call_policy = self.federation_call_policy;
+
+        spawn("reconcile_settlements", async move {
+            if let Err(e) =
+                Self::reconcile_settlements_with(db, module_api, federation_call_policy).await
+            {
+                warn!("failed to check for markets that paid out while offline: {e}");
+            }
+        });
+    }
+
+    async fn reconcile_settlements_with(
+        db: Database,
+        module_api: DynModuleApi,
+        federation_call_policy: FederationCallPolicy,
+    ) -> anyhow::Result<Vec<OutPoint>> {
+        let mut markets = {
+            let mut dbtx = db.begin_transaction_nc().await;
+            let markets_with_orders: BTreeSet<_> = dbtx
+                .find_by_prefix(&db::OrdersByMarketOutcomePrefixAll)
+                .await
+                .map(|(k, _)| k.market)
+                .collect()
+                .await;
+
+            let mut markets = Vec::new();
+            for market in markets_with_orders {
+                let cached = dbtx.get_value(&db::MarketKey(market)).await;
+                if !cached.as_ref().is_some_and(|m| m.1.payout.is_some()) {
+                    markets.push((market, cached));
+                }
+            }
+            markets.into_iter()
+        };
+
+        let request = |(market, cached): (OutPoint, Option<Market>)| {
+            let module_api = module_api.clone();
+            async move {
+                let res = federation_call_policy
+                    .call(|| module_api.get_market_dynamic(GetMarketDynamicParams { market }))
+                    .await;
+                (market, cached, res)
+            }
+        };
+        let mut futures = markets
+            .by_ref()
+            .take(MAX_CONCURRENT_SETTLEMENT_CHECKS)
+            .map(&request)
+            .collect::<FuturesUnordered<_>>();
+
+        let mut newly_settled = Vec::new();
+        while let Some((market, cached, res)) = futures.next().await {
+            if let Some(next_market) = markets.next() {
+                futures.push(request(next_market));
+            }
+
+            // one market failing should not keep the others from being checked
+            let market_dynamic = match res {
+                Ok(res) => res.market_dynamic,
+                Err(e) => {
+                    warn!("failed to check market {market} for a payout: {e}");
+                    continue;
+                }
+            };
+            let Some(market_dynamic) = market_dynamic else {
+                continue;
+            };
+
+            let mut dbtx = db.begin_transaction().await;
+            if let Some(Market(market_static, _)) = cached.clone() {
+                dbtx.insert_entry(
+                    &db::MarketKey(market),
+                    &Market(market_static, market_dynamic.clone()),
+                )
+                .await;
+            }
+            let settled = Self::record_pending_settlement(
+                &mut dbtx.to_ref_nc(),
+                market,
+                cached.as_ref(),
+                &market_dynamic,
+            )
+            .await;
+            match dbtx.commit_tx_result().await {
+                Ok(()) if settled => newly_settled.push(market),
+                Ok(()) => {}
+                Err(e) => warn!("failed to record payout of market {market}: {e}"),
+            }
+        }
+
+        Ok(newly_settled)
+    }
+
+    /// Records `market` as pending settlement if `market_dynamic` from the
+    /// federation has a payout that the `cached` market does not, and one of
+    /// the client's orders in the market has payout left to claim. Returns
+    /// whether it was recorded.
+    async fn record_pending_settlement(
+        dbtx: &mut DatabaseTransaction<'_>,
+        market: OutPoint,
+        cached: Option<&Market>,
+        market_dynamic: &MarketDynamic,
+    ) -> bool {
+        let Some(payout) = market_dynamic.payout.as_ref() else {
+            return false;
+        };
+        if cached.is_some_and(|m| m.1.payout.is_some()) {
+            return false;
+        }
+
+        let order_ids: Vec<_> = dbtx
+            .find_by_prefix(&db::OrdersByMarketOutcomePrefix1 { market })
+            .await
+            .map(|(k, _)| k.order)
+            .collect()
+            .await;
+        let mut has_payout_to_claim = false;
+        for order_id in order_ids {
+            let order = dbtx
+                .get_value(&db::OrderKey(order_id))
+                .await
+                .and_then(|slot| slot.to_order());
+            if order.is_some_and(|o| order_has_payout_to_claim(&o, payout)) {
+                has_payout_to_claim = true;
+                break;
+            }
+        }
+        if !has_payout_to_claim {
+            return false;
+        }
+
+        dbtx.insert_entry(&db::PendingSettlementKey(market), &UnixTimestamp::now())
+            .await;
+        true
+    }
+
+    fn order_can_change(order: &Order) -> bool {
+        order.quantity_waiting_for_match != ContractOfOutcomeAmount::ZERO
+            || order.contract_of_outcome_balance != ContractOfOutcomeAmount::ZERO
+            || order.bitcoin_balance != Amount::ZERO
+    }
+
+    async fn get_orders_page(
+        dbtx: &mut DatabaseTransaction<'_>,
+        filter: OrderFilter,
+        cursor: Option<OrderCursor>,
+        limit: NonZeroUsize,
+    ) -> (Vec<(OrderId, Order)>, Option<OrderCursor>) {
+        let order_ids = Self::get_order_ids(dbtx, filter).await;
+        let start = match cursor {
+            Some(OrderCursor(last)) => Excluded(last),
+            None => Unbounded,
+        };
+        let mut order_ids = order_ids.range((start, Unbounded));
+
+        let mut orders = Vec::with_capacity(limit.get());
+        let mut last = None;
+        for order_id in order_ids.by_ref().take(limit.get()) {
+            last = Some(*order_id);
+            if let Some(order) = dbtx
+                .get_value(&db::OrderKey(*order_id))
+                .await
+                .and_then(|slot| slot.to_order())
+            {
+                orders.push((*order_id, order));
+            }
+        }
+
+        let next = match order_ids.next() {
+            Some(_) => last.map(OrderCursor),
+            None => None,
+        };
+
+        (orders, next)
+    }
+
+    async fn get_order_ids<'a>(
+        dbtx: &mut DatabaseTransaction<'a>,
+        filter: OrderFilter,
+    ) -> BTreeSet<OrderId> {
+        match filter.1 {
+            OrderState::Any => {
+                match filter.0 {
+                    OrderPath::All => {
+                        dbtx.find_by_prefix(&db::OrdersByMarketOutcomePrefixAll)
+                            .await
+                    }
+                    OrderPath::Market { market } => {
                         dbtx.find_by_prefix(&db::OrdersByMarketOutcomePrefix1 { market })
                             .await
                     }
                     OrderPath::MarketOutcome { market, outcome } => {
-                        dbtx.find_by_prefix(&db::OrdersByMarketOutcomePrefix2 { market, outcome })
+                        dbtx.find_by_prefix(&db::OrdersByMarketOutcomePrefix2 { market, outcome })
+                            .await
+                    }
+                    OrderPath::MarketOutcomeSide {
+                        market,
+                        outcome,
+                        side,
+                    } => {
+                        dbtx.find_by_prefix(&db::OrdersByMarketOutcomePrefix3 {
+                            market,
+                            outcome,
+                            side,
+                        })
+                        .await
+                    }
+                }
+                .map(|(k, _)| k.order)
+                .collect()
+                .await
+            }
+
+            OrderState::NonZeroQuantityWaitingForMatch => {
+                match filter.0 {
+                    OrderPath::All => {
+                        dbtx.find_by_prefix(&db::OrderPriceTimePriorityPrefixAll)
+                            .await
+                    }
+                    OrderPath::Market { market } => {
+                        dbtx.find_by_prefix(&db::OrderPriceTimePriorityPrefix1 { market })
+                            .await
+                    }
+                    OrderPath::MarketOutcome { market, outcome } => {
+                        dbtx.find_by_prefix(&db::OrderPriceTimePriorityPrefix2 { market, outcome })
                             .await
                     }
                     OrderPath::MarketOutcomeSide {
@@ -1297,7 +5996,7 @@ impl PredictionMarketsClientModule {
                         outcome,
                         side,
                     } => {
-                        dbtx.find_by_prefix(&db::OrdersByMarketOutcomePrefix3 {
+                        dbtx.find_by_prefix(&db::OrderPriceTimePriorityPrefix3 {
                             market,
                             outcome,
                             side,
@@ -1305,314 +6004,2373 @@ impl PredictionMarketsClientModule {
                         .await
                     }
                 }
-                .map(|(k, _)| k.order)
-                .collect()
-                .await
+                .map(|(_, v)| v)
+                .collect()
+                .await
+            }
+            OrderState::NonZeroContractOfOutcomeBalance => match filter.0 {
+                OrderPath::All => {
+                    dbtx.find_by_prefix(
+                        &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSidePrefixAll,
+                    )
+                    .await
+                }
+                OrderPath::Market { market } => {
+                    dbtx.find_by_prefix(
+                        &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSidePrefix1 {
+                            market,
+                        },
+                    )
+                    .await
+                }
+                OrderPath::MarketOutcome { market, outcome } => {
+                    dbtx.find_by_prefix(
+                        &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSidePrefix2 {
+                            market,
+                            outcome,
+                        },
+                    )
+                    .await
+                }
+                OrderPath::MarketOutcomeSide {
+                    market,
+                    outcome,
+                    side,
+                } => {
+                    dbtx.find_by_prefix(
+                        &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSidePrefix3 {
+                            market,
+                            outcome,
+                            side,
+                        },
+                    )
+                    .await
+                }
+            }
+            .map(|(k, _)| k.order)
+            .collect()
+            .await,
+            OrderState::NonZeroBitcoinBalance => {
+                match filter.0 {
+                    OrderPath::All => {
+                        dbtx.find_by_prefix(
+                            &db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSidePrefixAll,
+                        )
+                        .await
+                    }
+                    OrderPath::Market { market } => {
+                        dbtx.find_by_prefix(
+                            &db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSidePrefix1 {
+                                market,
+                            },
+                        )
+                        .await
+                    }
+                    OrderPath::MarketOutcome { market, outcome } => {
+                        dbtx.find_by_prefix(
+                            &db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSidePrefix2 {
+                                market,
+                                outcome,
+                            },
+                        )
+                        .await
+                    }
+                    OrderPath::MarketOutcomeSide {
+                        market,
+                        outcome,
+                        side,
+                    } => {
+                        dbtx.find_by_prefix(
+                            &db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSidePrefix3 {
+                                market,
+                                outcome,
+                                side,
+                            },
+                        )
+                        .await
+                    }
+                }
+                .map(|(k, _)| k.order)
+                .collect()
+                .await
+            }
+        }
+    }
+
+    async fn watch_for_order_matches_on_market_outcome_side(
+        &self,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+    ) -> anyhow::Result<stop_signal::Sender> {
+        let module_api = self.module_api.clone();
+        let federation_call_policy = self.federation_call_policy;
+        let db = self.db.clone();
+        let root_secret = self.root_secret.clone();
+        let mut new_order_reciever = self.new_order_broadcast.0.subscribe();
+        let (stop_tx, mut stop_rx) = stop_signal::new();
+
+        self.sync_matches(
+            OrderPath::MarketOutcomeSide {
+                market,
+                outcome,
+                side,
+            },
+            true,
+        )
+        .await?;
+
+        spawn(
+            &format!("watch_orders_on_{market}_{outcome}_{side:?}"),
+            async move {
+                let mut order_to_watch = None;
+                loop {
+                    let wait_order_match_request = {
+                        let mut dbtx = db.begin_transaction_nc().await;
+                        if order_to_watch == None {
+                            order_to_watch = dbtx
+                                .find_by_prefix(&db::OrderPriceTimePriorityPrefix3 {
+                                    market,
+                                    outcome,
+                                    side,
+                                })
+                                .await
+                                .map(|(_, order_id)| order_id)
+                                .next()
+                                .await;
+                        }
+
+                        let order = if let Some(order_id) = order_to_watch {
+                            Some(
+                                dbtx.get_value(&db::OrderKey(order_id))
+                                    .await
+                                    .unwrap()
+                                    .to_order()
+                                    .unwrap(),
+                            )
+                        } else {
+                            None
+                        };
+
+                        async {
+                            if let Some(order_id) = order_to_watch {
+                                module_api
+                                    .wait_order_match(WaitOrderMatchParams {
+                                        order: order_id
+                                            .into_key_pair(root_secret.clone())
+                                            .public_key(),
+                                        current_quantity_waiting_for_match: order
+                                            .unwrap()
+                                            .quantity_waiting_for_match,
+                                    })
+                                    .await
+                            } else {
+                                futures::future::pending().await
+                            }
+                        }
+                    };
+
+                    select! {
+                        _ = stop_rx.0.recv() => {
+                            return;
+                        }
+                        res = wait_order_match_request => {
+                            if let Ok(WaitOrderMatchResult {order}) = res {
+                                while let Err(_) = {
+                                    let mut dbtx = db.begin_transaction().await;
+                                    Self::save_order_to_db(&mut dbtx.to_ref_nc(), order_to_watch.unwrap(), &order).await;
+                                    dbtx.commit_tx_result().await
+                                } {}
+                                if order.quantity_waiting_for_match == ContractOfOutcomeAmount::ZERO {
+                                    order_to_watch = None;
+                                }
+                            }
+                        }
+                        _ = new_order_reciever.recv() => {
+                            let mut dbtx = db.begin_transaction_nc().await;
+                            let order_priority_stream = dbtx
+                                .find_by_prefix(&db::OrderPriceTimePriorityPrefix3 {
+                                    market,
+                                    outcome,
+                                    side,
+                                })
+                                .await;
+                            let highest_priority_orders_till_watched_order =
+                                order_priority_stream
+                                    .map(|(_, order_id)| order_id)
+                                    .take_while(|order_id| {
+                                        let order_id = *order_id;
+                                        async move { Some(order_id) != order_to_watch }
+                                    })
+                                    .collect::<Vec<_>>()
+                                    .await;
+                            if highest_priority_orders_till_watched_order.len() == 0 {
+                                continue;
+                            }
+                            let new_order_to_watch = highest_priority_orders_till_watched_order
+                                .get(0)
+                                .map(|id| *id);
+                            let mut orders_to_sync = vec![];
+                            if let Some(watched_order) = new_order_to_watch {
+                                orders_to_sync.push(watched_order);
+                            }
+                            highest_priority_orders_till_watched_order
+                                .into_iter()
+                                    .skip(1)
+                                    .for_each(|order| orders_to_sync.push(order));
+
+                            while let Err(_) = {
+                                Self::sync_orders_from_federation_concurrent(
+                                    root_secret.clone(),
+                                    module_api.clone(),
+                                    federation_call_policy,
+                                    db.clone(),
+                                    orders_to_sync.clone()
+                                )
+                                .await
+                            } {}
+                            order_to_watch = new_order_to_watch;
+                        }
+                    }
+                }
+            },
+        );
+
+        Ok(stop_tx)
+    }
+}
+
+/// Same as the ChildID used from the order root secret to derive order owner
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+)]
+pub struct OrderId(pub u64);
+
+impl OrderId {
+    pub fn into_key_pair(&self, root_secret: DerivableSecret) -> KeyPair {
+        derive_key(&root_secret, KeyDomain::Order, self.0)
+    }
+
+    /// Public key that owns the order on the federation.
+    pub fn owner_public_key(&self, root_secret: DerivableSecret) -> PublicKey {
+        self.into_key_pair(root_secret).public_key()
+    }
+}
+
+impl FromStr for OrderId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(u64::from_str(s)?))
+    }
+}
+
+/// Remaining part of an order, shared between clients as a bech32m string
+/// by [PredictionMarketsClientModule::export_order_quote].
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct SharedQuote {
+    pub market: OutPoint,
+    pub outcome: Outcome,
+    pub side: Side,
+    pub price: Amount,
+    /// Quantity waiting for match when the quote was exported
+    pub quantity: ContractOfOutcomeAmount,
+}
+
+impl SharedQuote {
+    const HRP: Hrp = Hrp::parse_unchecked("pmquote");
+}
+
+impl fmt::Display for SharedQuote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = Vec::new();
+        self.consensus_encode(&mut bytes)
+            .expect("writing to a vec can not fail");
+        let s = bech32::encode::<Bech32m>(Self::HRP, &bytes).map_err(|_| fmt::Error)?;
+
+        f.write_str(&s)
+    }
+}
+
+impl FromStr for SharedQuote {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hrp, bytes) =
+            bech32::decode(s).map_err(|_| PredictionMarketsClientError::InvalidSharedQuote)?;
+        if hrp != Self::HRP {
+            bail!(PredictionMarketsClientError::InvalidSharedQuote)
+        }
+
+        let mut reader = bytes.as_slice();
+        let quote = Self::consensus_decode(&mut reader, &ModuleDecoderRegistry::default())
+            .map_err(|_| PredictionMarketsClientError::InvalidSharedQuote)?;
+        if !reader.is_empty() {
+            bail!(PredictionMarketsClientError::InvalidSharedQuote)
+        }
+
+        Ok(quote)
+    }
+}
+
+/// Encoded by [PredictionMarketsClientModule::export_order_cache].
+#[derive(Debug, Clone, Encodable, Decodable, PartialEq, Eq)]
+struct OrderCacheExport {
+    /// Owner of order id 0, tells whether the export was created by a client
+    /// with the same root secret
+    first_order_owner: PublicKey,
+    highest_order_id: Option<OrderId>,
+    orders: BTreeMap<OrderId, Order>,
+}
+
+/// Position in the pages returned by
+/// [PredictionMarketsClientModule::get_orders_from_db_paginated].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct OrderCursor(OrderId);
+
+pub fn market_outpoint_from_tx_id(tx_id: TransactionId) -> OutPoint {
+    OutPoint {
+        txid: tx_id,
+        out_idx: 0,
+    }
+}
+
+/// Returned by [PredictionMarketsClientModule::close_all_positions].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct CloseAllSummary {
+    pub cancelled_orders: Vec<OrderId>,
+    /// Bitcoin balance of all orders sent to the primary module, before
+    /// fees
+    pub withdrawn: Amount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct AvailableBitcoinBalance {
+    /// Sum of the bitcoin balance of all orders
+    pub total: Amount,
+    /// Fees paid for consuming each order's bitcoin balance
+    pub fees: Amount,
+    /// `total` minus `fees`
+    pub withdrawable: Amount,
+    pub by_order: BTreeMap<OrderId, Amount>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NewMarketResult {
+    pub out_point: OutPoint,
+    /// See [PredictionMarketsClientModule::get_operation_status]
+    pub operation_id: OperationId,
+    pub event_hash_hex: PredictionMarketEventHashHex,
+    pub outcome_count: Outcome,
+    /// Titles of the outcomes, empty if the event information has none
+    pub outcome_titles: Vec<String>,
+    /// Event information, such as outcome titles
+    pub information: Information,
+}
+
+/// Registered by [PredictionMarketsClientModule::register_dead_mans_switch].
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct DeadMansSwitch {
+    /// Longest time without a heartbeat before the switch expires
+    pub interval: Seconds,
+    /// Orders cancelled when the switch expires
+    pub filter: OrderFilter,
+    /// Last time the module was known to be alive
+    pub last_heartbeat: UnixTimestamp,
+}
+
+impl DeadMansSwitch {
+    pub fn expired(&self, now: UnixTimestamp) -> bool {
+        now.0.saturating_sub(self.last_heartbeat.0) > self.interval
+    }
+}
+
+/// Newest candlestick seen for a market outcome when its orders were last
+/// synced by [PredictionMarketsClientModule::sync_matches].
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct OrderSyncCursor {
+    pub candlestick_timestamp: UnixTimestamp,
+    pub candlestick_volume: ContractOfOutcomeAmount,
+}
+
+/// Whether the federation accepted the transaction submitted by an
+/// operation.
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub enum OperationOutcome {
+    Accepted,
+    Rejected { reason: String },
+}
+
+/// Returned by [PredictionMarketsClientModule::health_check].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ClientHealth {
+    /// The federation answered within [HEALTH_CHECK_TIMEOUT]
+    pub federation_reachable: bool,
+    /// Last time orders were synced from the federation. `None` if never.
+    pub last_order_sync: Option<UnixTimestamp>,
+    /// Orders whose cached state goes stale when they are matched
+    pub orders_waiting_for_match: usize,
+    /// Highest order id used, reserved or pruned. `None` before the first
+    /// order.
+    pub highest_order_id: Option<OrderId>,
+}
+
+/// Entry of the log read by
+/// [PredictionMarketsClientModule::get_submitted_transactions].
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct SubmittedTransaction {
+    pub submitted_at: UnixTimestamp,
+    pub summary: SubmittedTransactionSummary,
+}
+
+/// What a transaction submitted by the module does.
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub enum SubmittedTransactionSummary {
+    NewMarket {
+        outcome_count: Outcome,
+        contract_price: Amount,
+    },
+    PayoutMarket {
+        market: OutPoint,
+    },
+    NewOrder {
+        order_id: OrderId,
+        market: OutPoint,
+        outcome: Outcome,
+        side: Side,
+        price: Amount,
+        quantity: ContractOfOutcomeAmount,
+        /// Orders whose contracts of outcome fund a sell order
+        sources: Vec<OrderId>,
+    },
+    CancelOrders {
+        order_ids: Vec<OrderId>,
+    },
+    ConsumeOrderBitcoinBalance {
+        order_ids: Vec<OrderId>,
+        amount: Amount,
+    },
+    /// Orders placed together by
+    /// [PredictionMarketsClientModule::submit_multi_market]
+    NewOrders {
+        order_ids: Vec<OrderId>,
+        /// Orders whose contracts of outcome fund the sell orders
+        sources: Vec<OrderId>,
+    },
+}
+
+/// Returned by [PredictionMarketsClientModule::get_operation_status].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum PredictionMarketOperationStatus {
+    /// Transaction submitted, federation has not decided yet
+    Pending,
+    Accepted,
+    /// Transaction rejected by the federation
+    Failed {
+        reason: String,
+    },
+    /// Given up on by [PredictionMarketsClientModule::abandon_operation],
+    /// whatever the federation decided
+    Abandoned,
+}
+
+/// Balances of an order when a changed version of it was cached, see
+/// [PredictionMarketsClientModule::get_order_ledger].
+#[derive(Debug, Clone, Encodable, Decodable, PartialEq, Eq)]
+pub struct OrderBalanceRecord {
+    pub recorded_at: UnixTimestamp,
+    pub quantity_waiting_for_match: ContractOfOutcomeAmount,
+    pub quantity_fulfilled: ContractOfOutcomeAmount,
+    pub contract_of_outcome_balance: ContractOfOutcomeAmount,
+    pub bitcoin_balance: Amount,
+    pub bitcoin_acquired_from_payout: Amount,
+}
+
+impl OrderBalanceRecord {
+    fn from_order(recorded_at: UnixTimestamp, order: &Order) -> Self {
+        Self {
+            recorded_at,
+            quantity_waiting_for_match: order.quantity_waiting_for_match,
+            quantity_fulfilled: order.quantity_fulfilled,
+            contract_of_outcome_balance: order.contract_of_outcome_balance,
+            bitcoin_balance: order.bitcoin_balance,
+            bitcoin_acquired_from_payout: order.bitcoin_acquired_from_payout,
+        }
+    }
+}
+
+/// Returned by [PredictionMarketsClientModule::get_order_ledger].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LedgerEntry {
+    /// When the client cached the change, not when the federation made it
+    pub timestamp: UnixTimestamp,
+    pub kind: LedgerEntryKind,
+    pub bitcoin_balance_delta: SignedAmount,
+    pub contract_of_outcome_balance_delta: i64,
+    pub bitcoin_balance: Amount,
+    pub contract_of_outcome_balance: ContractOfOutcomeAmount,
+}
+
+/// What caused a [LedgerEntry]. When several things happened between two
+/// syncs, the first matching kind in declaration order is used.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LedgerEntryKind {
+    /// First version of the order seen by the client, including any matches
+    /// made when it was placed
+    Opened,
+    /// The market was paid out
+    Payout,
+    /// Quantity waiting for match was matched
+    Fill,
+    /// Quantity waiting for match was cancelled
+    Cancel,
+    /// Bitcoin balance was consumed, such as by
+    /// [PredictionMarketsClientModule::send_order_bitcoin_balance_to_primary_module]
+    Withdrawal,
+    /// Contracts of outcome were used to fund a sell order
+    ContractsSpent,
+}
+
+fn order_ledger_entries(records: &[OrderBalanceRecord]) -> Vec<LedgerEntry> {
+    let opened = OrderBalanceRecord {
+        recorded_at: UnixTimestamp::ZERO,
+        quantity_waiting_for_match: ContractOfOutcomeAmount::ZERO,
+        quantity_fulfilled: ContractOfOutcomeAmount::ZERO,
+        contract_of_outcome_balance: ContractOfOutcomeAmount::ZERO,
+        bitcoin_balance: Amount::ZERO,
+        bitcoin_acquired_from_payout: Amount::ZERO,
+    };
+
+    iter::once(&opened)
+        .chain(records)
+        .zip(records)
+        .enumerate()
+        .map(|(i, (previous, record))| {
+            let kind = if i == 0 {
+                LedgerEntryKind::Opened
+            } else if record.bitcoin_acquired_from_payout > previous.bitcoin_acquired_from_payout {
+                LedgerEntryKind::Payout
+            } else if record.quantity_fulfilled > previous.quantity_fulfilled {
+                LedgerEntryKind::Fill
+            } else if record.quantity_waiting_for_match < previous.quantity_waiting_for_match {
+                LedgerEntryKind::Cancel
+            } else if record.bitcoin_balance < previous.bitcoin_balance {
+                LedgerEntryKind::Withdrawal
+            } else {
+                LedgerEntryKind::ContractsSpent
+            };
+
+            LedgerEntry {
+                timestamp: record.recorded_at,
+                kind,
+                bitcoin_balance_delta: SignedAmount::from(record.bitcoin_balance)
+                    - SignedAmount::from(previous.bitcoin_balance),
+                contract_of_outcome_balance_delta: record.contract_of_outcome_balance.0 as i64
+                    - previous.contract_of_outcome_balance.0 as i64,
+                bitcoin_balance: record.bitcoin_balance,
+                contract_of_outcome_balance: record.contract_of_outcome_balance,
+            }
+        })
+        .collect()
+}
+
+/// An [Order] with its client side tag, see
+/// [PredictionMarketsClientModule::set_order_tag].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TaggedOrder {
+    #[serde(flatten)]
+    pub order: Order,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub tag: Option<String>,
+}
+
+/// Returned by [PredictionMarketsClientModule::import_payout_control_names].
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PayoutControlNamesImport {
+    /// Names assigned
+    pub imported: usize,
+    /// Names skipped because their payout control is not a valid public key
+    pub malformed: Vec<String>,
+}
+
+/// Entry of [PredictionMarketsClientModule::get_payout_control_weight_map_named].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NamedPayoutControlWeight {
+    /// Name of the payout control, or a truncated hex of its public key
+    pub label: String,
+    /// Weight of the payout control in the market
+    pub weight: Weight,
+}
+
+/// Client local replacement of the text a market is displayed with, see
+/// [PredictionMarketsClientModule::set_market_display_override]. `None`
+/// fields keep the text from the event.
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct MarketDisplayOverride {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Indexed by outcome
+    pub outcome_titles: Option<Vec<String>>,
+}
+
+/// Parameters of markets created with
+/// [PredictionMarketsClientModule::new_market_from_template].
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct MarketTemplate {
+    /// Event whose json strings may contain `{placeholder}` tokens, see
+    /// [fill_market_template]. Its nonce is replaced for every market.
+    pub event_json: PredictionMarketEventJson,
+    pub contract_price: Amount,
+    pub payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
+    pub weight_required_for_payout: WeightRequiredForPayout,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct SavedMarketMeta {
+    pub saved_at: UnixTimestamp,
+    pub note: Option<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct OrderBookInformation {
+    pub buys: BTreeMap<Amount, ContractOfOutcomeAmount>,
+    pub sells: BTreeMap<Amount, ContractOfOutcomeAmount>,
+    pub contract_price: Amount,
+    pub outcome_count: Outcome,
+}
+
+impl OrderBookInformation {
+    /// Implied probability (price / contract_price) of each price level in
+    /// `buys` and `sells`. Empty if `contract_price` is zero.
+    pub fn implied_probabilities(&self) -> BTreeMap<Amount, f64> {
+        // avoid dividing by zero, leave probabilities empty instead
+        if self.contract_price == Amount::ZERO {
+            return BTreeMap::new();
+        }
+
+        self.buys
+            .keys()
+            .chain(self.sells.keys())
+            .map(|price| {
+                (
+                    *price,
+                    price.msats as f64 / self.contract_price.msats as f64,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Returned by [PredictionMarketsClientModule::get_binary_market_bbo].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BinaryMarketBbo {
+    pub yes: BestBidOffer,
+    pub no: BestBidOffer,
+}
+
+/// Best prices in the order book of an outcome. `None` if that side of the
+/// order book is empty.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BestBidOffer {
+    /// Highest buy price
+    pub bid: Option<Amount>,
+    /// Lowest sell price
+    pub ask: Option<Amount>,
+}
+
+/// Outcome of a binary market that pays out if the event happens.
+pub const YES_OUTCOME: Outcome = 0;
+
+/// Outcome of a binary market that pays out if the event does not happen.
+pub const NO_OUTCOME: Outcome = 1;
+
+/// Outcome of a binary market for a yes/no answer, see [YES_OUTCOME] and
+/// [NO_OUTCOME].
+pub fn binary_outcome(yes: bool) -> Outcome {
+    match yes {
+        true => YES_OUTCOME,
+        false => NO_OUTCOME,
+    }
+}
+
+/// Price of a contract of outcome with the implied `probability`, rounded to
+/// the nearest msat. Fails with
+/// [PredictionMarketsClientError::InvalidProbability] unless `probability` is
+/// strictly between 0 and 1 and the rounded price is strictly between zero
+/// and `contract_price`, which rules out probabilities too close to 0 or 1
+/// for the contract price.
+pub fn price_from_probability(
+    contract_price: Amount,
+    probability: f64,
+) -> Result<Amount, PredictionMarketsClientError> {
+    if !(probability > 0.0 && probability < 1.0) {
+        return Err(PredictionMarketsClientError::InvalidProbability);
+    }
+
+    let price = Amount::from_msats((probability * contract_price.msats as f64).round() as u64);
+    if price == Amount::ZERO || price >= contract_price {
+        return Err(PredictionMarketsClientError::InvalidProbability);
+    }
+
+    Ok(price)
+}
+
+/// Finds the outcome titles in serialized event information. Information
+/// variants that have titles keep them in an `outcome_titles` field.
+fn outcome_titles(information: &Information) -> Option<Vec<String>> {
+    fn find(value: &serde_json::Value) -> Option<Vec<String>> {
+        let serde_json::Value::Object(map) = value else {
+            return None;
+        };
+        match map.get("outcome_titles") {
+            Some(titles) => serde_json::from_value(titles.to_owned()).ok(),
+            None => map.values().find_map(find),
+        }
+    }
+
+    find(&serde_json::to_value(information).ok()?)
+}
+
+/// Outcome whose title equals `title`, ignoring case. Fails with
+/// [PredictionMarketsClientError::UnknownOutcomeTitle] if no title matches
+/// and with [PredictionMarketsClientError::AmbiguousOutcomeTitle] if more than
+/// one does.
+pub fn outcome_by_title(
+    titles: &[String],
+    title: &str,
+) -> Result<Outcome, PredictionMarketsClientError> {
+    let title_lowercase = title.to_lowercase();
+    let mut matching = titles
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.to_lowercase() == title_lowercase)
+        .map(|(i, _)| i);
+
+    match (matching.next(), matching.next()) {
+        (Some(i), None) => Outcome::try_from(i)
+            .map_err(|_| PredictionMarketsClientError::UnknownOutcomeTitle(title.to_owned())),
+        (Some(_), Some(_)) => Err(PredictionMarketsClientError::AmbiguousOutcomeTitle(
+            title.to_owned(),
+        )),
+        (None, _) => Err(PredictionMarketsClientError::UnknownOutcomeTitle(
+            title.to_owned(),
+        )),
+    }
+}
+
+/// Replaces each `{placeholder}` in `template` with its entry in
+/// `substitutions`. Placeholders are ascii letters, digits and underscores
+/// in braces, so the braces of json objects are left alone. Substitutions
+/// are escaped for use inside json strings and are not searched for
+/// placeholders themselves. Fails with
+/// [PredictionMarketsClientError::MissingTemplateSubstitution] naming the
+/// first placeholder without a substitution.
+pub fn fill_market_template(
+    template: &str,
+    substitutions: &BTreeMap<String, String>,
+) -> Result<String, PredictionMarketsClientError> {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        let after_brace = &rest[start + 1..];
+        let name_len = after_brace
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after_brace.len());
+        let name = &after_brace[..name_len];
+        if name.is_empty() || !after_brace[name_len..].starts_with('}') {
+            filled.push('{');
+            rest = after_brace;
+            continue;
+        }
+
+        let Some(substitution) = substitutions.get(name) else {
+            return Err(PredictionMarketsClientError::MissingTemplateSubstitution(
+                name.to_owned(),
+            ));
+        };
+        let escaped = serde_json::to_string(substitution).expect("strings serialize to json");
+        filled.push_str(&escaped[1..escaped.len() - 1]);
+        rest = &after_brace[name_len + 1..];
+    }
+    filled.push_str(rest);
+
+    Ok(filled)
+}
+
+/// Order about to be submitted, as passed to the hook set by
+/// [PredictionMarketsClientModule::set_pre_order_hook].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct OrderRequest {
+    pub market: OutPoint,
+    pub outcome: Outcome,
+    pub side: Side,
+    pub price: Amount,
+    pub quantity: ContractOfOutcomeAmount,
+}
+
+/// Returns the reason as an error to reject the order.
+pub type PreOrderHook = Arc<dyn Fn(&OrderRequest) -> Result<(), String> + Send + Sync>;
+
+struct PreOrderHookSlot(Option<PreOrderHook>);
+
+impl fmt::Debug for PreOrderHookSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PreOrderHookSlot")
+            .field(&self.0.as_ref().map(|_| "hook"))
+            .finish()
+    }
+}
+
+/// Price of an order created by
+/// [PredictionMarketsClientModule::new_order_with_type].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum OrderType {
+    /// Order at `price`. Any quantity not matched immediately waits in the
+    /// order book.
+    Limit { price: Amount },
+    /// Order that matches immediately against the order book. The order is
+    /// priced at the worst price level within `max_slippage` of the best
+    /// price, and its quantity is reduced to what the order book can fill at
+    /// those prices. If the order book changes before the order is accepted,
+    /// the unmatched quantity waits in the order book like a limit order.
+    Market { max_slippage: Amount },
+    /// Limit order at `price` that is not submitted if it would match
+    /// immediately, so it only ever waits in the order book. Checked against
+    /// the order books when they are fetched. The federation matches the
+    /// order when it is accepted, so it can still match if the order books
+    /// change in between.
+    PostOnly { price: Amount },
+}
+
+/// Simulates how the federation matches a new order against `books`, the
+/// order book of each outcome of a market. An order matches either the
+/// opposite side of its own outcome, or the same side of every other outcome
+/// at `contract_price` minus the sum of their prices.
+///
+/// Walks price levels up to `max_slippage` from the best price and up to
+/// `limit_price`, each without a limit if `None`. Returns the quantity matched
+/// at each price level, best price first.
+fn walk_order_books(
+    mut books: BTreeMap<Outcome, OrderBookInformation>,
+    contract_price: Amount,
+    outcome: Outcome,
+    side: Side,
+    quantity: ContractOfOutcomeAmount,
+    max_slippage: Option<Amount>,
+    limit_price: Option<Amount>,
+) -> Vec<(Amount, ContractOfOutcomeAmount)> {
+    // best level is the lowest sell or the highest buy
+    fn levels(
+        book: &mut OrderBookInformation,
+        side: Side,
+    ) -> Option<OccupiedEntry<'_, Amount, ContractOfOutcomeAmount>> {
+        match side {
+            Side::Buy => book.buys.last_entry(),
+            Side::Sell => book.sells.first_entry(),
+        }
+    }
+
+    let opposite_side = match side {
+        Side::Buy => Side::Sell,
+        Side::Sell => Side::Buy,
+    };
+
+    let mut slippage_limit = None;
+    let mut filled = ContractOfOutcomeAmount::ZERO;
+    let mut matches: Vec<(Amount, ContractOfOutcomeAmount)> = vec![];
+    while filled < quantity {
+        let Some(book) = books.get_mut(&outcome) else {
+            break;
+        };
+        let own = levels(book, opposite_side).map(|e| (*e.key(), *e.get()));
+
+        // every other outcome needs an order on the same side
+        let mut other = (books.len() > 1).then_some((Amount::ZERO, quantity - filled));
+        for (_, book) in books.iter_mut().filter(|(o, _)| **o != outcome) {
+            other = match (other, levels(book, side)) {
+                (Some((price_sum, q)), Some(e)) => Some((price_sum + *e.key(), q.min(*e.get()))),
+                _ => None,
+            };
+        }
+        let other = other.and_then(|(price_sum, q)| match side {
+            Side::Buy => Some((contract_price.saturating_sub(price_sum), q)),
+            Side::Sell => contract_price
+                .checked_sub(price_sum)
+                .map(|price| (price, q)),
+        });
+
+        let (price, level_quantity, matches_own) = match (own, other) {
+            (Some(own), Some(other)) => {
+                let own_is_better = match side {
+                    Side::Buy => own.0 <= other.0,
+                    Side::Sell => own.0 >= other.0,
+                };
+                match own_is_better {
+                    true => (own.0, own.1, true),
+                    false => (other.0, other.1, false),
+                }
+            }
+            (Some(own), None) => (own.0, own.1, true),
+            (None, Some(other)) => (other.0, other.1, false),
+            (None, None) => break,
+        };
+
+        if let Some(max_slippage) = max_slippage {
+            slippage_limit.get_or_insert(match side {
+                // no price is above the contract price
+                Side::Buy => Amount::from_msats(price.msats.saturating_add(max_slippage.msats))
+                    .min(contract_price),
+                Side::Sell => price.saturating_sub(max_slippage),
+            });
+        }
+        let within_limits = [slippage_limit, limit_price]
+            .into_iter()
+            .flatten()
+            .all(|limit| match side {
+                Side::Buy => price <= limit,
+                Side::Sell => price >= limit,
+            });
+        if !within_limits {
+            break;
+        }
+
+        let matched = level_quantity.min(quantity - filled);
+        for (o, book) in books.iter_mut() {
+            let matched_side = match (*o == outcome, matches_own) {
+                (true, true) => opposite_side,
+                (false, false) => side,
+                _ => continue,
+            };
+            let mut level = levels(book, matched_side).expect("level was matched");
+            *level.get_mut() -= matched;
+            if *level.get() == ContractOfOutcomeAmount::ZERO {
+                level.remove();
+            }
+        }
+
+        filled += matched;
+        match matches.last_mut() {
+            Some((last_price, last_quantity)) if *last_price == price => *last_quantity += matched,
+            _ => matches.push((price, matched)),
+        }
+    }
+
+    matches
+}
+
+fn matches_quantity(matches: &[(Amount, ContractOfOutcomeAmount)]) -> ContractOfOutcomeAmount {
+    matches
+        .iter()
+        .fold(ContractOfOutcomeAmount::ZERO, |acc, (_, quantity)| {
+            acc + *quantity
+        })
+}
+
+/// Sum of price times quantity of `matches`.
+fn matches_total(matches: &[(Amount, ContractOfOutcomeAmount)]) -> Amount {
+    matches.iter().fold(Amount::ZERO, |acc, (price, quantity)| {
+        acc + *price * quantity.0
+    })
+}
+
+/// Quote for filling `quantity` on `side` with `matches`, as returned by
+/// [walk_order_books].
+fn fill_quote(
+    matches: &[(Amount, ContractOfOutcomeAmount)],
+    side: Side,
+    quantity: ContractOfOutcomeAmount,
+) -> FillQuote {
+    let filled = matches_quantity(matches);
+    let total = matches_total(matches);
+    let worst_price = matches.last().map(|(price, _)| *price);
+    let total_at_worst_price = worst_price.map_or(Amount::ZERO, |price| price * filled.0);
+
+    FillQuote {
+        quantity: filled,
+        total,
+        average_price: (filled != ContractOfOutcomeAmount::ZERO)
+            .then(|| Amount::from_msats((total.msats + filled.0 / 2) / filled.0)),
+        worst_price,
+        price_improvement: match side {
+            Side::Buy => total_at_worst_price.saturating_sub(total),
+            Side::Sell => total.saturating_sub(total_at_worst_price),
+        },
+        partial: filled < quantity,
+    }
+}
+
+/// Returned by [PredictionMarketsClientModule::quote_fill] and
+/// [PredictionMarketsClientModule::quote_market_order].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct FillQuote {
+    /// Quantity the order books can fill, at most the requested quantity
+    pub quantity: ContractOfOutcomeAmount,
+    /// Sum of the price of each filled contract. Paid when buying, received
+    /// when selling.
+    pub total: Amount,
+    /// `total` divided by `quantity`, rounded to the nearest msat. `None` if
+    /// nothing can be filled.
+    pub average_price: Option<Amount>,
+    /// Price of the worst level touched, which is the limit price an order
+    /// needs to fill `quantity`. `None` if nothing can be filled.
+    pub worst_price: Option<Amount>,
+    /// How much better `total` is than filling all of `quantity` at
+    /// `worst_price`. Levels better than the limit price are matched at their
+    /// own price, so an order at `worst_price` gets this back in its bitcoin
+    /// balance.
+    pub price_improvement: Amount,
+    /// The order books can not fill the requested quantity
+    pub partial: bool,
+}
+
+/// Returned by [PredictionMarketsClientModule::simulate_order_match].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct MatchSimulation {
+    /// Quantity matched at each price level, best price first
+    pub matches: Vec<(Amount, ContractOfOutcomeAmount)>,
+    /// Quantity matched immediately
+    pub matched_quantity: ContractOfOutcomeAmount,
+    /// Sum of the price of each matched contract. Paid when buying, received
+    /// when selling.
+    pub matched_total: Amount,
+    /// Quantity that waits in the order book at the order's price
+    pub resting_quantity: ContractOfOutcomeAmount,
+}
+
+/// Returned by [PredictionMarketsClientModule::market_making_capital_estimate].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct CapitalEstimate {
+    /// Paid by the primary module: the price of every buy quote plus the new
+    /// order fee of every quote
+    pub bitcoin_required: Amount,
+    /// New order fees, included in `bitcoin_required`
+    pub fees: Amount,
+    /// Contracts of outcome sold by the sell quotes
+    pub contracts_required: ContractOfOutcomeAmount,
+    /// Contracts of outcome held by cached orders of the outcome
+    pub contracts_held: ContractOfOutcomeAmount,
+    /// `contracts_required` minus `contracts_held`, or zero if enough are held
+    pub additional_contracts_required: ContractOfOutcomeAmount,
+}
+
+/// Returned by [PredictionMarketsClientModule::get_market_outcome_stats].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct OutcomeStats {
+    /// Sum of the volume of every candlestick still kept by the federation
+    pub volume: ContractOfOutcomeAmount,
+    /// Total quantity of buy orders in the order book
+    pub resting_bid_quantity: ContractOfOutcomeAmount,
+    /// Total quantity of sell orders in the order book
+    pub resting_ask_quantity: ContractOfOutcomeAmount,
+    /// Close of the newest candlestick. `None` if the outcome has not traded.
+    pub last_trade_price: Option<Amount>,
+}
+
+/// Returned by [PredictionMarketsClientModule::get_market_summary].
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketSummary {
+    pub market: OutPoint,
+    /// Event information, such as the title
+    pub information: Information,
+    /// Set by [PredictionMarketsClientModule::set_market_display_override] on
+    /// this client only. Takes precedence over `information` for display and
+    /// its outcome titles are used for `outcomes`.
+    pub display_override: Option<MarketDisplayOverride>,
+    pub contract_price: Amount,
+    pub status: MarketStatus,
+    /// Indexed by outcome
+    pub outcomes: Vec<OutcomeSummary>,
+    /// Sum of the volume of every outcome
+    pub volume: ContractOfOutcomeAmount,
+}
+
+/// Whether a market can still be traded.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum MarketStatus {
+    Open,
+    /// Closed for trading, see [PredictionMarketsClientModule::get_market_payout]
+    PaidOut,
+}
+
+/// Outcome of a [MarketSummary].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OutcomeSummary {
+    /// `None` if the event information has no outcome titles
+    pub title: Option<String>,
+    /// `None` if the market creator set no display hints
+    pub display_hint: Option<OutcomeDisplayHint>,
+    pub bbo: BestBidOffer,
+    /// Sum of the volume of every candlestick still kept by the federation
+    pub volume: ContractOfOutcomeAmount,
+}
+
+/// Limit on concurrent requests made by
+/// [PredictionMarketsClientModule::get_market_candlesticks].
+pub const MAX_CONCURRENT_CANDLESTICK_REQUESTS: usize = 8;
+
+/// Limit on outcomes whose order book and candlesticks are fetched at the
+/// same time by [PredictionMarketsClientModule::get_market_summary].
+pub const MAX_CONCURRENT_MARKET_SUMMARY_OUTCOMES: usize = 4;
+
+/// Limit on markets checked for a payout at the same time by
+/// [PredictionMarketsClientModule::reconcile_settlements].
+pub const MAX_CONCURRENT_SETTLEMENT_CHECKS: usize = 8;
+
+/// Limit on orders fetched at the same time by
+/// [PredictionMarketsClientModule::import_order_cache] when verifying.
+pub const MAX_CONCURRENT_ORDER_IMPORT_CHECKS: usize = 16;
+
+/// Highest order id [PredictionMarketsClientModule::import_order_cache]
+/// accepts, since every id up to it is marked as used.
+pub const MAX_IMPORTED_ORDER_ID: u64 = 1_000_000;
+
+/// Most order ids [PredictionMarketsClientModule::reserve_order_ids] reserves
+/// in a single call.
+pub const MAX_RESERVED_ORDER_IDS: usize = 1000;
+
+/// How often [PredictionMarketsClientModule::subscribe_all_order_updates]
+/// checks the local cache for changed orders.
+pub const ORDER_UPDATES_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often [PredictionMarketsClientModule::subscribe_all_order_updates]
+/// syncs orders waiting for a match from the federation.
+pub const ORDER_UPDATES_SYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Longest delay between syncs of
+/// [PredictionMarketsClientModule::start_background_sync] after repeated
+/// failures.
+pub const MAX_BACKGROUND_SYNC_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Longest time [PredictionMarketsClientModule::health_check] waits on the
+/// federation.
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most markets whose parsed event or outcome titles are kept in memory.
+pub const MAX_CACHED_MARKETS: usize = 1000;
+
+/// How long [PredictionMarketsClientModule::get_consensus_timestamp] reuses a
+/// consensus timestamp before asking the federation again.
+pub const CONSENSUS_TIMESTAMP_CACHE_DURATION: Duration = Duration::from_secs(5);
+
+/// How many times a dead man's switch is refreshed during its interval, so
+/// that a late heartbeat does not expire it while the module is alive.
+pub const DEAD_MANS_SWITCH_HEARTBEATS_PER_INTERVAL: u32 = 3;
+
+/// How [PredictionMarketsClientModule::get_candlesticks] handles candlesticks
+/// that fail [check_candlestick].
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum CandlestickValidation {
+    /// Fix the candlestick with [repair_candlestick]
+    #[default]
+    Repair,
+    /// Fail with [PredictionMarketsClientError::CorruptCandlestick]
+    Strict,
+}
+
+/// Checks that `high` is not below `low` and that `open` and `close` are
+/// between them.
+pub fn check_candlestick(candlestick: &Candlestick) -> Result<(), CandlestickViolation> {
+    let range = candlestick.low..=candlestick.high;
+    if candlestick.high < candlestick.low {
+        return Err(CandlestickViolation::HighBelowLow);
+    }
+    if !range.contains(&candlestick.open) {
+        return Err(CandlestickViolation::OpenOutOfRange);
+    }
+    if !range.contains(&candlestick.close) {
+        return Err(CandlestickViolation::CloseOutOfRange);
+    }
+
+    Ok(())
+}
+
+/// Widens `high` and `low` to cover every price of the candlestick. `open`,
+/// `close` and `volume` are kept as they are.
+pub fn repair_candlestick(candlestick: Candlestick) -> Candlestick {
+    let prices = [
+        candlestick.open,
+        candlestick.close,
+        candlestick.high,
+        candlestick.low,
+    ];
+
+    Candlestick {
+        high: prices.into_iter().max().expect("not empty"),
+        low: prices.into_iter().min().expect("not empty"),
+        ..candlestick
+    }
+}
+
+/// How [fill_candlestick_gaps] fills intervals without a candlestick.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum FillMode {
+    /// Leave empty intervals as `None`
+    None,
+    /// Use a flat candlestick at the previous candlestick's close with zero
+    /// volume
+    Carry,
+}
+
+/// Turns candlesticks keyed by timestamp into a contiguous series with one
+/// entry per `candlestick_interval` from the first to the last candlestick.
+/// Timestamps are rounded down to interval boundaries.
+pub fn fill_candlestick_gaps(
+    candlesticks: &BTreeMap<UnixTimestamp, Candlestick>,
+    candlestick_interval: Seconds,
+    fill: FillMode,
+) -> Vec<(UnixTimestamp, Option<Candlestick>)> {
+    if candlestick_interval == 0 {
+        return candlesticks
+            .iter()
+            .map(|(t, c)| (*t, Some(c.to_owned())))
+            .collect();
+    }
+
+    let aligned: BTreeMap<UnixTimestamp, &Candlestick> = candlesticks
+        .iter()
+        .map(|(t, c)| (t.round_down(candlestick_interval), c))
+        .collect();
+    let (Some(first), Some(last)) = (aligned.keys().next(), aligned.keys().next_back()) else {
+        return Vec::new();
+    };
+
+    let mut series = Vec::new();
+    let mut previous_close: Option<Amount> = None;
+    let mut timestamp = *first;
+    while timestamp <= *last {
+        let candlestick = match aligned.get(&timestamp) {
+            Some(c) => Some((*c).to_owned()),
+            None => match fill {
+                FillMode::None => None,
+                FillMode::Carry => previous_close.map(|close| Candlestick {
+                    open: close,
+                    close,
+                    high: close,
+                    low: close,
+                    volume: ContractOfOutcomeAmount::ZERO,
+                }),
+            },
+        };
+        if let Some(c) = &candlestick {
+            previous_close = Some(c.close);
+        }
+
+        series.push((timestamp, candlestick));
+        timestamp = UnixTimestamp(timestamp.0 + candlestick_interval);
+    }
+
+    series
+}
+
+/// Close prices of every outcome normalized to sum to 1.0, for each timestamp
+/// any outcome has a candlestick at. The vector is indexed by outcome.
+///
+/// An outcome without a candlestick at a timestamp is assumed to still be at
+/// its previous close, and timestamps before every outcome has traded are
+/// left out. With sparse data a carried close may be far from where the
+/// outcome would trade, so the probabilities are only as fresh as the least
+/// recently traded outcome. Timestamps where every close is zero are left
+/// out too.
+pub fn normalize_candlestick_closes(
+    market_candlesticks: &BTreeMap<Outcome, BTreeMap<UnixTimestamp, Candlestick>>,
+) -> BTreeMap<UnixTimestamp, Vec<f64>> {
+    let timestamps: BTreeSet<UnixTimestamp> = market_candlesticks
+        .values()
+        .flat_map(|candlesticks| candlesticks.keys().copied())
+        .collect();
+
+    let mut last_closes: BTreeMap<Outcome, Amount> = BTreeMap::new();
+    let mut normalized = BTreeMap::new();
+    for timestamp in timestamps {
+        for (outcome, candlesticks) in market_candlesticks {
+            if let Some(candlestick) = candlesticks.get(&timestamp) {
+                last_closes.insert(*outcome, candlestick.close);
             }
+        }
+        if last_closes.len() != market_candlesticks.len() {
+            continue;
+        }
+
+        let total: u64 = last_closes.values().map(|close| close.msats).sum();
+        if total == 0 {
+            continue;
+        }
+        normalized.insert(
+            timestamp,
+            last_closes
+                .values()
+                .map(|close| close.msats as f64 / total as f64)
+                .collect(),
+        );
+    }
+
+    normalized
+}
+
+/// A [Candlestick] with a client side approximation of its trade count.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CandlestickWithTradeCount {
+    #[serde(flatten)]
+    pub candlestick: Candlestick,
+    /// Lower bound on the number of trades in the candlestick. `None` if the
+    /// finer-grained candlesticks needed to approximate it are no longer kept.
+    pub trade_count: Option<u64>,
+}
+
+/// Approximates the trade count of each candlestick by counting the
+/// finer-grained candlesticks inside it that have nonzero volume. Trades
+/// landing in the same fine candlestick are counted once, so the result is a
+/// lower bound. Candlesticks starting before `fine_coverage_start` get `None`.
+pub fn approximate_trade_counts(
+    candlesticks: &BTreeMap<UnixTimestamp, Candlestick>,
+    candlestick_interval: Seconds,
+    fine_candlesticks: &BTreeMap<UnixTimestamp, Candlestick>,
+    fine_coverage_start: UnixTimestamp,
+) -> BTreeMap<UnixTimestamp, CandlestickWithTradeCount> {
+    candlesticks
+        .iter()
+        .map(|(timestamp, candlestick)| {
+            let trade_count = (*timestamp >= fine_coverage_start).then(|| {
+                let end = UnixTimestamp(timestamp.0.saturating_add(candlestick_interval));
+                fine_candlesticks
+                    .range(*timestamp..end)
+                    .filter(|(_, c)| c.volume != ContractOfOutcomeAmount::ZERO)
+                    .count() as u64
+            });
+
+            (
+                *timestamp,
+                CandlestickWithTradeCount {
+                    candlestick: candlestick.to_owned(),
+                    trade_count,
+                },
+            )
+        })
+        .collect()
+}
 
-            OrderState::NonZeroQuantityWaitingForMatch => {
-                match filter.0 {
-                    OrderPath::All => {
-                        dbtx.find_by_prefix(&db::OrderPriceTimePriorityPrefixAll)
-                            .await
-                    }
-                    OrderPath::Market { market } => {
-                        dbtx.find_by_prefix(&db::OrderPriceTimePriorityPrefix1 { market })
-                            .await
-                    }
-                    OrderPath::MarketOutcome { market, outcome } => {
-                        dbtx.find_by_prefix(&db::OrderPriceTimePriorityPrefix2 { market, outcome })
-                            .await
-                    }
-                    OrderPath::MarketOutcomeSide {
-                        market,
-                        outcome,
-                        side,
-                    } => {
-                        dbtx.find_by_prefix(&db::OrderPriceTimePriorityPrefix3 {
-                            market,
-                            outcome,
-                            side,
-                        })
-                        .await
-                    }
-                }
-                .map(|(_, v)| v)
-                .collect()
-                .await
+/// Checks that an [EventPayout] has one entry per outcome of the event and
+/// that its units sum to the event's `units_to_payout`.
+pub fn validate_event_payout(
+    event: &Event,
+    event_payout: &EventPayout,
+) -> Result<(), PredictionMarketsClientError> {
+    let expected_outcomes = u64::from(event.outcome_count);
+    let actual_outcomes = event_payout.units_per_outcome.len() as u64;
+    if actual_outcomes != expected_outcomes {
+        return Err(
+            PredictionMarketsClientError::EventPayoutOutcomeCountMismatch {
+                expected: expected_outcomes,
+                actual: actual_outcomes,
+            },
+        );
+    }
+
+    let expected_units = u64::from(event.units_to_payout);
+    let actual_units: u64 = event_payout
+        .units_per_outcome
+        .iter()
+        .map(|u| u64::from(*u))
+        .sum();
+    if actual_units != expected_units {
+        return Err(PredictionMarketsClientError::EventPayoutUnitsSumMismatch {
+            expected: expected_units,
+            actual: actual_units,
+        });
+    }
+
+    event_payout
+        .validate(event)
+        .map_err(|_| PredictionMarketsClientError::EventPayoutValidationFailed)
+}
+
+/// Payout attestations that agree on the same [EventPayout], together with
+/// the payout controls that signed them and their summed weight.
+#[derive(Debug, Clone, Default)]
+pub struct EventPayoutAttestationGroup {
+    pub event_payout_attestations_json: Vec<PredictionMarketEventJson>,
+    pub payout_controls: BTreeSet<NostrPublicKeyHex>,
+    pub total_weight: WeightRequiredForPayout,
+}
+
+/// Groups payout attestations for a market by the [EventPayout] they attest
+/// to.
+///
+/// Attestations that fail to parse or are signed by a key that is not a
+/// payout control of the market are skipped. Only the first attestation seen
+/// from each payout control is counted.
+pub fn group_event_payout_attestations(
+    market: &Market,
+    event_payout_attestations_json: impl IntoIterator<Item = PredictionMarketEventJson>,
+) -> HashMap<EventPayout, EventPayoutAttestationGroup> {
+    let mut seen_payout_controls = HashSet::new();
+    let mut groups: HashMap<EventPayout, EventPayoutAttestationGroup> = HashMap::new();
+
+    for event_json in event_payout_attestations_json {
+        let Ok((payout_control, event_payout)) =
+            EventPayoutAttestation::interpret_nostr_event_json(&event_json)
+        else {
+            continue;
+        };
+        let Some(weight) = market.0.payout_control_weight_map.get(&payout_control.0) else {
+            continue;
+        };
+        if !seen_payout_controls.insert(payout_control.0.clone()) {
+            continue;
+        }
+
+        let group = groups.entry(event_payout).or_default();
+        group.event_payout_attestations_json.push(event_json);
+        group.payout_controls.insert(payout_control.0);
+        group.total_weight += WeightRequiredForPayout::from(*weight);
+    }
+
+    groups
+}
+
+/// Picks the [EventPayout] backed by the most payout control weight out of
+/// grouped attestations, see [group_event_payout_attestations]. Payouts that
+/// fail [validate_event_payout] are ignored.
+///
+/// Ties are broken by choosing the lexicographically smallest
+/// `units_per_outcome`, so every caller suggests the same payout for the same
+/// attestations.
+pub fn suggest_event_payout(
+    event: &Event,
+    groups: &HashMap<EventPayout, EventPayoutAttestationGroup>,
+) -> Option<EventPayout> {
+    groups
+        .iter()
+        .filter(|(event_payout, _)| validate_event_payout(event, event_payout).is_ok())
+        .max_by(|(a_payout, a_group), (b_payout, b_group)| {
+            a_group
+                .total_weight
+                .cmp(&b_group.total_weight)
+                .then_with(|| b_payout.units_per_outcome.cmp(&a_payout.units_per_outcome))
+        })
+        .map(|(event_payout, _)| event_payout.to_owned())
+}
+
+/// Payout per contract of each outcome if the market is paid out with
+/// `event_payout`, computed the same way as the federation does.
+pub fn payout_amount_per_outcome(
+    market: &Market,
+    event: &Event,
+    event_payout: &EventPayout,
+) -> Vec<Amount> {
+    let payout_scaling_factor = market.0.contract_price.msats / u64::from(event.units_to_payout);
+
+    event_payout
+        .units_per_outcome
+        .iter()
+        .map(|units| Amount::from_msats(u64::from(*units) * payout_scaling_factor))
+        .collect()
+}
+
+/// Progress item of
+/// [PredictionMarketsClientModule::resync_order_slots_streamed].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResyncProgress {
+    /// Order ids checked with the federation so far
+    pub scanned: usize,
+    /// Orders found so far
+    pub found: usize,
+    /// Last order id checked
+    pub current_id: OrderId,
+    /// Highest order id with an order. `None` if none were found.
+    pub highest_order_id: Option<OrderId>,
+    /// Set on the last item, once `gap_size_to_check` consecutive ids
+    /// had no order
+    pub complete: bool,
+}
+
+/// Progress item of [PredictionMarketsClientModule::sync_all_payouts].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PayoutSyncProgress {
+    pub market: OutPoint,
+    pub markets_processed: usize,
+    pub markets_total: usize,
+    pub result: PayoutSyncResult,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PayoutSyncResult {
+    /// The market already had a payout, its orders were synced
+    AlreadyPaidOut,
+    /// A payout was submitted using attestations found on nostr and the
+    /// market's orders were synced
+    PayoutSubmitted {
+        event_payout: EventPayout,
+    },
+    /// Payout controls have not attested to a payout with enough weight yet
+    NoPayoutAvailable,
+    Failed {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PayoutAttestationStatus {
+    /// Summed weight of the payout controls attesting to the payout
+    pub weight_attested: WeightRequiredForPayout,
+    pub weight_required_for_payout: WeightRequiredForPayout,
+    /// Weight still missing before the payout can be submitted
+    pub weight_remaining: WeightRequiredForPayout,
+    /// Weight of the caller's own payout control, zero if it is not one
+    pub own_weight: WeightRequiredForPayout,
+    /// Whether the caller has already attested to this payout
+    pub own_attestation_included: bool,
+    /// Whether adding the caller's attestation would reach
+    /// `weight_required_for_payout`
+    pub own_attestation_reaches_threshold: bool,
+    /// Attestations backing the payout, usable with
+    /// [PredictionMarketsClientModule::payout_market] once the threshold is
+    /// reached
+    pub event_payout_attestations_json: Vec<PredictionMarketEventJson>,
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::core::ModuleInstanceId;
+    use fedimint_core::db::mem_impl::MemDatabase;
+    use fedimint_core::module::registry::ModuleDecoderRegistry;
+    use fedimint_prediction_markets_common::{MarketStatic, MarketStaticV0, SignedAmount};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn pruned_order_slot_is_not_reused() {
+        let database = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let order = Order {
+            market: market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64)).unwrap()),
+            outcome: 0,
+            side: Side::Buy,
+            price: Amount::from_msats(50),
+            original_quantity: ContractOfOutcomeAmount(10),
+            time_ordering: 0,
+            created_consensus_timestamp: UnixTimestamp::ZERO,
+            quantity_waiting_for_match: ContractOfOutcomeAmount::ZERO,
+            contract_of_outcome_balance: ContractOfOutcomeAmount::ZERO,
+            bitcoin_balance: Amount::ZERO,
+            quantity_fulfilled: ContractOfOutcomeAmount::ZERO,
+            bitcoin_acquired_from_order_matches: SignedAmount::ZERO,
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        };
+
+        let mut dbtx = database.begin_transaction().await;
+        for id in [OrderId(0), OrderId(1)] {
+            PredictionMarketsClientModule::save_order_to_db(&mut dbtx.to_ref_nc(), id, &order)
+                .await;
+        }
+        PredictionMarketsClientModule::prune_order_from_db(
+            &mut dbtx.to_ref_nc(),
+            OrderId(1),
+            &order,
+        )
+        .await;
+
+        assert_eq!(
+            dbtx.get_value(&db::OrderKey(OrderId(1))).await,
+            Some(OrderIdSlot::Pruned)
+        );
+        let indexed: Vec<_> = dbtx
+            .find_by_prefix(&db::OrdersByMarketOutcomePrefixAll)
+            .await
+            .map(|(key, _)| key.order)
+            .collect()
+            .await;
+        assert_eq!(indexed, vec![OrderId(0)]);
+        let ledgers: Vec<_> = dbtx
+            .find_by_prefix(&db::OrderLedgerPrefixAll)
+            .await
+            .map(|(key, _)| key.order)
+            .collect()
+            .await;
+        assert_eq!(ledgers, vec![OrderId(0)]);
+
+        // the pruned slot still holds the id ceiling
+        assert_eq!(
+            PredictionMarketsClientModule::next_order_id(&mut dbtx.to_ref_nc()).await,
+            OrderId(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn needs_sync_for_submitted_and_resting_orders() {
+        let database = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let order = Order {
+            market: market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64)).unwrap()),
+            outcome: 0,
+            side: Side::Buy,
+            price: Amount::from_msats(50),
+            original_quantity: ContractOfOutcomeAmount(10),
+            time_ordering: 0,
+            created_consensus_timestamp: UnixTimestamp::ZERO,
+            quantity_waiting_for_match: ContractOfOutcomeAmount::ZERO,
+            contract_of_outcome_balance: ContractOfOutcomeAmount(10),
+            bitcoin_balance: Amount::ZERO,
+            quantity_fulfilled: ContractOfOutcomeAmount(10),
+            bitcoin_acquired_from_order_matches: SignedAmount::ZERO,
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        };
+
+        let mut dbtx = database.begin_transaction().await;
+        assert!(!PredictionMarketsClientModule::needs_sync_from_db(&mut dbtx.to_ref_nc()).await);
+
+        // an unused reservation is not waiting for anything
+        dbtx.insert_entry(&db::OrderKey(OrderId(0)), &OrderIdSlot::Reserved)
+            .await;
+        dbtx.insert_entry(&db::OrderIdReservationKey(OrderId(0)), &())
+            .await;
+        assert!(!PredictionMarketsClientModule::needs_sync_from_db(&mut dbtx.to_ref_nc()).await);
+
+        // the reservation was used by a new order that is not cached yet
+        dbtx.remove_entry(&db::OrderIdReservationKey(OrderId(0)))
+            .await;
+        dbtx.insert_entry(&db::OrderAwaitingCacheKey(OrderId(0)), &())
+            .await;
+        assert!(PredictionMarketsClientModule::needs_sync_from_db(&mut dbtx.to_ref_nc()).await);
+
+        // the order was accepted and fully matched
+        PredictionMarketsClientModule::save_order_to_db(&mut dbtx.to_ref_nc(), OrderId(0), &order)
+            .await;
+        assert!(!PredictionMarketsClientModule::needs_sync_from_db(&mut dbtx.to_ref_nc()).await);
+
+        let resting = Order {
+            quantity_waiting_for_match: ContractOfOutcomeAmount(10),
+            contract_of_outcome_balance: ContractOfOutcomeAmount::ZERO,
+            quantity_fulfilled: ContractOfOutcomeAmount::ZERO,
+            ..order
+        };
+        PredictionMarketsClientModule::save_order_to_db(
+            &mut dbtx.to_ref_nc(),
+            OrderId(1),
+            &resting,
+        )
+        .await;
+        assert!(PredictionMarketsClientModule::needs_sync_from_db(&mut dbtx.to_ref_nc()).await);
+    }
+
+    #[tokio::test]
+    async fn order_cache_export_round_trip() {
+        let root_secret = DerivableSecret::new_root(&[0; 32], &[0; 32]);
+        let first_order_owner = OrderId(0).owner_public_key(root_secret);
+        let order = Order {
+            market: market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64)).unwrap()),
+            outcome: 0,
+            side: Side::Buy,
+            price: Amount::from_msats(50),
+            original_quantity: ContractOfOutcomeAmount(10),
+            time_ordering: 0,
+            created_consensus_timestamp: UnixTimestamp::ZERO,
+            quantity_waiting_for_match: ContractOfOutcomeAmount(10),
+            contract_of_outcome_balance: ContractOfOutcomeAmount::ZERO,
+            bitcoin_balance: Amount::ZERO,
+            quantity_fulfilled: ContractOfOutcomeAmount::ZERO,
+            bitcoin_acquired_from_order_matches: SignedAmount::ZERO,
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        };
+
+        let source = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let mut dbtx = source.begin_transaction().await;
+        for id in [OrderId(0), OrderId(2)] {
+            PredictionMarketsClientModule::save_order_to_db(&mut dbtx.to_ref_nc(), id, &order)
+                .await;
+        }
+        dbtx.insert_entry(&db::OrderKey(OrderId(3)), &OrderIdSlot::Reserved)
+            .await;
+        let export = PredictionMarketsClientModule::order_cache_export(
+            &mut dbtx.to_ref_nc(),
+            first_order_owner,
+        )
+        .await;
+
+        let mut bytes = Vec::new();
+        export.consensus_encode(&mut bytes).unwrap();
+        let decoded =
+            OrderCacheExport::consensus_decode(&mut &bytes[..], &ModuleDecoderRegistry::default())
+                .unwrap();
+        assert_eq!(decoded, export);
+        assert_eq!(decoded.highest_order_id, Some(OrderId(3)));
+
+        let destination = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let mut dbtx = destination.begin_transaction().await;
+        let saved = PredictionMarketsClientModule::save_order_cache_export(
+            &mut dbtx.to_ref_nc(),
+            decoded.orders.clone(),
+            decoded.highest_order_id,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(saved, 2);
+
+        for (id, slot) in [
+            (0, OrderIdSlot::Order(order.clone())),
+            (1, OrderIdSlot::Pruned),
+            (2, OrderIdSlot::Order(order.clone())),
+            (3, OrderIdSlot::Pruned),
+        ] {
+            assert_eq!(dbtx.get_value(&db::OrderKey(OrderId(id))).await, Some(slot));
+        }
+        assert_eq!(
+            PredictionMarketsClientModule::next_order_id(&mut dbtx.to_ref_nc()).await,
+            OrderId(4)
+        );
+
+        // importing again needs force and skips cached orders
+        let err = PredictionMarketsClientModule::save_order_cache_export(
+            &mut dbtx.to_ref_nc(),
+            decoded.orders.clone(),
+            decoded.highest_order_id,
+            false,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PredictionMarketsClientError>(),
+            Some(PredictionMarketsClientError::OrderCacheNotEmpty)
+        ));
+        let saved = PredictionMarketsClientModule::save_order_cache_export(
+            &mut dbtx.to_ref_nc(),
+            decoded.orders,
+            decoded.highest_order_id,
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(saved, 0);
+    }
+
+    const MODULE_INSTANCE_ID: ModuleInstanceId = 3;
+
+    /// Encodes a state machine the way state bytes are passed to migrations,
+    /// prefixed with the module instance id.
+    fn encode_state_with_prefix(state_machine: &PredictionMarketsStateMachine) -> Vec<u8> {
+        let mut bytes = MODULE_INSTANCE_ID.consensus_encode_to_vec();
+        bytes.extend(state_machine.consensus_encode_to_vec());
+        bytes
+    }
+
+    fn decode_state_with_prefix(bytes: &[u8]) -> PredictionMarketsStateMachine {
+        let decoders = ModuleDecoderRegistry::default();
+        let mut bytes = bytes;
+        assert_eq!(
+            ModuleInstanceId::consensus_decode(&mut bytes, &decoders).unwrap(),
+            MODULE_INSTANCE_ID
+        );
+        PredictionMarketsStateMachine::consensus_decode(&mut bytes, &decoders).unwrap()
+    }
+
+    #[tokio::test]
+    async fn v0_database_is_usable_after_migrations() {
+        let database = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let market = market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64)).unwrap());
+        let market_static = MarketStaticV0 {
+            event_json: Event::new_with_random_nonce(2, 1, Information::None)
+                .try_to_json_string()
+                .unwrap(),
+            contract_price: Amount::from_msats(100),
+            payout_control_weight_map: BTreeMap::new(),
+            weight_required_for_payout: 1,
+            created_consensus_timestamp: UnixTimestamp(100),
+        };
+        let market_dynamic = MarketDynamic {
+            open_contracts: ContractAmount::ZERO,
+            payout: None,
+        };
+
+        let mut dbtx = database.begin_transaction().await;
+        dbtx.insert_entry(&db::ClientSavedMarketsKeyV0 { market }, &UnixTimestamp(100))
+            .await;
+        // an id reserved by a new order that was never cached and a cached order
+        dbtx.insert_entry(&db::OrderKey(OrderId(9)), &OrderIdSlot::Reserved)
+            .await;
+        let order = Order {
+            market,
+            outcome: 0,
+            side: Side::Buy,
+            price: Amount::from_msats(50),
+            original_quantity: ContractOfOutcomeAmount(10),
+            time_ordering: 0,
+            created_consensus_timestamp: UnixTimestamp(100),
+            quantity_waiting_for_match: ContractOfOutcomeAmount(10),
+            contract_of_outcome_balance: ContractOfOutcomeAmount::ZERO,
+            bitcoin_balance: Amount::ZERO,
+            quantity_fulfilled: ContractOfOutcomeAmount::ZERO,
+            bitcoin_acquired_from_order_matches: SignedAmount::ZERO,
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        };
+        dbtx.insert_entry(
+            &db::OrderKey(OrderId(10)),
+            &OrderIdSlot::Order(order.clone()),
+        )
+        .await;
+        dbtx.insert_entry(
+            &db::MarketKeyV0(market),
+            &db::MarketV0(market_static.clone(), market_dynamic.clone()),
+        )
+        .await;
+        dbtx.commit_tx().await;
+
+        // states are encoded as they were before rejection reasons were kept
+        let operation_id = OperationId::new_random();
+        let state_machine = |state: PredictionMarketState| PredictionMarketsStateMachine {
+            operation_id,
+            state,
+        };
+        let mut active_states = vec![(
+            encode_state_with_prefix(&state_machine(CancelOrderState::Rejected.into())),
+            operation_id,
+        )];
+        let mut inactive_states = vec![(
+            encode_state_with_prefix(&state_machine(NewOrderState::Complete.into())),
+            operation_id,
+        )];
+
+        // every version has a migration to the next one
+        let migrations = PredictionMarketsClientInit::default().get_database_migrations();
+        assert_eq!(
+            migrations.keys().copied().collect::<Vec<_>>(),
+            (0..PredictionMarketsClientInit::DATABASE_VERSION.0)
+                .map(DatabaseVersion)
+                .collect::<Vec<_>>()
+        );
+        for migration in migrations.values() {
+            let mut dbtx = database.begin_transaction().await;
+            if let Some((active, inactive)) = migration(
+                &mut dbtx.to_ref_nc(),
+                active_states.clone(),
+                inactive_states.clone(),
+            )
+            .await
+            .unwrap()
+            {
+                active_states = active;
+                inactive_states = inactive;
             }
-            OrderState::NonZeroContractOfOutcomeBalance => match filter.0 {
-                OrderPath::All => {
-                    dbtx.find_by_prefix(
-                        &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSidePrefixAll,
-                    )
-                    .await
-                }
-                OrderPath::Market { market } => {
-                    dbtx.find_by_prefix(
-                        &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSidePrefix1 {
-                            market,
-                        },
-                    )
-                    .await
-                }
-                OrderPath::MarketOutcome { market, outcome } => {
-                    dbtx.find_by_prefix(
-                        &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSidePrefix2 {
-                            market,
-                            outcome,
-                        },
-                    )
-                    .await
-                }
-                OrderPath::MarketOutcomeSide {
-                    market,
-                    outcome,
-                    side,
-                } => {
-                    dbtx.find_by_prefix(
-                        &db::OrdersWithNonZeroContractOfOutcomeBalanceByMarketOutcomeSidePrefix3 {
-                            market,
-                            outcome,
-                            side,
-                        },
-                    )
-                    .await
+            dbtx.commit_tx().await;
+        }
+
+        let mut dbtx = database.begin_transaction_nc().await;
+        let saved_markets: Vec<_> = dbtx
+            .find_by_prefix(&db::ClientSavedMarketsPrefixAll)
+            .await
+            .map(|(key, meta)| (key.market, meta))
+            .collect()
+            .await;
+        assert_eq!(
+            saved_markets,
+            vec![(
+                market,
+                SavedMarketMeta {
+                    saved_at: UnixTimestamp(100),
+                    note: None,
+                    tags: Vec::new(),
                 }
-            }
-            .map(|(k, _)| k.order)
+            )]
+        );
+        assert_eq!(
+            dbtx.get_value(&db::MarketKey(market)).await,
+            Some(Market(market_static.into(), market_dynamic))
+        );
+        let awaiting_cache: Vec<_> = dbtx
+            .find_by_prefix(&db::OrderAwaitingCachePrefixAll)
+            .await
+            .map(|(key, _)| key.0)
             .collect()
-            .await,
-            OrderState::NonZeroBitcoinBalance => {
-                match filter.0 {
-                    OrderPath::All => {
-                        dbtx.find_by_prefix(
-                            &db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSidePrefixAll,
-                        )
-                        .await
-                    }
-                    OrderPath::Market { market } => {
-                        dbtx.find_by_prefix(
-                            &db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSidePrefix1 {
-                                market,
-                            },
-                        )
-                        .await
-                    }
-                    OrderPath::MarketOutcome { market, outcome } => {
-                        dbtx.find_by_prefix(
-                            &db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSidePrefix2 {
-                                market,
-                                outcome,
-                            },
-                        )
-                        .await
-                    }
-                    OrderPath::MarketOutcomeSide {
-                        market,
-                        outcome,
-                        side,
-                    } => {
-                        dbtx.find_by_prefix(
-                            &db::OrdersWithNonZeroBitcoinBalanceByMarketOutcomeSidePrefix3 {
-                                market,
-                                outcome,
-                                side,
-                            },
-                        )
-                        .await
-                    }
+            .await;
+        assert_eq!(awaiting_cache, vec![OrderId(9)]);
+        assert_eq!(
+            dbtx.get_value(&db::OrderKey(OrderId(10))).await,
+            Some(OrderIdSlot::Order(order))
+        );
+
+        let decode = |states: Vec<(Vec<u8>, OperationId)>| {
+            states
+                .into_iter()
+                .map(|(bytes, _)| decode_state_with_prefix(&bytes))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            decode(active_states),
+            [state_machine(
+                CancelOrderState::Rejected2 {
+                    reason: states::UNKNOWN_REJECTION_REASON.to_owned(),
+                }
+                .into()
+            )]
+        );
+        assert_eq!(
+            decode(inactive_states),
+            [state_machine(NewOrderState::Complete.into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn cached_markets_are_readable_after_migration() {
+        let database = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let market = market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64)).unwrap());
+        let market_static = MarketStaticV0 {
+            event_json: Event::new_with_random_nonce(2, 1, Information::None)
+                .try_to_json_string()
+                .unwrap(),
+            contract_price: Amount::from_msats(100),
+            payout_control_weight_map: BTreeMap::new(),
+            weight_required_for_payout: 1,
+            created_consensus_timestamp: UnixTimestamp(100),
+        };
+        let market_dynamic = MarketDynamic {
+            open_contracts: ContractAmount::ZERO,
+            payout: None,
+        };
+
+        let mut dbtx = database.begin_transaction().await;
+        dbtx.insert_entry(
+            &db::MarketKeyV0(market),
+            &db::MarketV0(market_static.clone(), market_dynamic.clone()),
+        )
+        .await;
+        dbtx.commit_tx().await;
+
+        let mut dbtx = database.begin_transaction().await;
+        db::migrate_to_v2(&mut dbtx.to_ref_nc()).await.unwrap();
+        dbtx.commit_tx().await;
+
+        let Market(migrated_static, migrated_dynamic) = database
+            .begin_transaction_nc()
+            .await
+            .get_value(&db::MarketKey(market))
+            .await
+            .unwrap();
+        assert_eq!(migrated_static, MarketStatic::from(market_static));
+        assert!(migrated_static.outcome_display_hints.is_empty());
+        assert_eq!(migrated_dynamic, market_dynamic);
+    }
+
+    #[test]
+    fn rejected_states_get_a_reason_after_migration() {
+        let operation_id = OperationId::new_random();
+        let state_machine = |state: PredictionMarketState| PredictionMarketsStateMachine {
+            operation_id,
+            state,
+        };
+        let reason = states::UNKNOWN_REJECTION_REASON.to_owned();
+        let order_id = OrderId(3);
+
+        let migrations: [(PredictionMarketState, PredictionMarketState); 6] = [
+            (
+                NewOrderState::Rejected {
+                    order_id,
+                    orders_to_sync_on_rejected: BTreeSet::from([OrderId(1)]),
                 }
-                .map(|(k, _)| k.order)
-                .collect()
-                .await
+                .into(),
+                NewOrderState::Rejected3 {
+                    order_id,
+                    orders_to_sync_on_rejected: BTreeSet::from([OrderId(1)]),
+                    reason: reason.clone(),
+                }
+                .into(),
+            ),
+            (
+                NewOrderState::Rejected2 { order_id }.into(),
+                NewOrderState::Rejected4 {
+                    order_id,
+                    reason: reason.clone(),
+                }
+                .into(),
+            ),
+            (
+                CancelOrderState::Rejected.into(),
+                CancelOrderState::Rejected2 {
+                    reason: reason.clone(),
+                }
+                .into(),
+            ),
+            (
+                ConsumeOrderBitcoinBalanceState::Rejected.into(),
+                ConsumeOrderBitcoinBalanceState::Rejected2 {
+                    reason: reason.clone(),
+                }
+                .into(),
+            ),
+            (
+                PayoutMarketState::Rejected.into(),
+                PayoutMarketState::Rejected2 {
+                    reason: reason.clone(),
+                }
+                .into(),
+            ),
+            (
+                NewOrderState::Complete.into(),
+                NewOrderState::Complete.into(),
+            ),
+        ];
+
+        for (legacy, migrated) in migrations {
+            let encoded = vec![(
+                encode_state_with_prefix(&state_machine(legacy)),
+                operation_id,
+            )];
+            let (active, inactive) = db::migrate_to_v3(encoded.clone(), encoded)
+                .unwrap()
+                .unwrap();
+            for (bytes, migrated_operation_id) in active.into_iter().chain(inactive) {
+                assert_eq!(migrated_operation_id, operation_id);
+                assert_eq!(
+                    decode_state_with_prefix(&bytes),
+                    state_machine(migrated.clone())
+                );
             }
         }
     }
 
-    async fn watch_for_order_matches_on_market_outcome_side(
-        &self,
-        market: OutPoint,
-        outcome: Outcome,
-        side: Side,
-    ) -> anyhow::Result<stop_signal::Sender> {
-        let module_api = self.module_api.clone();
-        let db = self.db.clone();
-        let root_secret = self.root_secret.clone();
-        let mut new_order_reciever = self.new_order_broadcast.0.subscribe();
-        let (stop_tx, mut stop_rx) = stop_signal::new();
-
-        self.sync_matches(OrderPath::MarketOutcomeSide {
+    #[tokio::test]
+    async fn payout_while_offline_is_pending_settlement() {
+        let database = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let market = market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64)).unwrap());
+        let market_static = MarketStatic::from(MarketStaticV0 {
+            event_json: Event::new_with_random_nonce(2, 1, Information::None)
+                .try_to_json_string()
+                .unwrap(),
+            contract_price: Amount::from_msats(100),
+            payout_control_weight_map: BTreeMap::new(),
+            weight_required_for_payout: 1,
+            created_consensus_timestamp: UnixTimestamp::ZERO,
+        });
+        let open = Market(
+            market_static.clone(),
+            MarketDynamic {
+                open_contracts: ContractAmount(1),
+                payout: None,
+            },
+        );
+        let paid_out = Market(
+            market_static,
+            MarketDynamic {
+                open_contracts: ContractAmount(1),
+                payout: Some(Payout {
+                    amount_per_outcome: vec![Amount::from_msats(100), Amount::ZERO],
+                    occurred_consensus_timestamp: UnixTimestamp(100),
+                }),
+            },
+        );
+        let order = Order {
             market,
-            outcome,
-            side,
-        })
-        .await?;
+            outcome: 1,
+            side: Side::Buy,
+            price: Amount::from_msats(40),
+            original_quantity: ContractOfOutcomeAmount(1),
+            time_ordering: 0,
+            created_consensus_timestamp: UnixTimestamp::ZERO,
+            quantity_waiting_for_match: ContractOfOutcomeAmount::ZERO,
+            contract_of_outcome_balance: ContractOfOutcomeAmount(1),
+            bitcoin_balance: Amount::ZERO,
+            quantity_fulfilled: ContractOfOutcomeAmount(1),
+            bitcoin_acquired_from_order_matches: SignedAmount::ZERO,
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        };
+        let pending_settlements = || async {
+            database
+                .begin_transaction_nc()
+                .await
+                .find_by_prefix(&db::PendingSettlementPrefixAll)
+                .await
+                .map(|(k, _)| k.0)
+                .collect::<Vec<_>>()
+                .await
+        };
 
-        spawn(
-            &format!("watch_orders_on_{market}_{outcome}_{side:?}"),
-            async move {
-                let mut order_to_watch = None;
-                loop {
-                    let wait_order_match_request = {
-                        let mut dbtx = db.begin_transaction_nc().await;
-                        if order_to_watch == None {
-                            order_to_watch = dbtx
-                                .find_by_prefix(&db::OrderPriceTimePriorityPrefix3 {
-                                    market,
-                                    outcome,
-                                    side,
-                                })
-                                .await
-                                .map(|(_, order_id)| order_id)
-                                .next()
-                                .await;
-                        }
+        // only holding contracts of an outcome that paid nothing, so there is
+        // nothing to claim
+        let mut dbtx = database.begin_transaction().await;
+        PredictionMarketsClientModule::save_order_to_db(&mut dbtx.to_ref_nc(), OrderId(0), &order)
+            .await;
+        assert!(
+            !PredictionMarketsClientModule::record_pending_settlement(
+                &mut dbtx.to_ref_nc(),
+                market,
+                Some(&open),
+                &paid_out.1,
+            )
+            .await
+        );
 
-                        let order = if let Some(order_id) = order_to_watch {
-                            Some(
-                                dbtx.get_value(&db::OrderKey(order_id))
-                                    .await
-                                    .unwrap()
-                                    .to_order()
-                                    .unwrap(),
-                            )
-                        } else {
-                            None
-                        };
+        // first session sees the market open, the payout happens before the
+        // next session starts
+        PredictionMarketsClientModule::save_order_to_db(
+            &mut dbtx.to_ref_nc(),
+            OrderId(1),
+            &Order {
+                outcome: 0,
+                ..order
+            },
+        )
+        .await;
+        assert!(
+            !PredictionMarketsClientModule::record_pending_settlement(
+                &mut dbtx.to_ref_nc(),
+                market,
+                Some(&open),
+                &open.1,
+            )
+            .await
+        );
+        assert!(
+            PredictionMarketsClientModule::record_pending_settlement(
+                &mut dbtx.to_ref_nc(),
+                market,
+                Some(&open),
+                &paid_out.1,
+            )
+            .await
+        );
+        dbtx.commit_tx().await;
+        assert_eq!(pending_settlements().await, vec![market]);
 
-                        async {
-                            if let Some(order_id) = order_to_watch {
-                                module_api
-                                    .wait_order_match(WaitOrderMatchParams {
-                                        order: order_id
-                                            .into_key_pair(root_secret.clone())
-                                            .public_key(),
-                                        current_quantity_waiting_for_match: order
-                                            .unwrap()
-                                            .quantity_waiting_for_match,
-                                    })
-                                    .await
-                            } else {
-                                futures::future::pending().await
-                            }
-                        }
-                    };
+        // payouts already in the cache were seen by the user
+        let mut dbtx = database.begin_transaction().await;
+        dbtx.remove_entry(&db::PendingSettlementKey(market)).await;
+        assert!(
+            !PredictionMarketsClientModule::record_pending_settlement(
+                &mut dbtx.to_ref_nc(),
+                market,
+                Some(&paid_out),
+                &paid_out.1,
+            )
+            .await
+        );
+        dbtx.commit_tx().await;
+        assert!(pending_settlements().await.is_empty());
+    }
 
-                    select! {
-                        _ = stop_rx.0.recv() => {
-                            return;
-                        }
-                        res = wait_order_match_request => {
-                            if let Ok(WaitOrderMatchResult {order}) = res {
-                                while let Err(_) = {
-                                    let mut dbtx = db.begin_transaction().await;
-                                    Self::save_order_to_db(&mut dbtx.to_ref_nc(), order_to_watch.unwrap(), &order).await;
-                                    dbtx.commit_tx_result().await
-                                } {}
-                                if order.quantity_waiting_for_match == ContractOfOutcomeAmount::ZERO {
-                                    order_to_watch = None;
-                                }
-                            }
-                        }
-                        _ = new_order_reciever.recv() => {
-                            let mut dbtx = db.begin_transaction_nc().await;
-                            let order_priority_stream = dbtx
-                                .find_by_prefix(&db::OrderPriceTimePriorityPrefix3 {
-                                    market,
-                                    outcome,
-                                    side,
-                                })
-                                .await;
-                            let highest_priority_orders_till_watched_order =
-                                order_priority_stream
-                                    .map(|(_, order_id)| order_id)
-                                    .take_while(|order_id| {
-                                        let order_id = *order_id;
-                                        async move { Some(order_id) != order_to_watch }
-                                    })
-                                    .collect::<Vec<_>>()
-                                    .await;
-                            if highest_priority_orders_till_watched_order.len() == 0 {
-                                continue;
-                            }
-                            let new_order_to_watch = highest_priority_orders_till_watched_order
-                                .get(0)
-                                .map(|id| *id);
-                            let mut orders_to_sync = vec![];
-                            if let Some(watched_order) = new_order_to_watch {
-                                orders_to_sync.push(watched_order);
-                            }
-                            highest_priority_orders_till_watched_order
-                                .into_iter()
-                                    .skip(1)
-                                    .for_each(|order| orders_to_sync.push(order));
+    #[test]
+    fn bounded_cache_evicts_when_full() {
+        let market = |i: u64| OutPoint {
+            txid: TransactionId::from_str(&"0".repeat(64)).unwrap(),
+            out_idx: i,
+        };
+        let mut cache = HashMap::new();
+        for i in 0..MAX_CACHED_MARKETS as u64 {
+            insert_into_bounded_cache(&mut cache, market(i), i);
+        }
 
-                            while let Err(_) = {
-                                Self::sync_orders_from_federation_concurrent(
-                                    root_secret.clone(),
-                                    module_api.clone(),
-                                    db.clone(),
-                                    orders_to_sync.clone()
-                                )
-                                .await
-                            } {}
-                            order_to_watch = new_order_to_watch;
-                        }
-                    }
-                }
-            },
-        );
+        // replacing a cached market does not evict another one
+        insert_into_bounded_cache(&mut cache, market(0), 0);
+        assert_eq!(cache.len(), MAX_CACHED_MARKETS);
 
-        Ok(stop_tx)
+        let new_market = market(MAX_CACHED_MARKETS as u64);
+        insert_into_bounded_cache(&mut cache, new_market, 0);
+        assert_eq!(cache.len(), MAX_CACHED_MARKETS);
+        assert!(cache.contains_key(&new_market));
     }
-}
 
-/// Same as the ChildID used from the order root secret to derive order owner
-#[derive(
-    Debug,
-    Clone,
-    Copy,
-    Serialize,
-    Deserialize,
-    Encodable,
-    Decodable,
-    PartialEq,
-    Eq,
-    Hash,
-    PartialOrd,
-    Ord,
-)]
-pub struct OrderId(pub u64);
+    #[test]
+    fn only_unfinished_markets_are_fetched() {
+        let market_static = MarketStatic::from(MarketStaticV0 {
+            event_json: Event::new_with_random_nonce(2, 1, Information::None)
+                .try_to_json_string()
+                .unwrap(),
+            contract_price: Amount::from_msats(100),
+            payout_control_weight_map: BTreeMap::new(),
+            weight_required_for_payout: 1,
+            created_consensus_timestamp: UnixTimestamp::ZERO,
+        });
+        let market = |payout: Option<Payout>| {
+            Some(Market(
+                market_static.clone(),
+                MarketDynamic {
+                    open_contracts: ContractAmount::ZERO,
+                    payout,
+                },
+            ))
+        };
+        let out_points: Vec<_> = (0..3u8)
+            .map(|i| {
+                market_outpoint_from_tx_id(
+                    TransactionId::from_str(&format!("{i:02}").repeat(32)).unwrap(),
+                )
+            })
+            .collect();
+
+        let cached = BTreeMap::from([
+            (out_points[0], market(None)),
+            (
+                out_points[1],
+                market(Some(Payout {
+                    amount_per_outcome: vec![Amount::from_msats(100), Amount::ZERO],
+                    occurred_consensus_timestamp: UnixTimestamp(100),
+                })),
+            ),
+            (out_points[2], None),
+        ]);
+        assert_eq!(
+            markets_to_fetch(&cached),
+            vec![out_points[0], out_points[2]]
+        );
+    }
 
-impl OrderId {
-    const ORDER_PATH: ChildId = ChildId(0);
+    #[test]
+    fn extra_outcome_display_hints_are_ignored() {
+        let hint = OutcomeDisplayHint {
+            color: "#000000".to_owned(),
+            sort_rank: 0,
+        };
+        let mut market_static = MarketStatic::from(MarketStaticV0 {
+            event_json: Event::new_with_random_nonce(2, 1, Information::None)
+                .try_to_json_string()
+                .unwrap(),
+            contract_price: Amount::from_msats(100),
+            payout_control_weight_map: BTreeMap::new(),
+            weight_required_for_payout: 1,
+            created_consensus_timestamp: UnixTimestamp::ZERO,
+        });
+        assert!(market_static.outcome_display_hints(2).is_empty());
+
+        market_static.outcome_display_hints = vec![hint.clone(); 3];
+        assert_eq!(
+            market_static.outcome_display_hints(2),
+            &[hint.clone(), hint]
+        );
+    }
 
-    pub fn into_key_pair(&self, root_secret: DerivableSecret) -> KeyPair {
-        root_secret
-            .child_key(Self::ORDER_PATH)
-            .child_key(ChildId(self.0))
-            .to_secp_key(&Secp256k1::new())
+    #[test]
+    fn payout_control_label_is_truncated_by_chars() {
+        let hex = "0123456789abcdef0123".to_owned();
+        assert_eq!(truncated_payout_control_label(&hex), "0123456789abcdef...");
+        assert_eq!(truncated_payout_control_label(&"ab".to_owned()), "ab");
+
+        // not valid hex, the 16th char spans bytes 15 to 17
+        let not_hex = format!("{}é{}", "0".repeat(15), "1".repeat(4));
+        assert_eq!(
+            truncated_payout_control_label(&not_hex),
+            format!("{}é...", "0".repeat(15))
+        );
     }
-}
 
-impl FromStr for OrderId {
-    type Err = anyhow::Error;
+    #[tokio::test]
+    async fn paginating_orders_has_no_gaps_or_duplicates() {
+        let database = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let market = market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64)).unwrap());
+        let other_market =
+            market_outpoint_from_tx_id(TransactionId::from_str(&"1".repeat(64)).unwrap());
+        let order = Order {
+            market,
+            outcome: 0,
+            side: Side::Buy,
+            price: Amount::from_msats(50),
+            original_quantity: ContractOfOutcomeAmount(10),
+            time_ordering: 0,
+            created_consensus_timestamp: UnixTimestamp::ZERO,
+            quantity_waiting_for_match: ContractOfOutcomeAmount::ZERO,
+            contract_of_outcome_balance: ContractOfOutcomeAmount::ZERO,
+            bitcoin_balance: Amount::ZERO,
+            quantity_fulfilled: ContractOfOutcomeAmount::ZERO,
+            bitcoin_acquired_from_order_matches: SignedAmount::ZERO,
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        };
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(u64::from_str(s)?))
+        // 500 orders in the market spread over outcomes and sides, with orders
+        // of another market in between
+        let mut dbtx = database.begin_transaction().await;
+        let mut expected = Vec::new();
+        for id in 0..600 {
+            let order = Order {
+                market: if id % 6 == 5 { other_market } else { market },
+                outcome: (id % 2) as Outcome,
+                side: if id % 3 == 0 { Side::Sell } else { Side::Buy },
+                ..order.clone()
+            };
+            if order.market == market {
+                expected.push(OrderId(id));
+            }
+            PredictionMarketsClientModule::save_order_to_db(
+                &mut dbtx.to_ref_nc(),
+                OrderId(id),
+                &order,
+            )
+            .await;
+        }
+        dbtx.commit_tx().await;
+        assert_eq!(expected.len(), 500);
+
+        let filter = OrderFilter(OrderPath::Market { market }, OrderState::Any);
+        let limit = NonZeroUsize::new(7).unwrap();
+        let mut paged = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (orders, next) = PredictionMarketsClientModule::get_orders_page(
+                &mut database.begin_transaction_nc().await,
+                filter,
+                cursor,
+                limit,
+            )
+            .await;
+            assert!(orders.len() <= limit.get());
+            assert!(orders.iter().all(|(_, order)| order.market == market));
+            paged.extend(orders.into_iter().map(|(id, _)| id));
+
+            cursor = next;
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        assert_eq!(paged, expected);
     }
-}
 
-pub fn market_outpoint_from_tx_id(tx_id: TransactionId) -> OutPoint {
-    OutPoint {
-        txid: tx_id,
-        out_idx: 0,
+    fn shared_quote() -> SharedQuote {
+        SharedQuote {
+            market: market_outpoint_from_tx_id(TransactionId::from_str(&"ab".repeat(32)).unwrap()),
+            outcome: 1,
+            side: Side::Sell,
+            price: Amount::from_msats(7_000),
+            quantity: ContractOfOutcomeAmount(25),
+        }
     }
-}
 
-#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
-pub struct OrderBookInformation {
-    buys: BTreeMap<Amount, ContractOfOutcomeAmount>,
-    sells: BTreeMap<Amount, ContractOfOutcomeAmount>,
+    #[test]
+    fn shared_quote_round_trip() {
+        let quote = shared_quote();
+        let s = quote.to_string();
+        assert!(s.starts_with("pmquote1"));
+        assert_eq!(SharedQuote::from_str(&s).unwrap(), quote);
+        assert_eq!(SharedQuote::from_str(&s.to_uppercase()).unwrap(), quote);
+    }
+
+    #[test]
+    fn corrupted_shared_quote_is_rejected() {
+        let s = shared_quote().to_string();
+        let mut corrupted = s.clone().into_bytes();
+        let last = corrupted.len() - 5;
+        corrupted[last] = if corrupted[last] == b'q' { b'p' } else { b'q' };
+        let corrupted = String::from_utf8(corrupted).unwrap();
+        assert_ne!(corrupted, s);
+
+        for s in [
+            corrupted.as_str(),
+            &s[..s.len() - 1],
+            "pmquote1",
+            "lnbc1qqqqqqqq",
+        ] {
+            let err = SharedQuote::from_str(s).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(PredictionMarketsClientError::InvalidSharedQuote)
+            ));
+        }
+    }
 }
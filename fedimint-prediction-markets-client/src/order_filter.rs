@@ -1,9 +1,16 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+
+use anyhow::bail;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::{Amount, OutPoint};
-use fedimint_prediction_markets_common::{ContractOfOutcomeAmount, Order, Side};
+use fedimint_prediction_markets_common::{ContractOfOutcomeAmount, Order, Side, UnixTimestamp};
 use prediction_market_event::Outcome;
 use serde::{Deserialize, Serialize};
 
+use crate::OrderId;
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
 pub struct OrderFilter(pub OrderPath, pub OrderState);
 
@@ -33,6 +40,105 @@ impl OrderFilter {
 
         res
     }
+
+    /// Orders matching both `a` and `b`.
+    pub fn and(a: impl Into<OrderQuery>, b: impl Into<OrderQuery>) -> OrderQuery {
+        OrderQuery::And(Box::new(a.into()), Box::new(b.into()))
+    }
+
+    /// Orders matching `a`, `b` or both.
+    pub fn or(a: impl Into<OrderQuery>, b: impl Into<OrderQuery>) -> OrderQuery {
+        OrderQuery::Or(Box::new(a.into()), Box::new(b.into()))
+    }
+}
+
+/// Filter composed of leaf predicates with [OrderFilter::and] and
+/// [OrderFilter::or], used by
+/// [crate::PredictionMarketsClientModule::get_orders_matching]. An
+/// [OrderFilter], [OrderPath] or [OrderState] converts into a leaf.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum OrderQuery {
+    Filter(OrderFilter),
+    /// Orders tagged with the tag by
+    /// [crate::PredictionMarketsClientModule::set_order_tag]
+    Tag(String),
+    /// Orders created at or after the consensus timestamp
+    CreatedSince(UnixTimestamp),
+    /// Orders created before the consensus timestamp
+    CreatedBefore(UnixTimestamp),
+    And(Box<OrderQuery>, Box<OrderQuery>),
+    Or(Box<OrderQuery>, Box<OrderQuery>),
+}
+
+impl OrderQuery {
+    /// Whether `order`, tagged with `tag`, matches the query.
+    pub fn matches(&self, order: &Order, tag: Option<&str>) -> bool {
+        match self {
+            Self::Filter(filter) => filter.filter(order),
+            Self::Tag(t) => tag == Some(t.as_str()),
+            Self::CreatedSince(timestamp) => order.created_consensus_timestamp >= *timestamp,
+            Self::CreatedBefore(timestamp) => order.created_consensus_timestamp < *timestamp,
+            Self::And(a, b) => a.matches(order, tag) && b.matches(order, tag),
+            Self::Or(a, b) => a.matches(order, tag) || b.matches(order, tag),
+        }
+    }
+
+    /// Leaves that can be looked up in an index: filters and tags.
+    pub(crate) fn indexed_leaves(&self) -> Vec<&OrderQuery> {
+        match self {
+            Self::Filter(_) | Self::Tag(_) => vec![self],
+            Self::CreatedSince(_) | Self::CreatedBefore(_) => vec![],
+            Self::And(a, b) | Self::Or(a, b) => {
+                let mut leaves = a.indexed_leaves();
+                leaves.extend(b.indexed_leaves());
+                leaves
+            }
+        }
+    }
+
+    /// Orders that can match the query given the orders of each indexed leaf
+    /// in `index`, which still have to be checked with [Self::matches].
+    /// `None` if every order can match, such as when only time is
+    /// constrained.
+    pub(crate) fn candidates(
+        &self,
+        index: &HashMap<&OrderQuery, BTreeSet<OrderId>>,
+    ) -> Option<BTreeSet<OrderId>> {
+        match self {
+            Self::Filter(_) | Self::Tag(_) => index.get(self).cloned(),
+            Self::CreatedSince(_) | Self::CreatedBefore(_) => None,
+            Self::And(a, b) => match (a.candidates(index), b.candidates(index)) {
+                (Some(a), Some(b)) => Some(a.intersection(&b).copied().collect()),
+                (Some(ids), None) | (None, Some(ids)) => Some(ids),
+                (None, None) => None,
+            },
+            Self::Or(a, b) => match (a.candidates(index), b.candidates(index)) {
+                (Some(mut a), Some(mut b)) => {
+                    a.append(&mut b);
+                    Some(a)
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+impl From<OrderFilter> for OrderQuery {
+    fn from(filter: OrderFilter) -> Self {
+        Self::Filter(filter)
+    }
+}
+
+impl From<OrderPath> for OrderQuery {
+    fn from(path: OrderPath) -> Self {
+        Self::Filter(OrderFilter(path, OrderState::Any))
+    }
+}
+
+impl From<OrderState> for OrderQuery {
+    fn from(state: OrderState) -> Self {
+        Self::Filter(OrderFilter(OrderPath::All, state))
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
@@ -59,3 +165,43 @@ pub enum OrderState {
     NonZeroContractOfOutcomeBalance,
     NonZeroBitcoinBalance,
 }
+
+/// Order of orders listed by
+/// [crate::PredictionMarketsClientModule::list_orders]. Ties are broken by
+/// ascending order id.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum OrderSort {
+    #[default]
+    IdAsc,
+    /// Newest first
+    CreatedDesc,
+    /// Largest quantity waiting for match first
+    RestingQuantityDesc,
+}
+
+impl OrderSort {
+    pub fn sort(&self, orders: &mut [(OrderId, Order)]) {
+        match self {
+            Self::IdAsc => orders.sort_by_key(|(id, _)| *id),
+            Self::CreatedDesc => {
+                orders.sort_by_key(|(id, order)| (Reverse(order.created_consensus_timestamp), *id))
+            }
+            Self::RestingQuantityDesc => {
+                orders.sort_by_key(|(id, order)| (Reverse(order.quantity_waiting_for_match), *id))
+            }
+        }
+    }
+}
+
+impl FromStr for OrderSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "id-asc" => Ok(Self::IdAsc),
+            "created-desc" => Ok(Self::CreatedDesc),
+            "resting-quantity-desc" => Ok(Self::RestingQuantityDesc),
+            _ => bail!("could not parse order sort"),
+        }
+    }
+}
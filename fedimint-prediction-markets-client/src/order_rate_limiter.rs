@@ -0,0 +1,52 @@
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Token bucket limiting how many order operations the client submits.
+///
+/// Holds up to `max_orders_per_minute` tokens and refills continuously at
+/// `max_orders_per_minute` tokens per minute. Each order operation takes one
+/// token.
+#[derive(Debug)]
+pub struct OrderRateLimiter {
+    max_orders_per_minute: NonZeroU32,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl OrderRateLimiter {
+    pub fn new(max_orders_per_minute: NonZeroU32, now: Instant) -> Self {
+        Self {
+            max_orders_per_minute,
+            tokens: max_orders_per_minute.get() as f64,
+            last_refill: now,
+        }
+    }
+
+    /// Takes a token. If none is available, returns how long until one is.
+    pub fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        let capacity = self.max_orders_per_minute.get() as f64;
+
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * capacity / 60.0).min(capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) * 60.0 / capacity,
+        ))
+    }
+
+    /// Gives back a token taken by [Self::try_acquire] for an operation that
+    /// was not submitted.
+    pub fn release(&mut self) {
+        let capacity = self.max_orders_per_minute.get() as f64;
+
+        self.tokens = (self.tokens + 1.0).min(capacity);
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use fedimint_core::{apply, async_trait_maybe_send};
+use fedimint_prediction_markets_common::{Market, PredictionMarketEventJson};
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use prediction_market_event::EventPayout;
+use serde::Serialize;
+use tokio::time::timeout;
+
+use crate::group_event_payout_attestations;
+
+/// Somewhere event payout attestations can be fetched from, such as a single
+/// nostr relay.
+#[apply(async_trait_maybe_send!)]
+pub trait PayoutAttestationRelay {
+    /// Identifies the relay in [PayoutAttestationSet::served_by].
+    fn url(&self) -> String;
+
+    /// Event payout attestations for the market's event signed by any of the
+    /// market's payout controls.
+    async fn get_event_payout_attestations(
+        &self,
+        market: &Market,
+    ) -> anyhow::Result<Vec<PredictionMarketEventJson>>;
+}
+
+/// Bounds how long [collect_from_relays] waits on relays.
+///
+/// Each relay is given `relay_timeout`. Whatever was gathered when
+/// `deadline` passes is returned, even if some relays have not answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayoutAttestationCollectionPolicy {
+    pub relay_timeout: Duration,
+    pub deadline: Duration,
+}
+
+impl Default for PayoutAttestationCollectionPolicy {
+    fn default() -> Self {
+        Self {
+            relay_timeout: Duration::from_secs(10),
+            deadline: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Event payout attestations gathered from relays by [collect_from_relays].
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct PayoutAttestationSet {
+    /// Event payout that reached the market's `weight_required_for_payout`,
+    /// with the attestations needed to payout the market
+    pub payout: Option<(EventPayout, Vec<PredictionMarketEventJson>)>,
+    /// Every attestation gathered, without duplicates
+    pub event_payout_attestations_json: Vec<PredictionMarketEventJson>,
+    /// Urls of the relays that served each attestation, in the order they
+    /// answered
+    pub served_by: BTreeMap<PredictionMarketEventJson, Vec<String>>,
+    pub relays_queried: usize,
+    /// Relays that answered within the relay timeout and the deadline
+    pub relays_answered: usize,
+}
+
+/// Asks every relay for the market's event payout attestations concurrently
+/// and looks for an event payout that has reached the market's
+/// `weight_required_for_payout`. Relays that fail or time out are skipped.
+pub async fn collect_from_relays<R: PayoutAttestationRelay>(
+    market: &Market,
+    relays: &[R],
+    policy: PayoutAttestationCollectionPolicy,
+) -> PayoutAttestationSet {
+    let mut event_payout_attestations_json = Vec::new();
+    let mut served_by: BTreeMap<_, Vec<String>> = BTreeMap::new();
+    let mut relays_answered = 0;
+
+    let mut requests = relays
+        .iter()
+        .map(|relay| async move {
+            let res = timeout(
+                policy.relay_timeout,
+                relay.get_event_payout_attestations(market),
+            )
+            .await;
+            (relay.url(), res)
+        })
+        .collect::<FuturesUnordered<_>>();
+    // relays still running at the deadline are dropped
+    let _ = timeout(policy.deadline, async {
+        while let Some((url, res)) = requests.next().await {
+            let Ok(Ok(attestations)) = res else {
+                continue;
+            };
+            relays_answered += 1;
+            for attestation in attestations {
+                let relays = served_by.entry(attestation.clone()).or_default();
+                if !relays.contains(&url) {
+                    relays.push(url.clone());
+                }
+                if !event_payout_attestations_json.contains(&attestation) {
+                    event_payout_attestations_json.push(attestation);
+                }
+            }
+        }
+    })
+    .await;
+
+    let payout = group_event_payout_attestations(market, event_payout_attestations_json.clone())
+        .into_iter()
+        .find(|(_, group)| group.total_weight >= market.0.weight_required_for_payout)
+        .map(|(event_payout, group)| (event_payout, group.event_payout_attestations_json));
+
+    PayoutAttestationSet {
+        payout,
+        event_payout_attestations_json,
+        served_by,
+        relays_queried: relays.len(),
+        relays_answered,
+    }
+}
+
+/// A nostr relay, connected to for each request.
+#[cfg(feature = "cli")]
+pub struct NostrRelay(pub prediction_market_event_nostr_client::nostr_sdk::Url);
+
+#[cfg(feature = "cli")]
+#[apply(async_trait_maybe_send!)]
+impl PayoutAttestationRelay for NostrRelay {
+    fn url(&self) -> String {
+        self.0.to_string()
+    }
+
+    async fn get_event_payout_attestations(
+        &self,
+        market: &Market,
+    ) -> anyhow::Result<Vec<PredictionMarketEventJson>> {
+        use prediction_market_event_nostr_client::nostr_sdk::JsonUtil;
+
+        let nostr_client =
+            prediction_market_event_nostr_client::Client::new_initialized_client_query_only(
+                std::iter::once(self.0.clone()).collect(),
+            )
+            .await?;
+
+        let event_hash_hex = market.0.event()?.hash_hex()?;
+        let event_payout_attestation_result = nostr_client.get::<prediction_market_event_nostr_client::prediction_market_event::nostr_event_types::EventPayoutAttestation>(|f| {
+            market.0.payout_control_weight_map.iter().map(|(pk, _)| {
+                let author = prediction_market_event_nostr_client::nostr_sdk::PublicKey::parse(pk).unwrap();
+                f.clone().author(author).hashtag(&event_hash_hex.0)
+            }).collect()
+        }, None).await?;
+
+        Ok(event_payout_attestation_result
+            .into_iter()
+            .map(|(nostr_event, _)| nostr_event.try_as_json())
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use fedimint_core::Amount;
+    use fedimint_prediction_markets_common::{
+        ContractAmount, MarketDynamic, MarketStatic, UnixTimestamp,
+    };
+    use prediction_market_event::information::Information;
+    use prediction_market_event::nostr_event_types::{EventPayoutAttestation, NostrEventUtils};
+    use prediction_market_event::Event;
+    use prediction_market_event_nostr_client::nostr_sdk::Keys;
+    use tokio::time::Instant;
+
+    use super::*;
+
+    enum MockRelay {
+        Answers(&'static str, Vec<PredictionMarketEventJson>),
+        Fails,
+        Hangs,
+    }
+
+    #[apply(async_trait_maybe_send!)]
+    impl PayoutAttestationRelay for MockRelay {
+        fn url(&self) -> String {
+            match self {
+                MockRelay::Answers(url, _) => url.to_string(),
+                MockRelay::Fails => "wss://fails".to_owned(),
+                MockRelay::Hangs => "wss://hangs".to_owned(),
+            }
+        }
+
+        async fn get_event_payout_attestations(
+            &self,
+            _market: &Market,
+        ) -> anyhow::Result<Vec<PredictionMarketEventJson>> {
+            match self {
+                MockRelay::Answers(_, attestations) => Ok(attestations.clone()),
+                MockRelay::Fails => Err(anyhow::anyhow!("relay unavailable")),
+                MockRelay::Hangs => std::future::pending().await,
+            }
+        }
+    }
+
+    fn market() -> Market {
+        Market(
+            MarketStatic {
+                event_json: Event::new_with_random_nonce(2, 1, Information::None)
+                    .try_to_json_string()
+                    .unwrap(),
+                contract_price: Amount::from_msats(100),
+                payout_control_weight_map: BTreeMap::new(),
+                weight_required_for_payout: 1,
+                outcome_display_hints: Vec::new(),
+                created_consensus_timestamp: UnixTimestamp::ZERO,
+            },
+            MarketDynamic {
+                open_contracts: ContractAmount::ZERO,
+                payout: None,
+            },
+        )
+    }
+
+    /// Market on `event` where each of `payout_controls` has weight 1.
+    fn market_with_payout_controls(
+        event: &Event,
+        payout_controls: &[Keys],
+        weight_required_for_payout: u64,
+    ) -> Market {
+        let mut market = market();
+        market.0.event_json = event.try_to_json_string().unwrap();
+        market.0.payout_control_weight_map = payout_controls
+            .iter()
+            .map(|keys| (keys.public_key.to_hex(), 1))
+            .collect();
+        market.0.weight_required_for_payout = weight_required_for_payout;
+        market
+    }
+
+    fn attestation(event: &Event, keys: &Keys, event_payout: &EventPayout) -> String {
+        EventPayoutAttestation::create_nostr_event_json(
+            event,
+            event_payout,
+            &keys.secret_key().to_secret_hex(),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn attestations_reaching_threshold_are_paid_out() {
+        let event = Event::new_with_random_nonce(2, 1, Information::None);
+        let payout_controls = [Keys::generate(), Keys::generate(), Keys::generate()];
+        let market = market_with_payout_controls(&event, &payout_controls, 2);
+        let first_outcome = EventPayout {
+            units_per_outcome: vec![1, 0],
+        };
+        let second_outcome = EventPayout {
+            units_per_outcome: vec![0, 1],
+        };
+
+        let first = attestation(&event, &payout_controls[0], &first_outcome);
+        let second = attestation(&event, &payout_controls[1], &first_outcome);
+        let dissent = attestation(&event, &payout_controls[2], &second_outcome);
+        let relays = [
+            MockRelay::Answers("wss://one", vec![first.clone()]),
+            MockRelay::Answers("wss://two", vec![first.clone(), second.clone()]),
+            MockRelay::Answers("wss://three", vec![dissent.clone()]),
+        ];
+
+        let set = collect_from_relays(
+            &market,
+            &relays,
+            PayoutAttestationCollectionPolicy::default(),
+        )
+        .await;
+
+        assert_eq!(set.relays_answered, 3);
+        assert_eq!(set.event_payout_attestations_json.len(), 3);
+        let (event_payout, mut attestations) = set.payout.unwrap();
+        assert_eq!(event_payout, first_outcome);
+        attestations.sort();
+        let mut expected = vec![first, second];
+        expected.sort();
+        assert_eq!(attestations, expected);
+    }
+
+    #[tokio::test]
+    async fn attestations_missing_threshold_are_not_paid_out() {
+        let event = Event::new_with_random_nonce(2, 1, Information::None);
+        let payout_controls = [Keys::generate(), Keys::generate(), Keys::generate()];
+        let market = market_with_payout_controls(&event, &payout_controls, 2);
+        let first_outcome = EventPayout {
+            units_per_outcome: vec![1, 0],
+        };
+        let second_outcome = EventPayout {
+            units_per_outcome: vec![0, 1],
+        };
+
+        // attestations from a key that is not a payout control carry no weight
+        let outsider = Keys::generate();
+        let relays = [
+            MockRelay::Answers(
+                "wss://one",
+                vec![
+                    attestation(&event, &payout_controls[0], &first_outcome),
+                    attestation(&event, &outsider, &first_outcome),
+                ],
+            ),
+            MockRelay::Answers(
+                "wss://two",
+                vec![attestation(&event, &payout_controls[1], &second_outcome)],
+            ),
+        ];
+
+        let set = collect_from_relays(
+            &market,
+            &relays,
+            PayoutAttestationCollectionPolicy::default(),
+        )
+        .await;
+
+        assert_eq!(set.relays_answered, 2);
+        assert_eq!(set.event_payout_attestations_json.len(), 3);
+        assert_eq!(set.payout, None);
+    }
+
+    #[tokio::test]
+    async fn hanging_relay_does_not_block_collection() {
+        let relays = [
+            MockRelay::Hangs,
+            MockRelay::Answers("wss://one", vec!["a".to_owned(), "b".to_owned()]),
+            MockRelay::Fails,
+            MockRelay::Answers("wss://two", vec!["b".to_owned(), "c".to_owned()]),
+        ];
+        let policy = PayoutAttestationCollectionPolicy {
+            relay_timeout: Duration::from_millis(100),
+            deadline: Duration::from_secs(60),
+        };
+
+        let start = Instant::now();
+        let set = collect_from_relays(&market(), &relays, policy).await;
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        assert_eq!(set.relays_queried, 4);
+        assert_eq!(set.relays_answered, 2);
+        let mut gathered = set.event_payout_attestations_json;
+        gathered.sort();
+        assert_eq!(gathered, ["a", "b", "c"]);
+        assert_eq!(set.served_by["a"], ["wss://one"]);
+        let mut b_served_by = set.served_by["b"].clone();
+        b_served_by.sort();
+        assert_eq!(b_served_by, ["wss://one", "wss://two"]);
+        assert_eq!(set.served_by["c"], ["wss://two"]);
+        assert_eq!(set.served_by.len(), 3);
+        // none of them are valid attestations from a payout control
+        assert_eq!(set.payout, None);
+    }
+
+    #[tokio::test]
+    async fn deadline_returns_what_was_gathered() {
+        let relays = [
+            MockRelay::Answers("wss://one", vec!["a".to_owned()]),
+            MockRelay::Hangs,
+        ];
+        let policy = PayoutAttestationCollectionPolicy {
+            relay_timeout: Duration::from_secs(60),
+            deadline: Duration::from_millis(100),
+        };
+
+        let start = Instant::now();
+        let set = collect_from_relays(&market(), &relays, policy).await;
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        assert_eq!(set.relays_answered, 1);
+        assert_eq!(set.event_payout_attestations_json, ["a"]);
+        assert_eq!(set.served_by["a"], ["wss://one"]);
+    }
+}
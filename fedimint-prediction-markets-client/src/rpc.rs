@@ -1,20 +1,26 @@
 use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
 use std::time::Duration;
 
 use async_stream::try_stream;
+use fedimint_core::core::OperationId;
 use fedimint_core::util::BoxStream;
 use fedimint_core::{Amount, OutPoint};
 use fedimint_prediction_markets_common::{
-    ContractOfOutcomeAmount, NostrPublicKeyHex, PredictionMarketEventJson, Seconds, Side,
-    UnixTimestamp, Weight, WeightRequiredForPayout,
+    ContractOfOutcomeAmount, NostrPublicKeyHex, OutcomeDisplayHint, PredictionMarketEventHashHex,
+    PredictionMarketEventJson, Seconds, Side, UnixTimestamp, Weight, WeightRequiredForPayout,
 };
 use futures::StreamExt;
-use prediction_market_event::Outcome;
+use prediction_market_event::{EventPayout, Outcome};
+use secp256k1::PublicKey;
 use serde::Deserialize;
 use serde_json::json;
 
-use crate::order_filter::{OrderFilter, OrderPath};
-use crate::{OrderId, PredictionMarketsClientModule};
+use crate::order_filter::{OrderFilter, OrderPath, OrderQuery, OrderSort};
+use crate::{
+    FillMode, MarketDisplayOverride, MarketTemplate, OrderCursor, OrderId, OrderType,
+    PredictionMarketsClientModule,
+};
 
 pub async fn handle_rpc(
     prediction_markets: &PredictionMarketsClientModule,
@@ -26,9 +32,37 @@ pub async fn handle_rpc(
             let res = prediction_markets.get_general_consensus();
             yield json!(res);
         }
+        "candlesticks_supported" => {
+            let res = prediction_markets.candlesticks_supported();
+            yield json!(res);
+        }
+        "supported_candlestick_intervals" => {
+            let res = prediction_markets.supported_candlestick_intervals();
+            yield json!(res);
+        }
+        "order_owner_pubkey" => {
+            let req = serde_json::from_value::<OrderOwnerPubkeyRequest>(request)?;
+            let res = prediction_markets.order_owner_pubkey(req.order_id);
+            yield json!(res);
+        }
         "new_market" => {
             let req = serde_json::from_value::<NewMarketRequest>(request)?;
-            let res = prediction_markets.new_market(req.event_json, req.contract_price, req.payout_control_weight_map, req.weight_required_for_payout).await?;
+            let res = prediction_markets.new_market(req.event_json, req.contract_price, req.payout_control_weight_map, req.weight_required_for_payout, req.max_fee).await?;
+            yield json!(res);
+        }
+        "new_market_with_display_hints" => {
+            let req = serde_json::from_value::<NewMarketWithDisplayHintsRequest>(request)?;
+            let res = prediction_markets.new_market_with_display_hints(req.event_json, req.contract_price, req.payout_control_weight_map, req.weight_required_for_payout, req.outcome_display_hints, req.max_fee).await?;
+            yield json!(res);
+        }
+        "new_market_detailed" => {
+            let req = serde_json::from_value::<NewMarketRequest>(request)?;
+            let res = prediction_markets.new_market_detailed(req.event_json, req.contract_price, req.payout_control_weight_map, req.weight_required_for_payout, req.max_fee).await?;
+            yield json!(res);
+        }
+        "new_market_idempotent" => {
+            let req = serde_json::from_value::<NewMarketIdempotentRequest>(request)?;
+            let res = prediction_markets.new_market_idempotent(req.idempotency_key, req.event_json, req.contract_price, req.payout_control_weight_map, req.weight_required_for_payout, req.max_fee).await?;
             yield json!(res);
         }
         "get_market" => {
@@ -36,6 +70,26 @@ pub async fn handle_rpc(
             let res = prediction_markets.get_market(req.market, req.from_local_cache).await?;
             yield json!(res);
         }
+        "get_market_payout" => {
+            let req = serde_json::from_value::<GetMarketPayoutRequest>(request)?;
+            let res = prediction_markets.get_market_payout(req.market).await?;
+            yield json!(res);
+        }
+        "get_markets" => {
+            let req = serde_json::from_value::<GetMarketsRequest>(request)?;
+            let res = prediction_markets.get_markets(req.markets, req.from_local_cache).await?;
+            yield json!(res);
+        }
+        "get_markets_where_payout_control" => {
+            let req = serde_json::from_value::<GetMarketsWherePayoutControlRequest>(request)?;
+            let res = prediction_markets.get_markets_where_payout_control(req.payout_control, req.created_after, req.from_local_cache).await?;
+            yield json!(res);
+        }
+        "find_market_by_event_hash" => {
+            let req = serde_json::from_value::<FindMarketByEventHashRequest>(request)?;
+            let res = prediction_markets.find_market_by_event_hash(req.event_hash_hex).await?;
+            yield json!(res);
+        }
         "payout_market" => {
             let req = serde_json::from_value::<PayoutMarketRequest>(request)?;
             let res = prediction_markets.payout_market(req.market, req.event_payout_attestations_json).await?;
@@ -46,9 +100,74 @@ pub async fn handle_rpc(
             let res = prediction_markets.get_event_payout_attestations_used_to_permit_payout(req.market).await?;
             yield json!(res);
         }
+        "get_payout_attestation_status" => {
+            let req = serde_json::from_value::<GetPayoutAttestationStatusRequest>(request)?;
+            let res = prediction_markets.get_payout_attestation_status(req.market, req.event_payout, req.event_payout_attestations_json, req.own_payout_control).await?;
+            yield json!(res);
+        }
+        "new_order_cost" => {
+            let req = serde_json::from_value::<NewOrderCostRequest>(request)?;
+            let res = prediction_markets.new_order_cost(req.side, req.price, req.quantity)?;
+            yield json!(res);
+        }
+        "check_new_order_affordable" => {
+            let req = serde_json::from_value::<CheckNewOrderAffordableRequest>(request)?;
+            let res = prediction_markets.check_new_order_affordable(req.side, req.price, req.quantity, req.primary_module_balance)?;
+            yield json!(res);
+        }
         "new_order" => {
             let req = serde_json::from_value::<NewOrderRequest>(request)?;
-            let res = prediction_markets.new_order(req.market, req.outcome, req.side, req.price, req.quantity).await?;
+            let res = prediction_markets.new_order(req.market, req.outcome, req.side, req.price, req.quantity, req.max_fee).await?;
+            yield json!(res);
+        }
+        "new_binary_order" => {
+            let req = serde_json::from_value::<NewBinaryOrderRequest>(request)?;
+            let res = prediction_markets.new_binary_order(req.market, req.yes, req.side, req.price, req.quantity, req.max_fee).await?;
+            yield json!(res);
+        }
+        "new_order_by_outcome_title" => {
+            let req = serde_json::from_value::<NewOrderByOutcomeTitleRequest>(request)?;
+            let res = prediction_markets.new_order_by_outcome_title(req.market, &req.outcome_title, req.side, req.price, req.quantity, req.max_fee).await?;
+            yield json!(res);
+        }
+        "new_order_by_probability" => {
+            let req = serde_json::from_value::<NewOrderByProbabilityRequest>(request)?;
+            let res = prediction_markets.new_order_by_probability(req.market, req.outcome, req.side, req.probability, req.quantity, req.max_fee).await?;
+            yield json!(res);
+        }
+        "new_order_with_operation_id" => {
+            let req = serde_json::from_value::<NewOrderWithOperationIdRequest>(request)?;
+            let res = prediction_markets.new_order_with_operation_id(req.operation_id, req.market, req.outcome, req.side, req.price, req.quantity, req.max_fee).await?;
+            yield json!(res);
+        }
+        "new_order_with_type" => {
+            let req = serde_json::from_value::<NewOrderWithTypeRequest>(request)?;
+            let res = prediction_markets.new_order_with_type(req.market, req.outcome, req.side, req.order_type, req.quantity, req.max_fee).await?;
+            yield json!(res);
+        }
+        "new_order_verify_sources" => {
+            let req = serde_json::from_value::<NewOrderRequest>(request)?;
+            let res = prediction_markets.new_order_verify_sources(req.market, req.outcome, req.side, req.price, req.quantity, req.max_fee).await?;
+            yield json!(res);
+        }
+        "submit_multi_market" => {
+            let req = serde_json::from_value::<SubmitMultiMarketRequest>(request)?;
+            let res = prediction_markets.submit_multi_market(req.requests, req.max_fee).await?;
+            yield json!(res);
+        }
+        "reserve_order_ids" => {
+            let req = serde_json::from_value::<ReserveOrderIdsRequest>(request)?;
+            let res = prediction_markets.reserve_order_ids(req.count).await?;
+            yield json!(res);
+        }
+        "release_order_id" => {
+            let req = serde_json::from_value::<ReleaseOrderIdRequest>(request)?;
+            let res = prediction_markets.release_order_id(req.order_id).await?;
+            yield json!(res);
+        }
+        "new_order_with_id" => {
+            let req = serde_json::from_value::<NewOrderWithIdRequest>(request)?;
+            let res = prediction_markets.new_order_with_id(req.order_id, req.market, req.outcome, req.side, req.price, req.quantity, req.max_fee).await?;
             yield json!(res);
         }
         "get_order" => {
@@ -56,9 +175,64 @@ pub async fn handle_rpc(
             let res = prediction_markets.get_order(req.order_id, req.from_local_cache).await?;
             yield json!(res);
         }
+        "get_order_checked" => {
+            let req = serde_json::from_value::<GetOrderCheckedRequest>(request)?;
+            let res = prediction_markets.get_order_checked(req.order_id).await?;
+            yield json!(res);
+        }
+        "get_order_ledger" => {
+            let req = serde_json::from_value::<GetOrderLedgerRequest>(request)?;
+            let res = prediction_markets.get_order_ledger(req.order_id).await;
+            yield json!(res);
+        }
+        "export_order_quote" => {
+            let req = serde_json::from_value::<ExportOrderQuoteRequest>(request)?;
+            let res = prediction_markets.export_order_quote(req.order_id).await?;
+            yield json!(res);
+        }
+        "preview_shared_quote" => {
+            let req = serde_json::from_value::<PreviewSharedQuoteRequest>(request)?;
+            let res = prediction_markets.preview_shared_quote(&req.quote).await?;
+            yield json!(res);
+        }
+        "get_market_orders" => {
+            let req = serde_json::from_value::<GetMarketOrdersRequest>(request)?;
+            let res = prediction_markets.get_market_orders(req.market, req.after, req.limit).await?;
+            yield json!(res);
+        }
         "get_orders_from_db" => {
             let req = serde_json::from_value::<GetOrdersFromDbRequest>(request)?;
-            let res = prediction_markets.get_orders_from_db(req.filter).await;
+            let res = prediction_markets.get_tagged_orders_from_db(req.filter).await;
+            yield json!(res);
+        }
+        "get_orders_from_db_paginated" => {
+            let req = serde_json::from_value::<GetOrdersFromDbPaginatedRequest>(request)?;
+            let res = prediction_markets.get_orders_from_db_paginated(req.filter, req.cursor, req.limit).await;
+            yield json!(res);
+        }
+        "set_order_tag" => {
+            let req = serde_json::from_value::<SetOrderTagRequest>(request)?;
+            let res = prediction_markets.set_order_tag(req.order_id, req.tag).await?;
+            yield json!(res);
+        }
+        "get_orders_by_tag" => {
+            let req = serde_json::from_value::<GetOrdersByTagRequest>(request)?;
+            let res = prediction_markets.get_orders_by_tag(&req.tag).await;
+            yield json!(res);
+        }
+        "get_orders_matching" => {
+            let req = serde_json::from_value::<GetOrdersMatchingRequest>(request)?;
+            let res = prediction_markets.get_orders_matching(req.query).await;
+            yield json!(res);
+        }
+        "list_orders" => {
+            let req = serde_json::from_value::<ListOrdersRequest>(request)?;
+            let res = prediction_markets.list_orders(req.filter, req.sort.unwrap_or_default()).await;
+            yield json!(res);
+        }
+        "prune_settled_orders" => {
+            let req = serde_json::from_value::<PruneSettledOrdersRequest>(request)?;
+            let res = prediction_markets.prune_settled_orders(req.older_than).await?;
             yield json!(res);
         }
         "stream_order_from_db" => {
@@ -75,13 +249,47 @@ pub async fn handle_rpc(
                 yield json!(res);
             }
         }
+        "subscribe_all_order_updates" => {
+            let mut stream = prediction_markets.subscribe_all_order_updates().await;
+            while let Some(res) = stream.next().await {
+                yield json!(res);
+            }
+        }
         "cancel_order" => {
             let req = serde_json::from_value::<CancelOrderRequest>(request)?;
             let res = prediction_markets.cancel_order(req.order_id).await?;
             yield json!(res);
         }
+        "cancel_all_orders" => {
+            let req = serde_json::from_value::<CancelAllOrdersRequest>(request)?;
+            let res = prediction_markets.cancel_all_orders(req.filter).await?;
+            yield json!(res);
+        }
+        "register_dead_mans_switch" => {
+            let req = serde_json::from_value::<RegisterDeadMansSwitchRequest>(request)?;
+            let res = prediction_markets.register_dead_mans_switch(req.interval, req.filter).await?;
+            yield json!(res);
+        }
+        "clear_dead_mans_switch" => {
+            let res = prediction_markets.clear_dead_mans_switch().await?;
+            yield json!(res);
+        }
+        "get_dead_mans_switch" => {
+            let res = prediction_markets.get_dead_mans_switch().await;
+            yield json!(res);
+        }
+        "get_available_bitcoin_balance" => {
+            let req = serde_json::from_value::<GetAvailableBitcoinBalanceRequest>(request)?;
+            let res = prediction_markets.get_available_bitcoin_balance(req.refresh).await?;
+            yield json!(res);
+        }
         "send_order_bitcoin_balance_to_primary_module" => {
-            let res = prediction_markets.send_order_bitcoin_balance_to_primary_module().await?;
+            let req = serde_json::from_value::<SendOrderBitcoinBalanceToPrimaryModuleRequest>(request)?;
+            let res = prediction_markets.send_order_bitcoin_balance_to_primary_module(req.max_fee).await?;
+            yield json!(res);
+        }
+        "close_all_positions" => {
+            let res = prediction_markets.close_all_positions().await?;
             yield json!(res);
         }
         "sync_payouts" => {
@@ -89,9 +297,29 @@ pub async fn handle_rpc(
             let res = prediction_markets.sync_payouts(req.market_specifier).await?;
             yield json!(res);
         }
+        #[cfg(feature = "cli")]
+        "sync_all_payouts" => {
+            let mut stream = prediction_markets.sync_all_payouts();
+            while let Some(res) = stream.next().await {
+                yield json!(res);
+            }
+        }
         "sync_matches" => {
             let req = serde_json::from_value::<SyncMatchesRequest>(request)?;
-            let res = prediction_markets.sync_matches(req.order_path).await?;
+            let res = prediction_markets.sync_matches(req.order_path, req.force).await?;
+            yield json!(res);
+        }
+        "set_trading_enabled" => {
+            let req = serde_json::from_value::<SetTradingEnabledRequest>(request)?;
+            let res = prediction_markets.set_trading_enabled(req.enabled);
+            yield json!(res);
+        }
+        "trading_enabled" => {
+            let res = prediction_markets.trading_enabled();
+            yield json!(res);
+        }
+        "needs_sync" => {
+            let res = prediction_markets.needs_sync().await;
             yield json!(res);
         }
         "start_watch_matches" => {
@@ -104,16 +332,52 @@ pub async fn handle_rpc(
             let res = prediction_markets.stop_watch_matches(req.id).await?;
             yield json!(res);
         }
+        "start_background_sync" => {
+            let req = serde_json::from_value::<StartBackgroundSyncRequest>(request)?;
+            let res = prediction_markets.start_background_sync(req.interval, req.filter).await?;
+            yield json!(res);
+        }
+        "stop_background_sync" => {
+            let res = prediction_markets.stop_background_sync().await?;
+            yield json!(res);
+        }
         "resync_order_slots" => {
             let req = serde_json::from_value::<ResyncOrderSlotsRequest>(request)?;
             let res = prediction_markets.resync_order_slots(req.gap_size_to_check).await?;
             yield json!(res);
         }
+        "resync_order_slots_streamed" => {
+            let req = serde_json::from_value::<ResyncOrderSlotsRequest>(request)?;
+            let mut stream = prediction_markets.resync_order_slots_streamed(req.gap_size_to_check);
+            while let Some(res) = stream.next().await {
+                yield json!(res?);
+            }
+        }
         "get_candlesticks" => {
             let req = serde_json::from_value::<GetCandlesticksRequest>(request)?;
             let res = prediction_markets.get_candlesticks(req.market, req.outcome, req.candlestick_interval, req.min_candlestick_timestamp).await?;
             yield json!(res);
         }
+        "get_candlesticks_filled" => {
+            let req = serde_json::from_value::<GetCandlesticksFilledRequest>(request)?;
+            let res = prediction_markets.get_candlesticks_filled(req.market, req.outcome, req.candlestick_interval, req.min_candlestick_timestamp, req.fill).await?;
+            yield json!(res);
+        }
+        "get_candlesticks_with_trade_count" => {
+            let req = serde_json::from_value::<GetCandlesticksRequest>(request)?;
+            let res = prediction_markets.get_candlesticks_with_trade_count(req.market, req.outcome, req.candlestick_interval, req.min_candlestick_timestamp).await?;
+            yield json!(res);
+        }
+        "get_market_candlesticks" => {
+            let req = serde_json::from_value::<GetMarketCandlesticksRequest>(request)?;
+            let res = prediction_markets.get_market_candlesticks(req.market, req.candlestick_interval, req.min_candlestick_timestamp).await?;
+            yield json!(res);
+        }
+        "get_market_candlesticks_normalized" => {
+            let req = serde_json::from_value::<GetMarketCandlesticksRequest>(request)?;
+            let res = prediction_markets.get_market_candlesticks_normalized(req.market, req.candlestick_interval, req.min_candlestick_timestamp).await?;
+            yield json!(res);
+        }
         "wait_candlesticks" => {
             let req = serde_json::from_value::<WaitCandlesticksRequest>(request)?;
             let res = prediction_markets.wait_candlesticks(req.market, req.outcome, req.candlestick_interval, req.candlestick_timestamp, req.candlestick_volume).await?;
@@ -129,11 +393,95 @@ pub async fn handle_rpc(
         "get_order_book" => {
             let req = serde_json::from_value::<GetOrderBookRequest>(request)?;
             let res = prediction_markets.get_order_book(req.market, req.outcome).await?;
+            let mut value = json!(res);
+            value["implied_probabilities"] = json!(res.implied_probabilities());
+            yield value;
+        }
+        "get_binary_market_bbo" => {
+            let req = serde_json::from_value::<GetBinaryMarketBboRequest>(request)?;
+            let res = prediction_markets.get_binary_market_bbo(req.market).await?;
+            yield json!(res);
+        }
+        "quote_fill" => {
+            let req = serde_json::from_value::<QuoteFillRequest>(request)?;
+            let res = prediction_markets.quote_fill(req.market, req.outcome, req.side, req.quantity).await?;
+            yield json!(res);
+        }
+        "quote_market_order" => {
+            let req = serde_json::from_value::<QuoteMarketOrderRequest>(request)?;
+            let res = prediction_markets.quote_market_order(req.market, req.outcome, req.side, req.quantity, req.max_slippage).await?;
+            yield json!(res);
+        }
+        "simulate_order_match" => {
+            let req = serde_json::from_value::<SimulateOrderMatchRequest>(request)?;
+            let res = prediction_markets.simulate_order_match(req.market, req.outcome, req.side, req.price, req.quantity).await?;
+            yield json!(res);
+        }
+        "market_making_capital_estimate" => {
+            let req = serde_json::from_value::<MarketMakingCapitalEstimateRequest>(request)?;
+            let res = prediction_markets.market_making_capital_estimate(req.market, req.outcome, req.quotes).await?;
+            yield json!(res);
+        }
+        "get_market_outcome_stats" => {
+            let req = serde_json::from_value::<GetMarketOutcomeStatsRequest>(request)?;
+            let res = prediction_markets.get_market_outcome_stats(req.market, req.outcome).await?;
+            yield json!(res);
+        }
+        "get_market_summary" => {
+            let req = serde_json::from_value::<GetMarketSummaryRequest>(request)?;
+            let res = prediction_markets.get_market_summary(req.market).await?;
+            yield json!(res);
+        }
+        "get_operation_outcome" => {
+            let req = serde_json::from_value::<GetOperationOutcomeRequest>(request)?;
+            let res = prediction_markets.get_operation_outcome(req.operation_id).await;
+            yield json!(res);
+        }
+        "get_operation_status" => {
+            let req = serde_json::from_value::<GetOperationOutcomeRequest>(request)?;
+            let res = prediction_markets.get_operation_status(req.operation_id).await;
+            yield json!(res);
+        }
+        "abandon_operation" => {
+            let req = serde_json::from_value::<GetOperationOutcomeRequest>(request)?;
+            let res = prediction_markets.abandon_operation(req.operation_id).await?;
+            yield json!(res);
+        }
+        "get_consensus_timestamp" => {
+            let res = prediction_markets.get_consensus_timestamp().await?;
+            yield json!(res);
+        }
+        "health_check" => {
+            let res = prediction_markets.health_check().await?;
+            yield json!(res);
+        }
+        "get_rejected_order_discrepancies" => {
+            let res = prediction_markets.get_rejected_order_discrepancies().await;
+            yield json!(res);
+        }
+        "dismiss_rejected_order_discrepancy" => {
+            let req = serde_json::from_value::<DismissRejectedOrderDiscrepancyRequest>(request)?;
+            let res = prediction_markets.dismiss_rejected_order_discrepancy(req.order_id).await?;
+            yield json!(res);
+        }
+        "get_submitted_transactions" => {
+            let req = serde_json::from_value::<GetSubmittedTransactionsRequest>(request)?;
+            let res = prediction_markets.get_submitted_transactions(req.since).await;
             yield json!(res);
         }
         "save_market" => {
             let req = serde_json::from_value::<SaveMarketRequest>(request)?;
-            let res = prediction_markets.save_market(req.market).await;
+            let res = prediction_markets.save_market(req.market, req.update_timestamp).await;
+            yield json!(res);
+        }
+        "save_market_with_note" => {
+            let req = serde_json::from_value::<SaveMarketWithNoteRequest>(request)?;
+            let res = prediction_markets.save_market_with_note(req.market, req.note, req.tags, req.update_timestamp).await;
+            yield json!(res);
+        }
+        "update_saved_market_note" => {
+            let req = serde_json::from_value::<UpdateSavedMarketNoteRequest>(request)?;
+            let res = prediction_markets.update_saved_market_note(req.market, req.note).await?;
             yield json!(res);
         }
         "unsave_market" => {
@@ -141,10 +489,72 @@ pub async fn handle_rpc(
             let res = prediction_markets.unsave_market(req.market).await;
             yield json!(res);
         }
+        "set_market_display_override" => {
+            let req = serde_json::from_value::<SetMarketDisplayOverrideRequest>(request)?;
+            let res = prediction_markets.set_market_display_override(req.market, req.display_override).await?;
+            yield json!(res);
+        }
+        "get_market_display_override" => {
+            let req = serde_json::from_value::<MarketDisplayOverrideRequest>(request)?;
+            let res = prediction_markets.get_market_display_override(req.market).await;
+            yield json!(res);
+        }
+        "clear_market_display_override" => {
+            let req = serde_json::from_value::<MarketDisplayOverrideRequest>(request)?;
+            let res = prediction_markets.clear_market_display_override(req.market).await;
+            yield json!(res);
+        }
+        "save_market_template" => {
+            let req = serde_json::from_value::<SaveMarketTemplateRequest>(request)?;
+            let res = prediction_markets.save_market_template(req.name, req.template).await;
+            yield json!(res);
+        }
+        "get_market_templates" => {
+            let res = prediction_markets.get_market_templates().await;
+            yield json!(res);
+        }
+        "remove_market_template" => {
+            let req = serde_json::from_value::<RemoveMarketTemplateRequest>(request)?;
+            let res = prediction_markets.remove_market_template(req.name).await;
+            yield json!(res);
+        }
+        "new_market_from_template" => {
+            let req = serde_json::from_value::<NewMarketFromTemplateRequest>(request)?;
+            let res = prediction_markets.new_market_from_template(&req.name, req.substitutions).await?;
+            yield json!(res);
+        }
+        "reconcile_settlements" => {
+            let res = prediction_markets.reconcile_settlements().await?;
+            yield json!(res);
+        }
+        "get_pending_settlements" => {
+            let res = prediction_markets.get_pending_settlements().await;
+            yield json!(res);
+        }
+        "acknowledge_pending_settlement" => {
+            let req = serde_json::from_value::<AcknowledgePendingSettlementRequest>(request)?;
+            let res = prediction_markets.acknowledge_pending_settlement(req.market).await;
+            yield json!(res);
+        }
+        "get_market_event" => {
+            let req = serde_json::from_value::<GetMarketEventRequest>(request)?;
+            let res = prediction_markets.get_market_event(req.market).await?;
+            yield json!(res.as_ref());
+        }
+        "get_outcome_title" => {
+            let req = serde_json::from_value::<GetOutcomeTitleRequest>(request)?;
+            let res = prediction_markets.get_outcome_title(req.market, req.outcome).await?;
+            yield json!(res);
+        }
         "get_saved_markets" => {
             let res = prediction_markets.get_saved_markets().await;
             yield json!(res);        
         }
+        "get_saved_markets_by_tag" => {
+            let req = serde_json::from_value::<GetSavedMarketsByTagRequest>(request)?;
+            let res = prediction_markets.get_saved_markets_by_tag(&req.tag).await;
+            yield json!(res);
+        }
         "set_name_to_payout_control" => {
             let req = serde_json::from_value::<SetNameToPayoutControlRequest>(request)?;
             let res = prediction_markets.set_name_to_payout_control(req.name, req.payout_control).await;
@@ -159,6 +569,25 @@ pub async fn handle_rpc(
             let res = prediction_markets.get_name_to_payout_control_map().await;
             yield json!(res);
         }
+        "export_payout_control_names" => {
+            let res = prediction_markets.export_payout_control_names().await;
+            yield json!(res);
+        }
+        "import_payout_control_names" => {
+            let req = serde_json::from_value::<ImportPayoutControlNamesRequest>(request)?;
+            let res = prediction_markets.import_payout_control_names(&req.json, req.overwrite).await?;
+            yield json!(res);
+        }
+        "resolve_payout_control_label" => {
+            let req = serde_json::from_value::<ResolvePayoutControlLabelRequest>(request)?;
+            let res = prediction_markets.resolve_payout_control_label(&req.payout_control).await;
+            yield json!(res);
+        }
+        "get_payout_control_weight_map_named" => {
+            let req = serde_json::from_value::<GetPayoutControlWeightMapNamedRequest>(request)?;
+            let res = prediction_markets.get_payout_control_weight_map_named(req.market).await?;
+            yield json!(res);
+        }
         _ => {
             Err(anyhow::format_err!("unknown method"))?;
             unreachable!();
@@ -166,12 +595,38 @@ pub async fn handle_rpc(
     }})
 }
 
+#[derive(Deserialize)]
+pub struct OrderOwnerPubkeyRequest {
+    order_id: OrderId,
+}
+
 #[derive(Deserialize)]
 pub struct NewMarketRequest {
     event_json: PredictionMarketEventJson,
     contract_price: Amount,
     payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
     weight_required_for_payout: WeightRequiredForPayout,
+    max_fee: Option<Amount>,
+}
+
+#[derive(Deserialize)]
+pub struct NewMarketWithDisplayHintsRequest {
+    event_json: PredictionMarketEventJson,
+    contract_price: Amount,
+    payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
+    weight_required_for_payout: WeightRequiredForPayout,
+    outcome_display_hints: Vec<OutcomeDisplayHint>,
+    max_fee: Option<Amount>,
+}
+
+#[derive(Deserialize)]
+pub struct NewMarketIdempotentRequest {
+    idempotency_key: [u8; 32],
+    event_json: PredictionMarketEventJson,
+    contract_price: Amount,
+    payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
+    weight_required_for_payout: WeightRequiredForPayout,
+    max_fee: Option<Amount>,
 }
 
 #[derive(Deserialize)]
@@ -180,6 +635,29 @@ pub struct GetMarketRequest {
     from_local_cache: bool,
 }
 
+#[derive(Deserialize)]
+pub struct GetMarketPayoutRequest {
+    market: OutPoint,
+}
+
+#[derive(Deserialize)]
+pub struct GetMarketsRequest {
+    markets: Vec<OutPoint>,
+    from_local_cache: bool,
+}
+
+#[derive(Deserialize)]
+pub struct GetMarketsWherePayoutControlRequest {
+    payout_control: NostrPublicKeyHex,
+    created_after: UnixTimestamp,
+    from_local_cache: bool,
+}
+
+#[derive(Deserialize)]
+pub struct FindMarketByEventHashRequest {
+    event_hash_hex: PredictionMarketEventHashHex,
+}
+
 #[derive(Deserialize)]
 pub struct PayoutMarketRequest {
     market: OutPoint,
@@ -191,6 +669,29 @@ pub struct GetEventPayoutAttestationsUsedToPermitPayoutRequest {
     market: OutPoint,
 }
 
+#[derive(Deserialize)]
+pub struct GetPayoutAttestationStatusRequest {
+    market: OutPoint,
+    event_payout: EventPayout,
+    event_payout_attestations_json: Vec<PredictionMarketEventJson>,
+    own_payout_control: Option<NostrPublicKeyHex>,
+}
+
+#[derive(Deserialize)]
+pub struct NewOrderCostRequest {
+    side: Side,
+    price: Amount,
+    quantity: ContractOfOutcomeAmount,
+}
+
+#[derive(Deserialize)]
+pub struct CheckNewOrderAffordableRequest {
+    side: Side,
+    price: Amount,
+    quantity: ContractOfOutcomeAmount,
+    primary_module_balance: Amount,
+}
+
 #[derive(Deserialize)]
 pub struct NewOrderRequest {
     market: OutPoint,
@@ -198,6 +699,85 @@ pub struct NewOrderRequest {
     side: Side,
     price: Amount,
     quantity: ContractOfOutcomeAmount,
+    max_fee: Option<Amount>,
+}
+
+#[derive(Deserialize)]
+pub struct NewBinaryOrderRequest {
+    market: OutPoint,
+    yes: bool,
+    side: Side,
+    price: Amount,
+    quantity: ContractOfOutcomeAmount,
+    max_fee: Option<Amount>,
+}
+
+#[derive(Deserialize)]
+pub struct NewOrderByOutcomeTitleRequest {
+    market: OutPoint,
+    outcome_title: String,
+    side: Side,
+    price: Amount,
+    quantity: ContractOfOutcomeAmount,
+    max_fee: Option<Amount>,
+}
+
+#[derive(Deserialize)]
+pub struct NewOrderByProbabilityRequest {
+    market: OutPoint,
+    outcome: Outcome,
+    side: Side,
+    probability: f64,
+    quantity: ContractOfOutcomeAmount,
+    max_fee: Option<Amount>,
+}
+
+#[derive(Deserialize)]
+pub struct NewOrderWithOperationIdRequest {
+    operation_id: OperationId,
+    market: OutPoint,
+    outcome: Outcome,
+    side: Side,
+    price: Amount,
+    quantity: ContractOfOutcomeAmount,
+    max_fee: Option<Amount>,
+}
+
+#[derive(Deserialize)]
+pub struct SubmitMultiMarketRequest {
+    requests: Vec<crate::OrderRequest>,
+    max_fee: Option<Amount>,
+}
+
+#[derive(Deserialize)]
+pub struct ReserveOrderIdsRequest {
+    count: usize,
+}
+
+#[derive(Deserialize)]
+pub struct ReleaseOrderIdRequest {
+    order_id: OrderId,
+}
+
+#[derive(Deserialize)]
+pub struct NewOrderWithIdRequest {
+    order_id: OrderId,
+    market: OutPoint,
+    outcome: Outcome,
+    side: Side,
+    price: Amount,
+    quantity: ContractOfOutcomeAmount,
+    max_fee: Option<Amount>,
+}
+
+#[derive(Deserialize)]
+pub struct NewOrderWithTypeRequest {
+    market: OutPoint,
+    outcome: Outcome,
+    side: Side,
+    order_type: OrderType,
+    quantity: ContractOfOutcomeAmount,
+    max_fee: Option<Amount>,
 }
 
 #[derive(Deserialize)]
@@ -206,11 +786,72 @@ pub struct GetOrderRequest {
     from_local_cache: bool,
 }
 
+#[derive(Deserialize)]
+pub struct GetOrderCheckedRequest {
+    order_id: OrderId,
+}
+
+#[derive(Deserialize)]
+pub struct GetOrderLedgerRequest {
+    order_id: OrderId,
+}
+
+#[derive(Deserialize)]
+pub struct ExportOrderQuoteRequest {
+    order_id: OrderId,
+}
+
+#[derive(Deserialize)]
+pub struct PreviewSharedQuoteRequest {
+    quote: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetMarketOrdersRequest {
+    market: OutPoint,
+    after: Option<PublicKey>,
+    limit: u32,
+}
+
 #[derive(Deserialize)]
 pub struct GetOrdersFromDbRequest {
     filter: OrderFilter,
 }
 
+#[derive(Deserialize)]
+pub struct GetOrdersFromDbPaginatedRequest {
+    filter: OrderFilter,
+    cursor: Option<OrderCursor>,
+    limit: NonZeroUsize,
+}
+
+#[derive(Deserialize)]
+pub struct SetOrderTagRequest {
+    order_id: OrderId,
+    tag: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetOrdersByTagRequest {
+    tag: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetOrdersMatchingRequest {
+    query: OrderQuery,
+}
+
+#[derive(Deserialize)]
+pub struct ListOrdersRequest {
+    filter: OrderFilter,
+    sort: Option<OrderSort>,
+}
+
+#[derive(Deserialize)]
+pub struct PruneSettledOrdersRequest {
+    older_than: UnixTimestamp,
+}
+
 #[derive(Deserialize)]
 pub struct StreamOrderFromDbRequest {
     id: OrderId,
@@ -226,6 +867,28 @@ pub struct CancelOrderRequest {
     order_id: OrderId,
 }
 
+#[derive(Deserialize)]
+pub struct CancelAllOrdersRequest {
+    filter: OrderFilter,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterDeadMansSwitchRequest {
+    interval: Duration,
+    filter: OrderFilter,
+}
+
+#[derive(Deserialize)]
+pub struct GetAvailableBitcoinBalanceRequest {
+    #[serde(default)]
+    refresh: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SendOrderBitcoinBalanceToPrimaryModuleRequest {
+    max_fee: Option<Amount>,
+}
+
 #[derive(Deserialize)]
 pub struct SyncPayoutsRequest {
     market_specifier: Option<OutPoint>,
@@ -234,6 +897,8 @@ pub struct SyncPayoutsRequest {
 #[derive(Deserialize)]
 pub struct SyncMatchesRequest {
     order_path: OrderPath,
+    #[serde(default)]
+    force: bool,
 }
 
 #[derive(Deserialize)]
@@ -246,6 +911,17 @@ pub struct StopWatchMatchesRequest {
     id: u64,
 }
 
+#[derive(Deserialize)]
+pub struct SetTradingEnabledRequest {
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct StartBackgroundSyncRequest {
+    interval: Duration,
+    filter: OrderFilter,
+}
+
 #[derive(Deserialize)]
 pub struct ResyncOrderSlotsRequest {
     gap_size_to_check: usize,
@@ -259,6 +935,22 @@ pub struct GetCandlesticksRequest {
     min_candlestick_timestamp: UnixTimestamp,
 }
 
+#[derive(Deserialize)]
+pub struct GetMarketCandlesticksRequest {
+    market: OutPoint,
+    candlestick_interval: Seconds,
+    min_candlestick_timestamp: UnixTimestamp,
+}
+
+#[derive(Deserialize)]
+pub struct GetCandlesticksFilledRequest {
+    market: OutPoint,
+    outcome: Outcome,
+    candlestick_interval: Seconds,
+    min_candlestick_timestamp: UnixTimestamp,
+    fill: FillMode,
+}
+
 #[derive(Deserialize)]
 pub struct WaitCandlesticksRequest {
     market: OutPoint,
@@ -283,9 +975,101 @@ pub struct GetOrderBookRequest {
     outcome: Outcome,
 }
 
+#[derive(Deserialize)]
+pub struct GetBinaryMarketBboRequest {
+    market: OutPoint,
+}
+
+#[derive(Deserialize)]
+pub struct QuoteFillRequest {
+    market: OutPoint,
+    outcome: Outcome,
+    side: Side,
+    quantity: ContractOfOutcomeAmount,
+}
+
+#[derive(Deserialize)]
+pub struct QuoteMarketOrderRequest {
+    market: OutPoint,
+    outcome: Outcome,
+    side: Side,
+    quantity: ContractOfOutcomeAmount,
+    max_slippage: Amount,
+}
+
+#[derive(Deserialize)]
+pub struct SimulateOrderMatchRequest {
+    market: OutPoint,
+    outcome: Outcome,
+    side: Side,
+    price: Amount,
+    quantity: ContractOfOutcomeAmount,
+}
+
+#[derive(Deserialize)]
+pub struct MarketMakingCapitalEstimateRequest {
+    market: OutPoint,
+    outcome: Outcome,
+    quotes: Vec<(Side, Amount, ContractOfOutcomeAmount)>,
+}
+
+#[derive(Deserialize)]
+pub struct GetMarketOutcomeStatsRequest {
+    market: OutPoint,
+    outcome: Outcome,
+}
+
+#[derive(Deserialize)]
+pub struct GetMarketSummaryRequest {
+    market: OutPoint,
+}
+
+#[derive(Deserialize)]
+pub struct GetMarketEventRequest {
+    market: OutPoint,
+}
+
+#[derive(Deserialize)]
+pub struct GetOutcomeTitleRequest {
+    market: OutPoint,
+    outcome: Outcome,
+}
+
+#[derive(Deserialize)]
+pub struct GetOperationOutcomeRequest {
+    operation_id: OperationId,
+}
+
+#[derive(Deserialize)]
+pub struct DismissRejectedOrderDiscrepancyRequest {
+    order_id: OrderId,
+}
+
+#[derive(Deserialize)]
+pub struct GetSubmittedTransactionsRequest {
+    since: UnixTimestamp,
+}
+
 #[derive(Deserialize)]
 pub struct SaveMarketRequest {
     market: OutPoint,
+    #[serde(default)]
+    update_timestamp: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SaveMarketWithNoteRequest {
+    market: OutPoint,
+    note: Option<String>,
+    tags: Vec<String>,
+    #[serde(default)]
+    update_timestamp: bool,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateSavedMarketNoteRequest {
+    market: OutPoint,
+    note: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -293,6 +1077,45 @@ pub struct UnsaveMarketRequest {
     market: OutPoint,
 }
 
+#[derive(Deserialize)]
+pub struct SetMarketDisplayOverrideRequest {
+    market: OutPoint,
+    display_override: MarketDisplayOverride,
+}
+
+#[derive(Deserialize)]
+pub struct MarketDisplayOverrideRequest {
+    market: OutPoint,
+}
+
+#[derive(Deserialize)]
+pub struct AcknowledgePendingSettlementRequest {
+    market: OutPoint,
+}
+
+#[derive(Deserialize)]
+pub struct SaveMarketTemplateRequest {
+    name: String,
+    template: MarketTemplate,
+}
+
+#[derive(Deserialize)]
+pub struct RemoveMarketTemplateRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewMarketFromTemplateRequest {
+    name: String,
+    #[serde(default)]
+    substitutions: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+pub struct GetSavedMarketsByTagRequest {
+    tag: String,
+}
+
 #[derive(Deserialize)]
 pub struct SetNameToPayoutControlRequest {
     name: String,
@@ -303,3 +1126,19 @@ pub struct SetNameToPayoutControlRequest {
 pub struct GetNameToPayoutControlRequest {
     name: String,
 }
+
+#[derive(Deserialize)]
+pub struct ImportPayoutControlNamesRequest {
+    json: String,
+    overwrite: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ResolvePayoutControlLabelRequest {
+    payout_control: NostrPublicKeyHex,
+}
+
+#[derive(Deserialize)]
+pub struct GetPayoutControlWeightMapNamedRequest {
+    market: OutPoint,
+}
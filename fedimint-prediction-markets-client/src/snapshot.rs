@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+
+use fedimint_core::db::{DatabaseTransaction, IDatabaseTransactionOpsCoreTyped};
+use fedimint_core::OutPoint;
+use fedimint_prediction_markets_common::{Market, Order};
+use futures::StreamExt;
+
+use crate::order_filter::OrderFilter;
+use crate::{db, AvailableBitcoinBalance, OrderId, PredictionMarketsClientModule, SavedMarketMeta};
+
+/// Read-only view of the client's local cache at a single point in time,
+/// created by [PredictionMarketsClientModule::snapshot].
+///
+/// Every method reads from the same database transaction, so results are
+/// consistent with each other: writes made after the snapshot was created,
+/// such as orders synced from the federation or new orders, are not visible.
+/// Nothing is fetched from the federation and nothing is ever written.
+///
+/// The underlying database snapshot is held until this is dropped, so it
+/// should be short-lived, such as for a single render pass.
+pub struct ClientSnapshot<'a> {
+    module: &'a PredictionMarketsClientModule,
+    dbtx: DatabaseTransaction<'a>,
+}
+
+impl<'a> ClientSnapshot<'a> {
+    pub(crate) fn new(
+        module: &'a PredictionMarketsClientModule,
+        dbtx: DatabaseTransaction<'a>,
+    ) -> Self {
+        Self { module, dbtx }
+    }
+
+    /// Cached version of [PredictionMarketsClientModule::get_market].
+    pub async fn get_market(&mut self, market: OutPoint) -> Option<Market> {
+        self.dbtx.get_value(&db::MarketKey(market)).await
+    }
+
+    /// Cached version of [PredictionMarketsClientModule::get_markets].
+    pub async fn get_markets(
+        &mut self,
+        markets: Vec<OutPoint>,
+    ) -> BTreeMap<OutPoint, Option<Market>> {
+        let mut result = BTreeMap::new();
+        for market in markets {
+            let cached = self.dbtx.get_value(&db::MarketKey(market)).await;
+            result.insert(market, cached);
+        }
+
+        result
+    }
+
+    /// Cached version of [PredictionMarketsClientModule::get_order].
+    pub async fn get_order(&mut self, order_id: OrderId) -> Option<Order> {
+        self.dbtx
+            .get_value(&db::OrderKey(order_id))
+            .await
+            .and_then(|slot| slot.to_order())
+    }
+
+    /// See [PredictionMarketsClientModule::get_orders_from_db].
+    pub async fn get_orders(&mut self, filter: OrderFilter) -> BTreeMap<OrderId, Order> {
+        let mut orders = BTreeMap::new();
+        for order_id in PredictionMarketsClientModule::get_order_ids(&mut self.dbtx, filter).await {
+            if let Some(order) = self.get_order(order_id).await {
+                orders.insert(order_id, order);
+            }
+        }
+
+        orders
+    }
+
+    /// See [PredictionMarketsClientModule::get_available_bitcoin_balance].
+    pub async fn get_available_bitcoin_balance(&mut self) -> AvailableBitcoinBalance {
+        self.module
+            .available_bitcoin_balance_from_db(&mut self.dbtx)
+            .await
+    }
+
+    /// See [PredictionMarketsClientModule::get_saved_markets].
+    pub async fn get_saved_markets(&mut self) -> Vec<(OutPoint, SavedMarketMeta)> {
+        self.dbtx
+            .find_by_prefix(&db::ClientSavedMarketsPrefixAll)
+            .await
+            .map(|(k, v)| (k.market, v))
+            .collect()
+            .await
+    }
+}
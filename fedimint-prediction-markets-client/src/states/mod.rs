@@ -8,13 +8,22 @@ use fedimint_core::db::IDatabaseTransactionOpsCoreTyped;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::TransactionId;
 use fedimint_prediction_markets_common::UnixTimestamp;
-use state_transitions::{await_tx_accepted, do_nothing, sync_market, sync_orders};
+use state_transitions::{await_tx_accepted, do_nothing, sync_market, sync_orders, transition_to};
+use tracing::warn;
+use triggers::await_order_lookup_from_federation;
 
-use crate::{db, market_outpoint_from_tx_id, OrderId, PredictionMarketsClientContext};
+use crate::{
+    db, market_outpoint_from_tx_id, OrderId, PredictionMarketsClientContext,
+    PredictionMarketsClientModule, SavedMarketMeta,
+};
 
 pub mod state_transitions;
 pub mod triggers;
 
+/// Reason kept for rejections recorded before the federation's reason was
+/// stored in the state.
+pub const UNKNOWN_REJECTION_REASON: &str = "unknown";
+
 /// Tracks a transaction.
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
 pub struct PredictionMarketsStateMachine {
@@ -31,6 +40,62 @@ pub enum PredictionMarketState {
     PayoutMarket(PayoutMarketState),
 }
 
+impl PredictionMarketState {
+    /// Order the state is about, if any.
+    pub fn order_id(&self) -> Option<OrderId> {
+        match self {
+            PredictionMarketState::NewOrder(
+                NewOrderState::Pending { order_id, .. }
+                | NewOrderState::Rejected { order_id, .. }
+                | NewOrderState::Rejected2 { order_id, .. }
+                | NewOrderState::Accepted { order_id, .. }
+                | NewOrderState::Accepted2 { order_id }
+                | NewOrderState::Rejected3 { order_id, .. }
+                | NewOrderState::Rejected4 { order_id, .. },
+            ) => Some(*order_id),
+            PredictionMarketState::CancelOrder(
+                CancelOrderState::Pending {
+                    order_to_sync_on_accepted,
+                    ..
+                }
+                | CancelOrderState::Accepted {
+                    order_to_sync_on_accepted,
+                },
+            )
+            | PredictionMarketState::ConsumeOrderBitcoinBalance(
+                ConsumeOrderBitcoinBalanceState::Pending {
+                    order_to_sync_on_accepted,
+                    ..
+                }
+                | ConsumeOrderBitcoinBalanceState::Accepted {
+                    order_to_sync_on_accepted,
+                },
+            ) => Some(*order_to_sync_on_accepted),
+            _ => None,
+        }
+    }
+
+    /// Transaction the state is tracking, if it is still known.
+    pub fn tx_id(&self) -> Option<TransactionId> {
+        match self {
+            PredictionMarketState::NewMarket(
+                NewMarketState::Pending { tx_id }
+                | NewMarketState::Accepted { tx_id }
+                | NewMarketState::Accepted2 { tx_id },
+            )
+            | PredictionMarketState::NewOrder(NewOrderState::Pending { tx_id, .. })
+            | PredictionMarketState::CancelOrder(CancelOrderState::Pending { tx_id, .. })
+            | PredictionMarketState::ConsumeOrderBitcoinBalance(
+                ConsumeOrderBitcoinBalanceState::Pending { tx_id, .. },
+            )
+            | PredictionMarketState::PayoutMarket(
+                PayoutMarketState::Pending { tx_id } | PayoutMarketState::Accepted { tx_id },
+            ) => Some(*tx_id),
+            _ => None,
+        }
+    }
+}
+
 impl State for PredictionMarketsStateMachine {
     type ModuleContext = PredictionMarketsClientContext;
 
@@ -88,6 +153,7 @@ pub enum NewMarketState {
     Accepted { tx_id: TransactionId },
     Accepted2 { tx_id: TransactionId },
     Complete,
+    Rejected { reason: String },
 }
 
 impl Into<PredictionMarketState> for NewMarketState {
@@ -109,7 +175,7 @@ impl StateCategoryTrait for NewMarketState {
                     global_context,
                     tx_id,
                     Self::Accepted { tx_id },
-                    Self::Complete,
+                    |reason| Self::Rejected { reason },
                 )]
             }
             NewMarketState::Accepted { tx_id } => {
@@ -121,24 +187,26 @@ impl StateCategoryTrait for NewMarketState {
                 )]
             }
             NewMarketState::Accepted2 { tx_id } => {
-                vec![StateTransition::new(async {}, move |dbtx, _, _| {
+                vec![StateTransition::new(async {}, move |dbtx, _, previous| {
                     Box::pin(async move {
                         dbtx.module_tx()
                             .insert_entry(
                                 &db::ClientSavedMarketsKey {
                                     market: market_outpoint_from_tx_id(tx_id),
                                 },
-                                &UnixTimestamp::now(),
+                                &SavedMarketMeta {
+                                    saved_at: UnixTimestamp::now(),
+                                    note: None,
+                                    tags: Vec::new(),
+                                },
                             )
                             .await;
-                        PredictionMarketsStateMachine {
-                            operation_id,
-                            state: Self::Complete.into(),
-                        }
+                        transition_to(operation_id, &previous.state, Self::Complete.into())
                     })
                 })]
             }
             NewMarketState::Complete => vec![],
+            NewMarketState::Rejected { .. } => vec![do_nothing(operation_id, Self::Complete)],
         }
     }
 }
@@ -151,10 +219,12 @@ pub enum NewOrderState {
         orders_to_sync_on_accepted: BTreeSet<OrderId>,
         orders_to_sync_on_rejected: BTreeSet<OrderId>,
     },
+    // replaced by Rejected3, which keeps the reason
     Rejected {
         order_id: OrderId,
         orders_to_sync_on_rejected: BTreeSet<OrderId>,
     },
+    // replaced by Rejected4, which keeps the reason
     Rejected2 {
         order_id: OrderId,
     },
@@ -166,6 +236,15 @@ pub enum NewOrderState {
         order_id: OrderId,
     },
     Complete,
+    Rejected3 {
+        order_id: OrderId,
+        orders_to_sync_on_rejected: BTreeSet<OrderId>,
+        reason: String,
+    },
+    Rejected4 {
+        order_id: OrderId,
+        reason: String,
+    },
 }
 
 impl Into<PredictionMarketState> for NewOrderState {
@@ -194,33 +273,80 @@ impl StateCategoryTrait for NewOrderState {
                     order_id,
                     orders_to_sync_on_accepted,
                 },
-                Self::Rejected {
+                move |reason| Self::Rejected3 {
                     order_id,
-                    orders_to_sync_on_rejected,
+                    orders_to_sync_on_rejected: orders_to_sync_on_rejected.clone(),
+                    reason,
                 },
             )],
             NewOrderState::Rejected {
                 order_id,
                 orders_to_sync_on_rejected,
+            } => Self::Rejected3 {
+                order_id,
+                orders_to_sync_on_rejected,
+                reason: UNKNOWN_REJECTION_REASON.to_owned(),
+            }
+            .transitions(operation_id, context, global_context),
+            NewOrderState::Rejected2 { order_id } => Self::Rejected4 {
+                order_id,
+                reason: UNKNOWN_REJECTION_REASON.to_owned(),
+            }
+            .transitions(operation_id, context, global_context),
+            NewOrderState::Rejected3 {
+                order_id,
+                orders_to_sync_on_rejected,
+                reason,
             } => {
                 vec![sync_orders(
                     operation_id,
                     context,
                     global_context,
                     orders_to_sync_on_rejected,
-                    Self::Rejected2 { order_id },
+                    Self::Rejected4 { order_id, reason },
                 )]
             }
-            NewOrderState::Rejected2 { order_id } => {
-                vec![StateTransition::new(async {}, move |dbtx, _, _| {
-                    Box::pin(async move {
-                        dbtx.module_tx().remove_entry(&db::OrderKey(order_id)).await;
-                        PredictionMarketsStateMachine {
-                            operation_id,
-                            state: Self::Complete.into(),
-                        }
-                    })
-                })]
+            NewOrderState::Rejected4 { order_id, reason } => {
+                let order_owner = order_id
+                    .into_key_pair(context.root_secret.clone())
+                    .public_key();
+                vec![StateTransition::new(
+                    await_order_lookup_from_federation(global_context.clone(), order_owner),
+                    move |dbtx, order, previous| {
+                        let reason = reason.clone();
+                        Box::pin(async move {
+                            match order {
+                                // the federation only has an order for a rejected
+                                // transaction if the order id was used before, such
+                                // as by a client restored without its database
+                                Some(order) => {
+                                    warn!(
+                                        ?operation_id,
+                                        ?order_id,
+                                        %reason,
+                                        "federation has an order for a rejected new order"
+                                    );
+                                    PredictionMarketsClientModule::save_order_to_db(
+                                        &mut dbtx.module_tx(),
+                                        order_id,
+                                        &order,
+                                    )
+                                    .await;
+                                    dbtx.module_tx()
+                                        .insert_entry(
+                                            &db::RejectedOrderDiscrepancyKey(order_id),
+                                            &reason,
+                                        )
+                                        .await;
+                                }
+                                None => {
+                                    dbtx.module_tx().remove_entry(&db::OrderKey(order_id)).await;
+                                }
+                            }
+                            transition_to(operation_id, &previous.state, Self::Complete.into())
+                        })
+                    },
+                )]
             }
             NewOrderState::Accepted {
                 order_id,
@@ -236,15 +362,12 @@ impl StateCategoryTrait for NewOrderState {
             }
             NewOrderState::Accepted2 { order_id } => {
                 let new_order_broadcast_sender = context.new_order_broadcast_sender.clone();
-                vec![StateTransition::new(async {}, move |_, _, _| {
+                vec![StateTransition::new(async {}, move |_, _, previous| {
                     let new_order_broadcast_sender = new_order_broadcast_sender.clone();
                     Box::pin(async move {
                         _ = new_order_broadcast_sender.send(order_id);
 
-                        PredictionMarketsStateMachine {
-                            operation_id,
-                            state: Self::Complete.into(),
-                        }
+                        transition_to(operation_id, &previous.state, Self::Complete.into())
                     })
                 })]
             }
@@ -259,11 +382,15 @@ pub enum CancelOrderState {
         tx_id: TransactionId,
         order_to_sync_on_accepted: OrderId,
     },
+    // replaced by Rejected2, which keeps the reason
     Rejected,
     Accepted {
         order_to_sync_on_accepted: OrderId,
     },
     Complete,
+    Rejected2 {
+        reason: String,
+    },
 }
 
 impl Into<PredictionMarketState> for CancelOrderState {
@@ -289,9 +416,11 @@ impl StateCategoryTrait for CancelOrderState {
                 Self::Accepted {
                     order_to_sync_on_accepted,
                 },
-                Self::Rejected,
+                |reason| Self::Rejected2 { reason },
             )],
-            CancelOrderState::Rejected => vec![do_nothing(operation_id, Self::Complete)],
+            CancelOrderState::Rejected | CancelOrderState::Rejected2 { .. } => {
+                vec![do_nothing(operation_id, Self::Complete)]
+            }
             CancelOrderState::Accepted {
                 order_to_sync_on_accepted,
             } => vec![sync_orders(
@@ -312,11 +441,15 @@ pub enum ConsumeOrderBitcoinBalanceState {
         tx_id: TransactionId,
         order_to_sync_on_accepted: OrderId,
     },
+    // replaced by Rejected2, which keeps the reason
     Rejected,
     Accepted {
         order_to_sync_on_accepted: OrderId,
     },
     Complete,
+    Rejected2 {
+        reason: String,
+    },
 }
 
 impl Into<PredictionMarketState> for ConsumeOrderBitcoinBalanceState {
@@ -342,9 +475,10 @@ impl StateCategoryTrait for ConsumeOrderBitcoinBalanceState {
                 Self::Accepted {
                     order_to_sync_on_accepted,
                 },
-                Self::Rejected,
+                |reason| Self::Rejected2 { reason },
             )],
-            ConsumeOrderBitcoinBalanceState::Rejected => {
+            ConsumeOrderBitcoinBalanceState::Rejected
+            | ConsumeOrderBitcoinBalanceState::Rejected2 { .. } => {
                 vec![do_nothing(operation_id, Self::Complete)]
             }
             ConsumeOrderBitcoinBalanceState::Accepted {
@@ -364,9 +498,11 @@ impl StateCategoryTrait for ConsumeOrderBitcoinBalanceState {
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
 pub enum PayoutMarketState {
     Pending { tx_id: TransactionId },
+    // replaced by Rejected2, which keeps the reason
     Rejected,
     Accepted { tx_id: TransactionId },
     Complete,
+    Rejected2 { reason: String },
 }
 
 impl Into<PredictionMarketState> for PayoutMarketState {
@@ -387,9 +523,11 @@ impl StateCategoryTrait for PayoutMarketState {
                 global_context,
                 tx_id,
                 Self::Accepted { tx_id },
-                Self::Rejected,
+                |reason| Self::Rejected2 { reason },
             )],
-            PayoutMarketState::Rejected => vec![do_nothing(operation_id, Self::Complete)],
+            PayoutMarketState::Rejected | PayoutMarketState::Rejected2 { .. } => {
+                vec![do_nothing(operation_id, Self::Complete)]
+            }
             PayoutMarketState::Accepted { tx_id } => {
                 vec![sync_market(
                     operation_id,
@@ -5,39 +5,66 @@ use fedimint_client::DynGlobalClientContext;
 use fedimint_core::core::OperationId;
 use fedimint_core::db::IDatabaseTransactionOpsCoreTyped;
 use fedimint_core::{OutPoint, TransactionId};
+use tracing::debug;
 
 use super::triggers::{await_market_from_federation, await_orders_from_federation};
 use super::{PredictionMarketState, PredictionMarketsStateMachine};
-use crate::{db, OrderId, PredictionMarketsClientContext};
+use crate::{db, OperationOutcome, OrderId, PredictionMarketsClientContext};
 
-pub fn await_tx_accepted(
+/// Builds the state machine moving to `next` from `previous`, emitting a
+/// debug event for the transition.
+pub fn transition_to(
+    operation_id: OperationId,
+    previous: &PredictionMarketState,
+    next: PredictionMarketState,
+) -> PredictionMarketsStateMachine {
+    debug!(
+        ?operation_id,
+        order_id = ?previous.order_id().or(next.order_id()),
+        tx_id = ?previous.tx_id().or(next.tx_id()),
+        from = ?previous,
+        to = ?next,
+        "prediction markets state transition"
+    );
+
+    PredictionMarketsStateMachine {
+        operation_id,
+        state: next,
+    }
+}
+
+/// Records the [OperationOutcome] of the transaction and moves to `accepted`,
+/// or to the state built by `rejected` from the federation's rejection
+/// reason.
+pub fn await_tx_accepted<R: Into<PredictionMarketState>>(
     operation_id: OperationId,
     global_context: &DynGlobalClientContext,
     tx_id: TransactionId,
     accepted: impl Into<PredictionMarketState>,
-    rejected: impl Into<PredictionMarketState>,
+    rejected: impl Fn(String) -> R + Send + Sync + 'static,
 ) -> StateTransition<PredictionMarketsStateMachine> {
     let accepted_next_state = accepted.into();
-    let rejected_next_state = rejected.into();
     let global_context = global_context.clone();
 
     StateTransition::new(
         async move { global_context.await_tx_accepted(tx_id).await },
-        move |_dbtx, res, _state| {
-            let accepted_next_state = accepted_next_state.clone();
-            let rejected_next_state = rejected_next_state.clone();
+        move |dbtx, res, previous| {
+            let (outcome, next_state) = match res {
+                Ok(_) => (OperationOutcome::Accepted, accepted_next_state.clone()),
+                Err(reason) => (
+                    OperationOutcome::Rejected {
+                        reason: reason.clone(),
+                    },
+                    rejected(reason).into(),
+                ),
+            };
 
             Box::pin(async move {
-                match res {
-                    Ok(_) => PredictionMarketsStateMachine {
-                        operation_id,
-                        state: accepted_next_state,
-                    },
-                    Err(_) => PredictionMarketsStateMachine {
-                        operation_id,
-                        state: rejected_next_state,
-                    },
-                }
+                dbtx.module_tx()
+                    .insert_entry(&db::OperationOutcomeKey(operation_id), &outcome)
+                    .await;
+
+                transition_to(operation_id, &previous.state, next_state)
             })
         },
     )
@@ -54,7 +81,7 @@ pub fn sync_orders(
 
     StateTransition::new(
         await_orders_from_federation(context.clone(), global_context.clone(), orders),
-        move |dbtx, orders, _state| {
+        move |dbtx, orders, previous| {
             let next = next.clone();
 
             Box::pin(async move {
@@ -67,10 +94,7 @@ pub fn sync_orders(
                     .await;
                 }
 
-                PredictionMarketsStateMachine {
-                    operation_id,
-                    state: next,
-                }
+                transition_to(operation_id, &previous.state, next)
             })
         },
     )
@@ -87,7 +111,7 @@ pub fn sync_market(
 
     StateTransition::new(
         await_market_from_federation(global_context.clone(), market_outpoint),
-        move |dbtx, market, _| {
+        move |dbtx, market, previous| {
             let next = next.clone();
 
             Box::pin(async move {
@@ -95,10 +119,7 @@ pub fn sync_market(
                     .insert_entry(&db::MarketKey(market_outpoint), &market)
                     .await;
 
-                PredictionMarketsStateMachine {
-                    operation_id,
-                    state: next,
-                }
+                transition_to(operation_id, &previous.state, next)
             })
         },
     )
@@ -110,14 +131,91 @@ pub fn do_nothing(
 ) -> StateTransition<PredictionMarketsStateMachine> {
     let next = next.into();
 
-    StateTransition::new(async {}, move |_, _, _| {
+    StateTransition::new(async {}, move |_, _, previous| {
         let next = next.clone();
 
-        Box::pin(async move {
-            PredictionMarketsStateMachine {
-                operation_id,
-                state: next,
-            }
-        })
+        Box::pin(async move { transition_to(operation_id, &previous.state, next) })
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::Registry;
+
+    use super::*;
+    use crate::states::NewOrderState;
+
+    /// Collects the fields of every event.
+    #[derive(Clone, Default)]
+    struct CaptureLayer(Arc<Mutex<Vec<BTreeMap<String, String>>>>);
+
+    struct FieldVisitor<'a>(&'a mut BTreeMap<String, String>);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.insert(field.name().to_owned(), format!("{value:?}"));
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for CaptureLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let mut fields = BTreeMap::new();
+            event.record(&mut FieldVisitor(&mut fields));
+            self.0.lock().unwrap().push(fields);
+        }
+    }
+
+    #[test]
+    fn accepted_order_transitions_are_traced() {
+        let operation_id = OperationId::new_random();
+        let order_id = OrderId(7);
+        let tx_id = TransactionId::from_str(&"1".repeat(64)).unwrap();
+
+        let states: Vec<PredictionMarketState> = vec![
+            NewOrderState::Pending {
+                tx_id,
+                order_id,
+                orders_to_sync_on_accepted: [order_id].into(),
+                orders_to_sync_on_rejected: BTreeSet::new(),
+            }
+            .into(),
+            NewOrderState::Accepted {
+                order_id,
+                orders_to_sync_on_accepted: [order_id].into(),
+            }
+            .into(),
+            NewOrderState::Accepted2 { order_id }.into(),
+            NewOrderState::Complete.into(),
+        ];
+
+        let capture = CaptureLayer::default();
+        tracing::subscriber::with_default(Registry::default().with(capture.clone()), || {
+            for transition in states.windows(2) {
+                let next = transition_to(operation_id, &transition[0], transition[1].clone());
+                assert_eq!(next.operation_id, operation_id);
+                assert_eq!(next.state, transition[1]);
+            }
+        });
+
+        let events = capture.0.lock().unwrap();
+        assert_eq!(events.len(), 3);
+        for (event, transition) in events.iter().zip(states.windows(2)) {
+            assert_eq!(event["message"], "prediction markets state transition");
+            assert_eq!(event["operation_id"], format!("{operation_id:?}"));
+            assert_eq!(event["order_id"], format!("{:?}", Some(order_id)));
+            assert_eq!(event["from"], format!("{:?}", transition[0]));
+            assert_eq!(event["to"], format!("{:?}", transition[1]));
+        }
+        // the transaction id is only known until the transaction is accepted
+        assert_eq!(events[0]["tx_id"], format!("{:?}", Some(tx_id)));
+        assert_eq!(events[1]["tx_id"], format!("{:?}", None::<TransactionId>));
+    }
+}
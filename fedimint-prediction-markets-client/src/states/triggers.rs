@@ -35,6 +35,26 @@ pub async fn await_order_from_federation(
     }
 }
 
+/// Like [await_order_from_federation], but also returns once the federation
+/// answers that the order does not exist.
+pub async fn await_order_lookup_from_federation(
+    global_context: DynGlobalClientContext,
+    order: PublicKey,
+) -> Option<Order> {
+    loop {
+        let res = global_context
+            .module_api()
+            .get_order(GetOrderParams { order })
+            .await;
+
+        if let Ok(GetOrderResult { order }) = res {
+            return order;
+        }
+
+        sleep(RETRY_DELAY).await;
+    }
+}
+
 pub async fn await_orders_from_federation(
     context: PredictionMarketsClientContext,
     global_context: DynGlobalClientContext,
@@ -1,10 +1,13 @@
+use std::collections::BTreeMap;
+
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::{Amount, OutPoint};
 use secp256k1::PublicKey;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    Candlestick, ContractOfOutcomeAmount, Market, MarketDynamic, NostrEventJson, Order, Outcome, Seconds, UnixTimestamp
+    Candlestick, ContractOfOutcomeAmount, Market, MarketDynamic, NostrEventJson, NostrPublicKeyHex,
+    Order, Outcome, PredictionMarketEventHashHex, Seconds, UnixTimestamp,
 };
 
 //
@@ -21,6 +24,63 @@ pub struct GetMarketResult {
     pub market: Option<Market>,
 }
 
+//
+// Get Markets
+//
+
+pub const GET_MARKETS_ENDPOINT: &str = "get_markets";
+/// Maximum number of markets that can be requested in one [GetMarketsParams]
+pub const GET_MARKETS_MAX_MARKETS: usize = 100;
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct GetMarketsParams {
+    pub markets: Vec<OutPoint>,
+}
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct GetMarketsResult {
+    /// Markets that do not exist are left out
+    pub markets: BTreeMap<OutPoint, Market>,
+}
+
+//
+// Get Markets By Payout Control
+//
+
+pub const GET_MARKETS_BY_PAYOUT_CONTROL_ENDPOINT: &str = "get_markets_by_payout_control";
+/// Most markets returned by a single [GET_MARKETS_BY_PAYOUT_CONTROL_ENDPOINT]
+/// request, unless more were created at the last returned timestamp
+pub const GET_MARKETS_BY_PAYOUT_CONTROL_MAX_MARKETS: usize = 1000;
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct GetMarketsByPayoutControlParams {
+    pub payout_control: NostrPublicKeyHex,
+    pub created_after: UnixTimestamp,
+}
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct GetMarketsByPayoutControlResult {
+    /// Markets where the payout control has nonzero weight, with their created
+    /// consensus timestamp, oldest first. A page never ends between markets
+    /// created at the same timestamp, so the next page is requested with
+    /// created_after set to the last timestamp.
+    pub markets: Vec<(OutPoint, UnixTimestamp)>,
+}
+
+//
+// Get Markets By Event Hash
+//
+
+pub const GET_MARKETS_BY_EVENT_HASH_ENDPOINT: &str = "get_markets_by_event_hash";
+/// Most markets returned by a [GET_MARKETS_BY_EVENT_HASH_ENDPOINT] request
+pub const GET_MARKETS_BY_EVENT_HASH_MAX_MARKETS: usize = 100;
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct GetMarketsByEventHashParams {
+    pub event_hash_hex: PredictionMarketEventHashHex,
+}
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct GetMarketsByEventHashResult {
+    /// The oldest [GET_MARKETS_BY_EVENT_HASH_MAX_MARKETS] markets created for
+    /// the event, with their created consensus timestamp, oldest first
+    pub markets: Vec<(OutPoint, UnixTimestamp)>,
+}
+
 //
 // Get Market Dynamic
 //
@@ -64,6 +124,30 @@ pub struct GetOrderResult {
     pub order: Option<Order>,
 }
 
+//
+// Get Market Orders
+//
+
+pub const GET_MARKET_ORDERS_ENDPOINT: &str = "get_market_orders";
+/// Most orders returned by a single [GET_MARKET_ORDERS_ENDPOINT] request
+pub const GET_MARKET_ORDERS_MAX_LIMIT: u32 = 1000;
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct GetMarketOrdersParams {
+    pub market: OutPoint,
+    /// Only orders after this one are returned. None starts from the first
+    /// order of the market.
+    pub after: Option<PublicKey>,
+    /// Between 1 and [GET_MARKET_ORDERS_MAX_LIMIT]
+    pub limit: u32,
+}
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct GetMarketOrdersResult {
+    /// Ordered by the order's [PublicKey]
+    pub orders: Vec<(PublicKey, Order)>,
+    /// Pass as `after` to get the next page. None if there are no more orders.
+    pub next: Option<PublicKey>,
+}
+
 //
 // Wait Order Match
 //
@@ -127,4 +211,15 @@ pub struct GetMarketOutcomeOrderBookParams {
 pub struct GetMarketOutcomeOrderBookResult {
     pub buys: Vec<(Amount, ContractOfOutcomeAmount)>,
     pub sells: Vec<(Amount, ContractOfOutcomeAmount)>,
+}
+
+//
+// Get Consensus Timestamp
+//
+
+pub const GET_CONSENSUS_TIMESTAMP_ENDPOINT: &str = "get_consensus_timestamp";
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct GetConsensusTimestampResult {
+    /// [UnixTimestamp::ZERO] until enough guardians have proposed a timestamp
+    pub consensus_timestamp: UnixTimestamp,
 }
\ No newline at end of file
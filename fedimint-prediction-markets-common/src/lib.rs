@@ -30,8 +30,10 @@ pub mod api;
 pub const KIND: ModuleKind = ModuleKind::from_static_str("prediction-markets");
 
 /// Modules are non-compatible with older versions
+///
+/// Minor version 1 added [PredictionMarketsOutput::NewMarketWithDisplayHints].
 pub const MODULE_CONSENSUS_VERSION: ModuleConsensusVersion =
-    ModuleConsensusVersion { major: 0, minor: 0 };
+    ModuleConsensusVersion { major: 0, minor: 1 };
 
 /// Non-transaction items that will be submitted to consensus
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
@@ -78,6 +80,15 @@ pub enum PredictionMarketsOutput {
         market: OutPoint,
         event_payout_attestations_json: Vec<NostrEventJson>,
     },
+    /// [PredictionMarketsOutput::NewMarket] with outcome display hints. A
+    /// separate variant so that markets without hints are encoded as before.
+    NewMarketWithDisplayHints {
+        event_json: PredictionMarketEventJson,
+        contract_price: Amount,
+        payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
+        weight_required_for_payout: WeightRequiredForPayout,
+        outcome_display_hints: Vec<OutcomeDisplayHint>,
+    },
 }
 
 /// Information needed by a client to update output funds
@@ -217,8 +228,9 @@ impl fmt::Display for PredictionMarketsConsensusItem {
     }
 }
 
-/// Markets are identified by the [PredictionMarketsOutput::NewMarket]
-/// [OutPoint] they were created in.
+/// Markets are identified by the [PredictionMarketsOutput::NewMarket] or
+/// [PredictionMarketsOutput::NewMarketWithDisplayHints] [OutPoint] they were
+/// created in.
 #[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
 pub struct Market(pub MarketStatic, pub MarketDynamic);
 
@@ -229,6 +241,7 @@ impl Market {
         contract_price: &Amount,
         payout_control_weight_map: &BTreeMap<NostrPublicKeyHex, Weight>,
         weight_required_for_payout: &WeightRequiredForPayout,
+        outcome_display_hints: &[OutcomeDisplayHint],
     ) -> Result<(), ()> {
         // validate event
         let accepted_information_variant_ids = gc
@@ -275,6 +288,18 @@ impl Market {
             return Err(());
         }
 
+        // validate outcome display hints
+        if !outcome_display_hints.is_empty()
+            && outcome_display_hints.len() != usize::from(event.outcome_count)
+        {
+            return Err(());
+        }
+        for hint in outcome_display_hints.iter() {
+            if hint.color.len() > MAX_OUTCOME_DISPLAY_HINT_COLOR_LEN {
+                return Err(());
+            }
+        }
+
         Ok(())
     }
 }
@@ -286,6 +311,8 @@ pub struct MarketStatic {
     pub contract_price: Amount,
     pub payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
     pub weight_required_for_payout: WeightRequiredForPayout,
+    #[serde(default)]
+    pub outcome_display_hints: Vec<OutcomeDisplayHint>,
 
     // set by guardians
     pub created_consensus_timestamp: UnixTimestamp,
@@ -295,6 +322,54 @@ impl MarketStatic {
     pub fn event(&self) -> Result<Event, prediction_market_event::Error> {
         Event::try_from_json_str(&self.event_json)
     }
+
+    /// Display hint of each outcome, empty if the market creator did not set
+    /// any. Hints beyond `outcome_count` are ignored.
+    pub fn outcome_display_hints(&self, outcome_count: Outcome) -> &[OutcomeDisplayHint] {
+        let len = self
+            .outcome_display_hints
+            .len()
+            .min(usize::from(outcome_count));
+
+        &self.outcome_display_hints[..len]
+    }
+}
+
+/// [MarketStatic] as encoded before outcome display hints were added. Only
+/// used to migrate databases.
+#[derive(Debug, Clone, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct MarketStaticV0 {
+    pub event_json: PredictionMarketEventJson,
+    pub contract_price: Amount,
+    pub payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight>,
+    pub weight_required_for_payout: WeightRequiredForPayout,
+    pub created_consensus_timestamp: UnixTimestamp,
+}
+
+impl From<MarketStaticV0> for MarketStatic {
+    fn from(v0: MarketStaticV0) -> Self {
+        MarketStatic {
+            event_json: v0.event_json,
+            contract_price: v0.contract_price,
+            payout_control_weight_map: v0.payout_control_weight_map,
+            weight_required_for_payout: v0.weight_required_for_payout,
+            outcome_display_hints: Vec::new(),
+            created_consensus_timestamp: v0.created_consensus_timestamp,
+        }
+    }
+}
+
+/// Longest [OutcomeDisplayHint::color] a market can be created with
+pub const MAX_OUTCOME_DISPLAY_HINT_COLOR_LEN: usize = 32;
+
+/// How the market creator suggests an outcome is displayed, so UIs render
+/// the market the same way. Not interpreted by the federation.
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
+pub struct OutcomeDisplayHint {
+    /// Such as `#1f77b4`
+    pub color: String,
+    /// Outcomes with a lower rank are listed first
+    pub sort_rank: u16,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable, PartialEq, Eq, Hash)]
@@ -367,12 +442,19 @@ impl Order {
             || price >= &market_contract_price
             || quantity == &ContractOfOutcomeAmount::ZERO
             || quantity > &gc.max_order_quantity
+            || Self::checked_cost(*price, *quantity).is_none()
         {
             Err(())
         } else {
             Ok(())
         }
     }
+
+    /// Bitcoin locked by a buy order of `quantity` at `price`, `None` if it
+    /// does not fit in an [Amount].
+    pub fn checked_cost(price: Amount, quantity: ContractOfOutcomeAmount) -> Option<Amount> {
+        price.msats.checked_mul(quantity.0).map(Amount::from_msats)
+    }
 }
 
 /// Side of order
@@ -1,9 +1,12 @@
+use fedimint_core::db::{DatabaseTransaction, IDatabaseTransactionOpsCoreTyped, MigrationContext};
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::{impl_db_lookup, impl_db_record, Amount, OutPoint, PeerId};
 use fedimint_prediction_markets_common::{
-    Candlestick, ContractOfOutcomeAmount, MarketDynamic, MarketStatic, NostrEventJson, Order,
+    Candlestick, ContractOfOutcomeAmount, MarketDynamic, MarketStatic, MarketStaticV0,
+    NostrEventJson, NostrPublicKeyHex, Order, PredictionMarketEventHashHex,
     PredictionMarketsOutputOutcome, Seconds, Side, TimeOrdering, UnixTimestamp,
 };
+use futures::StreamExt;
 use prediction_market_event::Outcome;
 use secp256k1::PublicKey;
 use serde::Serialize;
@@ -65,6 +68,19 @@ pub enum DbKeyPrefix {
     /// [ContractOfOutcomeAmount]
     MarketOutcomeOrderBook = 0x26,
 
+    /// Used to find markets by payout control. Only holds payout controls with
+    /// nonzero weight.
+    ///
+    /// (Payout control [NostrPublicKeyHex], Market's created
+    /// [UnixTimestamp], Market's [OutPoint]) to ()
+    MarketsByPayoutControl = 0x27,
+
+    /// Used to find markets by event hash.
+    ///
+    /// (Event's [PredictionMarketEventHashHex], Market's created
+    /// [UnixTimestamp], Market's [OutPoint]) to ()
+    MarketsByEventHash = 0x28,
+
     /// Stores timestamps proposed by peers.
     /// Used to create consensus timestamps.
     ///
@@ -108,6 +124,24 @@ impl_db_record!(
 
 impl_db_lookup!(key = MarketStaticKey, query_prefix = MarketStaticPrefixAll);
 
+/// MarketStatic before outcome display hints, see [migrate_to_v3]
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct MarketStaticKeyV0(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MarketStaticPrefixAllV0;
+
+impl_db_record!(
+    key = MarketStaticKeyV0,
+    value = MarketStaticV0,
+    db_prefix = DbKeyPrefix::MarketStatic,
+);
+
+impl_db_lookup!(
+    key = MarketStaticKeyV0,
+    query_prefix = MarketStaticPrefixAllV0
+);
+
 /// MarketDynamic
 #[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
 pub struct MarketDynamicKey(pub OutPoint);
@@ -338,6 +372,158 @@ impl_db_lookup!(
     query_prefix = MarketOutcomeOrderBookPrefix2
 );
 
+// MarketsByPayoutControl
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct MarketsByPayoutControlKey {
+    pub payout_control: NostrPublicKeyHex,
+    pub created_consensus_timestamp: UnixTimestamp,
+    pub market: OutPoint,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MarketsByPayoutControlPrefixAll;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MarketsByPayoutControlPrefix1 {
+    pub payout_control: NostrPublicKeyHex,
+}
+
+impl_db_record!(
+    key = MarketsByPayoutControlKey,
+    value = (),
+    db_prefix = DbKeyPrefix::MarketsByPayoutControl,
+);
+
+impl_db_lookup!(
+    key = MarketsByPayoutControlKey,
+    query_prefix = MarketsByPayoutControlPrefixAll,
+    query_prefix = MarketsByPayoutControlPrefix1
+);
+
+/// Indexes markets created before database version 1 by payout control.
+pub async fn migrate_to_v1(mut ctx: MigrationContext<'_>) -> anyhow::Result<()> {
+    let mut dbtx = ctx.dbtx();
+
+    // markets are still encoded as before v3
+    let markets: Vec<_> = dbtx
+        .find_by_prefix(&MarketStaticPrefixAllV0)
+        .await
+        .collect()
+        .await;
+
+    for (MarketStaticKeyV0(market), market_static) in markets {
+        let market_static = MarketStatic::from(market_static);
+        insert_markets_by_payout_control_entries(&mut dbtx, market, &market_static).await;
+    }
+
+    Ok(())
+}
+
+pub async fn insert_markets_by_payout_control_entries(
+    dbtx: &mut DatabaseTransaction<'_>,
+    market: OutPoint,
+    market_static: &MarketStatic,
+) {
+    for (payout_control, weight) in market_static.payout_control_weight_map.iter() {
+        if *weight == 0 {
+            continue;
+        }
+
+        dbtx.insert_entry(
+            &MarketsByPayoutControlKey {
+                payout_control: payout_control.to_owned(),
+                created_consensus_timestamp: market_static.created_consensus_timestamp,
+                market,
+            },
+            &(),
+        )
+        .await;
+    }
+}
+
+// MarketsByEventHash
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct MarketsByEventHashKey {
+    pub event_hash_hex: PredictionMarketEventHashHex,
+    pub created_consensus_timestamp: UnixTimestamp,
+    pub market: OutPoint,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MarketsByEventHashPrefixAll;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MarketsByEventHashPrefix1 {
+    pub event_hash_hex: PredictionMarketEventHashHex,
+}
+
+impl_db_record!(
+    key = MarketsByEventHashKey,
+    value = (),
+    db_prefix = DbKeyPrefix::MarketsByEventHash,
+);
+
+impl_db_lookup!(
+    key = MarketsByEventHashKey,
+    query_prefix = MarketsByEventHashPrefixAll,
+    query_prefix = MarketsByEventHashPrefix1
+);
+
+/// Indexes markets created before database version 2 by event hash.
+pub async fn migrate_to_v2(mut ctx: MigrationContext<'_>) -> anyhow::Result<()> {
+    let mut dbtx = ctx.dbtx();
+
+    // markets are still encoded as before v3
+    let markets: Vec<_> = dbtx
+        .find_by_prefix(&MarketStaticPrefixAllV0)
+        .await
+        .collect()
+        .await;
+
+    for (MarketStaticKeyV0(market), market_static) in markets {
+        let market_static = MarketStatic::from(market_static);
+        let event_hash_hex = market_static.event()?.hash_hex()?.0;
+        insert_markets_by_event_hash_entry(&mut dbtx, event_hash_hex, market, &market_static).await;
+    }
+
+    Ok(())
+}
+
+/// Re-encodes markets with an empty [MarketStatic::outcome_display_hints].
+pub async fn migrate_to_v3(mut ctx: MigrationContext<'_>) -> anyhow::Result<()> {
+    let mut dbtx = ctx.dbtx();
+
+    let markets: Vec<_> = dbtx
+        .find_by_prefix(&MarketStaticPrefixAllV0)
+        .await
+        .collect()
+        .await;
+
+    for (MarketStaticKeyV0(market), market_static) in markets {
+        dbtx.insert_entry(&MarketStaticKey(market), &MarketStatic::from(market_static))
+            .await;
+    }
+
+    Ok(())
+}
+
+pub async fn insert_markets_by_event_hash_entry(
+    dbtx: &mut DatabaseTransaction<'_>,
+    event_hash_hex: PredictionMarketEventHashHex,
+    market: OutPoint,
+    market_static: &MarketStatic,
+) {
+    dbtx.insert_entry(
+        &MarketsByEventHashKey {
+            event_hash_hex,
+            created_consensus_timestamp: market_static.created_consensus_timestamp,
+            market,
+        },
+        &(),
+    )
+    .await;
+}
+
 /// PeersProposedTimestamp
 #[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
 pub struct PeersProposedTimestampKey {
@@ -27,11 +27,12 @@ use fedimint_core::{push_db_pair_items, Amount, OutPoint, PeerId, ServerModule};
 use fedimint_prediction_markets_common::config::GeneralConsensus;
 use fedimint_prediction_markets_common::{
     api, config, Candlestick, ContractAmount, ContractOfOutcomeAmount, Market, MarketDynamic,
-    MarketStatic, Order, Outcome, Payout, PredictionMarketsCommonInit,
-    PredictionMarketsConsensusItem, PredictionMarketsInput, PredictionMarketsInputError,
-    PredictionMarketsModuleTypes, PredictionMarketsOutput, PredictionMarketsOutputError,
-    PredictionMarketsOutputOutcome, Side, SignedAmount, TimeOrdering, UnixTimestamp,
-    WeightRequiredForPayout, MODULE_CONSENSUS_VERSION,
+    MarketStatic, NostrPublicKeyHex, Order, Outcome, OutcomeDisplayHint, Payout,
+    PredictionMarketEventJson, PredictionMarketsCommonInit, PredictionMarketsConsensusItem,
+    PredictionMarketsInput, PredictionMarketsInputError, PredictionMarketsModuleTypes,
+    PredictionMarketsOutput, PredictionMarketsOutputError, PredictionMarketsOutputOutcome, Side,
+    SignedAmount, TimeOrdering, UnixTimestamp, Weight, WeightRequiredForPayout,
+    MODULE_CONSENSUS_VERSION,
 };
 use futures::{future, StreamExt};
 use highest_priority_order_cache::HighestPriorityOrderCache;
@@ -55,7 +56,7 @@ pub struct PredictionMarketsInit;
 
 impl ModuleInit for PredictionMarketsInit {
     type Common = PredictionMarketsCommonInit;
-    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(3);
 
     /// Dumps all database items for debugging
     async fn dump_database(
@@ -180,6 +181,26 @@ impl ModuleInit for PredictionMarketsInit {
                         "MarketOutcomeOrderBook"
                     );
                 }
+                DbKeyPrefix::MarketsByPayoutControl => {
+                    push_db_pair_items!(
+                        dbtx,
+                        db::MarketsByPayoutControlPrefixAll,
+                        db::MarketsByPayoutControlKey,
+                        (),
+                        items,
+                        "MarketsByPayoutControl"
+                    );
+                }
+                DbKeyPrefix::MarketsByEventHash => {
+                    push_db_pair_items!(
+                        dbtx,
+                        db::MarketsByEventHashPrefixAll,
+                        db::MarketsByEventHashKey,
+                        (),
+                        items,
+                        "MarketsByEventHash"
+                    );
+                }
                 DbKeyPrefix::PeersProposedTimestamp => {
                     push_db_pair_items!(
                         dbtx,
@@ -223,7 +244,10 @@ impl ServerModuleInit for PredictionMarketsInit {
 
     /// DB migrations to move from old to newer versions
     fn get_database_migrations(&self) -> BTreeMap<DatabaseVersion, CoreMigrationFn> {
-        let migrations = BTreeMap::new();
+        let mut migrations: BTreeMap<DatabaseVersion, CoreMigrationFn> = BTreeMap::new();
+        migrations.insert(DatabaseVersion(0), |ctx| Box::pin(db::migrate_to_v1(ctx)));
+        migrations.insert(DatabaseVersion(1), |ctx| Box::pin(db::migrate_to_v2(ctx)));
+        migrations.insert(DatabaseVersion(2), |ctx| Box::pin(db::migrate_to_v3(ctx)));
         migrations
     }
 
@@ -513,68 +537,42 @@ impl ServerModule for PredictionMarkets {
                 payout_control_weight_map,
                 weight_required_for_payout,
             } => {
-                let event = Event::try_from_json_str(event_json)
-                    .map_err(|_| PredictionMarketsOutputError::MarketValidationFailed)?;
+                // set output meta
+                amount = Amount::ZERO;
+                fee = self.cfg.consensus.gc.new_market_fee;
 
-                // verify market params
-                if let Err(()) = Market::validate_market_params(
-                    &self.cfg.consensus.gc,
-                    &event,
+                self.process_new_market(
+                    dbtx,
+                    out_point,
+                    event_json,
                     contract_price,
                     payout_control_weight_map,
                     weight_required_for_payout,
-                ) {
-                    return Err(PredictionMarketsOutputError::MarketValidationFailed);
-                }
-
+                    &[],
+                )
+                .await?;
+            }
+            PredictionMarketsOutput::NewMarketWithDisplayHints {
+                event_json,
+                contract_price,
+                payout_control_weight_map,
+                weight_required_for_payout,
+                outcome_display_hints,
+            } => {
                 // set output meta
                 amount = Amount::ZERO;
                 fee = self.cfg.consensus.gc.new_market_fee;
 
-                // save outcome
-                dbtx.insert_new_entry(
-                    &db::OutcomeKey(out_point),
-                    &PredictionMarketsOutputOutcome::NewMarket,
-                )
-                .await;
-
-                // save market static
-                let event_json = ensure_compact_json(event_json)
-                    .map_err(|e| PredictionMarketsOutputError::Other(e.to_string()))?;
-                let created_consensus_timestamp = self.get_consensus_timestamp(dbtx).await;
-
-                dbtx.insert_new_entry(
-                    &db::MarketStaticKey(out_point),
-                    &MarketStatic {
-                        event_json,
-                        contract_price: *contract_price,
-                        payout_control_weight_map: payout_control_weight_map.to_owned(),
-                        weight_required_for_payout: *weight_required_for_payout,
-                        created_consensus_timestamp,
-                    },
-                )
-                .await;
-
-                // save market dynamic
-                dbtx.insert_new_entry(
-                    &db::MarketDynamicKey(out_point),
-                    &MarketDynamic {
-                        open_contracts: ContractAmount::ZERO,
-                        payout: None,
-                    },
-                )
-                .await;
-
-                // save MarketSpecificationsNeededForNewOrders
-                dbtx.insert_new_entry(
-                    &db::MarketSpecificationsNeededForNewOrdersKey(out_point),
-                    &MarketSpecificationsNeededForNewOrders {
-                        outcome_count: event.outcome_count,
-                        contract_price: *contract_price,
-                        next_time_ordering: 0,
-                    },
+                self.process_new_market(
+                    dbtx,
+                    out_point,
+                    event_json,
+                    contract_price,
+                    payout_control_weight_map,
+                    weight_required_for_payout,
+                    outcome_display_hints,
                 )
-                .await;
+                .await?;
             }
             PredictionMarketsOutput::NewBuyOrder {
                 owner,
@@ -618,7 +616,8 @@ impl ServerModule for PredictionMarkets {
                 }
 
                 // set output meta
-                amount = *price * quantity.0;
+                amount = Order::checked_cost(*price, *quantity)
+                    .ok_or(PredictionMarketsOutputError::OrderValidationFailed)?;
                 fee = self.cfg.consensus.gc.new_order_fee;
 
                 // save outcome
@@ -850,6 +849,27 @@ impl ServerModule for PredictionMarkets {
                     module.api_get_market(context, params).await
                 }
             },
+            api_endpoint! {
+                api::GET_MARKETS_ENDPOINT,
+                ApiVersion::new(0, 0),
+                async |module: &PredictionMarkets, context, params: api::GetMarketsParams| -> api::GetMarketsResult {
+                    module.api_get_markets(context, params).await
+                }
+            },
+            api_endpoint! {
+                api::GET_MARKETS_BY_PAYOUT_CONTROL_ENDPOINT,
+                ApiVersion::new(0, 0),
+                async |module: &PredictionMarkets, context, params: api::GetMarketsByPayoutControlParams| -> api::GetMarketsByPayoutControlResult {
+                    module.api_get_markets_by_payout_control(context, params).await
+                }
+            },
+            api_endpoint! {
+                api::GET_MARKETS_BY_EVENT_HASH_ENDPOINT,
+                ApiVersion::new(0, 0),
+                async |module: &PredictionMarkets, context, params: api::GetMarketsByEventHashParams| -> api::GetMarketsByEventHashResult {
+                    module.api_get_markets_by_event_hash(context, params).await
+                }
+            },
             api_endpoint! {
                 api::GET_MARKET_DYNAMIC_ENDPOINT,
                 ApiVersion::new(0, 0),
@@ -871,6 +891,13 @@ impl ServerModule for PredictionMarkets {
                     module.api_get_order(context, params).await
                 }
             },
+            api_endpoint! {
+                api::GET_MARKET_ORDERS_ENDPOINT,
+                ApiVersion::new(0, 0),
+                async |module: &PredictionMarkets, context, params: api::GetMarketOrdersParams| -> api::GetMarketOrdersResult {
+                    module.api_get_market_orders(context, params).await
+                }
+            },
             api_endpoint! {
                 api::WAIT_ORDER_MATCH_ENDPOINT,
                 ApiVersion::new(0, 0),
@@ -899,6 +926,13 @@ impl ServerModule for PredictionMarkets {
                     module.api_get_market_outcome_order_book(context, params).await
                 }
             },
+            api_endpoint! {
+                api::GET_CONSENSUS_TIMESTAMP_ENDPOINT,
+                ApiVersion::new(0, 0),
+                async |module: &PredictionMarkets, context, _params: ()| -> api::GetConsensusTimestampResult {
+                    module.api_get_consensus_timestamp(context).await
+                }
+            },
         ]
     }
 }
@@ -930,6 +964,86 @@ impl PredictionMarkets {
         })
     }
 
+    async fn api_get_markets(
+        &self,
+        context: &mut ApiEndpointContext<'_>,
+        params: api::GetMarketsParams,
+    ) -> Result<api::GetMarketsResult, ApiError> {
+        if params.markets.len() > api::GET_MARKETS_MAX_MARKETS {
+            return Err(ApiError::bad_request(format!(
+                "at most {} markets can be requested at once",
+                api::GET_MARKETS_MAX_MARKETS
+            )));
+        }
+
+        let mut markets = BTreeMap::new();
+        for market in params.markets {
+            let Some(market_static) = context.dbtx().get_value(&db::MarketStaticKey(market)).await
+            else {
+                continue;
+            };
+            let market_dynamic = context
+                .dbtx()
+                .get_value(&db::MarketDynamicKey(market))
+                .await
+                .unwrap();
+
+            markets.insert(market, Market(market_static, market_dynamic));
+        }
+
+        Ok(api::GetMarketsResult { markets })
+    }
+
+    async fn api_get_markets_by_payout_control(
+        &self,
+        context: &mut ApiEndpointContext<'_>,
+        params: api::GetMarketsByPayoutControlParams,
+    ) -> Result<api::GetMarketsByPayoutControlResult, ApiError> {
+        let mut dbtx = context.dbtx();
+        let mut keys = dbtx
+            .find_by_prefix(&db::MarketsByPayoutControlPrefix1 {
+                payout_control: params.payout_control,
+            })
+            .await
+            .map(|(key, _)| key)
+            .filter(|key| future::ready(key.created_consensus_timestamp > params.created_after));
+
+        let mut markets: Vec<(OutPoint, UnixTimestamp)> = Vec::new();
+        while let Some(key) = keys.next().await {
+            // the page only ends at a new timestamp so the next page can start
+            // after the last one
+            if markets.len() >= api::GET_MARKETS_BY_PAYOUT_CONTROL_MAX_MARKETS
+                && markets
+                    .last()
+                    .is_some_and(|(_, last)| *last != key.created_consensus_timestamp)
+            {
+                break;
+            }
+            markets.push((key.market, key.created_consensus_timestamp));
+        }
+
+        Ok(api::GetMarketsByPayoutControlResult { markets })
+    }
+
+    async fn api_get_markets_by_event_hash(
+        &self,
+        context: &mut ApiEndpointContext<'_>,
+        params: api::GetMarketsByEventHashParams,
+    ) -> Result<api::GetMarketsByEventHashResult, ApiError> {
+        let markets = context
+            .dbtx()
+            .find_by_prefix(&db::MarketsByEventHashPrefix1 {
+                event_hash_hex: params.event_hash_hex,
+            })
+            .await
+            .map(|(key, _)| (key.market, key.created_consensus_timestamp))
+            .take(api::GET_MARKETS_BY_EVENT_HASH_MAX_MARKETS)
+            .collect()
+            .await;
+
+        Ok(api::GetMarketsByEventHashResult { markets })
+    }
+
     async fn api_get_market_dynamic(
         &self,
         context: &mut ApiEndpointContext<'_>,
@@ -968,6 +1082,88 @@ impl PredictionMarkets {
         })
     }
 
+    async fn api_get_market_orders(
+        &self,
+        context: &mut ApiEndpointContext<'_>,
+        params: api::GetMarketOrdersParams,
+    ) -> Result<api::GetMarketOrdersResult, ApiError> {
+        if params.limit == 0 || params.limit > api::GET_MARKET_ORDERS_MAX_LIMIT {
+            return Err(ApiError::bad_request(format!(
+                "limit must be between 1 and {}",
+                api::GET_MARKET_ORDERS_MAX_LIMIT
+            )));
+        }
+
+        // markets are always output 0 of their transaction
+        if params.market.out_idx != 0 {
+            return Ok(api::GetMarketOrdersResult {
+                orders: Vec::new(),
+                next: None,
+            });
+        }
+
+        // keys are ordered by the order's serialized public key, one extra is
+        // taken to know if there is a next page
+        let page_len = params.limit as usize + 1;
+        let mut order_owners: Vec<_> = match params.after {
+            None => {
+                context
+                    .dbtx()
+                    .find_by_prefix(&db::OrdersByMarketPrefix1 {
+                        market: params.market,
+                    })
+                    .await
+                    .map(|(key, _)| key.order)
+                    .take(page_len)
+                    .collect()
+                    .await
+            }
+            // seeks to the cursor, the market's keys end where the keys of
+            // output 1 of its transaction would start
+            Some(after) => {
+                context
+                    .dbtx()
+                    .find_by_range(
+                        db::OrdersByMarketKey {
+                            market: params.market,
+                            order: after,
+                        }..db::OrdersByMarketKey {
+                            market: OutPoint {
+                                txid: params.market.txid,
+                                out_idx: 1,
+                            },
+                            order: after,
+                        },
+                    )
+                    .await
+                    .map(|(key, _)| key.order)
+                    .filter(|order| future::ready(*order != after))
+                    .take(page_len)
+                    .collect()
+                    .await
+            }
+        };
+
+        let next = if order_owners.len() > params.limit as usize {
+            order_owners.truncate(params.limit as usize);
+            order_owners.last().copied()
+        } else {
+            None
+        };
+
+        let mut orders = Vec::with_capacity(order_owners.len());
+        for order_owner in order_owners {
+            let order = context
+                .dbtx()
+                .get_value(&db::OrderKey(order_owner))
+                .await
+                .expect("OrdersByMarket always has a matching Order");
+            orders.push((order_owner, order));
+        }
+
+        Ok(api::GetMarketOrdersResult { orders, next })
+    }
+
     async fn api_wait_order_match(
         &self,
         context: &mut ApiEndpointContext<'_>,
@@ -1073,12 +1269,106 @@ impl PredictionMarkets {
 
         Ok(result)
     }
+
+    async fn api_get_consensus_timestamp(
+        &self,
+        context: &mut ApiEndpointContext<'_>,
+    ) -> Result<api::GetConsensusTimestampResult, ApiError> {
+        let consensus_timestamp = self.get_consensus_timestamp(&mut context.dbtx()).await;
+
+        Ok(api::GetConsensusTimestampResult {
+            consensus_timestamp,
+        })
+    }
 }
 
 //
 // market operations
 //
 impl PredictionMarkets {
+    async fn process_new_market(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        out_point: OutPoint,
+        event_json: &PredictionMarketEventJson,
+        contract_price: &Amount,
+        payout_control_weight_map: &BTreeMap<NostrPublicKeyHex, Weight>,
+        weight_required_for_payout: &WeightRequiredForPayout,
+        outcome_display_hints: &[OutcomeDisplayHint],
+    ) -> Result<(), PredictionMarketsOutputError> {
+        let event = Event::try_from_json_str(event_json)
+            .map_err(|_| PredictionMarketsOutputError::MarketValidationFailed)?;
+        let event_hash_hex = event
+            .hash_hex()
+            .map_err(|_| PredictionMarketsOutputError::MarketValidationFailed)?
+            .0;
+
+        // verify market params
+        if let Err(()) = Market::validate_market_params(
+            &self.cfg.consensus.gc,
+            &event,
+            contract_price,
+            payout_control_weight_map,
+            weight_required_for_payout,
+            outcome_display_hints,
+        ) {
+            return Err(PredictionMarketsOutputError::MarketValidationFailed);
+        }
+
+        // save outcome
+        dbtx.insert_new_entry(
+            &db::OutcomeKey(out_point),
+            &PredictionMarketsOutputOutcome::NewMarket,
+        )
+        .await;
+
+        // save market static
+        let event_json = ensure_compact_json(event_json)
+            .map_err(|e| PredictionMarketsOutputError::Other(e.to_string()))?;
+        let created_consensus_timestamp = self.get_consensus_timestamp(dbtx).await;
+
+        let market_static = MarketStatic {
+            event_json,
+            contract_price: *contract_price,
+            payout_control_weight_map: payout_control_weight_map.to_owned(),
+            weight_required_for_payout: *weight_required_for_payout,
+            outcome_display_hints: outcome_display_hints.to_owned(),
+            created_consensus_timestamp,
+        };
+        dbtx.insert_new_entry(&db::MarketStaticKey(out_point), &market_static)
+            .await;
+
+        // index market by payout control
+        db::insert_markets_by_payout_control_entries(dbtx, out_point, &market_static).await;
+
+        // index market by event hash
+        db::insert_markets_by_event_hash_entry(dbtx, event_hash_hex, out_point, &market_static)
+            .await;
+
+        // save market dynamic
+        dbtx.insert_new_entry(
+            &db::MarketDynamicKey(out_point),
+            &MarketDynamic {
+                open_contracts: ContractAmount::ZERO,
+                payout: None,
+            },
+        )
+        .await;
+
+        // save MarketSpecificationsNeededForNewOrders
+        dbtx.insert_new_entry(
+            &db::MarketSpecificationsNeededForNewOrdersKey(out_point),
+            &MarketSpecificationsNeededForNewOrders {
+                outcome_count: event.outcome_count,
+                contract_price: *contract_price,
+                next_time_ordering: 0,
+            },
+        )
+        .await;
+
+        Ok(())
+    }
+
     async fn verify_and_process_contract_of_outcome_sources(
         dbtx: &mut DatabaseTransaction<'_>,
         gc: &GeneralConsensus,
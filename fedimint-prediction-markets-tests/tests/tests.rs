@@ -1,41 +1,143 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::iter;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
-use fedimint_client::ClientModuleInstance;
+use fedimint_client::derivable_secret::{ChildId, DerivableSecret};
+use fedimint_client::{Client, ClientHandleArc, ClientModuleInstance};
+use fedimint_core::core::OperationId;
+use fedimint_core::db::mem_impl::MemDatabase;
+use fedimint_core::db::Database;
 use fedimint_core::task::sleep;
 use fedimint_core::util::NextOrPending;
-use fedimint_core::Amount;
+use fedimint_core::{Amount, OutPoint, TransactionId};
 use fedimint_dummy_client::common::config::DummyGenParams;
 use fedimint_dummy_client::{DummyClientInit, DummyClientModule};
 use fedimint_dummy_server::DummyInit;
-use fedimint_prediction_markets_client::order_filter::{OrderFilter, OrderPath, OrderState};
+use fedimint_prediction_markets_client::error::{
+    CandlestickViolation, PredictionMarketsClientError,
+};
+use fedimint_prediction_markets_client::federation_call_policy::FederationCallPolicy;
+use fedimint_prediction_markets_client::key_domain::{derive_key, KeyDomain};
+use fedimint_prediction_markets_client::order_filter::{
+    OrderFilter, OrderPath, OrderQuery, OrderSort, OrderState,
+};
+use fedimint_prediction_markets_client::order_rate_limiter::OrderRateLimiter;
 use fedimint_prediction_markets_client::{
-    OrderId, PredictionMarketsClientInit, PredictionMarketsClientModule,
+    binary_outcome, check_candlestick, fill_candlestick_gaps, fill_market_template,
+    market_outpoint_from_tx_id, normalize_candlestick_closes, outcome_by_title,
+    payout_amount_per_outcome, price_from_probability, repair_candlestick, suggest_event_payout,
+    validate_event_payout, BestBidOffer, BinaryMarketBbo, CapitalEstimate, ClientHealth,
+    EventPayoutAttestationGroup, FillMode, FillQuote, LedgerEntryKind, MarketDisplayOverride,
+    MarketStatus, MarketTemplate, MatchSimulation, OperationOutcome, OrderId, OrderRequest,
+    OrderType, PredictionMarketOperationStatus, PredictionMarketsClientInit,
+    PredictionMarketsClientModule, SharedQuote, SubmittedTransactionSummary,
+    CONSENSUS_TIMESTAMP_CACHE_DURATION, MAX_RESERVED_ORDER_IDS,
 };
 use fedimint_prediction_markets_common::config::PredictionMarketsGenParams;
 use fedimint_prediction_markets_common::{
-    ContractAmount, ContractOfOutcomeAmount, Market, MarketDynamic, MarketStatic,
-    NostrPublicKeyHex, Side, SignedAmount, UnixTimestamp, Weight,
+    Candlestick, ContractAmount, ContractOfOutcomeAmount, Market, MarketDynamic, MarketStatic,
+    NostrPublicKeyHex, Order, Outcome, OutcomeDisplayHint, Side, SignedAmount, UnixTimestamp,
+    Weight,
 };
 use fedimint_prediction_markets_server::PredictionMarketsInit;
+use fedimint_testing::federation::FederationTest;
 use fedimint_testing::fixtures::Fixtures;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use prediction_market_event::information::Information;
-use prediction_market_event::Event;
+use prediction_market_event::nostr_event_types::{EventPayoutAttestation, NostrEventUtils};
+use prediction_market_event::{Event, EventPayout};
 use prediction_market_event_nostr_client::nostr_sdk::Keys;
+use secp256k1::Secp256k1;
 use tokio::spawn;
 use tracing::info;
 
 fn fixtures() -> Fixtures {
     Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default()).with_module(
-        PredictionMarketsClientInit,
+        PredictionMarketsClientInit::default(),
         PredictionMarketsInit,
         PredictionMarketsGenParams::default(),
     )
 }
 
+/// Signs an attestation to `event_payout` of `event` with each of
+/// `payout_controls`.
+fn sign_event_payout_attestations(
+    event: &Event,
+    event_payout: &EventPayout,
+    payout_controls: &[Keys],
+) -> anyhow::Result<Vec<String>> {
+    payout_controls
+        .iter()
+        .map(|keys| {
+            EventPayoutAttestation::create_nostr_event_json(
+                event,
+                event_payout,
+                &keys.secret_key().to_secret_hex(),
+            )
+            .map_err(|e| anyhow::anyhow!("failed to sign payout attestation: {e}"))
+        })
+        .collect()
+}
+
+/// Creates a market on a two outcome event that pays out one unit, with each
+/// of `payout_controls` weighted 1 and all of them required for payout.
+async fn new_market_with_payout_controls(
+    client_pm: &PredictionMarketsClientModule,
+    payout_controls: &[Keys],
+) -> anyhow::Result<(OutPoint, Event)> {
+    let event = Event::new_with_random_nonce(2, 1, Information::None);
+    let market = client_pm
+        .new_market(
+            event.try_to_json_string()?,
+            Amount::from_msats(100),
+            payout_controls
+                .iter()
+                .map(|keys| (keys.public_key.to_hex(), 1u16))
+                .collect(),
+            payout_controls.len() as u64,
+            None,
+        )
+        .await?;
+
+    Ok((market, event))
+}
+
+/// Pays out `market` according to `event_payout` with attestations signed by
+/// `payout_controls`.
+async fn payout_market_with(
+    client_pm: &PredictionMarketsClientModule,
+    market: OutPoint,
+    event: &Event,
+    payout_controls: &[Keys],
+    event_payout: &EventPayout,
+) -> anyhow::Result<()> {
+    client_pm
+        .payout_market(
+            market,
+            sign_event_payout_attestations(event, event_payout, payout_controls)?,
+        )
+        .await
+}
+
+/// Joins `fed` with a fresh database holding the same root secret as `db`,
+/// like a second device of the same user.
+async fn new_client_sharing_secret(
+    fed: &FederationTest,
+    db: &Database,
+) -> anyhow::Result<ClientHandleArc> {
+    let secret = Client::load_or_generate_client_secret(db).await?;
+    let other_db: Database = MemDatabase::new().into();
+    Client::store_encodable_client_secret(&other_db, secret).await?;
+
+    Ok(fed
+        .new_client_with(fed.client_config(), other_db, None)
+        .await)
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn create_market_and_get_market() -> anyhow::Result<()> {
     let fed = fixtures().new_default_fed().await;
@@ -56,6 +158,7 @@ async fn create_market_and_get_market() -> anyhow::Result<()> {
             contract_price,
             payout_control_weight_map.clone(),
             weight_required_for_payout,
+            None,
         )
         .await?;
 
@@ -72,6 +175,7 @@ async fn create_market_and_get_market() -> anyhow::Result<()> {
                 contract_price,
                 payout_control_weight_map,
                 weight_required_for_payout,
+                outcome_display_hints: Vec::new(),
                 created_consensus_timestamp
             },
             MarketDynamic {
@@ -105,6 +209,7 @@ async fn candlestick_stream() -> anyhow::Result<()> {
             contract_price,
             payout_control_weight_map.clone(),
             weight_required_for_payout,
+            None,
         )
         .await?;
 
@@ -124,6 +229,7 @@ async fn candlestick_stream() -> anyhow::Result<()> {
             Side::Buy,
             Amount::from_msats(60),
             ContractOfOutcomeAmount(10),
+            None,
         )
         .await?;
     for _ in 0..10 {
@@ -134,6 +240,7 @@ async fn candlestick_stream() -> anyhow::Result<()> {
                 Side::Buy,
                 Amount::from_msats(40),
                 ContractOfOutcomeAmount(1),
+                None,
             )
             .await?;
         sleep(Duration::from_millis(10)).await;
@@ -143,7 +250,7 @@ async fn candlestick_stream() -> anyhow::Result<()> {
 }
 
 #[tokio::test(flavor = "multi_thread")]
-async fn order_stream() -> anyhow::Result<()> {
+async fn candlestick_stream_with_trade_count() -> anyhow::Result<()> {
     let fed = fixtures().new_default_fed().await;
     let client1 = fed.new_client_rocksdb().await;
 
@@ -163,466 +270,6183 @@ async fn order_stream() -> anyhow::Result<()> {
             contract_price,
             payout_control_weight_map.clone(),
             weight_required_for_payout,
+            None,
         )
         .await?;
 
-    let watch_matches_id = client1_pm
-        .start_watch_matches(OrderPath::Market { market })
-        .await?;
+    let client1_pm_ref = &client1_pm;
+    let trade = move || async move {
+        client1_pm_ref
+            .new_order(
+                market,
+                0,
+                Side::Buy,
+                Amount::from_msats(60),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await?;
+        client1_pm_ref
+            .new_order(
+                market,
+                1,
+                Side::Buy,
+                Amount::from_msats(40),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await?;
+        anyhow::Ok(())
+    };
 
-    client1_pm
-        .new_order(
-            market,
-            0,
-            Side::Buy,
-            Amount::from_msats(60),
-            ContractOfOutcomeAmount(1000),
-        )
+    trade().await?;
+
+    assert!(client1_pm.candlesticks_supported());
+    let mut stream = client1_pm
+        .stream_candlesticks(market, 0, 60, UnixTimestamp::ZERO, Duration::ZERO)
+        .await;
+    let (first_timestamp, first_candlestick) = stream.ok().await?.last().unwrap().to_owned();
+
+    // the stream carries over the newest candlestick's volume, so the next
+    // item only arrives after another trade
+    trade().await?;
+    let (newest_timestamp, newest_candlestick) = stream.ok().await?.last().unwrap().to_owned();
+    assert!(
+        newest_timestamp > first_timestamp || newest_candlestick.volume > first_candlestick.volume
+    );
+
+    let candlesticks = client1_pm
+        .get_candlesticks_with_trade_count(market, 0, 60, UnixTimestamp::ZERO)
         .await?;
+    let newest = &candlesticks[&newest_timestamp];
+    assert_eq!(newest.candlestick, newest_candlestick);
+    assert!(newest.trade_count.unwrap() >= 1);
+    let total_trade_count: u64 = candlesticks.values().filter_map(|c| c.trade_count).sum();
+    assert!(total_trade_count >= 1);
 
-    // for _ in 0..10 {
-    //     client1_pm
-    //         .new_order(
-    //             market,
-    //             1,
-    //             Side::Buy,
-    //             Amount::from_msats(40),
-    //             ContractOfOutcomeAmount(1),
-    //         )
-    //         .await?;
-    // }
+    Ok(())
+}
 
-    let client1_s = client1.clone();
-    spawn(async move {
-        let client1_pm = client1_s.get_first_module::<PredictionMarketsClientModule>();
-        let mut stream_of_order_ids = client1_pm
-            .stream_order_ids(OrderFilter(OrderPath::All, OrderState::Any))
-            .await;
+#[tokio::test(flavor = "multi_thread")]
+async fn candlestick_stream_multi_interval() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
 
-        loop {
-            let order_id = stream_of_order_ids.next_or_pending().await;
-            let mut order_stream = client1_pm.stream_order_from_db(order_id).await;
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
 
-            spawn(async move {
-                loop {
-                    let order = order_stream.next_or_pending().await;
-                    info!(
-                        "{}: {}",
-                        order_id.0,
-                        order.unwrap().quantity_waiting_for_match.0
-                    );
-                }
-            });
-        }
-    });
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
 
-    let iter = 0..10;
-    iter.map(|_| async {
-        let res = client1_pm
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let client1_pm_ref = &client1_pm;
+    let trade = move || async move {
+        client1_pm_ref
+            .new_order(
+                market,
+                0,
+                Side::Buy,
+                Amount::from_msats(60),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await?;
+        client1_pm_ref
             .new_order(
                 market,
                 1,
                 Side::Buy,
                 Amount::from_msats(40),
                 ContractOfOutcomeAmount(1),
+                None,
             )
-            .await;
+            .await?;
+        anyhow::Ok(())
+    };
 
-        if let Err(e) = res {
-            info!("error creating order: {e}");
-        }
-    })
-    .collect::<FuturesUnordered<_>>()
-    .collect::<()>()
-    .await;
-    client1_pm.stop_watch_matches(watch_matches_id).await?;
+    trade().await?;
 
-    info!("test_main end");
+    // 61 is not supported and duplicates are streamed once
+    let mut stream = client1_pm
+        .stream_candlesticks_multi_interval(
+            market,
+            0,
+            vec![60, 15, 61, 60],
+            UnixTimestamp::ZERO,
+            Duration::ZERO,
+        )
+        .await;
+
+    let mut newest = BTreeMap::new();
+    for _ in 0..2 {
+        let (interval, candlesticks) = stream.ok().await?;
+        assert!(candlesticks.keys().all(|t| t.0 % interval == 0));
+        let (timestamp, candlestick) = candlesticks.last_key_value().unwrap();
+        assert_eq!(candlestick.volume, ContractOfOutcomeAmount(1));
+        assert!(newest
+            .insert(interval, (*timestamp, candlestick.volume))
+            .is_none());
+    }
+    assert_eq!(newest.keys().copied().collect::<Vec<_>>(), vec![15, 60]);
+
+    // each interval continues from its own newest candlestick
+    trade().await?;
+    for _ in 0..2 {
+        let (interval, candlesticks) = stream.ok().await?;
+        assert!(candlesticks.keys().all(|t| t.0 % interval == 0));
+        let (timestamp, candlestick) = candlesticks.last_key_value().unwrap();
+        let (previous_timestamp, previous_volume) = newest[&interval];
+        assert!(*timestamp > previous_timestamp || candlestick.volume > previous_volume);
+        newest.insert(interval, (*timestamp, candlestick.volume));
+    }
 
     Ok(())
 }
 
 #[tokio::test(flavor = "multi_thread")]
-async fn general1() -> anyhow::Result<()> {
+async fn unsupported_candlestick_interval() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let supported = client1_pm.supported_candlestick_intervals();
+    assert!(supported.contains(&60));
+    assert!(supported.windows(2).all(|w| w[0] < w[1]));
+
+    let market = market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64))?);
+    let err = client1_pm
+        .get_candlesticks(market, 0, 61, UnixTimestamp::ZERO)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::UnsupportedInterval {
+            requested: 61,
+            supported,
+        })
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn outcome_out_of_range() -> anyhow::Result<()> {
     let fed = fixtures().new_default_fed().await;
     let client1 = fed.new_client_rocksdb().await;
     let client2 = fed.new_client_rocksdb().await;
 
-    client1
-        .get_first_module::<DummyClientModule>()
-        .print_money(Amount::from_sats(1000))
-        .await?;
-    client2
-        .get_first_module::<DummyClientModule>()
-        .print_money(Amount::from_sats(1000))
-        .await?;
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
 
     let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
     let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
 
-    let event_json = Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?;
-    let contract_price = Amount::from_msats(100);
-    let payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight> =
-        iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect();
-    let weight_required_for_payout = 1;
-    let market_outpoint = client1_pm
+    let market = client1_pm
         .new_market(
-            event_json.clone(),
-            contract_price,
-            payout_control_weight_map.clone(),
-            weight_required_for_payout,
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
         )
         .await?;
+    let out_of_range = PredictionMarketsClientError::OutcomeOutOfRange {
+        outcome: 2,
+        outcomes: 2,
+    };
 
-    // orderbook setup
-
-    let client1_order0 = client1_pm
+    client1_pm
         .new_order(
-            market_outpoint,
-            0,
+            market,
+            1,
             Side::Buy,
-            Amount::from_msats(10),
-            ContractOfOutcomeAmount(30),
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
         )
         .await?;
-
-    let client1_order1 = client1_pm
+    let err = client1_pm
         .new_order(
-            market_outpoint,
-            0,
+            market,
+            2,
             Side::Buy,
-            Amount::from_msats(50),
-            ContractOfOutcomeAmount(15),
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
         )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&out_of_range)
+    );
+
+    // client2 has not cached the market, it is fetched from the federation
+    client2_pm
+        .get_candlesticks(market, 1, 60, UnixTimestamp::ZERO)
         .await?;
+    let err = client2_pm
+        .get_candlesticks(market, 2, 60, UnixTimestamp::ZERO)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&out_of_range)
+    );
 
-    let client1_order2 = client1_pm
-        .new_order(
-            market_outpoint,
-            0,
-            Side::Buy,
-            Amount::from_msats(30),
-            ContractOfOutcomeAmount(10),
+    client2_pm.get_order_book(market, 1).await?;
+    let err = client2_pm.get_order_book(market, 2).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&out_of_range)
+    );
+
+    let err = client2_pm
+        .quote_fill(market, 2, Side::Buy, ContractOfOutcomeAmount(1))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&out_of_range)
+    );
+
+    let err = client2_pm
+        .start_watch_matches(OrderPath::MarketOutcome { market, outcome: 2 })
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&out_of_range)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn binary_market_helpers() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    assert_eq!(binary_outcome(true), 0);
+    assert_eq!(binary_outcome(false), 1);
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
         )
         .await?;
 
-    let client2_order0 = client2_pm
-        .new_order(
-            market_outpoint,
-            1,
+    // prices sum to less than the contract price, so neither order matches
+    let yes_order = client1_pm
+        .new_binary_order(
+            market,
+            true,
             Side::Buy,
-            Amount::from_msats(15),
-            ContractOfOutcomeAmount(10),
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(5),
+            None,
         )
         .await?;
-
-    let client2_order1 = client2_pm
-        .new_order(
-            market_outpoint,
-            1,
+    let no_order = client1_pm
+        .new_binary_order(
+            market,
+            false,
             Side::Buy,
-            Amount::from_msats(25),
-            ContractOfOutcomeAmount(10),
+            Amount::from_msats(30),
+            ContractOfOutcomeAmount(5),
+            None,
         )
         .await?;
+    assert_eq!(
+        client1_pm
+            .get_order(yes_order, true)
+            .await?
+            .unwrap()
+            .outcome,
+        0
+    );
+    assert_eq!(
+        client1_pm.get_order(no_order, true).await?.unwrap().outcome,
+        1
+    );
 
-    let client2_order2 = client2_pm
-        .new_order(
-            market_outpoint,
+    assert_eq!(
+        client1_pm.get_binary_market_bbo(market).await?,
+        BinaryMarketBbo {
+            yes: BestBidOffer {
+                bid: Some(Amount::from_msats(40)),
+                ask: None,
+            },
+            no: BestBidOffer {
+                bid: Some(Amount::from_msats(30)),
+                ask: None,
+            },
+        }
+    );
+
+    let three_outcome_market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(3, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
             1,
-            Side::Buy,
-            Amount::from_msats(45),
-            ContractOfOutcomeAmount(10),
+            None,
         )
         .await?;
+    let not_binary = PredictionMarketsClientError::NotABinaryMarket { outcomes: 3 };
 
-    // orderbook state
-    //
-    // outcome 0
-    // 10 - 30
-    // 30 - 10
-    // 50 - 15
-    //
-    // outcome 1
-    // 15 - 10
-    // 25 - 10
-    // 45 - 10
-    dbg!(client1_pm.get_order_book(market_outpoint, 0).await?);
-    dbg!(client1_pm.get_order_book(market_outpoint, 1).await?);
+    let err = client1_pm
+        .new_binary_order(
+            three_outcome_market,
+            true,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(5),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&not_binary)
+    );
 
-    let client1_order3 = client1_pm
+    let err = client1_pm
+        .get_binary_market_bbo(three_outcome_market)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&not_binary)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn market_outcome_stats() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let stats = client1_pm.get_market_outcome_stats(market, 0).await?;
+    assert_eq!(stats.volume, ContractOfOutcomeAmount::ZERO);
+    assert_eq!(stats.resting_bid_quantity, ContractOfOutcomeAmount::ZERO);
+    assert_eq!(stats.resting_ask_quantity, ContractOfOutcomeAmount::ZERO);
+    assert_eq!(stats.last_trade_price, None);
+
+    client1_pm
         .new_order(
-            market_outpoint,
+            market,
             0,
             Side::Buy,
             Amount::from_msats(60),
-            ContractOfOutcomeAmount(15),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+    client1_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+    client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(30),
+            ContractOfOutcomeAmount(2),
+            None,
         )
         .await?;
 
-    assert_order_mutated_values(
-        &client1_pm,
-        client1_order3,
-        true,
-        AssertOrderMutatedValues {
-            quantity_waiting_for_match: ContractOfOutcomeAmount(5),
-            contract_of_outcome_balance: ContractOfOutcomeAmount(10),
-            bitcoin_balance: Amount::from_msats(50),
-            quantity_fulfilled: ContractOfOutcomeAmount(10),
-            bitcoin_acquired_from_order_matches: SignedAmount {
-                amount: Amount::from_msats(550),
-                negative: true,
-            },
-            bitcoin_acquired_from_payout: Amount::ZERO,
-        },
-    )
-    .await;
-    assert_order_mutated_values(
-        &client2_pm,
-        client2_order2,
-        true,
-        AssertOrderMutatedValues {
-            quantity_waiting_for_match: ContractOfOutcomeAmount(10),
-            contract_of_outcome_balance: ContractOfOutcomeAmount(0),
-            bitcoin_balance: Amount::from_msats(0),
-            quantity_fulfilled: ContractOfOutcomeAmount(0),
-            bitcoin_acquired_from_order_matches: SignedAmount::ZERO,
-            bitcoin_acquired_from_payout: Amount::ZERO,
-        },
-    )
-    .await;
-    assert_order_mutated_values(
-        &client2_pm,
-        client2_order2,
-        false,
-        AssertOrderMutatedValues {
-            quantity_waiting_for_match: ContractOfOutcomeAmount(0),
-            contract_of_outcome_balance: ContractOfOutcomeAmount(10),
-            bitcoin_balance: Amount::from_msats(0),
-            quantity_fulfilled: ContractOfOutcomeAmount(10),
-            bitcoin_acquired_from_order_matches: SignedAmount {
-                amount: Amount::from_msats(450),
-                negative: true,
-            },
-            bitcoin_acquired_from_payout: Amount::ZERO,
-        },
-    )
-    .await;
+    let stats = client1_pm.get_market_outcome_stats(market, 0).await?;
+    assert!(stats.volume > ContractOfOutcomeAmount::ZERO);
+    assert_eq!(stats.resting_bid_quantity, ContractOfOutcomeAmount(2));
+    assert_eq!(stats.resting_ask_quantity, ContractOfOutcomeAmount::ZERO);
+    assert_eq!(stats.last_trade_price, Some(Amount::from_msats(60)));
 
-    // orderbook state
-    //
-    // outcome 0
-    // 10 - 30
-    // 30 - 10
-    // 50 - 15
-    // 60 - 5
-    //
-    // outcome 1
-    // 15 - 10
-    // 25 - 10
-    dbg!(client1_pm.get_order_book(market_outpoint, 0).await?);
-    dbg!(client1_pm.get_order_book(market_outpoint, 1).await?);
+    Ok(())
+}
 
-    let client1_order4 = client1_pm
+#[tokio::test(flavor = "multi_thread")]
+async fn federation_without_candlesticks() -> anyhow::Result<()> {
+    let mut gen_params = PredictionMarketsGenParams::default();
+    gen_params.consensus.gc.candlestick_intervals = vec![];
+    let fed = Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default())
+        .with_module(
+            PredictionMarketsClientInit::default(),
+            PredictionMarketsInit,
+            gen_params,
+        )
+        .new_default_fed()
+        .await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    assert!(!client1_pm.candlesticks_supported());
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    client1_pm
         .new_order(
-            market_outpoint,
+            market,
             0,
             Side::Buy,
-            Amount::from_msats(80),
-            ContractOfOutcomeAmount(5),
+            Amount::from_msats(30),
+            ContractOfOutcomeAmount(2),
+            None,
         )
         .await?;
-    assert_order_mutated_values(
-        &client1_pm,
-        client1_order4,
-        true,
-        AssertOrderMutatedValues {
-            quantity_waiting_for_match: ContractOfOutcomeAmount(0),
-            contract_of_outcome_balance: ContractOfOutcomeAmount(5),
-            bitcoin_balance: Amount::from_msats(25),
-            quantity_fulfilled: ContractOfOutcomeAmount(5),
-            bitcoin_acquired_from_order_matches: SignedAmount {
-                amount: Amount::from_msats(375),
-                negative: true,
-            },
-            bitcoin_acquired_from_payout: Amount::ZERO,
-        },
-    )
-    .await;
-    assert_order_mutated_values(
-        &client2_pm,
-        client2_order1,
-        false,
-        AssertOrderMutatedValues {
-            quantity_waiting_for_match: ContractOfOutcomeAmount(5),
-            contract_of_outcome_balance: ContractOfOutcomeAmount(5),
-            bitcoin_balance: Amount::from_msats(0),
-            quantity_fulfilled: ContractOfOutcomeAmount(5),
-            bitcoin_acquired_from_order_matches: SignedAmount {
-                amount: Amount::from_msats(125),
-                negative: true,
-            },
-            bitcoin_acquired_from_payout: Amount::ZERO,
-        },
-    )
-    .await;
 
-    // orderbook state
-    //
-    // outcome 0
-    // 10 - 30
-    // 30 - 10
-    // 50 - 15
-    // 60 - 5
-    //
-    // outcome 1
-    // 15 - 10
-    // 25 - 5
-    dbg!(client1_pm.get_order_book(market_outpoint, 0).await?);
-    dbg!(client1_pm.get_order_book(market_outpoint, 1).await?);
+    let err = client1_pm
+        .get_candlesticks(market, 0, 60, UnixTimestamp::ZERO)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::CandlesticksUnsupported)
+    );
+    assert!(client1_pm
+        .stream_candlesticks(market, 0, 60, UnixTimestamp::ZERO, Duration::ZERO)
+        .await
+        .next()
+        .await
+        .is_none());
 
-    let client2_order3 = client2_pm
+    // everything that does not need candlesticks keeps working
+    assert_eq!(client1_pm.sync_matches(OrderPath::All, false).await?, 1);
+    assert_eq!(client1_pm.sync_matches(OrderPath::All, false).await?, 1);
+    let stats = client1_pm.get_market_outcome_stats(market, 0).await?;
+    assert_eq!(stats.volume, ContractOfOutcomeAmount::ZERO);
+    assert_eq!(stats.resting_bid_quantity, ContractOfOutcomeAmount(2));
+    assert_eq!(stats.last_trade_price, None);
+    client1_pm.get_market_summary(market).await?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn market_summary() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let empty = BestBidOffer {
+        bid: None,
+        ask: None,
+    };
+    let summary = client1_pm.get_market_summary(market).await?;
+    assert_eq!(summary.status, MarketStatus::Open);
+    assert_eq!(summary.volume, ContractOfOutcomeAmount::ZERO);
+    assert_eq!(summary.outcomes.len(), 2);
+    for outcome in &summary.outcomes {
+        assert_eq!(outcome.title, None);
+        assert_eq!(outcome.display_hint, None);
+        assert_eq!(outcome.bbo, empty);
+    }
+
+    // matched against each other, client 1 then holds a contract of each
+    // outcome
+    client1_pm
         .new_order(
-            market_outpoint,
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+    client1_pm
+        .new_order(
+            market,
             1,
             Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+    // each outcome has only one side of its order book
+    client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(30),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+    client1_pm
+        .new_order(
+            market,
+            1,
+            Side::Sell,
             Amount::from_msats(80),
-            ContractOfOutcomeAmount(35),
+            ContractOfOutcomeAmount(1),
+            None,
         )
         .await?;
-    assert_order_mutated_values(
-        &client2_pm,
-        client2_order3,
-        true,
-        AssertOrderMutatedValues {
-            quantity_waiting_for_match: ContractOfOutcomeAmount(5),
-            contract_of_outcome_balance: ContractOfOutcomeAmount(30),
-            bitcoin_balance: Amount::from_msats(40 * 5 + 30 * 15 + 10 * 10),
-            quantity_fulfilled: ContractOfOutcomeAmount(30),
-            bitcoin_acquired_from_order_matches: SignedAmount {
-                amount: Amount::from_msats(40 * 5 + 50 * 15 + 70 * 10),
-                negative: true,
-            },
-            bitcoin_acquired_from_payout: Amount::ZERO,
-        },
-    )
-    .await;
-    assert_order_mutated_values(
-        &client1_pm,
-        client1_order3,
-        false,
-        AssertOrderMutatedValues {
-            quantity_waiting_for_match: ContractOfOutcomeAmount(0),
-            contract_of_outcome_balance: ContractOfOutcomeAmount(15),
-            bitcoin_balance: Amount::from_msats(50),
-            quantity_fulfilled: ContractOfOutcomeAmount(15),
-            bitcoin_acquired_from_order_matches: SignedAmount {
-                amount: Amount::from_msats(550 + 300),
-                negative: true,
-            },
-            bitcoin_acquired_from_payout: Amount::ZERO,
+
+    let summary = client1_pm.get_market_summary(market).await?;
+    assert_eq!(
+        summary.outcomes[0].bbo,
+        BestBidOffer {
+            bid: Some(Amount::from_msats(30)),
+            ask: None,
+        }
+    );
+    assert_eq!(
+        summary.outcomes[1].bbo,
+        BestBidOffer {
+            bid: None,
+            ask: Some(Amount::from_msats(80)),
+        }
+    );
+    assert!(summary.outcomes[0].volume > ContractOfOutcomeAmount::ZERO);
+    assert_eq!(
+        summary.volume,
+        summary.outcomes[0].volume + summary.outcomes[1].volume
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn market_outcome_display_hints() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let hints = vec![
+        OutcomeDisplayHint {
+            color: "#2ca02c".to_owned(),
+            sort_rank: 1,
         },
-    )
-    .await;
-    assert_order_mutated_values(
-        &client1_pm,
-        client1_order1,
-        false,
-        AssertOrderMutatedValues {
-            quantity_waiting_for_match: ContractOfOutcomeAmount(0),
-            contract_of_outcome_balance: ContractOfOutcomeAmount(15),
-            bitcoin_balance: Amount::from_msats(0),
-            quantity_fulfilled: ContractOfOutcomeAmount(15),
-            bitcoin_acquired_from_order_matches: SignedAmount {
-                amount: Amount::from_msats(750),
-                negative: true,
-            },
-            bitcoin_acquired_from_payout: Amount::ZERO,
+        OutcomeDisplayHint {
+            color: "#d62728".to_owned(),
+            sort_rank: 0,
         },
-    )
-    .await;
-    assert_order_mutated_values(
-        &client1_pm,
-        client1_order2,
-        false,
-        AssertOrderMutatedValues {
-            quantity_waiting_for_match: ContractOfOutcomeAmount(0),
-            contract_of_outcome_balance: ContractOfOutcomeAmount(10),
-            bitcoin_balance: Amount::from_msats(0),
-            quantity_fulfilled: ContractOfOutcomeAmount(10),
-            bitcoin_acquired_from_order_matches: SignedAmount {
-                amount: Amount::from_msats(300),
-                negative: true,
+    ];
+    let new_market = |hints: Vec<OutcomeDisplayHint>| {
+        client1_pm.new_market_with_display_hints(
+            Event::new_with_random_nonce(2, 1, Information::None)
+                .try_to_json_string()
+                .unwrap(),
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            hints,
+            None,
+        )
+    };
+
+    let err = new_market(hints[..1].to_vec()).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(
+            &PredictionMarketsClientError::OutcomeDisplayHintCountMismatch {
+                hints: 1,
+                outcomes: 2,
+            }
+        )
+    );
+
+    let market = new_market(hints.clone()).await?;
+
+    // other clients see the hints of the market creator
+    let Market(market_static, _) = client2_pm.get_market(market, false).await?.unwrap();
+    assert_eq!(market_static.outcome_display_hints, hints);
+
+    let summary = client2_pm.get_market_summary(market).await?;
+    let summary_hints: Vec<_> = summary
+        .outcomes
+        .into_iter()
+        .map(|outcome| outcome.display_hint)
+        .collect();
+    assert_eq!(
+        summary_hints,
+        hints.into_iter().map(Some).collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nonexistent_market_is_rejected_early() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64))?);
+    let errors = [
+        client1_pm
+            .new_order(
+                market,
+                0,
+                Side::Buy,
+                Amount::from_msats(60),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await
+            .map(|_| ()),
+        client1_pm.payout_market(market, vec![]).await,
+        client1_pm
+            .get_event_payout_attestations_used_to_permit_payout(market)
+            .await
+            .map(|_| ()),
+        client1_pm
+            .get_payout_attestation_status(
+                market,
+                EventPayout {
+                    units_per_outcome: vec![1, 0],
+                },
+                vec![],
+                None,
+            )
+            .await
+            .map(|_| ()),
+        client1_pm
+            .get_payout_control_weight_map_named(market)
+            .await
+            .map(|_| ()),
+        client1_pm.get_order_book(market, 0).await.map(|_| ()),
+    ];
+    for (i, res) in errors.into_iter().enumerate() {
+        let err = res.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PredictionMarketsClientError>(),
+            Some(&PredictionMarketsClientError::MarketDoesNotExist),
+            "call {i}"
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn market_display_override() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    let event_hash = |market: Market| -> anyhow::Result<_> { Ok(market.0.event()?.hash_hex()?.0) };
+    let original_hash = event_hash(client1_pm.get_market(market, false).await?.unwrap())?;
+
+    let display_override = MarketDisplayOverride {
+        title: Some("Fixed title".to_owned()),
+        description: None,
+        outcome_titles: Some(vec!["Yes".to_owned(), "No".to_owned()]),
+    };
+    client1_pm
+        .set_market_display_override(market, display_override.clone())
+        .await?;
+
+    let summary = client1_pm.get_market_summary(market).await?;
+    assert_eq!(summary.display_override, Some(display_override.clone()));
+    assert_eq!(summary.outcomes[0].title, Some("Yes".to_owned()));
+    assert_eq!(summary.outcomes[1].title, Some("No".to_owned()));
+    assert_eq!(
+        event_hash(client1_pm.get_market(market, false).await?.unwrap())?,
+        original_hash
+    );
+
+    // the override is client local
+    let summary = client2_pm.get_market_summary(market).await?;
+    assert_eq!(summary.display_override, None);
+    assert_eq!(summary.outcomes[0].title, None);
+
+    client1_pm.clear_market_display_override(market).await;
+    assert_eq!(client1_pm.get_market_display_override(market).await, None);
+
+    // matched against each other
+    for (outcome, price) in [(0, 60), (1, 40)] {
+        client1_pm
+            .new_order(
+                market,
+                outcome,
+                Side::Buy,
+                Amount::from_msats(price),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await?;
+    }
+    let err = client1_pm
+        .set_market_display_override(market, display_override)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::MarketAlreadyTraded)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn market_templates() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let template = MarketTemplate {
+        event_json: Event::new_with_random_nonce(3, 1, Information::None).try_to_json_string()?,
+        contract_price: Amount::from_msats(300),
+        payout_control_weight_map: iter::once((Keys::generate().public_key.to_hex(), 1u16))
+            .collect(),
+        weight_required_for_payout: 1,
+    };
+    client1_pm
+        .save_market_template("weekly".to_owned(), template.clone())
+        .await;
+    assert_eq!(
+        client1_pm.get_market_templates().await,
+        iter::once(("weekly".to_owned(), template.clone())).collect()
+    );
+
+    // each market gets its own event
+    let market1 = client1_pm
+        .new_market_from_template("weekly", BTreeMap::new())
+        .await?;
+    let market2 = client1_pm
+        .new_market_from_template("weekly", BTreeMap::new())
+        .await?;
+    assert_ne!(market1, market2);
+    for market in [market1, market2] {
+        let Market(market_static, _) = client1_pm.get_market(market, false).await?.unwrap();
+        assert_eq!(market_static.contract_price, template.contract_price);
+        assert_eq!(market_static.event()?.outcome_count, 3);
+    }
+
+    client1_pm
+        .save_market_template(
+            "weekly".to_owned(),
+            MarketTemplate {
+                event_json: r#"{"title":"Week {week}"}"#.to_owned(),
+                ..template
             },
-            bitcoin_acquired_from_payout: Amount::ZERO,
-        },
-    )
-    .await;
+        )
+        .await;
+    let err = client1_pm
+        .new_market_from_template("weekly", BTreeMap::new())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::MissingTemplateSubstitution(
+            "week".to_owned()
+        ))
+    );
 
-    // orderbook state
-    //
-    // outcome 0
-    // 10 - 30
-    //
-    // outcome 1
-    // 15 - 10
-    // 25 - 5
-    // 80 - 5
-    dbg!(client1_pm.get_order_book(market_outpoint, 0).await?);
-    dbg!(client1_pm.get_order_book(market_outpoint, 1).await?);
+    client1_pm.remove_market_template("weekly".to_owned()).await;
+    let err = client1_pm
+        .new_market_from_template("weekly", BTreeMap::new())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::UnknownMarketTemplate(
+            "weekly".to_owned()
+        ))
+    );
 
     Ok(())
 }
 
 #[tokio::test(flavor = "multi_thread")]
-async fn order_book() -> anyhow::Result<()> {
+async fn market_order_without_liquidity_is_not_submitted() -> anyhow::Result<()> {
     let fed = fixtures().new_default_fed().await;
     let client1 = fed.new_client_rocksdb().await;
 
     let client1_dummy = client1.get_first_module::<DummyClientModule>();
-    client1_dummy.print_money(Amount::from_sats(10000)).await?;
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
 
     let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
 
-    let event_json = Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?;
-    let contract_price = Amount::from_msats(10000);
-    let payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight> =
-        iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect();
-    let weight_required_for_payout = 1;
     let market = client1_pm
         .new_market(
-            event_json.clone(),
-            contract_price,
-            payout_control_weight_map.clone(),
-            weight_required_for_payout,
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
         )
         .await?;
 
-    let iter = 1u64..501;
-    iter.map(|msat| {
-        let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let err = client1_pm
+        .new_order_with_type(
+            market,
+            0,
+            Side::Buy,
+            OrderType::Market {
+                max_slippage: Amount::from_msats(10),
+            },
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::NoLiquidity)
+    );
+    assert!(client1_pm
+        .get_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+        .await
+        .is_empty());
 
-        async move {
-            let res = client1_pm
-                .new_order(
-                    market,
-                    0,
-                    Side::Buy,
-                    Amount::from_msats(msat),
-                    ContractOfOutcomeAmount(1),
-                )
-                .await;
+    Ok(())
+}
 
-            if let Err(e) = res {
-                info!("error creating order: {e}");
-            }
-        }
-    })
-    .collect::<FuturesUnordered<_>>()
-    .collect::<()>()
-    .await;
+#[tokio::test(flavor = "multi_thread")]
+async fn market_order_fills_within_slippage() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
 
-    dbg!(client1_pm.get_order_book(market, 0).await?);
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // buys on outcome 1 are matched by buys on outcome 0 at 100 minus their
+    // price: 2 at 60, 3 at 65 and 4 at 80
+    for (price, quantity) in [(40, 2), (35, 3), (20, 4)] {
+        client2_pm
+            .new_order(
+                market,
+                1,
+                Side::Buy,
+                Amount::from_msats(price),
+                ContractOfOutcomeAmount(quantity),
+                None,
+            )
+            .await?;
+    }
+
+    // full fill at the best price
+    let order_id = client1_pm
+        .new_order_with_type(
+            market,
+            0,
+            Side::Buy,
+            OrderType::Market {
+                max_slippage: Amount::ZERO,
+            },
+            ContractOfOutcomeAmount(2),
+            None,
+        )
+        .await?;
+    let order = client1_pm.get_order(order_id, false).await?.unwrap();
+    assert_eq!(order.price, Amount::from_msats(60));
+    assert_eq!(order.original_quantity, ContractOfOutcomeAmount(2));
+    assert_eq!(
+        order.quantity_waiting_for_match,
+        ContractOfOutcomeAmount::ZERO
+    );
+    assert_eq!(
+        order.contract_of_outcome_balance,
+        ContractOfOutcomeAmount(2)
+    );
+
+    // only the level at 65 is within slippage of the best price, the rest of
+    // the quantity is dropped instead of waiting in the order book
+    let order_id = client1_pm
+        .new_order_with_type(
+            market,
+            0,
+            Side::Buy,
+            OrderType::Market {
+                max_slippage: Amount::from_msats(5),
+            },
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+    let order = client1_pm.get_order(order_id, false).await?.unwrap();
+    assert_eq!(order.price, Amount::from_msats(65));
+    assert_eq!(order.original_quantity, ContractOfOutcomeAmount(3));
+    assert_eq!(
+        order.quantity_waiting_for_match,
+        ContractOfOutcomeAmount::ZERO
+    );
+    assert_eq!(
+        order.contract_of_outcome_balance,
+        ContractOfOutcomeAmount(3)
+    );
+
+    let order_book = client1_pm.get_order_book(market, 1).await?;
+    assert_eq!(
+        order_book.buys,
+        BTreeMap::from([(Amount::from_msats(20), ContractOfOutcomeAmount(4))])
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn post_only_order() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // a buy on outcome 1 at 40 is matched by buys on outcome 0 at 60 or more
+    client2_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(5),
+            None,
+        )
+        .await?;
+
+    let err = client1_pm
+        .new_order_with_type(
+            market,
+            0,
+            Side::Buy,
+            OrderType::PostOnly {
+                price: Amount::from_msats(60),
+            },
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::WouldCrossBook)
+    );
+    assert!(client1_pm
+        .get_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+        .await
+        .is_empty());
+
+    let order_id = client1_pm
+        .new_order_with_type(
+            market,
+            0,
+            Side::Buy,
+            OrderType::PostOnly {
+                price: Amount::from_msats(59),
+            },
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+    let order = client1_pm.get_order(order_id, false).await?.unwrap();
+    assert_eq!(order.price, Amount::from_msats(59));
+    assert_eq!(order.quantity_waiting_for_match, ContractOfOutcomeAmount(1));
+    assert_eq!(order.quantity_fulfilled, ContractOfOutcomeAmount::ZERO);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pre_order_hook() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client = fed.new_client_rocksdb().await;
+
+    let client_dummy = client.get_first_module::<DummyClientModule>();
+    client_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client_pm = client.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    client_pm.set_pre_order_hook(Arc::new(|request: &OrderRequest| {
+        match request.quantity > ContractOfOutcomeAmount(10) {
+            true => Err(format!("quantity {} is above 10", request.quantity.0)),
+            false => Ok(()),
+        }
+    }));
+
+    let err = client_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(11),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::RejectedByHook(
+            "quantity 11 is above 10".to_owned()
+        ))
+    );
+    assert!(client_pm
+        .get_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+        .await
+        .is_empty());
+
+    let order_id = client_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+    assert_eq!(
+        client_pm
+            .get_order(order_id, false)
+            .await?
+            .unwrap()
+            .original_quantity,
+        ContractOfOutcomeAmount(10)
+    );
+
+    client_pm.clear_pre_order_hook();
+    client_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(11),
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_buy_order_is_reconciled() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let db: Database = MemDatabase::new().into();
+    let client1 = fed
+        .new_client_with(fed.client_config(), db.clone(), None)
+        .await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // the federation rejects buys at the contract price
+    let operation_id = OperationId::new_random();
+    client1_pm
+        .new_order_with_operation_id(
+            operation_id,
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(100),
+            ContractOfOutcomeAmount(5),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        client1_pm.get_operation_status(operation_id).await,
+        Some(PredictionMarketOperationStatus::Failed { .. })
+    ));
+
+    // the federation has no order for the rejected transaction, so nothing is
+    // flagged and nothing is left in the cache
+    assert!(client1_pm
+        .get_rejected_order_discrepancies()
+        .await
+        .is_empty());
+    assert!(client1_pm
+        .get_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+        .await
+        .is_empty());
+
+    // a device with the same root secret but without client1's database
+    // reuses the order id, so the federation already has an order for it
+    let order_id = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(5),
+            None,
+        )
+        .await?;
+    let device2 = new_client_sharing_secret(&fed, &db).await?;
+    device2
+        .get_first_module::<DummyClientModule>()
+        .print_money(Amount::from_sats(1000))
+        .await?;
+    let device2_pm = device2.get_first_module::<PredictionMarketsClientModule>();
+    let operation_id = OperationId::new_random();
+    device2_pm
+        .new_order_with_operation_id(
+            operation_id,
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(30),
+            ContractOfOutcomeAmount(3),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        device2_pm.get_operation_status(operation_id).await,
+        Some(PredictionMarketOperationStatus::Failed { .. })
+    ));
+
+    // the existing order is flagged and cached, so its funds can be recovered
+    let discrepancies = device2_pm.get_rejected_order_discrepancies().await;
+    assert_eq!(
+        discrepancies.keys().copied().collect::<Vec<_>>(),
+        [order_id]
+    );
+    let order = device2_pm.get_order(order_id, true).await?.unwrap();
+    assert_eq!(order.outcome, 0);
+    assert_eq!(order.quantity_waiting_for_match, ContractOfOutcomeAmount(5));
+
+    device2_pm
+        .dismiss_rejected_order_discrepancy(order_id)
+        .await?;
+    assert!(device2_pm
+        .get_rejected_order_discrepancies()
+        .await
+        .is_empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sell_from_source_spent_elsewhere() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let db: Database = MemDatabase::new().into();
+    let client1 = fed
+        .new_client_with(fed.client_config(), db.clone(), None)
+        .await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    client2_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(2),
+            None,
+        )
+        .await?;
+    let source = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(2),
+            None,
+        )
+        .await?;
+    let order = client1_pm.get_order(source, false).await?.unwrap();
+    assert_eq!(
+        order.contract_of_outcome_balance,
+        ContractOfOutcomeAmount(2)
+    );
+
+    // another device of the same user sells the contracts of outcome, leaving
+    // client1's cache of the source stale
+    let device2 = new_client_sharing_secret(&fed, &db).await?;
+    let device2_pm = device2.get_first_module::<PredictionMarketsClientModule>();
+    device2_pm.get_order(source, false).await?.unwrap();
+    let device2_sell = device2_pm
+        .new_order(
+            market,
+            0,
+            Side::Sell,
+            Amount::from_msats(70),
+            ContractOfOutcomeAmount(2),
+            None,
+        )
+        .await?;
+    // client1 learns about the id device2 used, but not about the spent source
+    client1_pm.get_order(device2_sell, false).await?.unwrap();
+    let order = client1_pm.get_order(source, true).await?.unwrap();
+    assert_eq!(
+        order.contract_of_outcome_balance,
+        ContractOfOutcomeAmount(2)
+    );
+
+    let err = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Sell,
+            Amount::from_msats(70),
+            ContractOfOutcomeAmount(2),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(PredictionMarketsClientError::StaleOrderSources)
+    ));
+
+    // the rejection synced the source
+    let order = client1_pm.get_order(source, true).await?.unwrap();
+    assert_eq!(
+        order.contract_of_outcome_balance,
+        ContractOfOutcomeAmount::ZERO
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn close_all_positions() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    client1
+        .get_first_module::<DummyClientModule>()
+        .print_money(Amount::from_sats(1000))
+        .await?;
+    client2
+        .get_first_module::<DummyClientModule>()
+        .print_money(Amount::from_sats(1000))
+        .await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let partially_filled = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(15),
+            None,
+        )
+        .await?;
+    let resting = client1_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(30),
+            ContractOfOutcomeAmount(5),
+            None,
+        )
+        .await?;
+    client2_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(45),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+
+    // the fill is only seen by syncing, which closing does first
+    let summary = client1_pm.close_all_positions().await?;
+    assert_eq!(summary.cancelled_orders, vec![partially_filled, resting]);
+    assert_eq!(summary.withdrawn, Amount::from_msats(60 * 5 + 30 * 5));
+
+    assert!(client1_pm
+        .get_orders_from_db(OrderFilter(
+            OrderPath::All,
+            OrderState::NonZeroQuantityWaitingForMatch
+        ))
+        .await
+        .is_empty());
+    assert!(client1_pm
+        .get_orders_from_db(OrderFilter(
+            OrderPath::All,
+            OrderState::NonZeroBitcoinBalance
+        ))
+        .await
+        .is_empty());
+    let order = client1_pm.get_order(partially_filled, true).await?.unwrap();
+    assert_eq!(
+        order.contract_of_outcome_balance,
+        ContractOfOutcomeAmount(10)
+    );
+
+    // nothing left to close
+    let summary = client1_pm.close_all_positions().await?;
+    assert!(summary.cancelled_orders.is_empty());
+    assert_eq!(summary.withdrawn, Amount::ZERO);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn order_ledger() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    client1
+        .get_first_module::<DummyClientModule>()
+        .print_money(Amount::from_sats(1000))
+        .await?;
+    client2
+        .get_first_module::<DummyClientModule>()
+        .print_money(Amount::from_sats(1000))
+        .await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let order_id = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(15),
+            None,
+        )
+        .await?;
+
+    // partially fill the order
+    client2_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(45),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+    client1_pm.get_order(order_id, false).await?;
+
+    // cancelling moves what is left into the bitcoin balance, which is then
+    // withdrawn
+    client1_pm.cancel_order(order_id).await?;
+    let withdrawn = client1_pm
+        .send_order_bitcoin_balance_to_primary_module(None)
+        .await?;
+
+    let ledger = client1_pm.get_order_ledger(order_id).await;
+    assert_eq!(
+        ledger.iter().map(|entry| entry.kind).collect::<Vec<_>>(),
+        vec![
+            LedgerEntryKind::Opened,
+            LedgerEntryKind::Fill,
+            LedgerEntryKind::Cancel,
+            LedgerEntryKind::Withdrawal,
+        ]
+    );
+
+    let opened = &ledger[0];
+    assert_eq!(opened.bitcoin_balance, Amount::ZERO);
+    assert_eq!(
+        opened.contract_of_outcome_balance,
+        ContractOfOutcomeAmount::ZERO
+    );
+
+    let fill = &ledger[1];
+    assert_eq!(fill.contract_of_outcome_balance_delta, 10);
+    assert_eq!(
+        fill.contract_of_outcome_balance,
+        ContractOfOutcomeAmount(10)
+    );
+
+    let cancel = &ledger[2];
+    assert_eq!(
+        cancel.bitcoin_balance,
+        fill.bitcoin_balance + Amount::from_msats(5 * 60)
+    );
+    assert_eq!(cancel.bitcoin_balance, withdrawn);
+
+    let withdrawal = &ledger[3];
+    assert_eq!(
+        withdrawal.bitcoin_balance_delta,
+        SignedAmount {
+            amount: cancel.bitcoin_balance,
+            negative: true,
+        }
+    );
+    assert_eq!(withdrawal.bitcoin_balance, Amount::ZERO);
+    assert_eq!(
+        withdrawal.contract_of_outcome_balance,
+        ContractOfOutcomeAmount(10)
+    );
+
+    // resyncing an unchanged order does not add entries
+    client1_pm.get_order(order_id, false).await?;
+    assert_eq!(client1_pm.get_order_ledger(order_id).await, ledger);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn quote_fill() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // buys on outcome 0 fill at 2 at 60, 3 at 65 and 4 at 80
+    for (price, quantity) in [(40, 2), (35, 3), (20, 4)] {
+        client2_pm
+            .new_order(
+                market,
+                1,
+                Side::Buy,
+                Amount::from_msats(price),
+                ContractOfOutcomeAmount(quantity),
+                None,
+            )
+            .await?;
+    }
+
+    for (requested, filled, total, average_price, worst_price, price_improvement, partial) in [
+        // exactly the first level
+        (2, 2, 120, 60, 60, 0, false),
+        // part of the second level
+        (3, 3, 185, 62, 65, 10, false),
+        // exactly the whole depth
+        (9, 9, 635, 71, 80, 85, false),
+        // more than the whole depth
+        (12, 9, 635, 71, 80, 85, true),
+    ] {
+        let quote = client1_pm
+            .quote_fill(market, 0, Side::Buy, ContractOfOutcomeAmount(requested))
+            .await?;
+        assert_eq!(
+            quote,
+            FillQuote {
+                quantity: ContractOfOutcomeAmount(filled),
+                total: Amount::from_msats(total),
+                average_price: Some(Amount::from_msats(average_price)),
+                worst_price: Some(Amount::from_msats(worst_price)),
+                price_improvement: Amount::from_msats(price_improvement),
+                partial,
+            },
+            "requested {requested}"
+        );
+    }
+
+    // nothing to sell into
+    let quote = client1_pm
+        .quote_fill(market, 0, Side::Sell, ContractOfOutcomeAmount(1))
+        .await?;
+    assert_eq!(
+        quote,
+        FillQuote {
+            quantity: ContractOfOutcomeAmount::ZERO,
+            total: Amount::ZERO,
+            average_price: None,
+            worst_price: None,
+            price_improvement: Amount::ZERO,
+            partial: true,
+        }
+    );
+
+    // quotes do not change the order book
+    let order_book = client1_pm.get_order_book(market, 1).await?;
+    assert_eq!(order_book.buys.len(), 3);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn market_order_captures_price_improvement() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // buys on outcome 0 fill at 2 at 60 and 3 at 65
+    for (price, quantity) in [(40, 2), (35, 3)] {
+        client2_pm
+            .new_order(
+                market,
+                1,
+                Side::Buy,
+                Amount::from_msats(price),
+                ContractOfOutcomeAmount(quantity),
+                None,
+            )
+            .await?;
+    }
+
+    let max_slippage = Amount::from_msats(5);
+    let quote = client1_pm
+        .quote_market_order(
+            market,
+            0,
+            Side::Buy,
+            ContractOfOutcomeAmount(4),
+            max_slippage,
+        )
+        .await?;
+    assert_eq!(
+        quote,
+        FillQuote {
+            quantity: ContractOfOutcomeAmount(4),
+            total: Amount::from_msats(250),
+            average_price: Some(Amount::from_msats(63)),
+            worst_price: Some(Amount::from_msats(65)),
+            price_improvement: Amount::from_msats(10),
+            partial: false,
+        }
+    );
+    assert!(quote.average_price < quote.worst_price);
+
+    // slippage past the contract price is clamped instead of overflowing
+    let unbounded = client1_pm
+        .quote_market_order(
+            market,
+            0,
+            Side::Buy,
+            ContractOfOutcomeAmount(4),
+            Amount::from_msats(u64::MAX),
+        )
+        .await?;
+    assert_eq!(unbounded, quote);
+
+    // the order is priced at the limit but pays the better level's price
+    let order_id = client1_pm
+        .new_order_with_type(
+            market,
+            0,
+            Side::Buy,
+            OrderType::Market { max_slippage },
+            ContractOfOutcomeAmount(4),
+            None,
+        )
+        .await?;
+    let order = client1_pm.get_order(order_id, false).await?.unwrap();
+    assert_eq!(order.price, Amount::from_msats(65));
+    assert_eq!(
+        order.contract_of_outcome_balance,
+        ContractOfOutcomeAmount(4)
+    );
+    assert_eq!(order.bitcoin_balance, quote.price_improvement);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn simulate_order_match() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // buys on outcome 0 match 2 at 60, 3 at 65 and 4 at 80
+    for (price, quantity) in [(40, 2), (35, 3), (20, 4)] {
+        client2_pm
+            .new_order(
+                market,
+                1,
+                Side::Buy,
+                Amount::from_msats(price),
+                ContractOfOutcomeAmount(quantity),
+                None,
+            )
+            .await?;
+    }
+
+    // fully rests below the best price
+    let simulation = client1_pm
+        .simulate_order_match(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(55),
+            ContractOfOutcomeAmount(3),
+        )
+        .await?;
+    assert_eq!(
+        simulation,
+        MatchSimulation {
+            matches: vec![],
+            matched_quantity: ContractOfOutcomeAmount::ZERO,
+            matched_total: Amount::ZERO,
+            resting_quantity: ContractOfOutcomeAmount(3),
+        }
+    );
+
+    // fully matches
+    let simulation = client1_pm
+        .simulate_order_match(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(65),
+            ContractOfOutcomeAmount(4),
+        )
+        .await?;
+    assert_eq!(
+        simulation,
+        MatchSimulation {
+            matches: vec![
+                (Amount::from_msats(60), ContractOfOutcomeAmount(2)),
+                (Amount::from_msats(65), ContractOfOutcomeAmount(2)),
+            ],
+            matched_quantity: ContractOfOutcomeAmount(4),
+            matched_total: Amount::from_msats(250),
+            resting_quantity: ContractOfOutcomeAmount::ZERO,
+        }
+    );
+
+    // partially matches, the level at 80 is above the price
+    let simulation = client1_pm
+        .simulate_order_match(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(65),
+            ContractOfOutcomeAmount(7),
+        )
+        .await?;
+    assert_eq!(
+        simulation,
+        MatchSimulation {
+            matches: vec![
+                (Amount::from_msats(60), ContractOfOutcomeAmount(2)),
+                (Amount::from_msats(65), ContractOfOutcomeAmount(3)),
+            ],
+            matched_quantity: ContractOfOutcomeAmount(5),
+            matched_total: Amount::from_msats(315),
+            resting_quantity: ContractOfOutcomeAmount(2),
+        }
+    );
+
+    // the federation agrees while the order books are unchanged
+    let order_id = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(65),
+            ContractOfOutcomeAmount(7),
+            None,
+        )
+        .await?;
+    let order = client1_pm.get_order(order_id, false).await?.unwrap();
+    assert_eq!(order.quantity_fulfilled, simulation.matched_quantity);
+    assert_eq!(
+        order.quantity_waiting_for_match,
+        simulation.resting_quantity
+    );
+    assert_eq!(
+        order.bitcoin_acquired_from_order_matches,
+        SignedAmount::ZERO - SignedAmount::from(simulation.matched_total)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn snapshot_does_not_see_later_writes() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    client1_pm.get_market(market, false).await?;
+
+    let mut snapshot = client1_pm.snapshot().await;
+    assert!(snapshot.get_market(market).await.is_some());
+
+    let order_id = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+
+    let filter = OrderFilter(OrderPath::Market { market }, OrderState::Any);
+    assert!(snapshot.get_order(order_id).await.is_none());
+    assert!(snapshot.get_orders(filter).await.is_empty());
+    drop(snapshot);
+
+    let mut snapshot = client1_pm.snapshot().await;
+    assert_eq!(
+        snapshot.get_orders(filter).await,
+        client1_pm.get_orders_from_db(filter).await
+    );
+    assert!(snapshot.get_order(order_id).await.is_some());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn order_tags() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let mut order_ids = vec![];
+    for _ in 0..3 {
+        let order_id = client1_pm
+            .new_order(
+                market,
+                0,
+                Side::Buy,
+                Amount::from_msats(60),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await?;
+        order_ids.push(order_id);
+    }
+
+    let err = client1_pm
+        .set_order_tag(OrderId(u64::MAX), "manual".to_owned())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::OrderDoesNotExist)
+    );
+
+    client1_pm
+        .set_order_tag(order_ids[0], "mm-strat-1".to_owned())
+        .await?;
+    client1_pm
+        .set_order_tag(order_ids[1], "mm-strat-1".to_owned())
+        .await?;
+    assert_eq!(
+        client1_pm
+            .get_orders_by_tag("mm-strat-1")
+            .await
+            .into_keys()
+            .collect::<Vec<_>>(),
+        vec![order_ids[0], order_ids[1]]
+    );
+    assert!(client1_pm.get_orders_by_tag("manual").await.is_empty());
+
+    // retagging replaces the previous tag
+    client1_pm
+        .set_order_tag(order_ids[1], "manual".to_owned())
+        .await?;
+    assert_eq!(
+        client1_pm
+            .get_orders_by_tag("mm-strat-1")
+            .await
+            .into_keys()
+            .collect::<Vec<_>>(),
+        vec![order_ids[0]]
+    );
+    assert_eq!(
+        client1_pm
+            .get_orders_by_tag("manual")
+            .await
+            .into_keys()
+            .collect::<Vec<_>>(),
+        vec![order_ids[1]]
+    );
+
+    let tagged = client1_pm
+        .get_tagged_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+        .await;
+    assert_eq!(tagged[&order_ids[0]].tag.as_deref(), Some("mm-strat-1"));
+    assert_eq!(tagged[&order_ids[1]].tag.as_deref(), Some("manual"));
+    assert_eq!(tagged[&order_ids[2]].tag, None);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn orders_matching_combined_filters() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let new_market = || async {
+        client1_pm
+            .new_market(
+                Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+                Amount::from_msats(100),
+                iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+                1,
+                None,
+            )
+            .await
+    };
+    let market1 = new_market().await?;
+    let market2 = new_market().await?;
+
+    // prices do not add up to the contract price, so nothing matches
+    let mut order_ids = vec![];
+    for (market, outcome, price) in [
+        (market1, 0, 60),
+        (market1, 1, 30),
+        (market1, 1, 30),
+        (market2, 1, 30),
+    ] {
+        let order_id = client1_pm
+            .new_order(
+                market,
+                outcome,
+                Side::Buy,
+                Amount::from_msats(price),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await?;
+        order_ids.push(order_id);
+    }
+    for order_id in [order_ids[2], order_ids[3]] {
+        client1_pm.set_order_tag(order_id, "mm".to_owned()).await?;
+    }
+
+    let matching = |query: OrderQuery| {
+        let client1_pm = &client1_pm;
+        async move {
+            client1_pm
+                .get_orders_matching(query)
+                .await
+                .into_keys()
+                .collect::<Vec<_>>()
+        }
+    };
+
+    // open orders in market 1 on outcome 1 tagged mm
+    let query = OrderFilter::and(
+        OrderFilter(
+            OrderPath::MarketOutcome {
+                market: market1,
+                outcome: 1,
+            },
+            OrderState::NonZeroQuantityWaitingForMatch,
+        ),
+        OrderQuery::Tag("mm".to_owned()),
+    );
+    assert_eq!(matching(query).await, vec![order_ids[2]]);
+
+    let query = OrderFilter::or(
+        OrderQuery::Tag("mm".to_owned()),
+        OrderPath::Market { market: market1 },
+    );
+    assert_eq!(matching(query).await, order_ids);
+
+    let query = OrderFilter::and(
+        OrderPath::Market { market: market2 },
+        OrderQuery::CreatedSince(UnixTimestamp::ZERO),
+    );
+    assert_eq!(matching(query).await, vec![order_ids[3]]);
+
+    // not narrowed down by an index, every order is checked
+    assert!(matching(OrderQuery::CreatedBefore(UnixTimestamp::ZERO))
+        .await
+        .is_empty());
+    let query = OrderFilter::or(
+        OrderQuery::Tag("mm".to_owned()),
+        OrderQuery::CreatedSince(UnixTimestamp::ZERO),
+    );
+    assert_eq!(matching(query).await, order_ids);
+
+    // plain filters work as before
+    let filter = OrderFilter(
+        OrderPath::MarketOutcome {
+            market: market1,
+            outcome: 1,
+        },
+        OrderState::Any,
+    );
+    assert_eq!(
+        client1_pm.get_orders_matching(filter).await,
+        client1_pm.get_orders_from_db(filter).await
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn subscribe_all_order_updates() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let mut updates = client1_pm.subscribe_all_order_updates().await;
+
+    let mut order_ids = vec![];
+    for _ in 0..2 {
+        let order_id = client1_pm
+            .new_order(
+                market,
+                0,
+                Side::Buy,
+                Amount::from_msats(60),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await?;
+        order_ids.push(order_id);
+    }
+
+    let mut latest = BTreeMap::new();
+    tokio::time::timeout(Duration::from_secs(60), async {
+        while latest.len() < 2 {
+            let (order_id, order) = updates.next().await.unwrap();
+            latest.insert(order_id, order);
+        }
+    })
+    .await?;
+    assert_eq!(latest.keys().copied().collect::<Vec<_>>(), order_ids);
+
+    // matches both orders, which is only seen by client 1 through the
+    // periodic sync
+    client2_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(2),
+            None,
+        )
+        .await?;
+
+    tokio::time::timeout(Duration::from_secs(60), async {
+        while latest
+            .values()
+            .any(|order| order.quantity_waiting_for_match != ContractOfOutcomeAmount::ZERO)
+        {
+            let (order_id, order) = updates.next().await.unwrap();
+            assert!(order_ids.contains(&order_id));
+            latest.insert(order_id, order);
+        }
+    })
+    .await?;
+    for order in latest.values() {
+        assert_eq!(
+            order.contract_of_outcome_balance,
+            ContractOfOutcomeAmount(1)
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn background_sync() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    let order_id = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+
+    let filter = OrderFilter(OrderPath::Market { market }, OrderState::Any);
+    let err = client1_pm
+        .start_background_sync(Duration::ZERO, filter)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::ZeroSyncInterval)
+    );
+
+    let interval = Duration::from_secs(2);
+    client1_pm.start_background_sync(interval, filter).await?;
+
+    // matched by client 2, client 1 only sees it through the background sync
+    client2_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+
+    // the next sync starts within one interval, the margin is for the sync
+    // itself
+    let order = tokio::time::timeout(interval + Duration::from_millis(500), async {
+        loop {
+            let order = client1_pm.get_order(order_id, true).await?.unwrap();
+            if order.quantity_waiting_for_match == ContractOfOutcomeAmount::ZERO {
+                return anyhow::Ok(order);
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await??;
+    assert_eq!(
+        order.contract_of_outcome_balance,
+        ContractOfOutcomeAmount(1)
+    );
+
+    client1_pm.stop_background_sync().await?;
+    // stopping again is a no-op
+    client1_pm.stop_background_sync().await?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reserve_order_ids() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let err = client1_pm
+        .reserve_order_ids(MAX_RESERVED_ORDER_IDS + 1)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::TooManyOrderIdsReserved {
+            count: MAX_RESERVED_ORDER_IDS + 1,
+            max: MAX_RESERVED_ORDER_IDS,
+        })
+    );
+
+    let reserved = client1_pm.reserve_order_ids(3).await?;
+    assert_eq!(reserved.len(), 3);
+    assert_eq!(reserved[1].0, reserved[0].0 + 1);
+    assert_eq!(reserved[2].0, reserved[0].0 + 2);
+
+    // other order creation skips reserved ids
+    let order_id = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+    assert!(order_id > reserved[2]);
+
+    client1_pm
+        .new_order_with_id(
+            reserved[1],
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+    assert!(client1_pm.get_order(reserved[1], true).await?.is_some());
+
+    // a reserved id can only be used once
+    let err = client1_pm
+        .new_order_with_id(
+            reserved[1],
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::OrderIdNotReserved)
+    );
+
+    client1_pm.release_order_id(reserved[2]).await?;
+    for order_id in [reserved[2], reserved[1]] {
+        let err = client1_pm.release_order_id(order_id).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PredictionMarketsClientError>(),
+            Some(&PredictionMarketsClientError::OrderIdNotReserved)
+        );
+    }
+    assert!(client1_pm.get_order(reserved[2], true).await?.is_none());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn submit_multi_market_is_atomic() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let mut markets = Vec::new();
+    for _ in 0..2 {
+        let market = client1_pm
+            .new_market(
+                Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+                Amount::from_msats(100),
+                iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+                1,
+                None,
+            )
+            .await?;
+        markets.push(market);
+    }
+
+    let buy_leg = OrderRequest {
+        market: markets[0],
+        outcome: 0,
+        side: Side::Buy,
+        price: Amount::from_msats(60),
+        quantity: ContractOfOutcomeAmount(2),
+    };
+
+    // the client has no contracts of outcome to sell, so the whole
+    // transaction fails and both reserved ids are released
+    client1_pm
+        .submit_multi_market(
+            vec![
+                buy_leg.clone(),
+                OrderRequest {
+                    market: markets[1],
+                    outcome: 1,
+                    side: Side::Sell,
+                    price: Amount::from_msats(40),
+                    quantity: ContractOfOutcomeAmount(2),
+                },
+            ],
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert!(client1_pm
+        .get_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+        .await
+        .is_empty());
+    assert_eq!(client1_pm.reserve_order_ids(1).await?, vec![OrderId(0)]);
+    client1_pm.release_order_id(OrderId(0)).await?;
+
+    let order_ids = client1_pm
+        .submit_multi_market(
+            vec![
+                buy_leg,
+                OrderRequest {
+                    market: markets[1],
+                    outcome: 1,
+                    side: Side::Buy,
+                    price: Amount::from_msats(40),
+                    quantity: ContractOfOutcomeAmount(2),
+                },
+            ],
+            None,
+        )
+        .await?;
+    assert_eq!(order_ids, vec![OrderId(0), OrderId(1)]);
+
+    for (order_id, market) in order_ids.into_iter().zip(markets) {
+        let order = client1_pm.get_order(order_id, true).await?.unwrap();
+        assert_eq!(order.market, market);
+        assert_eq!(order.quantity_waiting_for_match, ContractOfOutcomeAmount(2));
+    }
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn submit_multi_market_rejected_by_federation() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let payout_controls = [Keys::generate()];
+    let (paid_out_market, event) =
+        new_market_with_payout_controls(&client1_pm, &payout_controls).await?;
+    let (trading_market, _) =
+        new_market_with_payout_controls(&client1_pm, &payout_controls).await?;
+
+    // client2 still has the market cached as trading, so only the federation
+    // knows the leg in it is invalid
+    client2_pm.get_market(paid_out_market, false).await?;
+    payout_market_with(
+        &client1_pm,
+        paid_out_market,
+        &event,
+        &payout_controls,
+        &EventPayout {
+            units_per_outcome: vec![1, 0],
+        },
+    )
+    .await?;
+
+    client2_pm
+        .submit_multi_market(
+            [trading_market, paid_out_market]
+                .into_iter()
+                .map(|market| OrderRequest {
+                    market,
+                    outcome: 0,
+                    side: Side::Buy,
+                    price: Amount::from_msats(60),
+                    quantity: ContractOfOutcomeAmount(2),
+                })
+                .collect(),
+            None,
+        )
+        .await
+        .unwrap_err();
+
+    // the leg in the trading market was rejected with the other one
+    for order_id in [OrderId(0), OrderId(1)] {
+        assert!(client2_pm.get_order(order_id, false).await?.is_none());
+    }
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_market_event() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let event = Event::new_with_random_nonce(3, 1, Information::None);
+    let market = client1_pm
+        .new_market(
+            event.try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let parsed = client2_pm.get_market_event(market).await?;
+    assert_eq!(parsed.outcome_count, 3);
+    assert_eq!(parsed.hash_hex()?.0, event.hash_hex()?.0);
+    // served from the in memory cache
+    assert!(Arc::ptr_eq(
+        &parsed,
+        &client2_pm.get_market_event(market).await?
+    ));
+
+    let err = client2_pm
+        .get_market_event(market_outpoint_from_tx_id(TransactionId::from_str(
+            &"0".repeat(64),
+        )?))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::MarketDoesNotExist)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_outcome_title_out_of_range() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // no outcome titles in the event information
+    assert_eq!(client1_pm.get_outcome_title(market, 0).await?, None);
+    // served from the in memory cache
+    assert_eq!(client1_pm.get_outcome_title(market, 0).await?, None);
+    assert_eq!(client1_pm.get_outcome_title(market, 2).await?, None);
+    assert_eq!(client1_pm.get_outcome_title(market, 255).await?, None);
+
+    let err = client1_pm
+        .new_order_by_outcome_title(
+            market,
+            "Team A wins",
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::UnknownOutcomeTitle(
+            "Team A wins".to_owned()
+        ))
+    );
+
+    let err = client1_pm
+        .get_outcome_title(
+            market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64))?),
+            0,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::MarketDoesNotExist)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn order_stream() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let event_json = Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?;
+    let contract_price = Amount::from_msats(100);
+    let payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight> =
+        iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect();
+    let weight_required_for_payout = 1;
+    let market = client1_pm
+        .new_market(
+            event_json.clone(),
+            contract_price,
+            payout_control_weight_map.clone(),
+            weight_required_for_payout,
+            None,
+        )
+        .await?;
+
+    let watch_matches_id = client1_pm
+        .start_watch_matches(OrderPath::Market { market })
+        .await?;
+
+    client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1000),
+            None,
+        )
+        .await?;
+
+    // for _ in 0..10 {
+    //     client1_pm
+    //         .new_order(
+    //             market,
+    //             1,
+    //             Side::Buy,
+    //             Amount::from_msats(40),
+    //             ContractOfOutcomeAmount(1),
+    //         )
+    //         .await?;
+    // }
+
+    let client1_s = client1.clone();
+    spawn(async move {
+        let client1_pm = client1_s.get_first_module::<PredictionMarketsClientModule>();
+        let mut stream_of_order_ids = client1_pm
+            .stream_order_ids(OrderFilter(OrderPath::All, OrderState::Any))
+            .await;
+
+        loop {
+            let order_id = stream_of_order_ids.next_or_pending().await;
+            let mut order_stream = client1_pm.stream_order_from_db(order_id).await;
+
+            spawn(async move {
+                loop {
+                    let order = order_stream.next_or_pending().await;
+                    info!(
+                        "{}: {}",
+                        order_id.0,
+                        order.unwrap().quantity_waiting_for_match.0
+                    );
+                }
+            });
+        }
+    });
+
+    let iter = 0..10;
+    iter.map(|_| async {
+        let res = client1_pm
+            .new_order(
+                market,
+                1,
+                Side::Buy,
+                Amount::from_msats(40),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await;
+
+        if let Err(e) = res {
+            info!("error creating order: {e}");
+        }
+    })
+    .collect::<FuturesUnordered<_>>()
+    .collect::<()>()
+    .await;
+    client1_pm.stop_watch_matches(watch_matches_id).await?;
+
+    info!("test_main end");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn general1() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    client1
+        .get_first_module::<DummyClientModule>()
+        .print_money(Amount::from_sats(1000))
+        .await?;
+    client2
+        .get_first_module::<DummyClientModule>()
+        .print_money(Amount::from_sats(1000))
+        .await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let event_json = Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?;
+    let contract_price = Amount::from_msats(100);
+    let payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight> =
+        iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect();
+    let weight_required_for_payout = 1;
+    let market_outpoint = client1_pm
+        .new_market(
+            event_json.clone(),
+            contract_price,
+            payout_control_weight_map.clone(),
+            weight_required_for_payout,
+            None,
+        )
+        .await?;
+
+    // orderbook setup
+
+    let client1_order0 = client1_pm
+        .new_order(
+            market_outpoint,
+            0,
+            Side::Buy,
+            Amount::from_msats(10),
+            ContractOfOutcomeAmount(30),
+            None,
+        )
+        .await?;
+
+    let client1_order1 = client1_pm
+        .new_order(
+            market_outpoint,
+            0,
+            Side::Buy,
+            Amount::from_msats(50),
+            ContractOfOutcomeAmount(15),
+            None,
+        )
+        .await?;
+
+    let client1_order2 = client1_pm
+        .new_order(
+            market_outpoint,
+            0,
+            Side::Buy,
+            Amount::from_msats(30),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+
+    let client2_order0 = client2_pm
+        .new_order(
+            market_outpoint,
+            1,
+            Side::Buy,
+            Amount::from_msats(15),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+
+    let client2_order1 = client2_pm
+        .new_order(
+            market_outpoint,
+            1,
+            Side::Buy,
+            Amount::from_msats(25),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+
+    let client2_order2 = client2_pm
+        .new_order(
+            market_outpoint,
+            1,
+            Side::Buy,
+            Amount::from_msats(45),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+
+    // orderbook state
+    //
+    // outcome 0
+    // 10 - 30
+    // 30 - 10
+    // 50 - 15
+    //
+    // outcome 1
+    // 15 - 10
+    // 25 - 10
+    // 45 - 10
+    dbg!(client1_pm.get_order_book(market_outpoint, 0).await?);
+    dbg!(client1_pm.get_order_book(market_outpoint, 1).await?);
+
+    let client1_order3 = client1_pm
+        .new_order(
+            market_outpoint,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(15),
+            None,
+        )
+        .await?;
+
+    assert_order_mutated_values(
+        &client1_pm,
+        client1_order3,
+        true,
+        AssertOrderMutatedValues {
+            quantity_waiting_for_match: ContractOfOutcomeAmount(5),
+            contract_of_outcome_balance: ContractOfOutcomeAmount(10),
+            bitcoin_balance: Amount::from_msats(50),
+            quantity_fulfilled: ContractOfOutcomeAmount(10),
+            bitcoin_acquired_from_order_matches: SignedAmount {
+                amount: Amount::from_msats(550),
+                negative: true,
+            },
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        },
+    )
+    .await;
+    assert_order_mutated_values(
+        &client2_pm,
+        client2_order2,
+        true,
+        AssertOrderMutatedValues {
+            quantity_waiting_for_match: ContractOfOutcomeAmount(10),
+            contract_of_outcome_balance: ContractOfOutcomeAmount(0),
+            bitcoin_balance: Amount::from_msats(0),
+            quantity_fulfilled: ContractOfOutcomeAmount(0),
+            bitcoin_acquired_from_order_matches: SignedAmount::ZERO,
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        },
+    )
+    .await;
+    assert_order_mutated_values(
+        &client2_pm,
+        client2_order2,
+        false,
+        AssertOrderMutatedValues {
+            quantity_waiting_for_match: ContractOfOutcomeAmount(0),
+            contract_of_outcome_balance: ContractOfOutcomeAmount(10),
+            bitcoin_balance: Amount::from_msats(0),
+            quantity_fulfilled: ContractOfOutcomeAmount(10),
+            bitcoin_acquired_from_order_matches: SignedAmount {
+                amount: Amount::from_msats(450),
+                negative: true,
+            },
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        },
+    )
+    .await;
+
+    // orderbook state
+    //
+    // outcome 0
+    // 10 - 30
+    // 30 - 10
+    // 50 - 15
+    // 60 - 5
+    //
+    // outcome 1
+    // 15 - 10
+    // 25 - 10
+    dbg!(client1_pm.get_order_book(market_outpoint, 0).await?);
+    dbg!(client1_pm.get_order_book(market_outpoint, 1).await?);
+
+    let client1_order4 = client1_pm
+        .new_order(
+            market_outpoint,
+            0,
+            Side::Buy,
+            Amount::from_msats(80),
+            ContractOfOutcomeAmount(5),
+            None,
+        )
+        .await?;
+    assert_order_mutated_values(
+        &client1_pm,
+        client1_order4,
+        true,
+        AssertOrderMutatedValues {
+            quantity_waiting_for_match: ContractOfOutcomeAmount(0),
+            contract_of_outcome_balance: ContractOfOutcomeAmount(5),
+            bitcoin_balance: Amount::from_msats(25),
+            quantity_fulfilled: ContractOfOutcomeAmount(5),
+            bitcoin_acquired_from_order_matches: SignedAmount {
+                amount: Amount::from_msats(375),
+                negative: true,
+            },
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        },
+    )
+    .await;
+    assert_order_mutated_values(
+        &client2_pm,
+        client2_order1,
+        false,
+        AssertOrderMutatedValues {
+            quantity_waiting_for_match: ContractOfOutcomeAmount(5),
+            contract_of_outcome_balance: ContractOfOutcomeAmount(5),
+            bitcoin_balance: Amount::from_msats(0),
+            quantity_fulfilled: ContractOfOutcomeAmount(5),
+            bitcoin_acquired_from_order_matches: SignedAmount {
+                amount: Amount::from_msats(125),
+                negative: true,
+            },
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        },
+    )
+    .await;
+
+    // orderbook state
+    //
+    // outcome 0
+    // 10 - 30
+    // 30 - 10
+    // 50 - 15
+    // 60 - 5
+    //
+    // outcome 1
+    // 15 - 10
+    // 25 - 5
+    dbg!(client1_pm.get_order_book(market_outpoint, 0).await?);
+    dbg!(client1_pm.get_order_book(market_outpoint, 1).await?);
+
+    let client2_order3 = client2_pm
+        .new_order(
+            market_outpoint,
+            1,
+            Side::Buy,
+            Amount::from_msats(80),
+            ContractOfOutcomeAmount(35),
+            None,
+        )
+        .await?;
+    assert_order_mutated_values(
+        &client2_pm,
+        client2_order3,
+        true,
+        AssertOrderMutatedValues {
+            quantity_waiting_for_match: ContractOfOutcomeAmount(5),
+            contract_of_outcome_balance: ContractOfOutcomeAmount(30),
+            bitcoin_balance: Amount::from_msats(40 * 5 + 30 * 15 + 10 * 10),
+            quantity_fulfilled: ContractOfOutcomeAmount(30),
+            bitcoin_acquired_from_order_matches: SignedAmount {
+                amount: Amount::from_msats(40 * 5 + 50 * 15 + 70 * 10),
+                negative: true,
+            },
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        },
+    )
+    .await;
+    assert_order_mutated_values(
+        &client1_pm,
+        client1_order3,
+        false,
+        AssertOrderMutatedValues {
+            quantity_waiting_for_match: ContractOfOutcomeAmount(0),
+            contract_of_outcome_balance: ContractOfOutcomeAmount(15),
+            bitcoin_balance: Amount::from_msats(50),
+            quantity_fulfilled: ContractOfOutcomeAmount(15),
+            bitcoin_acquired_from_order_matches: SignedAmount {
+                amount: Amount::from_msats(550 + 300),
+                negative: true,
+            },
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        },
+    )
+    .await;
+    assert_order_mutated_values(
+        &client1_pm,
+        client1_order1,
+        false,
+        AssertOrderMutatedValues {
+            quantity_waiting_for_match: ContractOfOutcomeAmount(0),
+            contract_of_outcome_balance: ContractOfOutcomeAmount(15),
+            bitcoin_balance: Amount::from_msats(0),
+            quantity_fulfilled: ContractOfOutcomeAmount(15),
+            bitcoin_acquired_from_order_matches: SignedAmount {
+                amount: Amount::from_msats(750),
+                negative: true,
+            },
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        },
+    )
+    .await;
+    assert_order_mutated_values(
+        &client1_pm,
+        client1_order2,
+        false,
+        AssertOrderMutatedValues {
+            quantity_waiting_for_match: ContractOfOutcomeAmount(0),
+            contract_of_outcome_balance: ContractOfOutcomeAmount(10),
+            bitcoin_balance: Amount::from_msats(0),
+            quantity_fulfilled: ContractOfOutcomeAmount(10),
+            bitcoin_acquired_from_order_matches: SignedAmount {
+                amount: Amount::from_msats(300),
+                negative: true,
+            },
+            bitcoin_acquired_from_payout: Amount::ZERO,
+        },
+    )
+    .await;
+
+    // orderbook state
+    //
+    // outcome 0
+    // 10 - 30
+    //
+    // outcome 1
+    // 15 - 10
+    // 25 - 5
+    // 80 - 5
+    dbg!(client1_pm.get_order_book(market_outpoint, 0).await?);
+    dbg!(client1_pm.get_order_book(market_outpoint, 1).await?);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn order_book() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(10000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let event_json = Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?;
+    let contract_price = Amount::from_msats(10000);
+    let payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight> =
+        iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect();
+    let weight_required_for_payout = 1;
+    let market = client1_pm
+        .new_market(
+            event_json.clone(),
+            contract_price,
+            payout_control_weight_map.clone(),
+            weight_required_for_payout,
+            None,
+        )
+        .await?;
+
+    let iter = 1u64..501;
+    iter.map(|msat| {
+        let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+        async move {
+            let res = client1_pm
+                .new_order(
+                    market,
+                    0,
+                    Side::Buy,
+                    Amount::from_msats(msat),
+                    ContractOfOutcomeAmount(1),
+                    None,
+                )
+                .await;
+
+            if let Err(e) = res {
+                info!("error creating order: {e}");
+            }
+        }
+    })
+    .collect::<FuturesUnordered<_>>()
+    .collect::<()>()
+    .await;
+
+    dbg!(client1_pm.get_order_book(market, 0).await?);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancel_all_orders_in_market() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let contract_price = Amount::from_msats(100);
+    let payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight> =
+        iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect();
+    let weight_required_for_payout = 1;
+    let market1 = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            contract_price,
+            payout_control_weight_map.clone(),
+            weight_required_for_payout,
+            None,
+        )
+        .await?;
+    let market2 = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            contract_price,
+            payout_control_weight_map.clone(),
+            weight_required_for_payout,
+            None,
+        )
+        .await?;
+
+    let market1_order0 = client1_pm
+        .new_order(
+            market1,
+            0,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+    let market1_order1 = client1_pm
+        .new_order(
+            market1,
+            1,
+            Side::Buy,
+            Amount::from_msats(30),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+    let market2_order0 = client1_pm
+        .new_order(
+            market2,
+            0,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+
+    let cancelled = client1_pm
+        .cancel_all_orders(OrderFilter(
+            OrderPath::Market { market: market1 },
+            OrderState::Any,
+        ))
+        .await?;
+    assert_eq!(cancelled.len(), 2);
+    assert!(cancelled.contains(&market1_order0));
+    assert!(cancelled.contains(&market1_order1));
+
+    for order_id in [market1_order0, market1_order1] {
+        let order = client1_pm.get_order(order_id, false).await?.unwrap();
+        assert_eq!(
+            order.quantity_waiting_for_match,
+            ContractOfOutcomeAmount::ZERO
+        );
+    }
+    let order = client1_pm.get_order(market2_order0, false).await?.unwrap();
+    assert_eq!(
+        order.quantity_waiting_for_match,
+        ContractOfOutcomeAmount(10)
+    );
+
+    // fully matched after client1 last synced it, so it is skipped instead of
+    // failing the cancellation
+    client2_pm
+        .new_order(
+            market2,
+            1,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+    let cancelled = client1_pm
+        .cancel_all_orders(OrderFilter(
+            OrderPath::Market { market: market2 },
+            OrderState::Any,
+        ))
+        .await?;
+    assert!(cancelled.is_empty());
+    let order = client1_pm.get_order(market2_order0, true).await?.unwrap();
+    assert_eq!(
+        order.contract_of_outcome_balance,
+        ContractOfOutcomeAmount(10)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn expired_dead_mans_switch_cancels_orders_on_next_start() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let db: Database = MemDatabase::new().into();
+    let client1 = fed
+        .new_client_with(fed.client_config(), db.clone(), None)
+        .await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let new_market = || async {
+        client1_pm
+            .new_market(
+                Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+                Amount::from_msats(100),
+                iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+                1,
+                None,
+            )
+            .await
+    };
+    let market1 = new_market().await?;
+    let market2 = new_market().await?;
+
+    let mut order_ids = Vec::new();
+    for market in [market1, market2] {
+        let order_id = client1_pm
+            .new_order(
+                market,
+                0,
+                Side::Buy,
+                Amount::from_msats(40),
+                ContractOfOutcomeAmount(10),
+                None,
+            )
+            .await?;
+        order_ids.push(order_id);
+    }
+
+    client1_pm
+        .register_dead_mans_switch(
+            Duration::from_secs(1),
+            OrderFilter(OrderPath::Market { market: market1 }, OrderState::Any),
+        )
+        .await?;
+
+    // the client goes away without clearing the switch and misses the
+    // heartbeat
+    drop(client1_pm);
+    drop(client1_dummy);
+    drop(client1);
+    sleep(Duration::from_secs(3)).await;
+
+    let client1 = fed.new_client_with(fed.client_config(), db, None).await;
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    tokio::time::timeout(Duration::from_secs(60), async {
+        while client1_pm.get_dead_mans_switch().await.is_some() {
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await?;
+
+    let order = client1_pm.get_order(order_ids[0], false).await?.unwrap();
+    assert_eq!(
+        order.quantity_waiting_for_match,
+        ContractOfOutcomeAmount::ZERO
+    );
+    let order = client1_pm.get_order(order_ids[1], false).await?.unwrap();
+    assert_eq!(
+        order.quantity_waiting_for_match,
+        ContractOfOutcomeAmount(10)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn payout_while_offline_is_pending_settlement_on_next_start() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let db: Database = MemDatabase::new().into();
+    let client1 = fed
+        .new_client_with(fed.client_config(), db.clone(), None)
+        .await;
+    let client2 = fed.new_client().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let payout_controls = [Keys::generate()];
+    let (market_won, event_won) =
+        new_market_with_payout_controls(&client1_pm, &payout_controls).await?;
+    let (market_lost, event_lost) =
+        new_market_with_payout_controls(&client1_pm, &payout_controls).await?;
+
+    // client1 holds the outcome that pays out in one market and the outcome
+    // that pays nothing in the other
+    for (market, outcome) in [(market_won, 0), (market_lost, 1)] {
+        client1_pm
+            .new_order(
+                market,
+                outcome,
+                Side::Buy,
+                Amount::from_msats(50),
+                ContractOfOutcomeAmount(2),
+                None,
+            )
+            .await?;
+        client2_pm
+            .new_order(
+                market,
+                1 - outcome,
+                Side::Buy,
+                Amount::from_msats(50),
+                ContractOfOutcomeAmount(2),
+                None,
+            )
+            .await?;
+        client1_pm.get_market(market, false).await?;
+    }
+    client1_pm.sync_matches(OrderPath::All, false).await?;
+    assert!(client1_pm.get_pending_settlements().await.is_empty());
+
+    // both markets pay out while client1 is not running
+    drop(client1_pm);
+    drop(client1_dummy);
+    drop(client1);
+    for (market, event) in [(market_won, &event_won), (market_lost, &event_lost)] {
+        payout_market_with(
+            &client2_pm,
+            market,
+            event,
+            &payout_controls,
+            &EventPayout {
+                units_per_outcome: vec![1, 0],
+            },
+        )
+        .await?;
+    }
+
+    let client1 = fed.new_client_with(fed.client_config(), db, None).await;
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    // the check runs in the background and caches each payout it sees
+    tokio::time::timeout(Duration::from_secs(60), async {
+        for market in [market_won, market_lost] {
+            while !client1_pm
+                .get_market(market, true)
+                .await?
+                .is_some_and(|Market(_, market_dynamic)| market_dynamic.payout.is_some())
+            {
+                sleep(Duration::from_millis(100)).await;
+            }
+        }
+        anyhow::Ok(())
+    })
+    .await??;
+
+    assert_eq!(
+        client1_pm
+            .get_pending_settlements()
+            .await
+            .into_keys()
+            .collect::<Vec<_>>(),
+        vec![market_won]
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_matches_skips_unchanged_orders() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let payout_controls = [Keys::generate()];
+    let (market, event) = new_market_with_payout_controls(&client1_pm, &payout_controls).await?;
+    client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+
+    let order_path = OrderPath::Market { market };
+    assert_eq!(client1_pm.sync_matches(order_path, false).await?, 1);
+    assert_eq!(client1_pm.sync_matches(order_path, false).await?, 0);
+    assert_eq!(client1_pm.sync_matches(order_path, true).await?, 1);
+
+    // a payout changes no candlestick but is picked up anyway
+    payout_market_with(
+        &client1_pm,
+        market,
+        &event,
+        &payout_controls,
+        &EventPayout {
+            units_per_outcome: vec![1, 0],
+        },
+    )
+    .await?;
+    assert_eq!(client1_pm.sync_matches(order_path, false).await?, 1);
+    assert_eq!(client1_pm.sync_matches(order_path, false).await?, 0);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn needs_sync() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    assert!(!client1_pm.needs_sync().await);
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // unused reservations do not need a sync
+    let order_id = client1_pm.reserve_order_ids(1).await?[0];
+    assert!(!client1_pm.needs_sync().await);
+
+    // the resting order can be matched at any time
+    client1_pm
+        .new_order_with_id(
+            order_id,
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(10),
+            None,
+        )
+        .await?;
+    assert!(client1_pm.needs_sync().await);
+
+    // cancelling syncs the order, which has nothing waiting for match anymore
+    client1_pm.cancel_order(order_id).await?;
+    assert!(!client1_pm.needs_sync().await);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancel_order_is_idempotent() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let before = UnixTimestamp::now();
+    let matched_order_id = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+    let resting_order_id = client1_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(2),
+            None,
+        )
+        .await?;
+
+    // the cache still has the matched order waiting for match, so it is
+    // refreshed from the federation and nothing is submitted
+    client1_pm.cancel_order(matched_order_id).await?;
+    let order = client1_pm.get_order(matched_order_id, true).await?.unwrap();
+    assert_eq!(order.quantity_waiting_for_match, ContractOfOutcomeAmount(0));
+
+    // cancelled once, the second call is answered from the cache
+    client1_pm.cancel_order(resting_order_id).await?;
+    client1_pm.cancel_order(resting_order_id).await?;
+
+    let cancels = client1_pm
+        .get_submitted_transactions(before)
+        .await
+        .into_iter()
+        .filter(|(_, transaction)| {
+            matches!(
+                transaction.summary,
+                SubmittedTransactionSummary::CancelOrders { .. }
+            )
+        })
+        .count();
+    assert_eq!(cancels, 1);
+
+    let err = client1_pm.cancel_order(OrderId(1000)).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::OrderDoesNotExist)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_order_checked() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    let order_id = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+
+    // cached when the order was created
+    let (order, changed) = client1_pm.get_order_checked(order_id).await?;
+    assert!(!changed);
+    assert_eq!(order.quantity_waiting_for_match, ContractOfOutcomeAmount(1));
+
+    // matched by client 2
+    client2_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+    let (order, changed) = client1_pm.get_order_checked(order_id).await?;
+    assert!(changed);
+    assert_eq!(order.quantity_waiting_for_match, ContractOfOutcomeAmount(0));
+    assert_eq!(client1_pm.get_order(order_id, true).await?, Some(order));
+
+    let (_, changed) = client1_pm.get_order_checked(order_id).await?;
+    assert!(!changed);
+
+    let err = client1_pm
+        .get_order_checked(OrderId(1000))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::OrderDoesNotExist)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fee_exceeding_max_fee_is_not_submitted() -> anyhow::Result<()> {
+    let mut gen_params = PredictionMarketsGenParams::default();
+    gen_params.consensus.gc.new_market_fee = Amount::from_msats(1000);
+    gen_params.consensus.gc.new_order_fee = Amount::from_msats(100);
+    let fed = Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default())
+        .with_module(
+            PredictionMarketsClientInit::default(),
+            PredictionMarketsInit,
+            gen_params,
+        )
+        .new_default_fed()
+        .await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let event_json = Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?;
+    let contract_price = Amount::from_msats(100);
+    let payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight> =
+        iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect();
+
+    let err = client1_pm
+        .new_market(
+            event_json.clone(),
+            contract_price,
+            payout_control_weight_map.clone(),
+            1,
+            Some(Amount::from_msats(999)),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::FeeExceedsLimit {
+            fee: Amount::from_msats(1000),
+            limit: Amount::from_msats(999),
+        })
+    );
+
+    let market = client1_pm
+        .new_market(
+            event_json,
+            contract_price,
+            payout_control_weight_map,
+            1,
+            Some(Amount::from_msats(1000)),
+        )
+        .await?;
+
+    let err = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            Some(Amount::from_msats(99)),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::FeeExceedsLimit {
+            fee: Amount::from_msats(100),
+            limit: Amount::from_msats(99),
+        })
+    );
+
+    let leg = OrderRequest {
+        market,
+        outcome: 0,
+        side: Side::Buy,
+        price: Amount::from_msats(60),
+        quantity: ContractOfOutcomeAmount(1),
+    };
+    let err = client1_pm
+        .submit_multi_market(vec![leg.clone(), leg], Some(Amount::from_msats(199)))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::FeeExceedsLimit {
+            fee: Amount::from_msats(200),
+            limit: Amount::from_msats(199),
+        })
+    );
+    assert!(client1_pm
+        .get_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+        .await
+        .is_empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn market_making_capital_estimate() -> anyhow::Result<()> {
+    let mut gen_params = PredictionMarketsGenParams::default();
+    gen_params.consensus.gc.new_order_fee = Amount::from_msats(100);
+    let fed = Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default())
+        .with_module(
+            PredictionMarketsClientInit::default(),
+            PredictionMarketsInit,
+            gen_params,
+        )
+        .new_default_fed()
+        .await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let quotes = vec![
+        (
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(5),
+        ),
+        (
+            Side::Sell,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(5),
+        ),
+    ];
+
+    // without inventory every contract sold is missing
+    let estimate = client1_pm
+        .market_making_capital_estimate(market, 0, quotes.clone())
+        .await?;
+    assert_eq!(
+        estimate,
+        CapitalEstimate {
+            bitcoin_required: Amount::from_msats(40 * 5 + 2 * 100),
+            fees: Amount::from_msats(2 * 100),
+            contracts_required: ContractOfOutcomeAmount(5),
+            contracts_held: ContractOfOutcomeAmount::ZERO,
+            additional_contracts_required: ContractOfOutcomeAmount(5),
+        }
+    );
+
+    // buys on both outcomes at a total of the contract price match, leaving
+    // the buy on outcome 0 holding contracts of outcome 0
+    let inventory_order = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(3),
+            None,
+        )
+        .await?;
+    client1_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(3),
+            None,
+        )
+        .await?;
+    let inventory = client1_pm.get_order(inventory_order, false).await?.unwrap();
+    assert_eq!(
+        inventory.contract_of_outcome_balance,
+        ContractOfOutcomeAmount(3)
+    );
+
+    let estimate = client1_pm
+        .market_making_capital_estimate(market, 0, quotes)
+        .await?;
+    assert_eq!(estimate.bitcoin_required, Amount::from_msats(400));
+    assert_eq!(estimate.contracts_required, ContractOfOutcomeAmount(5));
+    assert_eq!(estimate.contracts_held, ContractOfOutcomeAmount(3));
+    assert_eq!(
+        estimate.additional_contracts_required,
+        ContractOfOutcomeAmount(2)
+    );
+
+    // quotes that can each be paid for but not all together
+    let quote = (
+        Side::Buy,
+        Amount::from_msats(60),
+        ContractOfOutcomeAmount(u64::MAX / 60),
+    );
+    let err = client1_pm
+        .market_making_capital_estimate(market, 0, vec![quote, quote])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::AmountOverflow)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unaffordable_order_is_detected() -> anyhow::Result<()> {
+    let mut gen_params = PredictionMarketsGenParams::default();
+    gen_params.consensus.gc.new_order_fee = Amount::from_msats(100);
+    let fed = Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default())
+        .with_module(
+            PredictionMarketsClientInit::default(),
+            PredictionMarketsInit,
+            gen_params,
+        )
+        .new_default_fed()
+        .await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(10)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // smallest quantity the balance can not pay for, fee included
+    let balance = client1.get_balance().await;
+    let price = Amount::from_msats(60);
+    let quantity = ContractOfOutcomeAmount((balance.msats - 100) / 60 + 1);
+    assert_eq!(
+        client1_pm.new_order_cost(Side::Buy, price, quantity)?,
+        Amount::from_msats(60 * quantity.0 + 100)
+    );
+
+    let err = client1_pm
+        .check_new_order_affordable(Side::Buy, price, quantity, balance)
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::InsufficientFunds {
+            needed: Amount::from_msats(60 * quantity.0 + 100),
+            available: balance,
+        })
+    );
+
+    // one contract less is affordable and can be submitted
+    let quantity = ContractOfOutcomeAmount(quantity.0 - 1);
+    client1_pm.check_new_order_affordable(Side::Buy, price, quantity, balance)?;
+    client1_pm
+        .new_order(market, 0, Side::Buy, price, quantity, None)
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn order_cost_overflow_is_rejected() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // 60 * (u64::MAX / 30) wraps to a small amount
+    let price = Amount::from_msats(60);
+    let quantity = ContractOfOutcomeAmount(u64::MAX / 30);
+    assert_eq!(Order::checked_cost(price, quantity), None);
+
+    assert_eq!(
+        client1_pm.new_order_cost(Side::Buy, price, quantity),
+        Err(PredictionMarketsClientError::AmountOverflow)
+    );
+    let err = client1_pm
+        .check_new_order_affordable(Side::Buy, price, quantity, client1.get_balance().await)
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::AmountOverflow)
+    );
+
+    let err = client1_pm
+        .new_order(market, 0, Side::Buy, price, quantity, None)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::AmountOverflow)
+    );
+
+    // each leg costs less than u64::MAX msats, but not both together
+    let leg = OrderRequest {
+        market,
+        outcome: 0,
+        side: Side::Buy,
+        price,
+        quantity: ContractOfOutcomeAmount(u64::MAX / 60),
+    };
+    assert!(Order::checked_cost(leg.price, leg.quantity).is_some());
+    let err = client1_pm
+        .submit_multi_market(vec![leg.clone(), leg], None)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::AmountOverflow)
+    );
+    assert!(client1_pm
+        .get_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+        .await
+        .is_empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn market_with_too_many_outcomes_is_not_submitted() -> anyhow::Result<()> {
+    let mut gen_params = PredictionMarketsGenParams::default();
+    gen_params.consensus.gc.max_market_outcomes = 3;
+    let fed = Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default())
+        .with_module(
+            PredictionMarketsClientInit::default(),
+            PredictionMarketsInit,
+            gen_params,
+        )
+        .new_default_fed()
+        .await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight> =
+        iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(3, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            payout_control_weight_map.clone(),
+            1,
+            None,
+        )
+        .await?;
+    assert!(client1_pm.get_market(market, false).await?.is_some());
+
+    let err = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(4, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            payout_control_weight_map,
+            1,
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::TooManyOutcomes { count: 4, max: 3 })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn order_sort() -> anyhow::Result<()> {
+    let market = market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64))?);
+    let order = |created: u64, resting: u64| Order {
+        market,
+        outcome: 0,
+        side: Side::Buy,
+        price: Amount::from_msats(50),
+        original_quantity: ContractOfOutcomeAmount(10),
+        time_ordering: created,
+        created_consensus_timestamp: UnixTimestamp(created),
+        quantity_waiting_for_match: ContractOfOutcomeAmount(resting),
+        contract_of_outcome_balance: ContractOfOutcomeAmount(10 - resting),
+        bitcoin_balance: Amount::ZERO,
+        quantity_fulfilled: ContractOfOutcomeAmount(10 - resting),
+        bitcoin_acquired_from_order_matches: SignedAmount::ZERO,
+        bitcoin_acquired_from_payout: Amount::ZERO,
+    };
+    let orders = vec![
+        (OrderId(2), order(100, 5)),
+        (OrderId(0), order(300, 5)),
+        (OrderId(3), order(200, 10)),
+        (OrderId(1), order(100, 0)),
+    ];
+    let sorted_ids = |sort: OrderSort| {
+        let mut orders = orders.clone();
+        sort.sort(&mut orders);
+        orders.into_iter().map(|(id, _)| id.0).collect::<Vec<_>>()
+    };
+
+    assert_eq!(sorted_ids(OrderSort::IdAsc), vec![0, 1, 2, 3]);
+    assert_eq!(sorted_ids(OrderSort::CreatedDesc), vec![0, 3, 1, 2]);
+    assert_eq!(sorted_ids(OrderSort::RestingQuantityDesc), vec![3, 0, 2, 1]);
+    assert_eq!(
+        OrderSort::from_str("resting-quantity-desc")?,
+        OrderSort::RestingQuantityDesc
+    );
+
+    Ok(())
+}
+
+#[test]
+fn order_rate_limiter_refills_over_window() {
+    let start = tokio::time::Instant::now();
+    let mut limiter = OrderRateLimiter::new(NonZeroU32::new(3).unwrap(), start);
+
+    for _ in 0..3 {
+        assert_eq!(limiter.try_acquire(start), Ok(()));
+    }
+    assert_eq!(limiter.try_acquire(start), Err(Duration::from_secs(20)));
+
+    // one token refilled
+    let t = start + Duration::from_secs(20);
+    assert_eq!(limiter.try_acquire(t), Ok(()));
+    assert!(limiter.try_acquire(t).is_err());
+
+    // full window elapsed, bucket is full again
+    let t = t + Duration::from_secs(60);
+    for _ in 0..3 {
+        assert_eq!(limiter.try_acquire(t), Ok(()));
+    }
+    assert!(limiter.try_acquire(t).is_err());
+
+    // a token given back for an unsubmitted order can be taken again, but
+    // never above capacity
+    limiter.release();
+    assert_eq!(limiter.try_acquire(t), Ok(()));
+    for _ in 0..5 {
+        limiter.release();
+    }
+    for _ in 0..3 {
+        assert_eq!(limiter.try_acquire(t), Ok(()));
+    }
+    assert!(limiter.try_acquire(t).is_err());
+}
+
+#[tokio::test]
+async fn federation_call_policy_times_out_unresponsive_api() -> anyhow::Result<()> {
+    let policy = FederationCallPolicy {
+        timeout: Duration::from_millis(10),
+        retries: 2,
+    };
+
+    // api that never responds
+    let mut attempts = 0;
+    let err = policy
+        .call(|| {
+            attempts += 1;
+            futures::future::pending::<anyhow::Result<()>>()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(attempts, 3);
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::FederationTimeout {
+            timeout: Duration::from_millis(10),
+            attempts: 3,
+        })
+    );
+
+    // api that responds after timing out once
+    let mut attempts = 0;
+    let res = policy
+        .call(|| {
+            attempts += 1;
+            let respond = attempts > 1;
+            async move {
+                match respond {
+                    true => anyhow::Ok(7),
+                    false => futures::future::pending().await,
+                }
+            }
+        })
+        .await?;
+    assert_eq!(res, 7);
+    assert_eq!(attempts, 2);
+
+    // errors from the federation are not retried
+    let mut attempts = 0;
+    let err = policy
+        .call(|| {
+            attempts += 1;
+            async { Err::<(), _>(anyhow::anyhow!("peer error")) }
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(attempts, 1);
+    assert_eq!(err.to_string(), "peer error");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn trading_kill_switch() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    assert!(client1_pm.trading_enabled());
+
+    let new_market = || {
+        client1_pm.new_market(
+            Event::new_with_random_nonce(2, 1, Information::None)
+                .try_to_json_string()
+                .unwrap(),
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+    };
+    let market = new_market().await?;
+    let order_id = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+
+    client1_pm.set_trading_enabled(false);
+    assert!(!client1_pm.trading_enabled());
+
+    let err = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(50),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::TradingDisabled)
+    );
+    let err = new_market().await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::TradingDisabled)
+    );
+
+    // resting orders can still be cancelled and withdrawn from
+    client1_pm.cancel_order(order_id).await?;
+    let withdrawn = client1_pm
+        .send_order_bitcoin_balance_to_primary_module(None)
+        .await?;
+    assert!(withdrawn > Amount::ZERO);
+
+    client1_pm.set_trading_enabled(true);
+    client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(50),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn order_over_exposure_limit_is_not_submitted() -> anyhow::Result<()> {
+    let fed = Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default())
+        .with_module(
+            PredictionMarketsClientInit {
+                max_total_exposure: Some(Amount::from_msats(1000)),
+                ..Default::default()
+            },
+            PredictionMarketsInit,
+            PredictionMarketsGenParams::default(),
+        )
+        .new_default_fed()
+        .await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let new_buy_order = |price: u64, quantity: u64| {
+        client1_pm.new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(price),
+            ContractOfOutcomeAmount(quantity),
+            None,
+        )
+    };
+
+    // up to the limit
+    let first_order_id = new_buy_order(60, 10).await?;
+    new_buy_order(40, 10).await?;
+
+    let err = new_buy_order(10, 1).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::ExposureLimitExceeded {
+            would_be: Amount::from_msats(1010),
+            limit: Amount::from_msats(1000),
+        })
+    );
+
+    // cancelled quantity no longer counts
+    client1_pm.cancel_order(first_order_id).await?;
+    new_buy_order(50, 10).await?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn new_order_over_rate_limit_is_not_submitted() -> anyhow::Result<()> {
+    let fed = Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default())
+        .with_module(
+            PredictionMarketsClientInit {
+                max_orders_per_minute: NonZeroU32::new(2),
+                ..Default::default()
+            },
+            PredictionMarketsInit,
+            PredictionMarketsGenParams::default(),
+        )
+        .new_default_fed()
+        .await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    for _ in 0..2 {
+        client1_pm
+            .new_order(
+                market,
+                0,
+                Side::Buy,
+                Amount::from_msats(60),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await?;
+    }
+
+    let err = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(PredictionMarketsClientError::RateLimited { retry_after })
+            if *retry_after > Duration::ZERO && *retry_after <= Duration::from_secs(30)
+    ));
+    assert_eq!(
+        client1_pm
+            .get_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+            .await
+            .len(),
+        2
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn multi_market_takes_a_rate_limit_token_per_order() -> anyhow::Result<()> {
+    let fed = Fixtures::new_primary(DummyClientInit, DummyInit, DummyGenParams::default())
+        .with_module(
+            PredictionMarketsClientInit {
+                max_orders_per_minute: NonZeroU32::new(2),
+                ..Default::default()
+            },
+            PredictionMarketsInit,
+            PredictionMarketsGenParams::default(),
+        )
+        .new_default_fed()
+        .await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    let leg = OrderRequest {
+        market,
+        outcome: 0,
+        side: Side::Buy,
+        price: Amount::from_msats(60),
+        quantity: ContractOfOutcomeAmount(1),
+    };
+
+    // three orders need three tokens, and the two that are available are
+    // given back
+    let err = client1_pm
+        .submit_multi_market(vec![leg.clone(), leg.clone(), leg.clone()], None)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(PredictionMarketsClientError::RateLimited { .. })
+    ));
+    assert!(client1_pm
+        .get_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+        .await
+        .is_empty());
+
+    client1_pm
+        .submit_multi_market(vec![leg.clone(), leg.clone()], None)
+        .await?;
+
+    let err = client1_pm
+        .submit_multi_market(vec![leg], None)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(PredictionMarketsClientError::RateLimited { .. })
+    ));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_market_payout_before_payout() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    assert_eq!(client1_pm.get_market_payout(market).await?, None);
+
+    let err = client1_pm
+        .get_market_payout(market_outpoint_from_tx_id(TransactionId::from_str(
+            &"0".repeat(64),
+        )?))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::MarketDoesNotExist)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_market_payout_after_payout() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let payout_controls = [Keys::generate()];
+    let (market, event) = new_market_with_payout_controls(&client1_pm, &payout_controls).await?;
+    payout_market_with(
+        &client1_pm,
+        market,
+        &event,
+        &payout_controls,
+        &EventPayout {
+            units_per_outcome: vec![1, 0],
+        },
+    )
+    .await?;
+
+    let expected = Some(vec![Amount::from_msats(100), Amount::ZERO]);
+    assert_eq!(client1_pm.get_market_payout(market).await?, expected);
+    // the payout is final, so it is served from cache from now on
+    assert!(client1_pm
+        .get_market(market, true)
+        .await?
+        .is_some_and(|Market(_, market_dynamic)| market_dynamic.payout.is_some()));
+    assert_eq!(client1_pm.get_market_payout(market).await?, expected);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn orders_on_paid_out_market_are_rejected_early() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let payout_controls = [Keys::generate()];
+    let (market, event) = new_market_with_payout_controls(&client1_pm, &payout_controls).await?;
+    payout_market_with(
+        &client1_pm,
+        market,
+        &event,
+        &payout_controls,
+        &EventPayout {
+            units_per_outcome: vec![0, 1],
+        },
+    )
+    .await?;
+    client1_pm.get_market(market, false).await?;
+
+    let request = OrderRequest {
+        market,
+        outcome: 0,
+        side: Side::Buy,
+        price: Amount::from_msats(60),
+        quantity: ContractOfOutcomeAmount(1),
+    };
+    let errors = [
+        client1_pm
+            .new_order(
+                request.market,
+                request.outcome,
+                request.side,
+                request.price,
+                request.quantity,
+                None,
+            )
+            .await
+            .map(|_| ()),
+        client1_pm
+            .submit_multi_market(vec![request], None)
+            .await
+            .map(|_| ()),
+    ];
+    for err in errors {
+        assert_eq!(
+            err.unwrap_err()
+                .downcast_ref::<PredictionMarketsClientError>(),
+            Some(&PredictionMarketsClientError::MarketClosedForTrading)
+        );
+    }
+    assert!(client1_pm
+        .get_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+        .await
+        .is_empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn payout_of_paid_out_market_fails_fast() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let payout_controls = [Keys::generate()];
+    let (market, event) = new_market_with_payout_controls(&client1_pm, &payout_controls).await?;
+    let event_payout = EventPayout {
+        units_per_outcome: vec![1, 0],
+    };
+
+    // client2 caches the market while it is still trading, then another
+    // payout control pays it out
+    client2_pm.get_market(market, false).await?;
+    payout_market_with(&client1_pm, market, &event, &payout_controls, &event_payout).await?;
+
+    let err = payout_market_with(&client2_pm, market, &event, &payout_controls, &event_payout)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::PayoutAlreadyExists)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_markets_mixed_cache() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let payout_controls = [Keys::generate()];
+    let mut markets = Vec::new();
+    let mut events = Vec::new();
+    for _ in 0..4 {
+        let (market, event) =
+            new_market_with_payout_controls(&client1_pm, &payout_controls).await?;
+        markets.push(market);
+        events.push(event);
+    }
+    let missing_market = market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64))?);
+    payout_market_with(
+        &client1_pm,
+        markets[3],
+        &events[3],
+        &payout_controls,
+        &EventPayout {
+            units_per_outcome: vec![1, 0],
+        },
+    )
+    .await?;
+    client1_pm.get_market(markets[3], false).await?;
+
+    // client2 has the first market cached and the last one cached with its
+    // payout, which is served from cache from now on
+    client2_pm.get_market(markets[0], false).await?;
+    client2_pm.get_market(markets[3], false).await?;
+    let cached = client2_pm
+        .get_markets(vec![markets[0], markets[1]], true)
+        .await?;
+    assert!(cached[&markets[0]].is_some());
+    assert!(cached[&markets[1]].is_none());
+
+    let mut request = markets.clone();
+    request.push(missing_market);
+    let fetched = client2_pm.get_markets(request, false).await?;
+    assert_eq!(fetched.len(), 5);
+    for market in &markets {
+        assert_eq!(fetched[market], client1_pm.get_market(*market, true).await?);
+    }
+    assert!(fetched[&missing_market].is_none());
+    assert!(fetched[&markets[3]]
+        .as_ref()
+        .is_some_and(|Market(_, market_dynamic)| market_dynamic.payout.is_some()));
+
+    // fetched markets are now cached
+    let cached = client2_pm.get_markets(markets.clone(), true).await?;
+    assert!(cached.values().all(Option::is_some));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_markets_where_payout_control() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    client1
+        .get_first_module::<DummyClientModule>()
+        .print_money(Amount::from_sats(1000))
+        .await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let payout_control = Keys::generate().public_key.to_hex();
+    let other_payout_control = Keys::generate().public_key.to_hex();
+    let contract_price = Amount::from_msats(100);
+
+    // created by client1, naming a payout control client2 is interested in
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            contract_price,
+            [
+                (payout_control.clone(), 1u16),
+                (other_payout_control.clone(), 1u16),
+            ]
+            .into_iter()
+            .collect(),
+            1,
+            None,
+        )
+        .await?;
+    client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            contract_price,
+            iter::once((other_payout_control.clone(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    let markets = client2_pm
+        .get_markets_where_payout_control(payout_control.clone(), UnixTimestamp::ZERO, false)
+        .await?;
+    assert_eq!(markets, vec![market]);
+
+    let cached = client2_pm
+        .get_markets_where_payout_control(payout_control.clone(), UnixTimestamp::ZERO, true)
+        .await?;
+    assert_eq!(cached, markets);
+
+    let created_consensus_timestamp = client2_pm
+        .get_market(market, false)
+        .await?
+        .unwrap()
+        .0
+        .created_consensus_timestamp;
+    assert!(client2_pm
+        .get_markets_where_payout_control(payout_control, created_consensus_timestamp, false)
+        .await?
+        .is_empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn find_market_by_event_hash() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    client1
+        .get_first_module::<DummyClientModule>()
+        .print_money(Amount::from_sats(1000))
+        .await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let new_market = client1_pm
+        .new_market_detailed(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // client2 never saw the market, so this goes to the federation
+    assert_eq!(
+        client2_pm
+            .find_market_by_event_hash(new_market.event_hash_hex.clone())
+            .await?,
+        Some(new_market.out_point)
+    );
+    // and this is served from the cache
+    assert_eq!(
+        client2_pm
+            .find_market_by_event_hash(new_market.event_hash_hex)
+            .await?,
+        Some(new_market.out_point)
+    );
+
+    let unknown_event_hash_hex = Event::new_with_random_nonce(2, 1, Information::None)
+        .hash_hex()?
+        .0;
+    assert_eq!(
+        client2_pm
+            .find_market_by_event_hash(unknown_event_hash_hex)
+            .await?,
+        None
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resync_order_slots_progress() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    let mut order_ids = Vec::new();
+    for price in [10, 20, 30] {
+        let order_id = client1_pm
+            .new_order(
+                market,
+                0,
+                Side::Buy,
+                Amount::from_msats(price),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await?;
+        order_ids.push(order_id);
+    }
+
+    let gap_size_to_check = 4;
+    let progress: Vec<_> = client1_pm
+        .resync_order_slots_streamed(gap_size_to_check)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<anyhow::Result<_>>()?;
+    assert_eq!(progress.len(), order_ids.len() + gap_size_to_check);
+    for (i, item) in progress.iter().enumerate() {
+        assert_eq!(item.scanned, i + 1);
+        assert_eq!(item.current_id, OrderId(i as u64));
+        assert_eq!(item.complete, i == progress.len() - 1);
+    }
+    let last = progress.last().unwrap();
+    assert_eq!(last.found, order_ids.len());
+    assert_eq!(last.highest_order_id, order_ids.last().copied());
+
+    client1_pm.resync_order_slots(gap_size_to_check).await?;
+    let cached = client1_pm
+        .get_orders_from_db(OrderFilter(OrderPath::All, OrderState::Any))
+        .await;
+    assert_eq!(cached.len(), last.found);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn iter_orders() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let new_market = || async {
+        client1_pm
+            .new_market(
+                Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+                Amount::from_msats(100),
+                iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+                1,
+                None,
+            )
+            .await
+    };
+    let market = new_market().await?;
+    let other_market = new_market().await?;
+
+    // orders of both markets interleaved, none of them match
+    let mut order_ids = Vec::new();
+    for (market, outcome, price) in [
+        (market, 0, 10),
+        (other_market, 0, 20),
+        (market, 1, 30),
+        (other_market, 1, 40),
+        (market, 0, 50),
+    ] {
+        let order_id = client1_pm
+            .new_order(
+                market,
+                outcome,
+                Side::Buy,
+                Amount::from_msats(price),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await?;
+        order_ids.push(order_id);
+    }
+    client1_pm.cancel_order(order_ids[4]).await?;
+
+    for filter in [
+        OrderFilter(OrderPath::All, OrderState::Any),
+        OrderFilter(OrderPath::Market { market }, OrderState::Any),
+        OrderFilter(
+            OrderPath::MarketOutcome { market, outcome: 0 },
+            OrderState::NonZeroQuantityWaitingForMatch,
+        ),
+        OrderFilter(OrderPath::All, OrderState::NonZeroBitcoinBalance),
+    ] {
+        let iterated: Vec<_> = client1_pm.iter_orders(filter).collect().await;
+        let expected: Vec<_> = client1_pm
+            .get_orders_from_db(filter)
+            .await
+            .into_iter()
+            .collect();
+        assert_eq!(iterated, expected, "{filter:?}");
+    }
+
+    let iterated: Vec<_> = client1_pm
+        .iter_orders(OrderFilter(OrderPath::Market { market }, OrderState::Any))
+        .map(|(order_id, _)| order_id)
+        .collect()
+        .await;
+    assert_eq!(iterated, [order_ids[0], order_ids[2], order_ids[4]]);
+
+    let iterated: Vec<_> = client1_pm
+        .iter_orders(OrderFilter(
+            OrderPath::MarketOutcome { market, outcome: 0 },
+            OrderState::NonZeroQuantityWaitingForMatch,
+        ))
+        .map(|(order_id, _)| order_id)
+        .collect()
+        .await;
+    assert_eq!(iterated, [order_ids[0]]);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_market_orders_paginated() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    let client2_dummy = client2.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+    client2_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let new_market = || async {
+        client1_pm
+            .new_market(
+                Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+                Amount::from_msats(100),
+                iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+                1,
+                None,
+            )
+            .await
+    };
+    let market = new_market().await?;
+    let other_market = new_market().await?;
+
+    // orders from both clients that do not match each other
+    for (client_pm, outcome, price) in [
+        (&client1_pm, 0, 10),
+        (&client1_pm, 0, 20),
+        (&client1_pm, 1, 30),
+        (&client2_pm, 1, 40),
+        (&client2_pm, 0, 50),
+    ] {
+        client_pm
+            .new_order(
+                market,
+                outcome,
+                Side::Buy,
+                Amount::from_msats(price),
+                ContractOfOutcomeAmount(1),
+                None,
+            )
+            .await?;
+    }
+    client2_pm
+        .new_order(
+            other_market,
+            0,
+            Side::Buy,
+            Amount::from_msats(10),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+
+    let mut paged = Vec::new();
+    let mut after = None;
+    loop {
+        let (orders, next) = client1_pm.get_market_orders(market, after, 2).await?;
+        assert!(orders.len() <= 2);
+        paged.extend(orders);
+
+        after = next;
+        if after.is_none() {
+            break;
+        }
+    }
+
+    assert_eq!(paged.len(), 5);
+    assert!(paged.iter().all(|(_, order)| order.market == market));
+    // strictly increasing keys means no duplicates
+    assert!(paged
+        .windows(2)
+        .all(|w| w[0].0.serialize() < w[1].0.serialize()));
+    let mut prices: Vec<_> = paged.iter().map(|(_, order)| order.price.msats).collect();
+    prices.sort();
+    assert_eq!(prices, vec![10, 20, 30, 40, 50]);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn new_market_idempotent() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let idempotency_key: [u8; 32] = rand::random();
+    let event_json = Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?;
+    let contract_price = Amount::from_msats(100);
+    let payout_control_weight_map: BTreeMap<NostrPublicKeyHex, Weight> =
+        iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect();
+    let weight_required_for_payout = 1;
+
+    let market1 = client1_pm
+        .new_market_idempotent(
+            idempotency_key,
+            event_json.clone(),
+            contract_price,
+            payout_control_weight_map.clone(),
+            weight_required_for_payout,
+            None,
+        )
+        .await?;
+    let market2 = client1_pm
+        .new_market_idempotent(
+            idempotency_key,
+            event_json.clone(),
+            contract_price,
+            payout_control_weight_map.clone(),
+            weight_required_for_payout,
+            None,
+        )
+        .await?;
+    assert_eq!(market1, market2);
+    assert!(client1_pm.get_market(market1, false).await?.is_some());
+
+    let market3 = client1_pm
+        .new_market_idempotent(
+            rand::random(),
+            event_json,
+            contract_price,
+            payout_control_weight_map,
+            weight_required_for_payout,
+            None,
+        )
+        .await?;
+    assert_ne!(market1, market3);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_operation_outcome_has_reason() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    // zero contract price does not pass server validation
+    let idempotency_key: [u8; 32] = rand::random();
+    let res = client1_pm
+        .new_market_idempotent(
+            idempotency_key,
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::ZERO,
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await;
+    let Err(e) = res else {
+        panic!("market with zero contract price should be rejected");
+    };
+
+    let operation_id = OperationId(idempotency_key);
+    let outcome = loop {
+        if let Some(outcome) = client1_pm.get_operation_outcome(operation_id).await {
+            break outcome;
+        }
+        sleep(Duration::from_millis(100)).await;
+    };
+    assert_eq!(
+        outcome,
+        OperationOutcome::Rejected {
+            reason: e.to_string()
+        }
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn operation_status_accepted_and_failed() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let unknown_operation_id = OperationId::new_random();
+    assert_eq!(
+        client1_pm.get_operation_status(unknown_operation_id).await,
+        None
+    );
+
+    let new_market = client1_pm
+        .new_market_detailed(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    assert_eq!(
+        client1_pm
+            .get_operation_status(new_market.operation_id)
+            .await,
+        Some(PredictionMarketOperationStatus::Accepted)
+    );
+
+    let operation_id = OperationId::new_random();
+    client1_pm
+        .new_order_with_operation_id(
+            operation_id,
+            new_market.out_point,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(1),
+            None,
+        )
+        .await?;
+    assert_eq!(
+        client1_pm.get_operation_status(operation_id).await,
+        Some(PredictionMarketOperationStatus::Accepted)
+    );
+
+    // zero contract price does not pass server validation
+    let idempotency_key: [u8; 32] = rand::random();
+    let e = client1_pm
+        .new_market_idempotent(
+            idempotency_key,
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::ZERO,
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await
+        .unwrap_err();
+
+    let operation_id = OperationId(idempotency_key);
+    let status = loop {
+        match client1_pm.get_operation_status(operation_id).await {
+            Some(PredictionMarketOperationStatus::Pending) => {
+                sleep(Duration::from_millis(100)).await
+            }
+            status => break status,
+        }
+    };
+    assert_eq!(
+        status,
+        Some(PredictionMarketOperationStatus::Failed {
+            reason: e.to_string()
+        })
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn abandon_operation() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let err = client1_pm
+        .abandon_operation(OperationId::new_random())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::OperationDoesNotExist)
+    );
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+
+    // abandon as soon as the operation exists, racing the federation
+    let operation_id = OperationId::new_random();
+    let submit = client1_pm.new_order_with_operation_id(
+        operation_id,
+        market,
+        0,
+        Side::Buy,
+        Amount::from_msats(60),
+        ContractOfOutcomeAmount(1),
+        None,
+    );
+    let abandon = async {
+        while client1_pm
+            .get_operation_status(operation_id)
+            .await
+            .is_none()
+        {
+            sleep(Duration::from_millis(1)).await;
+        }
+        client1_pm.abandon_operation(operation_id).await
+    };
+    let (submit_res, abandon_res) = tokio::join!(submit, abandon);
+    abandon_res?;
+    // returns without waiting for the order's state machine to complete
+    assert_eq!(
+        submit_res
+            .unwrap_err()
+            .downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::OperationAbandoned)
+    );
+
+    assert_eq!(
+        client1_pm.get_operation_status(operation_id).await,
+        Some(PredictionMarketOperationStatus::Abandoned)
+    );
+
+    // the federation still processes the transaction
+    let outcome = loop {
+        match client1_pm.get_operation_outcome(operation_id).await {
+            Some(outcome) => break outcome,
+            None => sleep(Duration::from_millis(100)).await,
+        }
+    };
+    assert_eq!(outcome, OperationOutcome::Accepted);
+    assert_eq!(
+        client1_pm.get_operation_status(operation_id).await,
+        Some(PredictionMarketOperationStatus::Abandoned)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn health_check() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    assert_eq!(
+        client1_pm.health_check().await?,
+        ClientHealth {
+            federation_reachable: true,
+            last_order_sync: None,
+            orders_waiting_for_match: 0,
+            highest_order_id: None,
+        }
+    );
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    for outcome in [0, 1] {
+        client1_pm
+            .new_order(
+                market,
+                outcome,
+                Side::Buy,
+                Amount::from_msats(30),
+                ContractOfOutcomeAmount(2),
+                None,
+            )
+            .await?;
+    }
+    client1_pm.sync_matches(OrderPath::All, true).await?;
+
+    let health = client1_pm.health_check().await?;
+    assert!(health.federation_reachable);
+    assert!(health.last_order_sync.is_some());
+    assert_eq!(health.orders_waiting_for_match, 2);
+    assert_eq!(health.highest_order_id, Some(OrderId(1)));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn consensus_timestamp_does_not_go_backwards() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    let created = client1_pm
+        .get_market(market, true)
+        .await?
+        .unwrap()
+        .0
+        .created_consensus_timestamp;
+
+    let mut previous = created;
+    for i in 0..4 {
+        // let every other call skip the cache
+        if i % 2 == 1 {
+            sleep(CONSENSUS_TIMESTAMP_CACHE_DURATION + Duration::from_secs(1)).await;
+        }
+
+        let consensus_timestamp = client1_pm.get_consensus_timestamp().await?;
+        assert!(consensus_timestamp >= previous);
+        previous = consensus_timestamp;
+    }
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn submitted_transactions_are_logged() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let before = UnixTimestamp::now();
+    assert!(client1_pm
+        .get_submitted_transactions(before)
+        .await
+        .is_empty());
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    let order_id = client1_pm
+        .new_order(
+            market,
+            0,
+            Side::Buy,
+            Amount::from_msats(60),
+            ContractOfOutcomeAmount(2),
+            None,
+        )
+        .await?;
+    client1_pm.cancel_order(order_id).await?;
+
+    let summaries: Vec<_> = client1_pm
+        .get_submitted_transactions(before)
+        .await
+        .into_iter()
+        .map(|(_, transaction)| transaction.summary)
+        .collect();
+    assert_eq!(summaries.len(), 3);
+    // entries submitted within the same second have no defined order
+    for summary in [
+        SubmittedTransactionSummary::NewMarket {
+            outcome_count: 2,
+            contract_price: Amount::from_msats(100),
+        },
+        SubmittedTransactionSummary::NewOrder {
+            order_id,
+            market,
+            outcome: 0,
+            side: Side::Buy,
+            price: Amount::from_msats(60),
+            quantity: ContractOfOutcomeAmount(2),
+            sources: vec![],
+        },
+        SubmittedTransactionSummary::CancelOrders {
+            order_ids: vec![order_id],
+        },
+    ] {
+        assert!(summaries.contains(&summary), "{summary:?} not logged");
+    }
+
+    let after = UnixTimestamp(UnixTimestamp::now().0 + 1);
+    assert!(client1_pm
+        .get_submitted_transactions(after)
+        .await
+        .is_empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shared_quote_round_trip() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    let market = client1_pm
+        .new_market(
+            Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+            Amount::from_msats(100),
+            iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+            1,
+            None,
+        )
+        .await?;
+    let order_id = client1_pm
+        .new_order(
+            market,
+            1,
+            Side::Buy,
+            Amount::from_msats(40),
+            ContractOfOutcomeAmount(8),
+            None,
+        )
+        .await?;
+
+    let quote = client1_pm.export_order_quote(order_id).await?;
+    assert_eq!(
+        client2_pm.preview_shared_quote(&quote).await?,
+        SharedQuote {
+            market,
+            outcome: 1,
+            side: Side::Buy,
+            price: Amount::from_msats(40),
+            quantity: ContractOfOutcomeAmount(8),
+        }
+    );
+
+    let err = client1_pm
+        .export_order_quote(OrderId(order_id.0 + 1))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::OrderDoesNotExist)
+    );
+
+    let unknown_market = SharedQuote {
+        market: market_outpoint_from_tx_id(TransactionId::from_str(&"0".repeat(64))?),
+        outcome: 0,
+        side: Side::Sell,
+        price: Amount::from_msats(50),
+        quantity: ContractOfOutcomeAmount(1),
+    };
+    let err = client2_pm
+        .preview_shared_quote(&unknown_market.to_string())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<PredictionMarketsClientError>(),
+        Some(&PredictionMarketsClientError::MarketDoesNotExist)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn payout_control_names_round_trip() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+    let client2 = fed.new_client_rocksdb().await;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+    let client2_pm = client2.get_first_module::<PredictionMarketsClientModule>();
+
+    for name in ["alice", "bob"] {
+        client1_pm
+            .set_name_to_payout_control(name.to_owned(), Some(Keys::generate().public_key.to_hex()))
+            .await;
+    }
+
+    let json = client1_pm.export_payout_control_names().await;
+    let import = client2_pm.import_payout_control_names(&json, false).await?;
+    assert_eq!(import.imported, 2);
+    assert!(import.malformed.is_empty());
+    assert_eq!(
+        client2_pm.get_name_to_payout_control_map().await,
+        client1_pm.get_name_to_payout_control_map().await
+    );
+
+    let carol = Keys::generate().public_key.to_hex();
+    let json = format!(r#"{{"carol": "{carol}", "dave": "not a public key"}}"#);
+    let import = client2_pm.import_payout_control_names(&json, false).await?;
+    assert_eq!(import.imported, 1);
+    assert_eq!(import.malformed, vec!["dave".to_owned()]);
+    assert!(client2_pm
+        .get_name_to_payout_control("dave".to_owned())
+        .await
+        .is_none());
+
+    assert!(client2_pm
+        .import_payout_control_names("[]", false)
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn payout_control_names_import_merge_and_overwrite() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let existing = Keys::generate().public_key.to_hex();
+    let imported = Keys::generate().public_key.to_hex();
+    let new = Keys::generate().public_key.to_hex();
+    client1_pm
+        .set_name_to_payout_control("alice".to_owned(), Some(existing.clone()))
+        .await;
+    let json = format!(r#"{{"alice": "{imported}", "bob": "{new}"}}"#);
+
+    // merging keeps names that are already assigned
+    let import = client1_pm.import_payout_control_names(&json, false).await?;
+    assert_eq!(import.imported, 1);
+    assert_eq!(
+        client1_pm
+            .get_name_to_payout_control("alice".to_owned())
+            .await,
+        Some(existing)
+    );
+    assert_eq!(
+        client1_pm
+            .get_name_to_payout_control("bob".to_owned())
+            .await,
+        Some(new.clone())
+    );
+
+    let import = client1_pm.import_payout_control_names(&json, true).await?;
+    assert_eq!(import.imported, 2);
+    assert_eq!(
+        client1_pm
+            .get_name_to_payout_control("alice".to_owned())
+            .await,
+        Some(imported)
+    );
+    assert_eq!(
+        client1_pm
+            .get_name_to_payout_control("bob".to_owned())
+            .await,
+        Some(new)
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn saved_markets_by_tag() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let mut markets = Vec::new();
+    for _ in 0..3 {
+        let market = client1_pm
+            .new_market(
+                Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+                Amount::from_msats(100),
+                iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+                1,
+                None,
+            )
+            .await?;
+        markets.push(market);
+    }
+
+    client1_pm
+        .save_market_with_note(
+            markets[0],
+            Some("first".to_owned()),
+            vec!["sports".to_owned(), "football".to_owned()],
+            false,
+        )
+        .await;
+    client1_pm
+        .save_market_with_note(markets[1], None, vec!["sports".to_owned()], false)
+        .await;
+    client1_pm.save_market(markets[2], false).await;
+
+    let sports: Vec<_> = client1_pm
+        .get_saved_markets_by_tag("sports")
+        .await
+        .into_iter()
+        .map(|(market, _)| market)
+        .collect();
+    assert_eq!(sports.len(), 2);
+    assert!(sports.contains(&markets[0]));
+    assert!(sports.contains(&markets[1]));
+
+    let football = client1_pm.get_saved_markets_by_tag("football").await;
+    assert_eq!(football.len(), 1);
+    assert_eq!(football[0].0, markets[0]);
+    assert_eq!(football[0].1.note, Some("first".to_owned()));
+
+    assert!(client1_pm.get_saved_markets_by_tag("foot").await.is_empty());
+
+    client1_pm
+        .update_saved_market_note(markets[1], Some("second".to_owned()))
+        .await?;
+    let sports = client1_pm.get_saved_markets_by_tag("sports").await;
+    assert!(sports
+        .iter()
+        .any(|(market, meta)| market == &markets[1] && meta.note == Some("second".to_owned())));
+
+    assert_eq!(client1_pm.get_saved_markets().await.len(), 3);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resaving_market_keeps_saved_at() -> anyhow::Result<()> {
+    let fed = fixtures().new_default_fed().await;
+    let client1 = fed.new_client_rocksdb().await;
+
+    let client1_dummy = client1.get_first_module::<DummyClientModule>();
+    client1_dummy.print_money(Amount::from_sats(1000)).await?;
+
+    let client1_pm = client1.get_first_module::<PredictionMarketsClientModule>();
+
+    let mut markets = Vec::new();
+    for _ in 0..2 {
+        let market = client1_pm
+            .new_market(
+                Event::new_with_random_nonce(2, 1, Information::None).try_to_json_string()?,
+                Amount::from_msats(100),
+                iter::once((Keys::generate().public_key.to_hex(), 1u16)).collect(),
+                1,
+                None,
+            )
+            .await?;
+        markets.push(market);
+    }
+
+    let saved_order = || async {
+        let mut saved = client1_pm.get_saved_markets().await;
+        saved.sort_by_key(|(market, meta)| (meta.saved_at, *market));
+        saved
+    };
+
+    // timestamps have second resolution
+    assert!(client1_pm.save_market(markets[0], false).await);
+    sleep(Duration::from_millis(1100)).await;
+    assert!(client1_pm.save_market(markets[1], false).await);
+    let before = saved_order().await;
+    assert_eq!(before[0].0, markets[0]);
+
+    sleep(Duration::from_millis(1100)).await;
+    assert!(
+        !client1_pm
+            .save_market_with_note(markets[0], Some("note".to_owned()), Vec::new(), false)
+            .await
+    );
+    let after = saved_order().await;
+    assert_eq!(after[0].0, markets[0]);
+    assert_eq!(after[0].1.saved_at, before[0].1.saved_at);
+    assert_eq!(after[0].1.note, Some("note".to_owned()));
+
+    // resaving without a note keeps the one already saved
+    assert!(!client1_pm.save_market(markets[0], false).await);
+    assert_eq!(saved_order().await, after);
+
+    assert!(!client1_pm.save_market(markets[0], true).await);
+    let refreshed = saved_order().await;
+    assert_eq!(refreshed[1].0, markets[0]);
+    assert!(refreshed[1].1.saved_at > before[0].1.saved_at);
+    assert_eq!(refreshed[1].1.note, Some("note".to_owned()));
+
+    Ok(())
+}
+
+#[test]
+fn order_owner_public_key_derivation() {
+    let root_secret = DerivableSecret::new_root(&[42u8; 32], b"prediction-markets-test");
+
+    // changing the derivation breaks recovery of existing orders, so the
+    // expected key is pinned rather than derived the same way again
+    let expected = secp256k1::PublicKey::from_str(
+        "0368942aa269f088a7005225f7fff1fefad2225c888a4d61beed2aeb10ca986af0",
+    )
+    .unwrap();
+    assert_eq!(
+        root_secret
+            .child_key(ChildId(0))
+            .child_key(ChildId(5))
+            .to_secp_key(&Secp256k1::new())
+            .public_key(),
+        expected
+    );
+    assert_eq!(OrderId(5).owner_public_key(root_secret.clone()), expected);
+    assert_eq!(OrderId(5).owner_public_key(root_secret.clone()), expected);
+    assert_ne!(OrderId(6).owner_public_key(root_secret), expected);
+}
+
+#[test]
+fn key_domains_are_distinct_and_stable() {
+    let root_secret = DerivableSecret::new_root(&[42u8; 32], b"prediction-markets-test");
+    let domains = [
+        KeyDomain::Order,
+        KeyDomain::PayoutControl,
+        KeyDomain::NostrIdentity,
+    ];
+
+    // child ids are pinned, changing them breaks recovery of existing keys
+    for (domain, child_id) in domains.into_iter().zip([0, 1, 2]) {
+        let expected = root_secret
+            .child_key(ChildId(child_id))
+            .child_key(ChildId(7))
+            .to_secp_key(&Secp256k1::new())
+            .public_key();
+        assert_eq!(
+            derive_key(&root_secret, domain, 7).public_key(),
+            expected,
+            "{domain:?}"
+        );
+    }
+    assert_eq!(
+        OrderId(7).owner_public_key(root_secret.clone()),
+        derive_key(&root_secret, KeyDomain::Order, 7).public_key()
+    );
+
+    let keys: Vec<_> = domains
+        .into_iter()
+        .map(|domain| derive_key(&root_secret, domain, 0).public_key())
+        .collect();
+    for (i, a) in keys.iter().enumerate() {
+        for b in &keys[i + 1..] {
+            assert_ne!(a, b);
+        }
+    }
+}
+
+#[test]
+fn outcome_by_title_matches() {
+    let titles = ["Team A wins", "Team B wins", "Draw", "draw"].map(String::from);
+
+    assert_eq!(outcome_by_title(&titles, "Team A wins"), Ok(0));
+    assert_eq!(outcome_by_title(&titles, "team b WINS"), Ok(1));
+    assert_eq!(
+        outcome_by_title(&titles, "DRAW"),
+        Err(PredictionMarketsClientError::AmbiguousOutcomeTitle(
+            "DRAW".to_owned()
+        ))
+    );
+    // only exact titles match
+    assert_eq!(
+        outcome_by_title(&titles, "Team A"),
+        Err(PredictionMarketsClientError::UnknownOutcomeTitle(
+            "Team A".to_owned()
+        ))
+    );
+    assert_eq!(
+        outcome_by_title(&[], "Team A wins"),
+        Err(PredictionMarketsClientError::UnknownOutcomeTitle(
+            "Team A wins".to_owned()
+        ))
+    );
+}
+
+#[test]
+fn market_template_substitution() {
+    let substitutions: BTreeMap<String, String> = [
+        ("home", "Team \"A\""),
+        ("away", "Team B"),
+        ("unused", "{home}"),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_owned(), v.to_owned()))
+    .collect();
+
+    assert_eq!(
+        fill_market_template(r#"{"title":"{home} vs {away}","tags":{}}"#, &substitutions),
+        Ok(r#"{"title":"Team \"A\" vs Team B","tags":{}}"#.to_owned())
+    );
+    // substitutions are not filled again
+    assert_eq!(
+        fill_market_template("{unused}", &substitutions),
+        Ok("{home}".to_owned())
+    );
+    assert_eq!(
+        fill_market_template("{home} on {date}", &substitutions),
+        Err(PredictionMarketsClientError::MissingTemplateSubstitution(
+            "date".to_owned()
+        ))
+    );
+}
+
+#[test]
+fn price_from_implied_probability() {
+    let contract_price = Amount::from_msats(1000);
+    for (probability, price) in [(0.4, 400), (0.5, 500), (0.0125, 13), (0.9994, 999)] {
+        assert_eq!(
+            price_from_probability(contract_price, probability),
+            Ok(Amount::from_msats(price))
+        );
+    }
+
+    // outside the open interval, or rounding to zero or the contract price
+    for probability in [0.0, 1.0, -0.5, 1.5, f64::NAN, 0.0004, 0.9996] {
+        assert_eq!(
+            price_from_probability(contract_price, probability),
+            Err(PredictionMarketsClientError::InvalidProbability)
+        );
+    }
+}
+
+#[test]
+fn candlestick_price_invariants() {
+    let candlestick = |open: u64, close: u64, high: u64, low: u64| Candlestick {
+        open: Amount::from_msats(open),
+        close: Amount::from_msats(close),
+        high: Amount::from_msats(high),
+        low: Amount::from_msats(low),
+        volume: ContractOfOutcomeAmount(2),
+    };
+
+    let valid = candlestick(20, 30, 40, 10);
+    assert_eq!(check_candlestick(&valid), Ok(()));
+    assert_eq!(repair_candlestick(valid.clone()), valid);
+
+    for (corrupt, violation, repaired) in [
+        (
+            candlestick(20, 30, 10, 40),
+            CandlestickViolation::HighBelowLow,
+            candlestick(20, 30, 40, 10),
+        ),
+        (
+            candlestick(50, 30, 40, 10),
+            CandlestickViolation::OpenOutOfRange,
+            candlestick(50, 30, 50, 10),
+        ),
+        (
+            candlestick(20, 5, 40, 10),
+            CandlestickViolation::CloseOutOfRange,
+            candlestick(20, 5, 40, 5),
+        ),
+    ] {
+        assert_eq!(check_candlestick(&corrupt), Err(violation));
+        assert_eq!(repair_candlestick(corrupt), repaired.clone());
+        assert_eq!(check_candlestick(&repaired), Ok(()));
+    }
+}
+
+#[test]
+fn normalize_candlestick_closes_carries_forward() {
+    let close = |msats: u64| Candlestick {
+        open: Amount::from_msats(msats),
+        close: Amount::from_msats(msats),
+        high: Amount::from_msats(msats),
+        low: Amount::from_msats(msats),
+        volume: ContractOfOutcomeAmount(1),
+    };
+    let series = |closes: &[(u64, u64)]| -> BTreeMap<UnixTimestamp, Candlestick> {
+        closes
+            .iter()
+            .map(|(timestamp, msats)| (UnixTimestamp(*timestamp), close(*msats)))
+            .collect()
+    };
+    let market_candlesticks: BTreeMap<Outcome, _> = [
+        (0, series(&[(0, 50), (60, 40), (180, 20)])),
+        (1, series(&[(0, 30), (120, 60)])),
+        // has not traded at 0
+        (2, series(&[(60, 20)])),
+    ]
+    .into_iter()
+    .collect();
+
+    let normalized = normalize_candlestick_closes(&market_candlesticks);
+    assert_eq!(
+        normalized.keys().copied().collect::<Vec<_>>(),
+        [60, 120, 180].map(UnixTimestamp)
+    );
+    // closes carried forward: 40, 30, 20
+    assert_eq!(
+        normalized[&UnixTimestamp(60)],
+        [4.0 / 9.0, 3.0 / 9.0, 2.0 / 9.0]
+    );
+    // 40, 60, 20
+    assert_eq!(normalized[&UnixTimestamp(120)], [1.0 / 3.0, 0.5, 1.0 / 6.0]);
+    // 20, 60, 20
+    assert_eq!(normalized[&UnixTimestamp(180)], [0.2, 0.6, 0.2]);
+    for probabilities in normalized.values() {
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    assert!(normalize_candlestick_closes(&BTreeMap::new()).is_empty());
+}
+
+#[test]
+fn fill_candlestick_gaps_with_interior_gaps() {
+    let candlestick = |open: u64, close: u64| Candlestick {
+        open: Amount::from_msats(open),
+        close: Amount::from_msats(close),
+        high: Amount::from_msats(open.max(close)),
+        low: Amount::from_msats(open.min(close)),
+        volume: ContractOfOutcomeAmount(2),
+    };
+    let candlesticks: BTreeMap<UnixTimestamp, Candlestick> = [
+        (UnixTimestamp(60), candlestick(10, 20)),
+        (UnixTimestamp(180), candlestick(20, 30)),
+        (UnixTimestamp(360), candlestick(30, 25)),
+    ]
+    .into_iter()
+    .collect();
+
+    let series = fill_candlestick_gaps(&candlesticks, 60, FillMode::None);
+    assert_eq!(
+        series,
+        vec![
+            (UnixTimestamp(60), Some(candlestick(10, 20))),
+            (UnixTimestamp(120), None),
+            (UnixTimestamp(180), Some(candlestick(20, 30))),
+            (UnixTimestamp(240), None),
+            (UnixTimestamp(300), None),
+            (UnixTimestamp(360), Some(candlestick(30, 25))),
+        ]
+    );
+
+    let flat = |price: u64| Candlestick {
+        open: Amount::from_msats(price),
+        close: Amount::from_msats(price),
+        high: Amount::from_msats(price),
+        low: Amount::from_msats(price),
+        volume: ContractOfOutcomeAmount::ZERO,
+    };
+    let series = fill_candlestick_gaps(&candlesticks, 60, FillMode::Carry);
+    assert_eq!(
+        series,
+        vec![
+            (UnixTimestamp(60), Some(candlestick(10, 20))),
+            (UnixTimestamp(120), Some(flat(20))),
+            (UnixTimestamp(180), Some(candlestick(20, 30))),
+            (UnixTimestamp(240), Some(flat(30))),
+            (UnixTimestamp(300), Some(flat(30))),
+            (UnixTimestamp(360), Some(candlestick(30, 25))),
+        ]
+    );
+
+    assert!(fill_candlestick_gaps(&BTreeMap::new(), 60, FillMode::Carry).is_empty());
+}
+
+#[test]
+fn validate_event_payout_rejects_wrong_outcome_count() -> anyhow::Result<()> {
+    let event = Event::new_with_random_nonce(2, 1, Information::None);
+
+    let event_payout = EventPayout {
+        units_per_outcome: vec![1, 0, 0],
+    };
+    assert_eq!(
+        validate_event_payout(&event, &event_payout),
+        Err(
+            PredictionMarketsClientError::EventPayoutOutcomeCountMismatch {
+                expected: 2,
+                actual: 3
+            }
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn validate_event_payout_rejects_wrong_units_sum() -> anyhow::Result<()> {
+    let event = Event::new_with_random_nonce(2, 1, Information::None);
+
+    let event_payout = EventPayout {
+        units_per_outcome: vec![1, 1],
+    };
+    assert_eq!(
+        validate_event_payout(&event, &event_payout),
+        Err(PredictionMarketsClientError::EventPayoutUnitsSumMismatch {
+            expected: 1,
+            actual: 2
+        })
+    );
+
+    let event_payout = EventPayout {
+        units_per_outcome: vec![0, 1],
+    };
+    assert_eq!(validate_event_payout(&event, &event_payout), Ok(()));
+
+    Ok(())
+}
+
+#[test]
+fn suggest_event_payout_picks_most_weight() -> anyhow::Result<()> {
+    let event = Event::new_with_random_nonce(3, 6, Information::None);
+    let group = |total_weight| EventPayoutAttestationGroup {
+        total_weight,
+        ..Default::default()
+    };
+    let payout = |units_per_outcome| EventPayout { units_per_outcome };
+
+    assert_eq!(suggest_event_payout(&event, &HashMap::new()), None);
+
+    let mut groups = HashMap::from([
+        (payout(vec![6, 0, 0]), group(2)),
+        (payout(vec![0, 6, 0]), group(5)),
+        (payout(vec![2, 2, 2]), group(3)),
+    ]);
+    assert_eq!(
+        suggest_event_payout(&event, &groups),
+        Some(payout(vec![0, 6, 0]))
+    );
+
+    // invalid payouts are never suggested, whatever their weight
+    groups.insert(payout(vec![6, 6, 6]), group(10));
+    assert_eq!(
+        suggest_event_payout(&event, &groups),
+        Some(payout(vec![0, 6, 0]))
+    );
+
+    // ties go to the lexicographically smallest payout
+    groups.insert(payout(vec![0, 0, 6]), group(5));
+    assert_eq!(
+        suggest_event_payout(&event, &groups),
+        Some(payout(vec![0, 0, 6]))
+    );
+
+    let market = Market(
+        MarketStatic {
+            event_json: event.try_to_json_string()?,
+            contract_price: Amount::from_msats(600),
+            payout_control_weight_map: BTreeMap::new(),
+            weight_required_for_payout: 1,
+            outcome_display_hints: Vec::new(),
+            created_consensus_timestamp: UnixTimestamp::ZERO,
+        },
+        MarketDynamic {
+            open_contracts: ContractAmount::ZERO,
+            payout: None,
+        },
+    );
+    assert_eq!(
+        payout_amount_per_outcome(&market, &event, &payout(vec![1, 2, 3])),
+        vec![
+            Amount::from_msats(100),
+            Amount::from_msats(200),
+            Amount::from_msats(300)
+        ]
+    );
 
     Ok(())
 }